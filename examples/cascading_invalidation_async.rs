@@ -0,0 +1,69 @@
+use moka2::{future::Cache, notification::RemovalCause};
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() {
+    // A DNS-like cache from names to records. A "CNAME" record is an alias of
+    // another name, and an "A" record has an IPv4 address.
+    let cache: Cache<String, String> = Cache::builder()
+        .max_capacity(100)
+        .time_to_live(Duration::from_secs(60))
+        .eviction_listener(|name, record, cause| {
+            // The entries removed because of their dependencies have the
+            // `Cascaded` cause.
+            if cause == RemovalCause::Cascaded {
+                println!("Removed {name} ({record}) as its dependency was removed");
+            } else {
+                println!("Removed {name} ({record}) because {cause:?}");
+            }
+        })
+        .build();
+
+    cache
+        .insert("db.example.com".into(), "A 10.0.0.1".into())
+        .await;
+    cache
+        .insert("www.example.com".into(), "A 10.0.0.2".into())
+        .await;
+
+    // An alias explicitly declared to depend on its target.
+    cache
+        .insert("primary.example.com".into(), "CNAME db.example.com".into())
+        .await;
+    assert!(
+        cache
+            .add_dependencies("primary.example.com", ["db.example.com"])
+            .await
+    );
+
+    // Another alias resolved by `get_with`. The entries read from the cache inside
+    // the `init` future become its dependencies automatically.
+    let resolved = cache
+        .get_with("api.example.com".into(), async {
+            let target = cache.get("www.example.com").await.unwrap();
+            format!("CNAME www.example.com ({target})")
+        })
+        .await;
+    println!("Resolved api.example.com: {resolved}");
+
+    // A chain of aliases: "app" -> "api" -> "www".
+    cache
+        .get_with("app.example.com".into(), async {
+            let target = cache.get("api.example.com").await.unwrap();
+            format!("CNAME api.example.com ({target})")
+        })
+        .await;
+
+    // Invalidating an A record discards the aliases depending on it, directly or
+    // through other aliases.
+    cache.invalidate("www.example.com").await;
+    assert!(!cache.contains_key("api.example.com"));
+    assert!(!cache.contains_key("app.example.com"));
+
+    cache.invalidate("db.example.com").await;
+    assert!(!cache.contains_key("primary.example.com"));
+
+    cache.run_pending_tasks().await;
+    assert_eq!(cache.entry_count(), 0);
+    println!("Exit program.");
+}
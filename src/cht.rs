@@ -1,5 +1,11 @@
 //! Lock-free hash tables.
 //!
+//! This module provides [`HashMap`], a lock-free, unbounded concurrent hash map.
+//! It is the hash table used by the caches in this crate as their central
+//! key-value storage.
+//!
+//! # Algorithm
+//!
 //! The hash tables in this crate are, at their core, open addressing hash
 //! tables implemented using open addressing and boxed buckets. The core of
 //! these hash tables are bucket arrays, which consist of a vector of atomic
@@ -71,6 +77,7 @@
 //! [Junction]: https://github.com/preshing/junction
 //! [a tech talk]: https://youtu.be/HJ-719EGIts
 
+mod hash_map;
 pub(crate) mod iter;
 pub(crate) mod map;
 pub(crate) mod segment;
@@ -79,4 +86,8 @@ pub(crate) mod segment;
 #[macro_use]
 pub(crate) mod test_util;
 
-pub(crate) use segment::HashMap as SegmentedHashMap;
+pub use hash_map::{HashMap, Iter};
+
+pub(crate) use segment::{
+    HashMap as SegmentedHashMap, SHRINK_ON_MAINTENANCE_LOAD_FACTOR, SHRINK_TO_FIT_LOAD_FACTOR,
+};
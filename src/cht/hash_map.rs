@@ -0,0 +1,502 @@
+use super::{
+    iter::Iter as InnerIter,
+    segment::{self, HashMap as SegmentedHashMap},
+};
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+};
+
+/// A lock-free, unbounded concurrent hash map.
+///
+/// This is the hash table used by the caches as their central key-value storage.
+/// Unlike the caches, it does not evict or expire entries, and does not keep any
+/// access history; it is a plain map that can be shared across threads (e.g. in
+/// an `Arc`) and updated through a shared reference.
+///
+/// Entries are divided between a number of segments, each of them is an
+/// independent lock-free hash table with open addressing and linear probing. See
+/// the [module-level document](./index.html) for the details of the algorithm.
+///
+/// The methods returning a value, such as [`get`](#method.get) and
+/// [`remove`](#method.remove), return a _clone_ of the value, because other
+/// threads may replace or remove the value at any time. Wrap values in an `Arc`
+/// if they are expensive to clone.
+///
+/// By default, `HashMap` uses the same hashing algorithm as
+/// `std::collections::HashMap`, and creates twice as many segments as the
+/// available parallelism of the system. They can be changed by the
+/// [`with_num_segments_capacity_and_hasher`][with-everything] method and its
+/// variants.
+///
+/// [with-everything]: #method.with_num_segments_capacity_and_hasher
+///
+/// # Example
+///
+/// ```rust
+/// use moka2::cht::HashMap;
+/// use std::{sync::Arc, thread};
+///
+/// let map = Arc::new(HashMap::new());
+///
+/// let handles = (0..4u64)
+///     .map(|i| {
+///         let map = Arc::clone(&map);
+///         thread::spawn(move || {
+///             map.insert(i, i * 10);
+///             // Count the number of threads in the entry for key 100.
+///             map.insert_with_or_modify(100, || 1, |_k, v| v + 1);
+///         })
+///     })
+///     .collect::<Vec<_>>();
+/// handles.into_iter().for_each(|h| h.join().unwrap());
+///
+/// assert_eq!(map.get(&2), Some(20));
+/// assert_eq!(map.get(&100), Some(4));
+/// assert_eq!(map.len(), 5);
+/// ```
+pub struct HashMap<K, V, S = RandomState> {
+    inner: SegmentedHashMap<K, V, S>,
+}
+
+impl<K, V> HashMap<K, V, RandomState> {
+    /// Creates an empty `HashMap`.
+    ///
+    /// The hash map is created with a capacity of 0, so it will not allocate
+    /// bucket pointer arrays until it is first inserted into.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty `HashMap` with the specified capacity.
+    ///
+    /// The hash map will be able to hold at least `capacity` elements without
+    /// reallocating any bucket pointer arrays.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::default())
+    }
+
+    /// Creates an empty `HashMap` with the specified number of segments.
+    ///
+    /// The number of segments is rounded up to the next power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn with_num_segments(num_segments: usize) -> Self {
+        Self::with_num_segments_and_capacity(num_segments, 0)
+    }
+
+    /// Creates an empty `HashMap` with the specified number of segments and
+    /// capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn with_num_segments_and_capacity(num_segments: usize, capacity: usize) -> Self {
+        Self::with_num_segments_capacity_and_hasher(num_segments, capacity, RandomState::default())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Creates an empty `HashMap` that will use `build_hasher` to hash the keys.
+    pub fn with_hasher(build_hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, build_hasher)
+    }
+
+    /// Creates an empty `HashMap` with the specified capacity, using
+    /// `build_hasher` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, build_hasher: S) -> Self {
+        Self::with_num_segments_capacity_and_hasher(
+            segment::default_num_segments(),
+            capacity,
+            build_hasher,
+        )
+    }
+
+    /// Creates an empty `HashMap` with the specified number of segments, using
+    /// `build_hasher` to hash the keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn with_num_segments_and_hasher(num_segments: usize, build_hasher: S) -> Self {
+        Self::with_num_segments_capacity_and_hasher(num_segments, 0, build_hasher)
+    }
+
+    /// Creates an empty `HashMap` with the specified number of segments and
+    /// capacity, using `build_hasher` to hash the keys.
+    ///
+    /// The number of segments is rounded up to the next power of two. The
+    /// capacity is divided between the segments.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn with_num_segments_capacity_and_hasher(
+        num_segments: usize,
+        capacity: usize,
+        build_hasher: S,
+    ) -> Self {
+        Self {
+            inner: SegmentedHashMap::with_num_segments_capacity_and_hasher(
+                num_segments,
+                capacity,
+                build_hasher,
+            ),
+        }
+    }
+
+    /// Returns the number of elements in the map.
+    ///
+    /// The value returned is a snapshot; other threads can add or remove elements
+    /// at any time.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    ///
+    /// The value returned is a snapshot; other threads can add or remove elements
+    /// at any time.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of segments in the map. It is always a power of two.
+    pub fn num_segments(&self) -> usize {
+        self.inner.actual_num_segments()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns `true` if the map contains a value for the specified key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.contains_key(hash, |k| k.borrow() == key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.get(hash, |k| k.borrow() == key)
+    }
+
+    /// Returns the result of invoking `with_entry` with a reference to the
+    /// key-value pair corresponding to the key. Unlike [`get`](#method.get), this
+    /// does not clone the value.
+    pub fn get_key_value_and<Q, T>(
+        &self,
+        key: &Q,
+        with_entry: impl FnOnce(&K, &V) -> T,
+    ) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .get_key_value_and(hash, |k| k.borrow() == key, with_entry)
+    }
+
+    /// Inserts a key-value pair into the map. Returns `true` if the map did have
+    /// this key present; in that case, both the key and value are updated.
+    ///
+    /// The value is moved into the map without being cloned. Use
+    /// [`insert_entry_and`](#method.insert_entry_and) to read the value previously
+    /// corresponding to the key.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.insert_entry_and(key, value, |_k, _v| ()).is_some()
+    }
+
+    /// Inserts a key-value pair into the map, returning the result of invoking
+    /// `with_previous_entry` with a reference to the key-value pair previously
+    /// corresponding to the key.
+    ///
+    /// If the map did have this key present, both the key and value are updated.
+    pub fn insert_entry_and<T>(
+        &self,
+        key: K,
+        value: V,
+        with_previous_entry: impl FnOnce(&K, &V) -> T,
+    ) -> Option<T> {
+        let hash = self.inner.hash(&key);
+        self.inner
+            .insert_entry_and(key, hash, value, with_previous_entry)
+    }
+
+    /// Inserts a key-value pair into the map only if no value corresponds to the
+    /// key. Returns a _clone_ of the existing value, or `None` if the pair was
+    /// inserted.
+    pub fn insert_if_not_present(&self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let hash = self.inner.hash(&key);
+        self.inner.insert_if_not_present(key, hash, value)
+    }
+
+    /// If no value corresponds to the key, invokes `on_insert` to insert a new
+    /// key-value pair into the map. Otherwise, replaces the existing value with
+    /// the one returned by `on_modify`, and returns a _clone_ of the value
+    /// previously corresponding to the key.
+    ///
+    /// `on_insert` may be invoked even if `Some` is returned. `on_modify` will be
+    /// invoked at least once if `Some` is returned, and may be invoked more than
+    /// once when it conflicts with concurrent updates to the same key.
+    pub fn insert_with_or_modify(
+        &self,
+        key: K,
+        on_insert: impl FnOnce() -> V,
+        on_modify: impl FnMut(&K, &V) -> V,
+    ) -> Option<V>
+    where
+        V: Clone,
+    {
+        let hash = self.inner.hash(&key);
+        self.inner
+            .insert_with_or_modify(key, hash, on_insert, on_modify)
+    }
+
+    /// Removes the key from the map, returning a _clone_ of the value previously
+    /// corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.remove(hash, |k| k.borrow() == key)
+    }
+
+    /// Removes the key from the map only if `condition` returns `true` for the
+    /// key-value pair, returning a _clone_ of the value previously corresponding
+    /// to the key.
+    ///
+    /// `condition` will be invoked at least once if `Some` is returned, and may be
+    /// invoked more than once when it conflicts with concurrent updates to the
+    /// same key.
+    pub fn remove_if<Q>(&self, key: &Q, condition: impl FnMut(&K, &V) -> bool) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.remove_if(hash, |k| k.borrow() == key, condition)
+    }
+
+    /// Shrinks the bucket pointer arrays of the segments to fit the current number
+    /// of elements.
+    ///
+    /// The bucket pointer arrays grow while elements are inserted, but may keep
+    /// their grown size after most of the elements have been removed. Other threads
+    /// can keep reading and writing the map while it is being shrunk.
+    pub fn shrink_to_fit(&self) {
+        self.inner.shrink(super::SHRINK_TO_FIT_LOAD_FACTOR);
+    }
+
+    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator element type is `(K, V)`, with _clones_ of the keys and values.
+    ///
+    /// The iterator does not hold a snapshot of the map. It visits one segment at a
+    /// time, so entries inserted or removed by other threads during the iteration
+    /// may or may not be returned.
+    pub fn iter(&self) -> Iter<'_, K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        Iter(self.inner.iter())
+    }
+}
+
+impl<K, V> Default for HashMap<K, V, RandomState> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug + Eq + Hash + Clone + Send + Sync + 'static,
+    V: fmt::Debug + Clone + Send + Sync + 'static,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher,
+{
+    type Item = (K, V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator visiting all key-value pairs in a [`HashMap`] in arbitrary order.
+///
+/// Call [`HashMap::iter`](./struct.HashMap.html#method.iter) method to obtain an
+/// `Iter`.
+pub struct Iter<'i, K, V>(InnerIter<'i, K, V>);
+
+impl<K, V> Iterator for Iter<'_, K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashMap;
+
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    #[test]
+    fn basic_ops() {
+        let map = HashMap::with_num_segments(3);
+        assert_eq!(map.num_segments(), 4);
+        assert!(map.is_empty());
+
+        assert!(!map.insert("a".to_string(), 1));
+        assert!(!map.insert("b".to_string(), 2));
+        assert!(map.insert("a".to_string(), 0));
+        assert_eq!(
+            map.insert_entry_and("a".to_string(), 3, |_k, v| *v),
+            Some(0)
+        );
+        assert_eq!(map.insert_if_not_present("b".to_string(), 4), Some(2));
+        assert_eq!(map.len(), 2);
+
+        assert!(map.contains_key("a"));
+        assert_eq!(map.get("a"), Some(3));
+        assert_eq!(
+            map.get_key_value_and("b", |k, v| format!("{k}{v}")),
+            Some("b2".into())
+        );
+        assert_eq!(map.get("c"), None);
+
+        assert_eq!(map.remove_if("a", |_k, v| *v == 1), None);
+        assert_eq!(map.remove_if("a", |_k, v| *v == 3), Some(3));
+        assert_eq!(map.remove("b"), Some(2));
+        assert_eq!(map.remove("b"), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn insert_moves_value() {
+        // A value type that cannot be cloned.
+        #[derive(Debug, PartialEq)]
+        struct Value(u32);
+
+        let map = HashMap::new();
+        assert!(!map.insert(0, Value(1)));
+        assert!(map.insert(0, Value(2)));
+        assert_eq!(map.get_key_value_and(&0, |_k, v| v.0), Some(2));
+        assert_eq!(map.insert_entry_and(0, Value(3), |_k, v| v.0), Some(2));
+        assert_eq!(map.get_key_value_and(&0, |_k, v| v.0), Some(3));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn insert_with_or_modify_concurrent() {
+        const NUM_THREADS: usize = 8;
+        const NUM_KEYS: u64 = 128;
+
+        let map = Arc::new(HashMap::with_capacity(16));
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let map = Arc::clone(&map);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    for key in 0..NUM_KEYS {
+                        map.insert_with_or_modify(key, || 1, |_k, v| v + 1);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        assert_eq!(map.len(), NUM_KEYS as usize);
+        for key in 0..NUM_KEYS {
+            assert_eq!(map.get(&key), Some(NUM_THREADS));
+        }
+    }
+
+    #[test]
+    fn iter_and_custom_hasher() {
+        const NUM_KEYS: u32 = 200;
+
+        let map = HashMap::with_num_segments_capacity_and_hasher(
+            8,
+            32,
+            std::collections::hash_map::RandomState::new(),
+        );
+        for key in 0..NUM_KEYS {
+            map.insert(key, key * 2);
+        }
+
+        let entries = map.iter().collect::<BTreeMap<_, _>>();
+        assert_eq!(entries.len(), NUM_KEYS as usize);
+        assert!(entries.iter().all(|(k, v)| *v == k * 2));
+        assert_eq!((&map).into_iter().count(), NUM_KEYS as usize);
+
+        for key in 1..NUM_KEYS {
+            map.remove(&key);
+        }
+        map.shrink_to_fit();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(0, 0)]);
+
+        let map = HashMap::new();
+        map.insert('a', "alice");
+        assert_eq!(format!("{map:?}"), r#"{'a': "alice"}"#);
+    }
+}
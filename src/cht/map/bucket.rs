@@ -15,6 +15,10 @@ use crossbeam_epoch::{Atomic, CompareExchangeError, Guard, Owned, Shared};
 
 pub(crate) const BUCKET_ARRAY_DEFAULT_LENGTH: usize = 128;
 
+/// The maximum load factor for `RehashOp::for_shrink`. Halving a bucket array
+/// below this load factor leaves it at most half full.
+pub(crate) const MAX_SHRINK_LOAD_FACTOR: f64 = 0.25;
+
 pub(crate) struct BucketArray<K, V> {
     pub(crate) buckets: Box<[Atomic<Bucket<K, V>>]>,
     pub(crate) next: Atomic<BucketArray<K, V>>,
@@ -63,6 +67,12 @@ impl<K, V> BucketArray<K, V> {
 
         self.buckets.len() / 2
     }
+
+    /// Returns the number of bytes allocated for this bucket array, excluding the
+    /// buckets pointed by it.
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        mem::size_of::<Self>() + self.buckets.len() * mem::size_of::<Atomic<Bucket<K, V>>>()
+    }
 }
 
 #[cfg(feature = "unstable-debug-counters")]
@@ -170,6 +180,71 @@ impl<'g, K: 'g + Eq, V: 'g> BucketArray<K, V> {
         Ok(Shared::null())
     }
 
+    pub(crate) fn modify_if<F>(
+        &self,
+        guard: &'g Guard,
+        hash: u64,
+        mut eq: impl FnMut(&K) -> bool,
+        mut modifier: F,
+    ) -> Result<Shared<'g, Bucket<K, V>>, F>
+    where
+        K: Clone,
+        F: FnMut(&K, &V) -> Option<V>,
+    {
+        let mut probe = self.probe(guard, hash);
+        while let Some(bucket) = probe.next() {
+            let Ok((_, this_bucket, this_bucket_ptr)) = bucket else {
+                return Err(modifier);
+            };
+
+            let Some(this_bucket_ref) = (unsafe { this_bucket_ptr.as_ref() }) else {
+                // Nothing to modify.
+                return Ok(Shared::null());
+            };
+
+            let this_key = &this_bucket_ref.key;
+
+            if !eq(this_key) {
+                // Different key. Try next bucket.
+                continue;
+            }
+
+            if is_tombstone(this_bucket_ptr) {
+                // Already removed.
+                return Ok(Shared::null());
+            }
+
+            let this_value = unsafe { &*this_bucket_ref.maybe_value.as_ptr() };
+
+            let Some(new_value) = modifier(this_key, this_value) else {
+                // Found but the modifier declined. Do not modify.
+                return Ok(Shared::null());
+            };
+
+            // Found and the modifier returned a new value. Replace it.
+
+            let new_bucket = Owned::new(Bucket::new(this_key.clone(), new_value));
+
+            match this_bucket.compare_exchange_weak(
+                this_bucket_ptr,
+                new_bucket,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+                guard,
+            ) {
+                // Succeeded. Return the previous value.
+                Ok(_) => return Ok(this_bucket_ptr),
+                // Failed. Drop the new value and reload to retry.
+                Err(CompareExchangeError { mut new, .. }) => {
+                    unsafe { ptr::drop_in_place(new.maybe_value.as_mut_ptr()) };
+                    probe.reload();
+                }
+            }
+        }
+
+        Ok(Shared::null())
+    }
+
     pub(crate) fn insert_if_not_present<F>(
         &self,
         guard: &'g Guard,
@@ -218,6 +293,53 @@ impl<'g, K: 'g + Eq, V: 'g> BucketArray<K, V> {
         Err(state)
     }
 
+    /// Inserts the key-value pair of `state`, replacing the existing bucket for the
+    /// key if any. Unlike `insert_or_modify`, the value is moved into the bucket
+    /// in either case.
+    pub(crate) fn insert_or_replace<F>(
+        &self,
+        guard: &'g Guard,
+        hash: u64,
+        mut state: InsertOrModifyState<K, V, F>,
+    ) -> Result<Shared<'g, Bucket<K, V>>, InsertOrModifyState<K, V, F>>
+    where
+        F: FnOnce() -> V,
+    {
+        let mut probe = self.probe(guard, hash);
+        while let Some(bucket) = probe.next() {
+            let Ok((_, this_bucket, this_bucket_ptr)) = bucket else {
+                return Err(state);
+            };
+
+            if let Some(this_bucket_ref) = unsafe { this_bucket_ptr.as_ref() } {
+                if &this_bucket_ref.key != state.key() {
+                    // Different key. Try next bucket.
+                    continue;
+                }
+            }
+
+            // Not found, or found a tombstone or a bucket for this key. Replace it.
+            let new_bucket = state.into_insert_bucket();
+
+            if let Err(CompareExchangeError { new, .. }) = this_bucket.compare_exchange_weak(
+                this_bucket_ptr,
+                new_bucket,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+                guard,
+            ) {
+                // Failed. Reload to retry.
+                state = InsertOrModifyState::from_bucket_value(new, None);
+                probe.reload();
+            } else {
+                // Succeeded. Return the previous value. (can be null)
+                return Ok(this_bucket_ptr);
+            }
+        }
+
+        Err(state)
+    }
+
     // https://rust-lang.github.io/rust-clippy/master/index.html#type_complexity
     #[allow(clippy::type_complexity)]
     pub(crate) fn insert_or_modify<F, G>(
@@ -715,6 +837,26 @@ pub(crate) unsafe fn defer_acquire_destroy<'g, T>(guard: &'g Guard, ptr: Shared<
     });
 }
 
+/// Defers the destruction of a bucket array that has been replaced by a newer one.
+/// Its size is added to `retired_bytes` until crossbeam-epoch reclaims it.
+pub(crate) unsafe fn defer_destroy_bucket_array<'g, K, V>(
+    guard: &'g Guard,
+    ptr: Shared<'g, BucketArray<K, V>>,
+    retired_bytes: &Arc<AtomicUsize>,
+) {
+    assert!(!ptr.is_null());
+
+    let size = ptr.deref().allocated_bytes();
+    retired_bytes.fetch_add(size, Ordering::Relaxed);
+    let retired_bytes = Arc::clone(retired_bytes);
+
+    guard.defer_unchecked(move || {
+        atomic::fence(Ordering::Acquire);
+        mem::drop(ptr.into_owned());
+        retired_bytes.fetch_sub(size, Ordering::Relaxed);
+    });
+}
+
 #[derive(Clone, Copy)]
 pub(crate) enum RehashOp {
     Expand,
@@ -745,6 +887,25 @@ impl RehashOp {
         Self::Skip
     }
 
+    /// Returns `Shrink` if the load factor of the bucket array, the number of live
+    /// entries divided by its length, is below `min_load_factor` and the bucket
+    /// array is not at the default length yet. Otherwise returns `Skip`.
+    ///
+    /// `min_load_factor` must not exceed `MAX_SHRINK_LOAD_FACTOR`, so that a
+    /// shrunk bucket array will not need to be expanded right away.
+    pub(crate) fn for_shrink(buckets_len: usize, len: &AtomicUsize, min_load_factor: f64) -> Self {
+        debug_assert!(min_load_factor <= MAX_SHRINK_LOAD_FACTOR);
+        let len = len.load(Ordering::Relaxed) as f64;
+
+        if buckets_len / 2 >= BUCKET_ARRAY_DEFAULT_LENGTH
+            && len < buckets_len as f64 * min_load_factor
+        {
+            Self::Shrink
+        } else {
+            Self::Skip
+        }
+    }
+
     pub(crate) fn is_skip(self) -> bool {
         matches!(self, Self::Skip)
     }
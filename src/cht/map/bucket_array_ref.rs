@@ -2,7 +2,10 @@ use super::bucket::{self, Bucket, BucketArray, InsertOrModifyState, RehashOp};
 
 use std::{
     hash::{BuildHasher, Hash},
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use crossbeam_epoch::{Atomic, CompareExchangeError, Guard, Owned, Shared};
@@ -11,6 +14,7 @@ pub(crate) struct BucketArrayRef<'a, K, V, S> {
     pub(crate) bucket_array: &'a Atomic<BucketArray<K, V>>,
     pub(crate) build_hasher: &'a S,
     pub(crate) len: &'a AtomicUsize,
+    pub(crate) retired_bytes: &'a Arc<AtomicUsize>,
 }
 
 impl<K, V, S> BucketArrayRef<'_, K, V, S>
@@ -125,6 +129,69 @@ where
         result
     }
 
+    pub(crate) fn modify_entry_if_and<T>(
+        &self,
+        hash: u64,
+        mut eq: impl FnMut(&K) -> bool,
+        mut modifier: impl FnMut(&K, &V) -> Option<V>,
+        with_old_entry: impl FnOnce(&K, &V) -> T,
+    ) -> Option<T>
+    where
+        K: Clone,
+    {
+        let guard = &crossbeam_epoch::pin();
+        let current_ref = self.get(guard);
+        let mut bucket_array_ref = current_ref;
+
+        let result;
+
+        loop {
+            loop {
+                let rehash_op = RehashOp::new(
+                    bucket_array_ref.capacity(),
+                    &bucket_array_ref.tombstone_count,
+                    self.len,
+                );
+                if rehash_op.is_skip() {
+                    break;
+                }
+                if let Some(r) = bucket_array_ref.rehash(guard, self.build_hasher, rehash_op) {
+                    bucket_array_ref = r;
+                }
+            }
+
+            match bucket_array_ref.modify_if(guard, hash, &mut eq, modifier) {
+                Ok(previous_bucket_ptr) => {
+                    if let Some(previous_bucket_ref) = unsafe { previous_bucket_ptr.as_ref() } {
+                        let Bucket {
+                            key,
+                            maybe_value: value,
+                        } = previous_bucket_ref;
+                        result = Some(with_old_entry(key, unsafe { &*value.as_ptr() }));
+
+                        unsafe { bucket::defer_destroy_bucket(guard, previous_bucket_ptr) };
+                    } else {
+                        result = None;
+                    }
+
+                    break;
+                }
+                Err(m) => {
+                    modifier = m;
+                    if let Some(r) =
+                        bucket_array_ref.rehash(guard, self.build_hasher, RehashOp::Expand)
+                    {
+                        bucket_array_ref = r;
+                    }
+                }
+            }
+        }
+
+        self.swing(guard, current_ref, bucket_array_ref);
+
+        result
+    }
+
     pub(crate) fn insert_if_not_present_and<T>(
         &self,
         key: K,
@@ -195,6 +262,73 @@ where
         result
     }
 
+    pub(crate) fn insert_entry_and<T>(
+        &self,
+        key: K,
+        hash: u64,
+        value: V,
+        with_previous_entry: impl FnOnce(&K, &V) -> T,
+    ) -> Option<T> {
+        let guard = &crossbeam_epoch::pin();
+        let current_ref = self.get(guard);
+        let mut bucket_array_ref = current_ref;
+        let mut state = InsertOrModifyState::New(key, || value);
+
+        let result;
+
+        loop {
+            loop {
+                let rehash_op = RehashOp::new(
+                    bucket_array_ref.capacity(),
+                    &bucket_array_ref.tombstone_count,
+                    self.len,
+                );
+                if rehash_op.is_skip() {
+                    break;
+                }
+                if let Some(r) = bucket_array_ref.rehash(guard, self.build_hasher, rehash_op) {
+                    bucket_array_ref = r;
+                }
+            }
+
+            match bucket_array_ref.insert_or_replace(guard, hash, state) {
+                Ok(previous_bucket_ptr) => {
+                    if let Some(previous_bucket_ref) = unsafe { previous_bucket_ptr.as_ref() } {
+                        if bucket::is_tombstone(previous_bucket_ptr) {
+                            self.len.fetch_add(1, Ordering::Relaxed);
+                            result = None;
+                        } else {
+                            let Bucket {
+                                key,
+                                maybe_value: value,
+                            } = previous_bucket_ref;
+                            result = Some(with_previous_entry(key, unsafe { &*value.as_ptr() }));
+                        }
+
+                        unsafe { bucket::defer_destroy_bucket(guard, previous_bucket_ptr) };
+                    } else {
+                        self.len.fetch_add(1, Ordering::Relaxed);
+                        result = None;
+                    }
+
+                    break;
+                }
+                Err(s) => {
+                    state = s;
+                    if let Some(r) =
+                        bucket_array_ref.rehash(guard, self.build_hasher, RehashOp::Expand)
+                    {
+                        bucket_array_ref = r;
+                    }
+                }
+            }
+        }
+
+        self.swing(guard, current_ref, bucket_array_ref);
+
+        result
+    }
+
     pub(crate) fn insert_with_or_modify_entry_and<T>(
         &self,
         key: K,
@@ -291,6 +425,35 @@ where
 
         result
     }
+    /// Rehashes the bucket array into smaller ones while its load factor is below
+    /// `min_load_factor`. Returns `true` if the bucket array has been shrunk.
+    ///
+    /// Concurrent operations are not blocked; they help relocating the buckets or
+    /// wait for it in the same way as they do on expanding.
+    pub(crate) fn shrink(&self, min_load_factor: f64) -> bool {
+        let guard = &crossbeam_epoch::pin();
+        let current_ptr = self.bucket_array.load_consume(guard);
+        let Some(current_ref) = (unsafe { current_ptr.as_ref() }) else {
+            // No bucket array has been allocated yet.
+            return false;
+        };
+        let mut bucket_array_ref = current_ref;
+
+        loop {
+            let rehash_op =
+                RehashOp::for_shrink(bucket_array_ref.buckets.len(), self.len, min_load_factor);
+            if rehash_op.is_skip() {
+                break;
+            }
+            if let Some(r) = bucket_array_ref.rehash(guard, self.build_hasher, rehash_op) {
+                bucket_array_ref = r;
+            }
+        }
+
+        self.swing(guard, current_ref, bucket_array_ref);
+
+        bucket_array_ref.buckets.len() < current_ref.buckets.len()
+    }
 }
 
 impl<'g, K, V, S> BucketArrayRef<'_, K, V, S> {
@@ -343,7 +506,9 @@ impl<'g, K, V, S> BucketArrayRef<'_, K, V, S> {
                 Ordering::Relaxed,
                 guard,
             ) {
-                Ok(_) => unsafe { bucket::defer_acquire_destroy(guard, current_ptr) },
+                Ok(_) => unsafe {
+                    bucket::defer_destroy_bucket_array(guard, current_ptr, self.retired_bytes)
+                },
                 Err(_) => {
                     let new_ptr = self.bucket_array.load_consume(guard);
                     assert!(!new_ptr.is_null());
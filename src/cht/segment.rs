@@ -40,8 +40,11 @@ use super::iter::{Iter, ScanningGet};
 use std::{
     borrow::Borrow,
     hash::{BuildHasher, Hash},
-    ptr,
-    sync::atomic::{self, AtomicUsize, Ordering},
+    mem, ptr,
+    sync::{
+        atomic::{self, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use crossbeam_epoch::Atomic;
@@ -109,6 +112,7 @@ pub(crate) struct HashMap<K, V, S = DefaultHashBuilder> {
     build_hasher: S,
     len: AtomicUsize,
     segment_shift: u32,
+    retired_bytes: Arc<AtomicUsize>,
 }
 
 #[cfg(test)]
@@ -192,6 +196,7 @@ impl<K, V, S> HashMap<K, V, S> {
             build_hasher,
             len: AtomicUsize::new(0),
             segment_shift,
+            retired_bytes: Arc::default(),
         }
     }
 
@@ -241,6 +246,42 @@ impl<K, V, S> HashMap<K, V, S> {
             .sum::<usize>()
     }
 
+    /// Returns the approximate memory usage of the map, including the bucket arrays
+    /// being rehashed and the buckets, but excluding the heap memory owned by the
+    /// keys and values.
+    ///
+    /// # Safety
+    ///
+    /// This method on its own is safe, but other threads can add or remove
+    /// elements and rehash the segments at any time.
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let guard = &crossbeam_epoch::pin();
+        let mut bucket_arrays = mem::size_of_val(&*self.segments);
+        let mut buckets = 0;
+        let mut len = 0;
+
+        for segment in self.segments.iter() {
+            let mut current_ptr = segment.bucket_array.load_consume(guard);
+            if let Some(current_ref) = unsafe { current_ptr.as_ref() } {
+                let seg_len = segment.len.load(Ordering::Relaxed);
+                let tombstones = current_ref.tombstone_count.load(Ordering::Relaxed);
+                buckets += (seg_len + tombstones) * mem::size_of::<bucket::Bucket<K, V>>();
+                len += seg_len;
+            }
+            while let Some(current_ref) = unsafe { current_ptr.as_ref() } {
+                bucket_arrays += current_ref.allocated_bytes();
+                current_ptr = current_ref.next.load_consume(guard);
+            }
+        }
+
+        MemoryUsage {
+            len,
+            bucket_arrays,
+            buckets,
+            retired_bucket_arrays: self.retired_bytes.load(Ordering::Relaxed),
+        }
+    }
+
     #[cfg(test)]
     /// Returns the number of segments in the map.
     pub(crate) fn num_segments(&self) -> usize {
@@ -302,20 +343,10 @@ impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
         hash: u64,
         value: V,
         with_previous_entry: impl FnOnce(&K, &V) -> T,
-    ) -> Option<T>
-    where
-        V: Clone,
-    {
-        let result = self
-            .bucket_array_ref(hash)
-            // .insert_entry_and(key, hash, value, with_previous_entry);
-            .insert_with_or_modify_entry_and(
-                key,
-                hash,
-                || value,
-                |_k, v| v.clone(),
-                with_previous_entry,
-            );
+    ) -> Option<T> {
+        let result =
+            self.bucket_array_ref(hash)
+                .insert_entry_and(key, hash, value, with_previous_entry);
 
         if result.is_none() {
             self.len.fetch_add(1, Ordering::Relaxed);
@@ -390,6 +421,31 @@ impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
             })
     }
 
+    /// If a value corresponds to the key and `modifier` returns [`Some`] for it,
+    /// replace the value with the returned one and return the result of
+    /// invoking a function with a reference to the key-value pair previously
+    /// corresponding to the supplied key.
+    ///
+    /// `modifier` will be invoked at least once if [`Some`] is returned. It
+    /// may also be invoked one or more times if [`None`] is returned.
+    ///
+    /// [`Some`]: https://doc.rust-lang.org/std/option/enum.Option.html#variant.Some
+    /// [`None`]: https://doc.rust-lang.org/std/option/enum.Option.html#variant.None
+    #[inline]
+    pub(crate) fn modify_entry_if_and<T>(
+        &self,
+        hash: u64,
+        eq: impl FnMut(&K) -> bool,
+        modifier: impl FnMut(&K, &V) -> Option<V>,
+        with_old_entry: impl FnOnce(&K, &V) -> T,
+    ) -> Option<T>
+    where
+        K: Clone,
+    {
+        self.bucket_array_ref(hash)
+            .modify_entry_if_and(hash, eq, modifier, with_old_entry)
+    }
+
     /// If no value corresponds to the key, invoke a default function to insert
     /// a new key-value pair into the map. Otherwise, modify the existing value
     /// and return a clone of the value previously corresponding to the key.
@@ -484,11 +540,32 @@ impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
             bucket_array,
             build_hasher: &self.build_hasher,
             len,
+            retired_bytes: &self.retired_bytes,
         };
 
         Some(bucket_array_ref.keys(with_key))
     }
 
+    /// Shrinks the bucket arrays of the segments whose load factor, the number of
+    /// elements divided by the length of the bucket array, is below
+    /// `min_load_factor`. Returns the number of segments shrunk.
+    ///
+    /// `min_load_factor` must not exceed [`SHRINK_TO_FIT_LOAD_FACTOR`].
+    pub(crate) fn shrink(&self, min_load_factor: f64) -> usize {
+        self.segments
+            .iter()
+            .filter(|segment| {
+                BucketArrayRef {
+                    bucket_array: &segment.bucket_array,
+                    build_hasher: &self.build_hasher,
+                    len: &segment.len,
+                    retired_bytes: &self.retired_bytes,
+                }
+                .shrink(min_load_factor)
+            })
+            .count()
+    }
+
     pub(crate) fn iter(&self) -> Iter<'_, K, V>
     where
         K: Clone,
@@ -585,6 +662,7 @@ impl<K, V, S> HashMap<K, V, S> {
             bucket_array,
             build_hasher: &self.build_hasher,
             len,
+            retired_bytes: &self.retired_bytes,
         }
     }
 
@@ -598,13 +676,35 @@ impl<K, V, S> HashMap<K, V, S> {
     }
 }
 
+/// The approximate memory usage of a `HashMap` in bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MemoryUsage {
+    /// The number of elements in the map.
+    pub(crate) len: usize,
+    /// The bucket pointer arrays, including the ones being rehashed.
+    pub(crate) bucket_arrays: usize,
+    /// The buckets holding the keys and values, including tombstones.
+    pub(crate) buckets: usize,
+    /// The bucket pointer arrays replaced by rehashing and awaiting reclamation by
+    /// crossbeam-epoch.
+    pub(crate) retired_bucket_arrays: usize,
+}
+
 struct Segment<K, V> {
     bucket_array: Atomic<BucketArray<K, V>>,
     len: AtomicUsize,
 }
 
-#[cfg(test)]
-fn default_num_segments() -> usize {
+/// The load factor below which `HashMap::shrink` shrinks a bucket array when the
+/// user asks to shrink the map to fit. A shrunk bucket array is at most half full.
+pub(crate) const SHRINK_TO_FIT_LOAD_FACTOR: f64 = bucket::MAX_SHRINK_LOAD_FACTOR;
+
+/// The load factor below which the cache maintenance shrinks a bucket array. It is
+/// lower than `SHRINK_TO_FIT_LOAD_FACTOR`, so that a cache whose size fluctuates
+/// will not rehash its bucket arrays back and forth.
+pub(crate) const SHRINK_ON_MAINTENANCE_LOAD_FACTOR: f64 = 1.0 / 16.0;
+
+pub(crate) fn default_num_segments() -> usize {
     crate::common::available_parallelism() * 2
 }
 
@@ -643,6 +743,37 @@ mod tests {
         run_deferred();
     }
 
+    #[test]
+    fn memory_usage() {
+        let map =
+            HashMap::with_num_segments_capacity_and_hasher(1, 0, DefaultHashBuilder::default());
+
+        let usage = map.memory_usage();
+        assert_eq!(usage.len, 0);
+        assert_eq!(usage.buckets, 0);
+        assert_eq!(usage.retired_bucket_arrays, 0);
+
+        for i in 0..1024 {
+            let hash = map.hash(&i);
+            assert_eq!(map.insert_entry_and(i, hash, i, |_, v| *v), None);
+        }
+
+        // The bucket arrays have been expanded several times, and the smaller
+        // ones are awaiting reclamation.
+        let usage = map.memory_usage();
+        let bucket_ptr_size = mem::size_of::<Atomic<bucket::Bucket<i32, i32>>>();
+        assert_eq!(usage.len, 1024);
+        assert_eq!(
+            usage.buckets,
+            1024 * mem::size_of::<bucket::Bucket<i32, i32>>()
+        );
+        assert!(usage.bucket_arrays > 2048 * bucket_ptr_size);
+        assert!(usage.retired_bucket_arrays > 0);
+
+        run_deferred();
+        assert_eq!(map.memory_usage().retired_bucket_arrays, 0);
+    }
+
     #[test]
     fn insert_if_not_present() {
         let map =
@@ -1752,6 +1883,45 @@ mod tests {
         run_deferred();
     }
 
+    #[test]
+    fn modify_entry_if_and() {
+        const NUM_VALUES: i32 = 512;
+
+        let add_if_even = |_: &i32, v: &i32| (*v % 2 == 0).then(|| *v + NUM_VALUES);
+
+        let map = HashMap::with_capacity(0);
+
+        for i in 0..NUM_VALUES {
+            assert_eq!(map.insert_entry_and(i, map.hash(&i), i, |_, v| *v), None);
+        }
+
+        for i in 0..NUM_VALUES {
+            let result = map.modify_entry_if_and(map.hash(&i), |&k| k == i, add_if_even, |_, v| *v);
+            if i % 2 == 0 {
+                assert_eq!(result, Some(i));
+            } else {
+                assert_eq!(result, None);
+            }
+        }
+
+        // Absent keys are not inserted.
+        let absent = NUM_VALUES;
+        let hash = map.hash(&absent);
+        assert_eq!(
+            map.modify_entry_if_and(hash, |&k| k == absent, add_if_even, |_, v| *v),
+            None
+        );
+        assert_eq!(map.get(hash, |&k| k == absent), None);
+        assert_eq!(map.len(), NUM_VALUES as usize);
+
+        for i in 0..NUM_VALUES {
+            let expected = if i % 2 == 0 { i + NUM_VALUES } else { i };
+            assert_eq!(map.get(map.hash(&i), |&k| k == i), Some(expected));
+        }
+
+        run_deferred();
+    }
+
     #[test]
     fn keys_in_single_segment() {
         let map =
@@ -1795,4 +1965,72 @@ mod tests {
 
         run_deferred();
     }
+
+    #[test]
+    fn shrink() {
+        const NUM_VALUES: usize = 4096;
+        const NUM_RETAINED: usize = 16;
+        const NUM_READERS: usize = 4;
+
+        let map = Arc::new(HashMap::with_num_segments_capacity_and_hasher(
+            1,
+            NUM_VALUES,
+            DefaultHashBuilder::default(),
+        ));
+        assert_eq!(map.capacity(), NUM_VALUES);
+        // The bucket arrays of an empty map are not shrunk below the default length.
+        assert_eq!(map.shrink(SHRINK_TO_FIT_LOAD_FACTOR), 1);
+        assert_eq!(map.capacity(), 64);
+        assert_eq!(map.shrink(SHRINK_TO_FIT_LOAD_FACTOR), 0);
+
+        for i in 0..NUM_VALUES {
+            assert_eq!(map.insert_entry_and(i, map.hash(&i), i, |_, v| *v), None);
+        }
+        let grown_capacity = map.capacity();
+        assert!(grown_capacity >= NUM_VALUES);
+        // Nothing to shrink while the map is full.
+        assert_eq!(map.shrink(SHRINK_ON_MAINTENANCE_LOAD_FACTOR), 0);
+
+        for i in NUM_RETAINED..NUM_VALUES {
+            map.remove(map.hash(&i), |&k| k == i);
+        }
+        assert_eq!(map.len(), NUM_RETAINED);
+
+        // Shrink while other threads are reading the retained entries.
+        let barrier = Arc::new(Barrier::new(NUM_READERS + 1));
+        let readers = (0..NUM_READERS)
+            .map(|_| {
+                let map = Arc::clone(&map);
+                let barrier = Arc::clone(&barrier);
+                spawn(move || {
+                    barrier.wait();
+                    for _ in 0..100 {
+                        for i in 0..NUM_RETAINED {
+                            assert_eq!(map.get(map.hash(&i), |&k| k == i), Some(i));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        barrier.wait();
+        map.shrink(SHRINK_TO_FIT_LOAD_FACTOR);
+        readers.into_iter().for_each(|r| r.join().unwrap());
+
+        assert_eq!(map.capacity(), 64);
+        assert_eq!(map.len(), NUM_RETAINED);
+        for i in 0..NUM_VALUES {
+            let expected = if i < NUM_RETAINED { Some(i) } else { None };
+            assert_eq!(map.get(map.hash(&i), |&k| k == i), expected);
+        }
+
+        // The shrunk map grows again.
+        for i in NUM_RETAINED..NUM_VALUES {
+            assert_eq!(map.insert_entry_and(i, map.hash(&i), i, |_, v| *v), None);
+        }
+        assert_eq!(map.len(), NUM_VALUES);
+        assert!(map.capacity() >= NUM_VALUES);
+
+        run_deferred();
+    }
 }
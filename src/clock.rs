@@ -0,0 +1,130 @@
+//! Clocks used by caches to get the current time.
+//!
+//! By default, a cache reads the system's monotonic clock. You can replace it with
+//! your own [`CacheClock`] by the `clock` method of a cache builder, for example to
+//! advance the time instantly in tests or simulations. Enable the `testing` crate
+//! feature to use [`MockClock`], a manually controlled clock.
+
+use std::time::{Instant, SystemTime};
+
+#[cfg(feature = "testing")]
+use parking_lot::RwLock;
+#[cfg(feature = "testing")]
+use std::{sync::Arc, time::Duration};
+
+/// A source of the current time for a cache.
+///
+/// The cache uses the clock to decide when the entries expire (`time_to_live`,
+/// `time_to_idle` and the per-entry expiration), and the `Instant`s it gives to
+/// the methods of [`Expiry`](../policy/trait.Expiry.html) and takes as deadlines
+/// are in the time of the clock.
+///
+/// The clock should be monotonic. If `now` returns a time earlier than the time
+/// when the cache was built, the cache uses the latter.
+pub trait CacheClock: Send + Sync + 'static {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Returns the current wall-clock time.
+    ///
+    /// The cache uses it to convert the absolute deadlines given to
+    /// `insert_expiring_at` into the time of this clock. The default
+    /// implementation returns `SystemTime::now()`.
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones of a `MockClock` share the same time, so you can keep a clone to
+/// advance the time after giving the clock to a cache builder.
+///
+/// The clock also keeps a wall-clock time (`SystemTime`), which moves together
+/// with the monotonic time, or alone by [`set_system_time`](#method.set_system_time)
+/// to simulate a jump of the system clock.
+///
+/// To use this clock, enable a crate feature called "testing".
+///
+/// # Example
+///
+/// ```rust
+/// use moka2::{clock::MockClock, sync::Cache};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let cache = Cache::builder()
+///     .time_to_live(Duration::from_secs(30))
+///     .clock(clock.clone())
+///     .build();
+///
+/// cache.insert("key", "value");
+///
+/// clock.increment(Duration::from_secs(29));
+/// assert_eq!(cache.get(&"key"), Some("value"));
+///
+/// clock.increment(Duration::from_secs(1));
+/// assert_eq!(cache.get(&"key"), None);
+/// ```
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<RwLock<(Instant, SystemTime)>>,
+}
+
+#[cfg(feature = "testing")]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "testing")]
+impl MockClock {
+    /// Creates a new `MockClock` starting at the current time of the system's
+    /// monotonic clock and wall clock.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(RwLock::new((Instant::now(), SystemTime::now()))),
+        }
+    }
+
+    /// Advances the time by `amount`. The wall-clock time advances by the same
+    /// amount.
+    pub fn increment(&self, amount: Duration) {
+        let mut now = self.now.write();
+        now.0 += amount;
+        now.1 += amount;
+    }
+
+    /// Sets the time to `now`. The wall-clock time moves by the same amount.
+    ///
+    /// Setting the time backward is allowed, but the caches using this clock do not
+    /// go back before the time when they were built.
+    pub fn set(&self, now: Instant) {
+        let mut current = self.now.write();
+        current.1 = if now >= current.0 {
+            current.1 + (now - current.0)
+        } else {
+            current.1 - (current.0 - now)
+        };
+        current.0 = now;
+    }
+
+    /// Sets the wall-clock time to `now` without moving the monotonic time.
+    pub fn set_system_time(&self, now: SystemTime) {
+        self.now.write().1 = now;
+    }
+}
+
+#[cfg(feature = "testing")]
+impl CacheClock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.read().0
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.now.read().1
+    }
+}
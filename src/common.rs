@@ -1,20 +1,29 @@
 use std::time::Duration;
 
 pub(crate) mod builder_utils;
+pub(crate) mod cache_metrics;
+pub(crate) mod callback_panic;
 pub(crate) mod concurrent;
+pub(crate) mod dependency;
 pub(crate) mod deque;
 pub(crate) mod entry;
 pub(crate) mod error;
 pub(crate) mod frequency_sketch;
+pub(crate) mod maintenance_stats;
+pub(crate) mod memory_report;
+pub(crate) mod predicate;
+pub(crate) mod tag;
 pub(crate) mod time;
 pub(crate) mod timer_wheel;
+pub(crate) mod trace;
+pub(crate) mod wall_clock;
 
 #[cfg(test)]
 pub(crate) mod test_utils;
 
 use self::concurrent::constants::{
     DEFAULT_EVICTION_BATCH_SIZE, DEFAULT_MAINTENANCE_TASK_TIMEOUT_MILLIS,
-    DEFAULT_MAX_LOG_SYNC_REPEATS,
+    DEFAULT_MAX_LOG_SYNC_REPEATS, READ_LOG_CH_SIZE, WRITE_LOG_CH_SIZE,
 };
 
 // Note: `CacheRegion` cannot have more than four enum variants. This is because
@@ -49,6 +58,15 @@ impl CacheRegion {
             Self::Other => "other",
         }
     }
+
+    pub(crate) fn entry_region(self) -> Option<entry::EntryRegion> {
+        match self {
+            Self::Window => Some(entry::EntryRegion::Window),
+            Self::MainProbation => Some(entry::EntryRegion::MainProbation),
+            Self::MainProtected => Some(entry::EntryRegion::MainProtected),
+            Self::Other => None,
+        }
+    }
 }
 
 impl PartialEq<Self> for CacheRegion {
@@ -80,6 +98,10 @@ pub(crate) struct HousekeeperConfig {
     /// The batch size of entries to be processed by each internal eviction method.
     /// Default: `EVICTION_BATCH_SIZE`.
     pub(crate) eviction_batch_size: u32,
+    /// The capacity of the read log channel. Default: `READ_LOG_CH_SIZE`.
+    pub(crate) read_log_capacity: usize,
+    /// The capacity of the write log channel. Default: `WRITE_LOG_CH_SIZE`.
+    pub(crate) write_log_capacity: usize,
 }
 
 impl Default for HousekeeperConfig {
@@ -90,6 +112,8 @@ impl Default for HousekeeperConfig {
             ),
             max_log_sync_repeats: DEFAULT_MAX_LOG_SYNC_REPEATS as u32,
             eviction_batch_size: DEFAULT_EVICTION_BATCH_SIZE,
+            read_log_capacity: READ_LOG_CH_SIZE,
+            write_log_capacity: WRITE_LOG_CH_SIZE,
         }
     }
 }
@@ -108,6 +132,7 @@ impl HousekeeperConfig {
             max_log_sync_repeats: max_log_sync_repeats
                 .unwrap_or(DEFAULT_MAX_LOG_SYNC_REPEATS as u32),
             eviction_batch_size: eviction_batch_size.unwrap_or(DEFAULT_EVICTION_BATCH_SIZE),
+            ..Default::default()
         }
     }
 }
@@ -117,7 +142,6 @@ pub(crate) fn sketch_capacity(max_capacity: u64) -> u32 {
     max_capacity.try_into().unwrap_or(u32::MAX).max(128)
 }
 
-#[cfg(test)]
 pub(crate) fn available_parallelism() -> usize {
     use std::{num::NonZeroUsize, thread::available_parallelism};
     available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
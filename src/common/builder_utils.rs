@@ -1,3 +1,8 @@
+use super::{
+    concurrent::constants::{READ_LOG_FLUSH_POINT, WRITE_LOG_FLUSH_POINT},
+    HousekeeperConfig,
+};
+
 use std::time::Duration;
 
 const YEAR_SECONDS: u64 = 365 * 24 * 3600;
@@ -14,3 +19,35 @@ pub(crate) fn ensure_expirations_or_panic(
         assert!(d <= max_duration, "time_to_idle is longer than 1000 years");
     }
 }
+
+pub(crate) fn ensure_maintenance_interval_or_panic(interval: Option<Duration>) {
+    if let Some(d) = interval {
+        assert!(
+            !d.is_zero(),
+            "the interval of the maintenance scheduler must not be zero"
+        );
+    }
+}
+
+pub(crate) fn ensure_housekeeper_config_or_panic(config: &HousekeeperConfig) {
+    assert!(
+        !config.maintenance_task_timeout.is_zero(),
+        "maintenance_task_timeout must not be zero"
+    );
+    assert!(
+        config.max_log_sync_repeats != 0,
+        "max_log_sync_repeats must not be zero"
+    );
+    assert!(
+        config.eviction_batch_size != 0,
+        "eviction_batch_size must not be zero"
+    );
+    assert!(
+        config.read_log_capacity >= READ_LOG_FLUSH_POINT,
+        "read_log_capacity must be at least {READ_LOG_FLUSH_POINT}"
+    );
+    assert!(
+        config.write_log_capacity >= WRITE_LOG_FLUSH_POINT,
+        "write_log_capacity must be at least {WRITE_LOG_FLUSH_POINT}"
+    );
+}
@@ -0,0 +1,482 @@
+use super::maintenance_stats::{AtomicDurationHistogram, DurationHistogram, PhaseCounts};
+
+use std::{
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+const ENTRY_COUNT: (&str, &str) = (
+    "moka_cache_entry_count",
+    "The approximate number of entries in the cache.",
+);
+const WEIGHTED_SIZE: (&str, &str) = (
+    "moka_cache_weighted_size",
+    "The approximate total weighted size of the entries in the cache.",
+);
+const MAX_CAPACITY: (&str, &str) = (
+    "moka_cache_max_capacity",
+    "The maximum capacity of the cache.",
+);
+const HITS: (&str, &str) = (
+    "moka_cache_hits_total",
+    "The number of lookups that found a live entry.",
+);
+const MISSES: (&str, &str) = (
+    "moka_cache_misses_total",
+    "The number of lookups that did not find a live entry.",
+);
+const EVICTIONS: (&str, &str) = (
+    "moka_cache_evictions_total",
+    "The number of entries evicted from the cache, by removal cause.",
+);
+const LOADS: (&str, &str) = (
+    "moka_cache_loads_total",
+    "The number of values loaded by the get_with family methods, by result.",
+);
+const LOAD_DURATION: (&str, &str) = (
+    "moka_cache_load_duration_seconds",
+    "The time spent to load values by the get_with family methods.",
+);
+
+/// The counters of the lookups and the value loads of a cache.
+///
+/// When the `metrics` feature is enabled, they are also exported to the `metrics`
+/// crate facade, together with the size of the cache and the number of evicted
+/// entries.
+pub(crate) struct MetricsCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    loads_succeeded: AtomicU64,
+    loads_failed: AtomicU64,
+    load_duration: AtomicDurationHistogram,
+    #[cfg(feature = "metrics")]
+    exporter: facade::Exporter,
+}
+
+impl MetricsCounters {
+    pub(crate) fn new(_cache_name: Option<&str>, _max_capacity: Option<u64>) -> Self {
+        Self {
+            hits: AtomicU64::default(),
+            misses: AtomicU64::default(),
+            loads_succeeded: AtomicU64::default(),
+            loads_failed: AtomicU64::default(),
+            load_duration: AtomicDurationHistogram::default(),
+            #[cfg(feature = "metrics")]
+            exporter: facade::Exporter::new(_cache_name, _max_capacity),
+        }
+    }
+
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exporter.hits.increment(1);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.exporter.misses.increment(1);
+    }
+
+    fn record_load(&self, duration: Duration, succeeded: bool) {
+        if succeeded {
+            self.loads_succeeded.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.loads_failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.load_duration.record(duration);
+
+        #[cfg(feature = "metrics")]
+        self.exporter.record_load(duration, succeeded);
+    }
+
+    /// Exports the entries evicted and the changes of the cache size made by a
+    /// maintenance run. Does nothing unless the `metrics` feature is enabled, as
+    /// `render_prometheus` reads them from the cache.
+    pub(crate) fn record_maintenance(
+        &self,
+        _phases: &PhaseCounts,
+        _entry_count_change: i64,
+        _weighted_size_change: i64,
+    ) {
+        #[cfg(feature = "metrics")]
+        self.exporter
+            .record_maintenance(_phases, _entry_count_change, _weighted_size_change);
+    }
+
+    /// Returns a snapshot of the counters. The caller should fill the `pub(crate)`
+    /// fields.
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            hits: load(&self.hits),
+            misses: load(&self.misses),
+            loads_succeeded: load(&self.loads_succeeded),
+            loads_failed: load(&self.loads_failed),
+            load_duration: self.load_duration.snapshot(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A snapshot of the metrics of a cache.
+#[derive(Default)]
+pub(crate) struct MetricsSnapshot {
+    pub(crate) entry_count: u64,
+    pub(crate) weighted_size: u64,
+    pub(crate) max_capacity: Option<u64>,
+    pub(crate) entries_evicted: u64,
+    pub(crate) entries_expired: u64,
+    hits: u64,
+    misses: u64,
+    loads_succeeded: u64,
+    loads_failed: u64,
+    load_duration: DurationHistogram,
+}
+
+impl MetricsSnapshot {
+    /// Adds the metrics of another cache segment to this one. `max_capacity` is
+    /// not changed.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.entry_count += other.entry_count;
+        self.weighted_size += other.weighted_size;
+        self.entries_evicted += other.entries_evicted;
+        self.entries_expired += other.entries_expired;
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.loads_succeeded += other.loads_succeeded;
+        self.loads_failed += other.loads_failed;
+        self.load_duration.merge(&other.load_duration);
+    }
+
+    /// Writes the metrics in the Prometheus text exposition format. Every sample is
+    /// labeled with `cache="<cache_name>"`.
+    pub(crate) fn render_prometheus(
+        &self,
+        cache_name: Option<&str>,
+        w: &mut impl io::Write,
+    ) -> io::Result<()> {
+        let cache = escape_label_value(cache_name.unwrap_or_default());
+        let label = format!("cache=\"{cache}\"");
+
+        write_header(w, ENTRY_COUNT, "gauge")?;
+        writeln!(w, "{}{{{label}}} {}", ENTRY_COUNT.0, self.entry_count)?;
+        write_header(w, WEIGHTED_SIZE, "gauge")?;
+        writeln!(w, "{}{{{label}}} {}", WEIGHTED_SIZE.0, self.weighted_size)?;
+        if let Some(max_capacity) = self.max_capacity {
+            write_header(w, MAX_CAPACITY, "gauge")?;
+            writeln!(w, "{}{{{label}}} {max_capacity}", MAX_CAPACITY.0)?;
+        }
+
+        write_header(w, HITS, "counter")?;
+        writeln!(w, "{}{{{label}}} {}", HITS.0, self.hits)?;
+        write_header(w, MISSES, "counter")?;
+        writeln!(w, "{}{{{label}}} {}", MISSES.0, self.misses)?;
+
+        write_header(w, EVICTIONS, "counter")?;
+        let evictions = [
+            ("size", self.entries_evicted),
+            ("expired", self.entries_expired),
+        ];
+        for (cause, count) in evictions {
+            writeln!(w, "{}{{{label},cause=\"{cause}\"}} {count}", EVICTIONS.0)?;
+        }
+
+        write_header(w, LOADS, "counter")?;
+        let loads = [
+            ("success", self.loads_succeeded),
+            ("failure", self.loads_failed),
+        ];
+        for (result, count) in loads {
+            writeln!(w, "{}{{{label},result=\"{result}\"}} {count}", LOADS.0)?;
+        }
+
+        write_header(w, LOAD_DURATION, "histogram")?;
+        let name = LOAD_DURATION.0;
+        let mut cumulative = 0;
+        for (bound, count) in self.load_duration.buckets() {
+            cumulative += count;
+            let le = bound.map_or_else(|| "+Inf".to_string(), |b| b.as_secs_f64().to_string());
+            writeln!(w, "{name}_bucket{{{label},le=\"{le}\"}} {cumulative}")?;
+        }
+        let sum = self.load_duration.sum().as_secs_f64();
+        writeln!(w, "{name}_sum{{{label}}} {sum}")?;
+        writeln!(w, "{name}_count{{{label}}} {cumulative}")
+    }
+}
+
+fn write_header(w: &mut impl io::Write, (name, help): (&str, &str), kind: &str) -> io::Result<()> {
+    writeln!(w, "# HELP {name} {help}")?;
+    writeln!(w, "# TYPE {name} {kind}")
+}
+
+/// Escapes the backslashes, double quotes and line feeds in a label value.
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The outcome of a call to a `get_with` family method.
+#[derive(Clone, Copy)]
+pub(crate) enum LoadOutcome {
+    /// The value was loaded by this call.
+    Initialized,
+    /// The loader returned an error or `None`.
+    InitError,
+    /// The loader panicked.
+    Panicked,
+    /// The value had been inserted by another call before this call started to
+    /// load it.
+    ReadExisting,
+    /// This call waited for another call to load the value.
+    Coalesced,
+}
+
+impl LoadOutcome {
+    #[cfg(feature = "tracing")]
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Initialized => "initialized",
+            Self::InitError => "init_error",
+            Self::Panicked => "panicked",
+            Self::ReadExisting => "read_existing",
+            Self::Coalesced => "coalesced",
+        }
+    }
+}
+
+/// Records the outcome of a value load to the `MetricsCounters`, and emits a
+/// `tracing` event for it. The event will be in the span created by
+/// `common::trace::load_span`.
+pub(crate) struct LoadRecorder<'a> {
+    counters: &'a MetricsCounters,
+    started: Instant,
+}
+
+impl<'a> LoadRecorder<'a> {
+    pub(crate) fn start(counters: &'a MetricsCounters) -> Self {
+        Self {
+            counters,
+            started: Instant::now(),
+        }
+    }
+
+    /// `waiters` is the number of the other callers that were coalesced into
+    /// this load and waited for its result.
+    pub(crate) fn finish(self, outcome: LoadOutcome, _waiters: usize) {
+        let elapsed = self.started.elapsed();
+        match outcome {
+            LoadOutcome::Initialized => self.counters.record_load(elapsed, true),
+            LoadOutcome::InitError | LoadOutcome::Panicked => {
+                self.counters.record_load(elapsed, false)
+            }
+            LoadOutcome::ReadExisting | LoadOutcome::Coalesced => (),
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            outcome = outcome.as_str(),
+            waiters = _waiters,
+            ?elapsed,
+            "Finished loading the value",
+        );
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod facade {
+    use super::{
+        PhaseCounts, ENTRY_COUNT, EVICTIONS, HITS, LOADS, LOAD_DURATION, MAX_CAPACITY, MISSES,
+        WEIGHTED_SIZE,
+    };
+
+    use metrics::{Counter, Gauge, Histogram, Unit};
+    use std::time::Duration;
+
+    /// The handles of the metrics of a cache registered to the `metrics` facade.
+    ///
+    /// The gauges are updated by increments and decrements, so that the segments
+    /// of a `SegmentedCache`, which share the same labels, add up to the whole
+    /// cache.
+    pub(super) struct Exporter {
+        pub(super) hits: Counter,
+        pub(super) misses: Counter,
+        evicted_size: Counter,
+        evicted_expired: Counter,
+        loads_succeeded: Counter,
+        loads_failed: Counter,
+        load_duration: Histogram,
+        entry_count: Gauge,
+        weighted_size: Gauge,
+    }
+
+    impl Exporter {
+        pub(super) fn new(cache_name: Option<&str>, max_capacity: Option<u64>) -> Self {
+            metrics::describe_gauge!(ENTRY_COUNT.0, Unit::Count, ENTRY_COUNT.1);
+            metrics::describe_gauge!(WEIGHTED_SIZE.0, WEIGHTED_SIZE.1);
+            metrics::describe_gauge!(MAX_CAPACITY.0, MAX_CAPACITY.1);
+            metrics::describe_counter!(HITS.0, Unit::Count, HITS.1);
+            metrics::describe_counter!(MISSES.0, Unit::Count, MISSES.1);
+            metrics::describe_counter!(EVICTIONS.0, Unit::Count, EVICTIONS.1);
+            metrics::describe_counter!(LOADS.0, Unit::Count, LOADS.1);
+            metrics::describe_histogram!(LOAD_DURATION.0, Unit::Seconds, LOAD_DURATION.1);
+
+            let cache = cache_name.unwrap_or_default().to_string();
+            if let Some(max_capacity) = max_capacity {
+                metrics::gauge!(MAX_CAPACITY.0, "cache" => cache.clone())
+                    .increment(max_capacity as f64);
+            }
+
+            Self {
+                hits: metrics::counter!(HITS.0, "cache" => cache.clone()),
+                misses: metrics::counter!(MISSES.0, "cache" => cache.clone()),
+                evicted_size: metrics::counter!(
+                    EVICTIONS.0, "cache" => cache.clone(), "cause" => "size"
+                ),
+                evicted_expired: metrics::counter!(
+                    EVICTIONS.0, "cache" => cache.clone(), "cause" => "expired"
+                ),
+                loads_succeeded: metrics::counter!(
+                    LOADS.0, "cache" => cache.clone(), "result" => "success"
+                ),
+                loads_failed: metrics::counter!(
+                    LOADS.0, "cache" => cache.clone(), "result" => "failure"
+                ),
+                load_duration: metrics::histogram!(LOAD_DURATION.0, "cache" => cache.clone()),
+                entry_count: metrics::gauge!(ENTRY_COUNT.0, "cache" => cache.clone()),
+                weighted_size: metrics::gauge!(WEIGHTED_SIZE.0, "cache" => cache),
+            }
+        }
+
+        pub(super) fn record_load(&self, duration: Duration, succeeded: bool) {
+            if succeeded {
+                self.loads_succeeded.increment(1);
+            } else {
+                self.loads_failed.increment(1);
+            }
+            self.load_duration.record(duration.as_secs_f64());
+        }
+
+        pub(super) fn record_maintenance(
+            &self,
+            phases: &PhaseCounts,
+            entry_count_change: i64,
+            weighted_size_change: i64,
+        ) {
+            if phases.entries_evicted > 0 {
+                self.evicted_size.increment(phases.entries_evicted);
+            }
+            if phases.entries_expired > 0 {
+                self.evicted_expired.increment(phases.entries_expired);
+            }
+            if entry_count_change != 0 {
+                self.entry_count.increment(entry_count_change as f64);
+            }
+            if weighted_size_change != 0 {
+                self.weighted_size.increment(weighted_size_change as f64);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics", feature = "sync"))]
+mod tests {
+    use crate::sync::SegmentedCache;
+
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, SharedString, Unit};
+    use parking_lot::Mutex;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+
+    /// A recorder that keeps the counters and the gauges in a map.
+    #[derive(Default)]
+    struct MapRecorder {
+        metrics: Mutex<HashMap<String, Arc<AtomicU64>>>,
+    }
+
+    impl MapRecorder {
+        fn register(&self, key: &Key) -> Arc<AtomicU64> {
+            let labels = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect::<Vec<_>>();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            Arc::clone(self.metrics.lock().entry(name).or_default())
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            self.metrics.lock()[name].load(Ordering::Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.counter(name))
+        }
+    }
+
+    impl metrics::Recorder for MapRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.register(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.register(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn export_to_metrics_facade() {
+        let recorder = MapRecorder::default();
+        // The handles of the metrics are registered when the cache is created.
+        let cache = metrics::with_local_recorder(&recorder, || {
+            SegmentedCache::builder(2)
+                .name("seg")
+                .max_capacity(100)
+                .build()
+        });
+
+        for i in 0..3 {
+            cache.get_with(i, || i);
+        }
+        cache.get(&0);
+        cache.run_pending_tasks();
+
+        assert_eq!(recorder.gauge("moka_cache_max_capacity{cache=seg}"), 100.0);
+        // The gauges of the segments add up to the whole cache.
+        assert_eq!(recorder.gauge("moka_cache_entry_count{cache=seg}"), 3.0);
+        assert_eq!(recorder.gauge("moka_cache_weighted_size{cache=seg}"), 3.0);
+        assert_eq!(recorder.counter("moka_cache_hits_total{cache=seg}"), 1);
+        assert_eq!(recorder.counter("moka_cache_misses_total{cache=seg}"), 3);
+        assert_eq!(
+            recorder.counter("moka_cache_loads_total{cache=seg,result=success}"),
+            3
+        );
+
+        cache.invalidate_all();
+        cache.run_pending_tasks();
+        assert_eq!(recorder.gauge("moka_cache_entry_count{cache=seg}"), 0.0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Applies the [`CallbackPanicPolicy`] to the user-supplied callbacks.
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::concurrent::Weigher;
+use crate::policy::{
+    CallbackKind, CallbackPanicPolicy, CallbackPanicPolicyConfig, ExpirationPolicy, Expiry,
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct CallbackPanicReporter {
+    config: CallbackPanicPolicyConfig,
+    #[cfg(any(feature = "logging", feature = "tracing"))]
+    cache_name: Option<Arc<str>>,
+}
+
+impl CallbackPanicReporter {
+    pub(crate) fn new(policy: CallbackPanicPolicy, _cache_name: Option<&str>) -> Self {
+        Self {
+            config: policy.config,
+            #[cfg(any(feature = "logging", feature = "tracing"))]
+            cache_name: _cache_name.map(Into::into),
+        }
+    }
+
+    /// Returns `true` if panics from the callbacks are caught and reported, and the
+    /// cache keeps using the callbacks.
+    pub(crate) fn catches_panics(&self) -> bool {
+        matches!(
+            self.config,
+            CallbackPanicPolicyConfig::LogAndContinue | CallbackPanicPolicyConfig::Handler(_)
+        )
+    }
+
+    /// Returns `true` if panics from the eviction listener should be propagated to
+    /// the caller.
+    pub(crate) fn propagates_panics(&self) -> bool {
+        matches!(self.config, CallbackPanicPolicyConfig::Propagate)
+    }
+
+    /// Reports a panic caught from a callback. Does nothing unless the policy
+    /// catches panics.
+    pub(crate) fn report(&self, kind: CallbackKind, payload: &(dyn Any + Send)) {
+        match &self.config {
+            CallbackPanicPolicyConfig::Handler(handler) => handler(kind, payload),
+            CallbackPanicPolicyConfig::LogAndContinue => {
+                #[cfg(any(feature = "logging", feature = "tracing"))]
+                self.log_panic(kind, payload, describe(kind));
+            }
+            _ => (),
+        }
+    }
+
+    /// Logs that the eviction listener has been disabled because it panicked.
+    pub(crate) fn report_disabled_listener(&self, _payload: &(dyn Any + Send)) {
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        self.log_panic(
+            CallbackKind::EvictionListener,
+            _payload,
+            "Disabled the eviction listener because it",
+        );
+    }
+
+    /// Wraps the weigher so that a panic is reported and the weight `1` is used
+    /// instead. Returns the weigher as is unless the policy catches panics.
+    pub(crate) fn guard_weigher<K, V>(&self, weigher: Weigher<K, V>) -> Weigher<K, V>
+    where
+        K: 'static,
+        V: 'static,
+    {
+        if !self.catches_panics() {
+            return weigher;
+        }
+        let reporter = self.clone();
+        Arc::new(move |key, value| {
+            // Safety: It is safe to assert unwind safety here because the weigher
+            // only gets shared references to the key and value.
+            catch_unwind(AssertUnwindSafe(|| weigher(key, value))).unwrap_or_else(|payload| {
+                reporter.report(CallbackKind::Weigher, &*payload);
+                1
+            })
+        })
+    }
+
+    /// Wraps the `Expiry` of the expiration policy, if any, so that a panic is
+    /// reported and the default of each `Expiry` method is used instead. Does
+    /// nothing unless the policy catches panics.
+    pub(crate) fn guard_expiry<K, V>(&self, expiration_policy: &mut ExpirationPolicy<K, V>)
+    where
+        K: 'static,
+        V: 'static,
+    {
+        if !self.catches_panics() {
+            return;
+        }
+        if let Some(expiry) = expiration_policy.expiry() {
+            expiration_policy.set_expiry(Arc::new(PanicSafeExpiry {
+                expiry,
+                reporter: self.clone(),
+            }));
+        }
+    }
+
+    #[cfg(any(feature = "logging", feature = "tracing"))]
+    fn log_panic(&self, _kind: CallbackKind, payload: &(dyn Any + Send), subject: &str) {
+        // Try to downcast the payload into &str or String.
+        //
+        // NOTE: Clippy will complain if we use `if let Some(_)` here.
+        // https://rust-lang.github.io/rust-clippy/master/index.html#manual_map
+        let message: Option<std::borrow::Cow<'_, str>> =
+            (payload.downcast_ref::<&str>().map(|s| (*s).into()))
+                .or_else(|| payload.downcast_ref::<String>().map(Into::into));
+        let cache_name = self.cache_name.as_deref();
+
+        #[cfg(feature = "logging")]
+        {
+            let cn = cache_name
+                .map(|name| format!("[{name}] "))
+                .unwrap_or_default();
+
+            if let Some(m) = &message {
+                log::error!("{cn}{subject} panicked at '{m}'");
+            } else {
+                log::error!("{cn}{subject} panicked");
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::error!(
+            cache = cache_name.unwrap_or_default(),
+            callback = ?_kind,
+            panic_message = message.as_deref().unwrap_or_default(),
+            "{subject} panicked"
+        );
+    }
+}
+
+#[cfg(any(feature = "logging", feature = "tracing"))]
+fn describe(kind: CallbackKind) -> &'static str {
+    match kind {
+        CallbackKind::EvictionListener => "The eviction listener",
+        CallbackKind::Weigher => "The weigher",
+        CallbackKind::Expiry => "The expiry",
+        CallbackKind::Init => "The init closure",
+    }
+}
+
+/// An `Expiry` that reports panics from the wrapped `Expiry` and falls back to the
+/// default implementations of the trait methods.
+struct PanicSafeExpiry<K, V> {
+    expiry: Arc<dyn Expiry<K, V> + Send + Sync + 'static>,
+    reporter: CallbackPanicReporter,
+}
+
+impl<K, V> PanicSafeExpiry<K, V> {
+    fn call<T>(&self, f: impl FnOnce() -> T, fallback: T) -> T {
+        // Safety: It is safe to assert unwind safety here because the expiry only
+        // gets shared references to the key and value.
+        catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+            self.reporter.report(CallbackKind::Expiry, &*payload);
+            fallback
+        })
+    }
+}
+
+impl<K, V> Expiry<K, V> for PanicSafeExpiry<K, V> {
+    fn expire_after_create(&self, key: &K, value: &V, created_at: Instant) -> Option<Duration> {
+        self.call(
+            || self.expiry.expire_after_create(key, value, created_at),
+            None,
+        )
+    }
+
+    fn expire_after_read(
+        &self,
+        key: &K,
+        value: &V,
+        read_at: Instant,
+        duration_until_expiry: Option<Duration>,
+        last_modified_at: Instant,
+    ) -> Option<Duration> {
+        self.call(
+            || {
+                self.expiry.expire_after_read(
+                    key,
+                    value,
+                    read_at,
+                    duration_until_expiry,
+                    last_modified_at,
+                )
+            },
+            duration_until_expiry,
+        )
+    }
+
+    fn expire_after_update(
+        &self,
+        key: &K,
+        value: &V,
+        updated_at: Instant,
+        duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        self.call(
+            || {
+                self.expiry
+                    .expire_after_update(key, value, updated_at, duration_until_expiry)
+            },
+            duration_until_expiry,
+        )
+    }
+}
@@ -1,4 +1,4 @@
-use crate::common::{deque::DeqNode, time::Instant};
+use crate::common::{deque::DeqNode, time::Instant, CacheRegion};
 
 use parking_lot::Mutex;
 use std::{fmt, ptr::NonNull, sync::Arc};
@@ -8,6 +8,7 @@ use triomphe::Arc as TrioArc;
 pub(crate) mod constants;
 pub(crate) mod deques;
 pub(crate) mod entry_info;
+pub(crate) mod maintenance;
 
 #[cfg(feature = "sync")]
 pub(crate) mod housekeeper;
@@ -16,7 +17,11 @@ pub(crate) mod housekeeper;
 // https://github.com/rust-lang/rust/issues/32976
 // #[cfg_attr(target_has_atomic = "64", path = "common/time_atomic64.rs")]
 
-#[path = "concurrent/atomic_time/atomic_time.rs"]
+#[cfg_attr(feature = "quanta", path = "concurrent/atomic_time/atomic_time.rs")]
+#[cfg_attr(
+    not(feature = "quanta"),
+    path = "concurrent/atomic_time/atomic_time_compat.rs"
+)]
 pub(crate) mod atomic_time;
 
 #[cfg(feature = "unstable-debug-counters")]
@@ -166,6 +171,11 @@ impl<K> DeqNodes<K> {
     pub(crate) fn set_timer_node(&mut self, timer_node: Option<DeqNodeTimer<K>>) {
         self.timer_node = timer_node;
     }
+
+    #[cfg(test)]
+    pub(crate) fn timer_node(&self) -> Option<DeqNodeTimer<K>> {
+        self.timer_node
+    }
 }
 
 pub(crate) struct ValueEntry<K, V> {
@@ -225,6 +235,13 @@ impl<K, V> ValueEntry<K, V> {
         self.nodes.lock().access_order_q_node
     }
 
+    /// Returns the cache region of the access-order queue where this entry
+    /// resides, or `None` if the entry is not in any of the queues.
+    pub(crate) fn region(&self) -> Option<CacheRegion> {
+        self.access_order_q_node()
+            .map(|node| node.decompose_tag().into())
+    }
+
     pub(crate) fn set_access_order_q_node(&self, node: Option<KeyDeqNodeAo<K>>) {
         self.nodes.lock().access_order_q_node = node;
     }
@@ -315,6 +332,10 @@ pub(crate) enum WriteOp<K, V> {
         kv_entry: KvEntry<K, V>,
         entry_gen: u16,
     },
+    /// The per-entry expiration time of the entry has been changed.
+    SetExpiry {
+        value_entry: TrioArc<ValueEntry<K, V>>,
+    },
 }
 
 /// Cloning a `WriteOp` is safe and cheap because it uses `Arc` and `TrioArc` pointers to
@@ -342,6 +363,9 @@ impl<K, V> Clone for WriteOp<K, V> {
                 kv_entry: kv_entry.clone(),
                 entry_gen: *entry_gen,
             },
+            Self::SetExpiry { value_entry } => Self::SetExpiry {
+                value_entry: TrioArc::clone(value_entry),
+            },
         }
     }
 }
@@ -351,6 +375,7 @@ impl<K, V> fmt::Debug for WriteOp<K, V> {
         match self {
             Self::Upsert { .. } => f.debug_struct("Upsert").finish(),
             Self::Remove { .. } => f.debug_tuple("Remove").finish(),
+            Self::SetExpiry { .. } => f.debug_struct("SetExpiry").finish(),
         }
     }
 }
@@ -1 +0,0 @@
-atomic_time.rs
\ No newline at end of file
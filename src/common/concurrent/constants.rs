@@ -21,3 +21,11 @@ pub(crate) const DEFAULT_MAINTENANCE_TASK_TIMEOUT_MILLIS: u64 = 100;
 
 #[cfg(feature = "sync")]
 pub(crate) const WRITE_RETRY_INTERVAL_MICROS: u64 = 50;
+
+/// The default maximum interval between two runs of the maintenance tasks by a
+/// maintenance scheduler.
+pub(crate) const DEFAULT_MAINTENANCE_INTERVAL_MILLIS: u64 = 1_000;
+
+/// The minimum delay between two runs of the maintenance tasks by a maintenance
+/// scheduler.
+pub(crate) const MIN_MAINTENANCE_DELAY_MILLIS: u64 = 10;
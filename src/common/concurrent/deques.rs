@@ -34,6 +34,15 @@ impl<K> Default for Deques<K> {
 }
 
 impl<K> Deques<K> {
+    /// Returns the number of bytes allocated for the deque nodes.
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        let len = self.window.len()
+            + self.probation.len()
+            + self.protected.len()
+            + self.write_order.len();
+        len * std::mem::size_of::<DeqNode<KeyHashDate<K>>>()
+    }
+
     pub(crate) fn select_mut(
         &mut self,
         selector: CacheRegion,
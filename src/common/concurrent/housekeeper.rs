@@ -5,7 +5,7 @@ use super::{
     constants::{READ_LOG_FLUSH_POINT, WRITE_LOG_FLUSH_POINT},
 };
 use crate::common::time::{CheckedTimeOps, Instant};
-use crate::common::HousekeeperConfig;
+use crate::common::{maintenance_stats::MaintenanceCounters, HousekeeperConfig};
 
 use parking_lot::{Mutex, MutexGuard};
 use std::{
@@ -24,6 +24,8 @@ pub(crate) trait InnerSync {
     ) -> bool;
 
     fn now(&self) -> Instant;
+
+    fn maintenance_counters(&self) -> &MaintenanceCounters;
 }
 
 pub(crate) struct Housekeeper {
@@ -0,0 +1,87 @@
+use crossbeam_channel::{Receiver, Sender};
+use std::sync::Arc;
+
+#[cfg(feature = "sync")]
+use std::{thread, time::Duration};
+
+/// A handle to a maintenance thread or task. The thread or task stops when all
+/// clones of the handle are dropped.
+#[derive(Clone)]
+pub(crate) struct MaintenanceHandle {
+    _stop: Sender<()>,
+}
+
+impl MaintenanceHandle {
+    /// Creates a handle and a receiver that gets disconnected when all clones of
+    /// the handle are dropped.
+    pub(crate) fn new() -> (Self, Receiver<()>) {
+        let (stop_snd, stop_rcv) = crossbeam_channel::bounded::<()>(0);
+        (Self { _stop: stop_snd }, stop_rcv)
+    }
+}
+
+/// Wakes up a maintenance thread or task before its scheduled time.
+#[derive(Clone)]
+pub(crate) struct MaintenanceWaker {
+    wake: Arc<dyn Fn() + Send + Sync + 'static>,
+}
+
+impl MaintenanceWaker {
+    pub(crate) fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            wake: Arc::new(wake),
+        }
+    }
+
+    pub(crate) fn wake(&self) {
+        (self.wake)();
+    }
+}
+
+/// Spawns a thread that calls `run` after `first_delay`, and then repeatedly after
+/// the delay returned by the previous call. The thread also calls `run` when it is
+/// woken up by the returned `MaintenanceWaker`. The thread stops when `run`
+/// returns `None` or the returned handle is dropped.
+#[cfg(feature = "sync")]
+pub(crate) fn spawn_maintenance_thread(
+    first_delay: Duration,
+    mut run: impl FnMut() -> Option<Duration> + Send + 'static,
+) -> (MaintenanceHandle, MaintenanceWaker) {
+    let (handle, stop_rcv) = MaintenanceHandle::new();
+    let (wake_snd, wake_rcv) = crossbeam_channel::bounded::<()>(1);
+    // Keep the wake channel connected even if the waker is not used.
+    let wake_snd2 = wake_snd.clone();
+    thread::Builder::new()
+        .name("moka2-maintenance".to_string())
+        .spawn(move || {
+            let _wake_snd = wake_snd2;
+            let mut delay = first_delay;
+            while wait(&stop_rcv, &wake_rcv, delay) {
+                match run() {
+                    Some(next_delay) => delay = next_delay,
+                    None => break,
+                }
+            }
+        })
+        .expect("Failed to spawn the maintenance thread");
+
+    let waker = MaintenanceWaker::new(move || {
+        // The channel is full if the thread has not woken up for the previous
+        // call yet.
+        let _ = wake_snd.try_send(());
+    });
+    (handle, waker)
+}
+
+/// Waits until the delay has elapsed or the thread is woken up. Returns `false`
+/// if the thread should stop.
+#[cfg(feature = "sync")]
+fn wait(stop_rcv: &Receiver<()>, wake_rcv: &Receiver<()>, delay: Duration) -> bool {
+    // Nothing is sent to the stop channel. It gets disconnected when the handle is
+    // dropped.
+    crossbeam_channel::select! {
+        recv(stop_rcv) -> _ => false,
+        recv(wake_rcv) -> _ => true,
+        default(delay) => true,
+    }
+}
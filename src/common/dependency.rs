@@ -0,0 +1,219 @@
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// A read tracking frame: the address of the `DependencyGraph` and the keys
+/// (`Arc<K>`) read from the cache owning the graph.
+type Frame = (usize, Vec<Box<dyn Any>>);
+
+thread_local! {
+    /// The stack of the read tracking frames of the current thread.
+    static TRACKING_FRAMES: RefCell<Vec<Frame>> = RefCell::new(Vec::new());
+}
+
+/// A graph of the dependencies between cached entries. When an entry is removed
+/// from the cache, the entries depending on it are removed too.
+///
+/// The edges are held per key, not per entry; replacing the value of a key does
+/// not affect its dependents.
+pub(crate) struct DependencyGraph<K> {
+    inner: Mutex<GraphInner<K>>,
+    is_empty: AtomicBool,
+    /// The dependents of the removed entries, waiting to be removed by the
+    /// pending tasks.
+    pending: Mutex<Vec<Arc<K>>>,
+    /// The number of the active read tracking frames on all threads.
+    active_frames: AtomicUsize,
+}
+
+struct GraphInner<K> {
+    /// Key to the keys that depend on it.
+    dependents: HashMap<Arc<K>, HashSet<Arc<K>>>,
+    /// Key to the keys that it depends on.
+    dependencies: HashMap<Arc<K>, HashSet<Arc<K>>>,
+}
+
+impl<K> Default for DependencyGraph<K> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(GraphInner {
+                dependents: HashMap::default(),
+                dependencies: HashMap::default(),
+            }),
+            is_empty: AtomicBool::new(true),
+            pending: Mutex::default(),
+            active_frames: AtomicUsize::default(),
+        }
+    }
+}
+
+impl<K> DependencyGraph<K>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn is_empty(&self) -> bool {
+        self.is_empty.load(Ordering::Acquire)
+    }
+
+    /// Records that `key` depends on `dependencies`. A dependency on the key itself
+    /// is ignored.
+    pub(crate) fn add(&self, key: &Arc<K>, dependencies: &[Arc<K>]) {
+        let mut inner = self.inner.lock();
+        for dep in dependencies.iter().filter(|d| *d != key) {
+            inner
+                .dependents
+                .entry(Arc::clone(dep))
+                .or_default()
+                .insert(Arc::clone(key));
+            inner
+                .dependencies
+                .entry(Arc::clone(key))
+                .or_default()
+                .insert(Arc::clone(dep));
+        }
+        self.is_empty
+            .store(inner.dependents.is_empty(), Ordering::Release);
+    }
+
+    /// Removes the key and all of its edges from the graph, and returns the keys
+    /// that depended on it.
+    ///
+    /// Because the edges are removed, a key is returned at most once for a
+    /// dependency cycle.
+    pub(crate) fn take_dependents(&self, key: &K) -> Vec<Arc<K>> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut inner = self.inner.lock();
+        if let Some(deps) = inner.dependencies.remove(key) {
+            for dep in deps {
+                inner.remove_dependent(&dep, key);
+            }
+        }
+        let dependents = inner.dependents.remove(key).unwrap_or_default();
+        for dependent in &dependents {
+            if let Some(deps) = inner.dependencies.get_mut(dependent) {
+                deps.remove(key);
+                if deps.is_empty() {
+                    inner.dependencies.remove(dependent);
+                }
+            }
+        }
+        self.is_empty
+            .store(inner.dependents.is_empty(), Ordering::Release);
+        dependents.into_iter().collect()
+    }
+
+    /// Takes the dependents of the key and adds them to the pending removals.
+    pub(crate) fn queue_dependents(&self, key: &K) {
+        let dependents = self.take_dependents(key);
+        if !dependents.is_empty() {
+            self.pending.lock().extend(dependents);
+        }
+    }
+
+    pub(crate) fn take_pending(&self) -> Vec<Arc<K>> {
+        std::mem::take(&mut *self.pending.lock())
+    }
+
+    /// Adds the keys back to the pending removals, so that they are removed by a
+    /// later call.
+    pub(crate) fn requeue_pending(&self, keys: Vec<Arc<K>>) {
+        if !keys.is_empty() {
+            self.pending.lock().extend(keys);
+        }
+    }
+}
+
+impl<K> GraphInner<K>
+where
+    K: Hash + Eq,
+{
+    fn remove_dependent(&mut self, dependency: &K, dependent: &K) {
+        if let Some(keys) = self.dependents.get_mut(dependency) {
+            keys.remove(dependent);
+            if keys.is_empty() {
+                self.dependents.remove(dependency);
+            }
+        }
+    }
+}
+
+//
+// Read tracking.
+//
+impl<K> DependencyGraph<K>
+where
+    K: Send + Sync + 'static,
+{
+    /// Calls `f` while tracking the keys read from the cache owning this graph on
+    /// the current thread. Returns the result of `f` and the keys read.
+    pub(crate) fn track_reads<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<Arc<K>>) {
+        // Pops the frame even if `f` panics.
+        struct FrameGuard<'a, K> {
+            graph: &'a DependencyGraph<K>,
+            popped: bool,
+        }
+
+        impl<K> Drop for FrameGuard<'_, K> {
+            fn drop(&mut self) {
+                if !self.popped {
+                    pop_frame();
+                }
+                self.graph.active_frames.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+
+        self.active_frames.fetch_add(1, Ordering::AcqRel);
+        TRACKING_FRAMES.with(|frames| frames.borrow_mut().push((self.id(), Vec::new())));
+        let mut guard = FrameGuard {
+            graph: self,
+            popped: false,
+        };
+
+        let result = f();
+
+        let keys = pop_frame()
+            .into_iter()
+            .filter_map(|k| k.downcast::<Arc<K>>().ok())
+            .map(|k| *k)
+            .collect();
+        guard.popped = true;
+        (result, keys)
+    }
+
+    /// Records that the key has been read, if there is a read tracking frame for
+    /// this graph on top of the current thread's stack.
+    pub(crate) fn record_read(&self, key: &Arc<K>) {
+        if self.active_frames.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let id = self.id();
+        TRACKING_FRAMES.with(|frames| {
+            if let Some((frame_id, keys)) = frames.borrow_mut().last_mut() {
+                if *frame_id == id {
+                    keys.push(Box::new(Arc::clone(key)));
+                }
+            }
+        });
+    }
+
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+fn pop_frame() -> Vec<Box<dyn Any>> {
+    TRACKING_FRAMES
+        .with(|frames| frames.borrow_mut().pop().map(|(_, keys)| keys))
+        .unwrap_or_default()
+}
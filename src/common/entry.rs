@@ -1,4 +1,4 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Instant};
 
 /// A snapshot of a single entry in the cache.
 ///
@@ -22,6 +22,8 @@ pub struct Entry<K, V> {
     value: V,
     is_fresh: bool,
     is_old_value_replaced: bool,
+    old_value: Option<V>,
+    metadata: Option<EntryMetadata>,
 }
 
 impl<K, V> Debug for Entry<K, V>
@@ -35,6 +37,8 @@ where
             .field("value", &self.value)
             .field("is_fresh", &self.is_fresh)
             .field("is_old_value_replaced", &self.is_old_value_replaced)
+            .field("old_value", &self.old_value)
+            .field("metadata", &self.metadata)
             .finish()
     }
 }
@@ -51,9 +55,21 @@ impl<K, V> Entry<K, V> {
             value,
             is_fresh,
             is_old_value_replaced,
+            old_value: None,
+            metadata: None,
         }
     }
 
+    pub(crate) fn with_old_value(mut self, old_value: Option<V>) -> Self {
+        self.old_value = old_value;
+        self
+    }
+
+    pub(crate) fn with_metadata(mut self, metadata: Option<EntryMetadata>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
     /// Returns a reference to the wrapped key.
     pub fn key(&self) -> &K {
         self.key.as_ref().expect("Bug: Key is None")
@@ -89,4 +105,106 @@ impl<K, V> Entry<K, V> {
     pub fn is_old_value_replaced(&self) -> bool {
         self.is_old_value_replaced
     }
+
+    /// Returns a reference to the old value that was replaced by the value in this
+    /// `Entry`, if any.
+    ///
+    /// The old value is available on the `Entry` in a `CompResult::ReplacedWith`
+    /// returned by the `and_compute_with` family of methods, and on the `Entry`
+    /// returned by the `and_upsert_with` methods when an existing value was
+    /// replaced. It is `None` if the old value had already expired.
+    pub fn old_value(&self) -> Option<&V> {
+        self.old_value.as_ref()
+    }
+
+    /// Consumes this `Entry`, returning the wrapped value and the old value that
+    /// was replaced by it, if any.
+    ///
+    /// See [`old_value`](#method.old_value) for when the old value is available.
+    pub fn into_value_and_old_value(self) -> (V, Option<V>) {
+        (self.value, self.old_value)
+    }
+
+    /// Returns the metadata of the entry at the time this `Entry` was constructed,
+    /// if available.
+    ///
+    /// The metadata is available on the `Entry` returned by the cache's `get_entry`
+    /// method, and on the `Entry` in a `CompResult` returned by the `and_compute_with`
+    /// family of methods, unless the entry has been removed.
+    pub fn metadata(&self) -> Option<&EntryMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+/// The region of the cache's eviction policy where an entry resides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryRegion {
+    /// The admission window of the TinyLFU policy.
+    Window,
+    /// The probation segment of the main space.
+    MainProbation,
+    /// The protected segment of the main space.
+    MainProtected,
+}
+
+/// A snapshot of the metadata of a cache entry.
+///
+/// The timestamps are based on the clock used by the cache for expiration, and
+/// converted to `std::time::Instant`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryMetadata {
+    last_modified: Option<Instant>,
+    last_accessed: Option<Instant>,
+    expiration_time: Option<Instant>,
+    policy_weight: u32,
+    region: Option<EntryRegion>,
+}
+
+impl EntryMetadata {
+    pub(crate) fn new(
+        last_modified: Option<Instant>,
+        last_accessed: Option<Instant>,
+        expiration_time: Option<Instant>,
+        policy_weight: u32,
+        region: Option<EntryRegion>,
+    ) -> Self {
+        Self {
+            last_modified,
+            last_accessed,
+            expiration_time,
+            policy_weight,
+            region,
+        }
+    }
+
+    /// Returns the time when the entry was inserted or its value was last
+    /// updated.
+    pub fn last_modified(&self) -> Option<Instant> {
+        self.last_modified
+    }
+
+    /// Returns the time when the entry was last read or written.
+    pub fn last_accessed(&self) -> Option<Instant> {
+        self.last_accessed
+    }
+
+    /// Returns the time when the entry will expire, which is the earliest of the
+    /// per-entry expiration time, the time-to-live and the time-to-idle. Returns
+    /// `None` if the entry does not expire.
+    pub fn expiration_time(&self) -> Option<Instant> {
+        self.expiration_time
+    }
+
+    /// Returns the weight of the entry computed by the weigher of the cache. It is
+    /// `1` if the cache has no weigher.
+    pub fn policy_weight(&self) -> u32 {
+        self.policy_weight
+    }
+
+    /// Returns the region of the eviction policy where the entry resides. Returns
+    /// `None` if the entry has not been admitted to the policy yet; this happens
+    /// until the pending tasks of the cache are processed after an insertion.
+    pub fn region(&self) -> Option<EntryRegion> {
+        self.region
+    }
 }
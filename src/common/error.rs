@@ -19,3 +19,32 @@ pub enum PredicateError {
     )]
     InvalidationClosuresDisabled,
 }
+
+/// The error type for the `lock_key` method of [`sync::Cache`][sync-lock-key] and
+/// [`future::Cache`][future-lock-key].
+///
+/// [sync-lock-key]: ./sync/struct.Cache.html#method.lock_key
+/// [future-lock-key]: ./future/struct.Cache.html#method.lock_key
+#[derive(thiserror::Error, Debug)]
+pub enum KeyLockError {
+    /// This cache does not have a necessary configuration enabled to support
+    /// per-key locks.
+    ///
+    /// To enable the configuration, call
+    /// [`CacheBuilder::support_key_locks`][support-key-locks] method at the cache
+    /// creation time.
+    ///
+    /// [support-key-locks]: ./sync/struct.CacheBuilder.html#method.support_key_locks
+    #[error(
+        "Support for key locks is disabled in this cache. \
+    Please enable it by calling the support_key_locks method \
+    of the builder at the cache creation time"
+    )]
+    KeyLocksDisabled,
+
+    /// The given [`KeyGuard`][future-key-guard] was not returned by this cache.
+    ///
+    /// [future-key-guard]: ./future/struct.KeyGuard.html
+    #[error("The key guard was not returned by the lock_key method of this cache")]
+    ForeignKeyGuard,
+}
@@ -184,7 +184,6 @@ impl FrequencySketch {
         (hash & self.table_mask) as usize
     }
 
-    #[cfg(feature = "unstable-debug-counters")]
     pub(crate) fn table_size(&self) -> u64 {
         (self.table.len() * std::mem::size_of::<u64>()) as u64
     }
@@ -211,7 +210,7 @@ mod tests {
     static ITEM: Lazy<u32> = Lazy::new(|| {
         let mut buf = [0; 4];
         getrandom::getrandom(&mut buf).unwrap();
-        unsafe { std::mem::transmute::<[u8; 4], u32>(buf) }
+        u32::from_ne_bytes(buf)
     });
 
     // This test was ported from Caffeine.
@@ -0,0 +1,263 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The upper bounds (in microseconds) of the buckets of a `DurationHistogram`,
+/// except the last bucket, which has no upper bound.
+const BUCKET_BOUNDS_MICROS: [u64; 6] = [10, 100, 1_000, 10_000, 100_000, 1_000_000];
+const NUM_BUCKETS: usize = BUCKET_BOUNDS_MICROS.len() + 1;
+
+/// The statistics of the maintenance tasks of a cache.
+///
+/// The maintenance tasks apply the recorded reads and writes to the cache policy,
+/// and remove the expired, evicted and invalidated entries. They are run by the
+/// cache while it is being read or written, by the `run_pending_tasks` method, or
+/// by a maintenance scheduler. Use these statistics to find out if the maintenance
+/// tasks are causing latency spikes.
+///
+/// Use the `maintenance_stats` method of [`sync::Cache`][sync-cache] or
+/// [`future::Cache`][future-cache] to get a snapshot of the statistics.
+///
+/// [sync-cache]: ./sync/struct.Cache.html#method.maintenance_stats
+/// [future-cache]: ./future/struct.Cache.html#method.maintenance_stats
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    runs: u64,
+    run_duration: DurationHistogram,
+    reads_applied: u64,
+    writes_applied: u64,
+    entries_expired: u64,
+    entries_evicted: u64,
+    entries_invalidated: u64,
+    read_ops_dropped: u64,
+    write_op_retries: u64,
+    timeouts: u64,
+}
+
+impl MaintenanceStats {
+    /// Returns the number of times the maintenance tasks have run.
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    /// Returns the histogram of the durations of the maintenance runs.
+    pub fn run_duration(&self) -> &DurationHistogram {
+        &self.run_duration
+    }
+
+    /// Returns the number of recorded reads applied to the cache policy.
+    pub fn reads_applied(&self) -> u64 {
+        self.reads_applied
+    }
+
+    /// Returns the number of recorded writes (insertions, updates and removals)
+    /// applied to the cache policy.
+    pub fn writes_applied(&self) -> u64 {
+        self.writes_applied
+    }
+
+    /// Returns the number of entries removed because they had expired.
+    pub fn entries_expired(&self) -> u64 {
+        self.entries_expired
+    }
+
+    /// Returns the number of entries removed, or not admitted, to keep the cache
+    /// within its capacity.
+    pub fn entries_evicted(&self) -> u64 {
+        self.entries_evicted
+    }
+
+    /// Returns the number of entries removed by invalidation predicates
+    /// (`invalidate_entries_if`) or because an entry they depend on was removed.
+    pub fn entries_invalidated(&self) -> u64 {
+        self.entries_invalidated
+    }
+
+    /// Returns the number of reads that were not recorded because the read log
+    /// was full.
+    ///
+    /// The cache policy does not see these reads, so it may evict recently read
+    /// entries. Consider increasing the `read_log_capacity` of the cache if this
+    /// number keeps growing.
+    pub fn read_ops_dropped(&self) -> u64 {
+        self.read_ops_dropped
+    }
+
+    /// Returns the number of times a write had to wait and retry because the write
+    /// log was full.
+    ///
+    /// Consider increasing the `write_log_capacity` of the cache if this number
+    /// keeps growing.
+    pub fn write_op_retries(&self) -> u64 {
+        self.write_op_retries
+    }
+
+    /// Returns the number of maintenance runs stopped by the
+    /// `maintenance_task_timeout`, usually because of a slow eviction listener.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts
+    }
+
+    /// Adds the statistics of another cache (segment) to this one.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.runs += other.runs;
+        self.run_duration.merge(&other.run_duration);
+        self.reads_applied += other.reads_applied;
+        self.writes_applied += other.writes_applied;
+        self.entries_expired += other.entries_expired;
+        self.entries_evicted += other.entries_evicted;
+        self.entries_invalidated += other.entries_invalidated;
+        self.read_ops_dropped += other.read_ops_dropped;
+        self.write_op_retries += other.write_op_retries;
+        self.timeouts += other.timeouts;
+    }
+}
+
+/// A histogram of durations with fixed buckets.
+///
+/// The upper bounds of the buckets are 10µs, 100µs, 1ms, 10ms, 100ms and 1s. The
+/// last bucket has no upper bound.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DurationHistogram {
+    counts: [u64; NUM_BUCKETS],
+    sum: Duration,
+}
+
+impl DurationHistogram {
+    /// Returns the number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the sum of the recorded durations.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Returns the upper bound and the number of recorded durations of each
+    /// bucket. The counts are not cumulative. The upper bound of the last bucket is
+    /// `None`.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        BUCKET_BOUNDS_MICROS
+            .iter()
+            .map(|&micros| Some(Duration::from_micros(micros)))
+            .chain(std::iter::once(None))
+            .zip(self.counts.iter().copied())
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        self.sum += other.sum;
+    }
+}
+
+/// A `DurationHistogram` that can be updated concurrently.
+#[derive(Default)]
+pub(crate) struct AtomicDurationHistogram {
+    counts: [AtomicU64; NUM_BUCKETS],
+    sum_nanos: AtomicU64,
+}
+
+impl AtomicDurationHistogram {
+    pub(crate) fn record(&self, duration: Duration) {
+        let micros = duration.as_micros();
+        let bucket = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|&bound| micros <= bound as u128)
+            .unwrap_or(NUM_BUCKETS - 1);
+        let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> DurationHistogram {
+        let mut counts = [0; NUM_BUCKETS];
+        for (c, a) in counts.iter_mut().zip(self.counts.iter()) {
+            *c = a.load(Ordering::Relaxed);
+        }
+        DurationHistogram {
+            counts,
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The counters of the maintenance tasks of a cache.
+#[derive(Default)]
+pub(crate) struct MaintenanceCounters {
+    runs: AtomicU64,
+    run_duration: AtomicDurationHistogram,
+    reads_applied: AtomicU64,
+    writes_applied: AtomicU64,
+    entries_expired: AtomicU64,
+    entries_evicted: AtomicU64,
+    entries_invalidated: AtomicU64,
+    read_ops_dropped: AtomicU64,
+    write_op_retries: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+/// The number of the operations and entries processed by each phase of a
+/// maintenance run.
+#[derive(Default)]
+pub(crate) struct PhaseCounts {
+    pub(crate) reads_applied: u64,
+    pub(crate) writes_applied: u64,
+    pub(crate) entries_expired: u64,
+    pub(crate) entries_evicted: u64,
+    pub(crate) entries_invalidated: u64,
+}
+
+impl MaintenanceCounters {
+    pub(crate) fn record_run(&self, duration: Duration, phases: &PhaseCounts, timed_out: bool) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.run_duration.record(duration);
+        self.record_phases(phases);
+        if timed_out {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the entries removed outside of a full maintenance run, for example,
+    /// by the expiration timers.
+    pub(crate) fn record_phases(&self, phases: &PhaseCounts) {
+        let add = |counter: &AtomicU64, n: u64| {
+            if n > 0 {
+                counter.fetch_add(n, Ordering::Relaxed);
+            }
+        };
+        add(&self.reads_applied, phases.reads_applied);
+        add(&self.writes_applied, phases.writes_applied);
+        add(&self.entries_expired, phases.entries_expired);
+        add(&self.entries_evicted, phases.entries_evicted);
+        add(&self.entries_invalidated, phases.entries_invalidated);
+    }
+
+    pub(crate) fn record_read_op_dropped(&self) {
+        self.read_ops_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_write_op_retry(&self) {
+        self.write_op_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> MaintenanceStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MaintenanceStats {
+            runs: load(&self.runs),
+            run_duration: self.run_duration.snapshot(),
+            reads_applied: load(&self.reads_applied),
+            writes_applied: load(&self.writes_applied),
+            entries_expired: load(&self.entries_expired),
+            entries_evicted: load(&self.entries_evicted),
+            entries_invalidated: load(&self.entries_invalidated),
+            read_ops_dropped: load(&self.read_ops_dropped),
+            write_op_retries: load(&self.write_op_retries),
+            timeouts: load(&self.timeouts),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::mem;
+
+use crate::cht::segment::MemoryUsage;
+
+/// Reports the heap memory owned by a key or a value.
+///
+/// Implement this trait for the key and value types to include their heap memory
+/// in the [`MemoryReport`][memory-report] returned by the
+/// `memory_usage_with_heap_size` method of a cache.
+///
+/// [memory-report]: ./struct.MemoryReport.html
+///
+/// # Example
+///
+/// ```rust
+/// use moka2::HeapSize;
+///
+/// struct Record {
+///     name: String,
+///     tags: Vec<String>,
+/// }
+///
+/// impl HeapSize for Record {
+///     fn heap_size(&self) -> usize {
+///         self.name.heap_size() + self.tags.heap_size()
+///     }
+/// }
+/// ```
+pub trait HeapSize {
+    /// Returns the number of bytes allocated on the heap by this value. It should
+    /// not include `size_of::<Self>()`, which is already counted by the cache.
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! impl_heap_size_for_inline_types {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_heap_size_for_inline_types!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    &'static str
+);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Box<str> {
+    fn heap_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<[T]> {
+    fn heap_size(&self) -> usize {
+        mem::size_of_val(&**self) + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_size)
+    }
+}
+
+/// An approximate breakdown of the memory used by a cache, in bytes.
+///
+/// The numbers are estimated from the sizes of the internal data structures and
+/// the number of their elements, so they do not include the overhead of the
+/// memory allocator. The heap memory owned by the keys and values is only included
+/// if the report was taken by the `memory_usage_with_heap_size` method.
+///
+/// Use the `memory_usage` method of [`sync::Cache`][sync-cache] or
+/// [`future::Cache`][future-cache] to get a report.
+///
+/// [sync-cache]: ./sync/struct.Cache.html#method.memory_usage
+/// [future-cache]: ./future/struct.Cache.html#method.memory_usage
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub(crate) hash_table: u64,
+    pub(crate) retired_hash_tables: u64,
+    pub(crate) entries: u64,
+    pub(crate) deque_nodes: u64,
+    pub(crate) timer_nodes: u64,
+    pub(crate) frequency_sketch: u64,
+    pub(crate) op_channels: u64,
+    pub(crate) waiters: u64,
+    pub(crate) key_locks: u64,
+    pub(crate) key_value_heap: Option<u64>,
+}
+
+impl MemoryReport {
+    /// Returns the sum of all the numbers in this report.
+    pub fn total(&self) -> u64 {
+        self.hash_table
+            + self.retired_hash_tables
+            + self.entries
+            + self.deque_nodes
+            + self.timer_nodes
+            + self.frequency_sketch
+            + self.op_channels
+            + self.waiters
+            + self.key_locks
+            + self.key_value_heap.unwrap_or_default()
+    }
+
+    /// Returns the bytes used by the bucket arrays and the buckets of the
+    /// concurrent hash table holding the cache entries.
+    pub fn hash_table(&self) -> u64 {
+        self.hash_table
+    }
+
+    /// Returns the bytes used by the bucket arrays of the internal hash tables that
+    /// have been replaced by resizing, and are awaiting reclamation by the epoch
+    /// based garbage collector.
+    pub fn retired_hash_tables(&self) -> u64 {
+        self.retired_hash_tables
+    }
+
+    /// Returns the bytes used by the cache entries, including the keys and values
+    /// themselves (`size_of::<K>()` and `size_of::<V>()`) and their metadata.
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// Returns the bytes used by the nodes of the access-order and write-order
+    /// queues.
+    pub fn deque_nodes(&self) -> u64 {
+        self.deque_nodes
+    }
+
+    /// Returns the bytes used by the buckets and nodes of the hierarchical timer
+    /// wheel for per-entry expiration.
+    pub fn timer_nodes(&self) -> u64 {
+        self.timer_nodes
+    }
+
+    /// Returns the bytes used by the table of the frequency sketch, the historic
+    /// popularity estimator of the TinyLFU policy.
+    pub fn frequency_sketch(&self) -> u64 {
+        self.frequency_sketch
+    }
+
+    /// Returns the bytes used by the channels buffering the recorded reads and
+    /// writes until the maintenance tasks apply them.
+    pub fn op_channels(&self) -> u64 {
+        self.op_channels
+    }
+
+    /// Returns the bytes used by the map of the in-flight `get_with` family calls.
+    pub fn waiters(&self) -> u64 {
+        self.waiters
+    }
+
+    /// Returns the bytes used by the per-key locks of the eviction listener.
+    pub fn key_locks(&self) -> u64 {
+        self.key_locks
+    }
+
+    /// Returns the heap memory owned by the keys and values reported by their
+    /// [`HeapSize`][heap-size] implementations, or `None` if the report was not
+    /// taken by the `memory_usage_with_heap_size` method.
+    ///
+    /// [heap-size]: ./trait.HeapSize.html
+    pub fn key_value_heap(&self) -> Option<u64> {
+        self.key_value_heap
+    }
+
+    /// Returns the bytes of a hash table and the allocations owned by its entries,
+    /// and adds its retired bucket arrays to `retired_hash_tables`.
+    pub(crate) fn add_hash_table(&mut self, usage: &MemoryUsage, bytes_per_entry: usize) -> u64 {
+        self.retired_hash_tables += usage.retired_bucket_arrays as u64;
+        (usage.bucket_arrays + usage.buckets + usage.len * bytes_per_entry) as u64
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.hash_table += other.hash_table;
+        self.retired_hash_tables += other.retired_hash_tables;
+        self.entries += other.entries;
+        self.deque_nodes += other.deque_nodes;
+        self.timer_nodes += other.timer_nodes;
+        self.frequency_sketch += other.frequency_sketch;
+        self.op_channels += other.op_channels;
+        self.waiters += other.waiters;
+        self.key_locks += other.key_locks;
+        self.key_value_heap = match (self.key_value_heap, other.key_value_heap) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// Returns the size of the heap allocation of a `std::sync::Arc<T>`, which has
+/// the strong and weak counts.
+pub(crate) fn arc_alloc_size<T>() -> usize {
+    mem::size_of::<usize>() * 2 + mem::size_of::<T>()
+}
+
+/// Returns the size of the heap allocation of a `triomphe::Arc<T>`, which has no
+/// weak count.
+pub(crate) fn trio_arc_alloc_size<T>() -> usize {
+    mem::size_of::<usize>() + mem::size_of::<T>()
+}
+
+/// Returns the size of the slots of a bounded crossbeam channel.
+pub(crate) fn channel_alloc_size<T>(capacity: Option<usize>) -> usize {
+    capacity.unwrap_or_default() * (mem::size_of::<usize>() + mem::size_of::<T>())
+}
+
+/// Returns the size of the heap allocations of a cache entry: the key, the
+/// `ValueEntry` (holding the value), the `EntryInfo` and the `DeqNodes`.
+pub(crate) fn entry_alloc_size<K, V>() -> usize {
+    use super::concurrent::{entry_info::EntryInfo, DeqNodes, ValueEntry};
+
+    arc_alloc_size::<K>()
+        + trio_arc_alloc_size::<ValueEntry<K, V>>()
+        + trio_arc_alloc_size::<EntryInfo<K>>()
+        + trio_arc_alloc_size::<parking_lot::Mutex<DeqNodes<K>>>()
+}
@@ -0,0 +1,141 @@
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+/// The number of finished (completed or cancelled) predicates whose status is
+/// remembered by a cache.
+const MAX_FINISHED_PREDICATES: usize = 1024;
+
+/// The status of a predicate registered by the `invalidate_entries_if` method of a
+/// cache.
+///
+/// Use `predicate_status` method of [`sync::Cache`][sync-cache] or
+/// [`future::Cache`][future-cache] to get the status.
+///
+/// The `scanned` count is the number of cached entries that were checked by the
+/// predicate while the cache was scanning for it, and the `removed` count is the
+/// number of entries that were invalidated by the predicate. Note that an entry can
+/// be checked by more than one predicate, but it is counted as removed only by the
+/// first predicate that returned `true` on it.
+///
+/// [sync-cache]: ./sync/struct.Cache.html#method.predicate_status
+/// [future-cache]: ./future/struct.Cache.html#method.predicate_status
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PredicateStatus {
+    /// The predicate has been registered, but the cache has not started scanning
+    /// the entries for it yet.
+    Pending,
+    /// The cache is scanning the entries for the predicate.
+    InProgress { scanned: u64, removed: u64 },
+    /// The cache has scanned all entries inserted before the predicate was
+    /// registered. The predicate is no longer applied to the entries.
+    Completed { scanned: u64, removed: u64 },
+    /// The predicate was cancelled by the `cancel_predicate` method before the
+    /// scan was completed. The entries that have already been invalidated are not
+    /// restored.
+    Cancelled { scanned: u64, removed: u64 },
+}
+
+impl PredicateStatus {
+    /// Returns `true` if the predicate has been completed or cancelled.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed { .. } | Self::Cancelled { .. })
+    }
+}
+
+const PENDING: u8 = 0;
+const IN_PROGRESS: u8 = 1;
+const COMPLETED: u8 = 2;
+const CANCELLED: u8 = 3;
+
+/// Tracks the progress of a registered predicate. Shared between the predicate
+/// registry, the scan context and the record of finished predicates.
+#[derive(Default)]
+pub(crate) struct PredicateProgress {
+    state: AtomicU8,
+    scanned: AtomicU64,
+    removed: AtomicU64,
+}
+
+impl PredicateProgress {
+    pub(crate) fn status(&self) -> PredicateStatus {
+        let scanned = self.scanned.load(Ordering::Acquire);
+        let removed = self.removed.load(Ordering::Acquire);
+        match self.state.load(Ordering::Acquire) {
+            PENDING => PredicateStatus::Pending,
+            IN_PROGRESS => PredicateStatus::InProgress { scanned, removed },
+            COMPLETED => PredicateStatus::Completed { scanned, removed },
+            CANCELLED => PredicateStatus::Cancelled { scanned, removed },
+            s => unreachable!("Invalid predicate state: {s}"),
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::Acquire) == CANCELLED
+    }
+
+    pub(crate) fn start(&self) {
+        let _ = self.transit(PENDING, IN_PROGRESS);
+    }
+
+    pub(crate) fn increment_scanned(&self) {
+        self.scanned.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn increment_removed(&self) {
+        self.removed.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Marks the predicate as completed. Returns `false` if it has already been
+    /// finished.
+    pub(crate) fn complete(&self) -> bool {
+        self.finish(COMPLETED)
+    }
+
+    /// Marks the predicate as cancelled. Returns `false` if it has already been
+    /// finished.
+    pub(crate) fn cancel(&self) -> bool {
+        self.finish(CANCELLED)
+    }
+
+    fn finish(&self, new_state: u8) -> bool {
+        self.transit(PENDING, new_state) || self.transit(IN_PROGRESS, new_state)
+    }
+
+    fn transit(&self, current: u8, new: u8) -> bool {
+        self.state
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+/// Remembers the status of the most recently finished predicates, so that they can
+/// be queried after the predicates have been removed from the registry.
+#[derive(Default)]
+pub(crate) struct FinishedPredicates {
+    records: Mutex<VecDeque<(String, Arc<PredicateProgress>)>>,
+}
+
+impl FinishedPredicates {
+    pub(crate) fn record(&self, id: &str, progress: &Arc<PredicateProgress>) {
+        let mut records = self.records.lock();
+        if records.len() >= MAX_FINISHED_PREDICATES {
+            records.pop_front();
+        }
+        records.push_back((id.to_string(), Arc::clone(progress)));
+    }
+
+    pub(crate) fn status(&self, id: &str) -> Option<PredicateStatus> {
+        self.records
+            .lock()
+            .iter()
+            .rev()
+            .find(|(i, _)| i == id)
+            .map(|(_, p)| p.status())
+    }
+}
@@ -0,0 +1,152 @@
+use super::concurrent::{entry_info::EntryInfo, ValueEntry};
+
+use parking_lot::Mutex;
+use smallvec::SmallVec;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::Arc,
+};
+use triomphe::Arc as TrioArc;
+
+/// A tag attached to cached entries by the tag extractor closure. Entries sharing
+/// a tag can be invalidated together by the `invalidate_tag` method of a cache.
+pub type Tag = Arc<str>;
+
+/// The tags returned by the tag extractor closure.
+///
+/// The closure can build it from an iterator of [`Tag`](./type.Tag.html)s with
+/// `collect`, or from a `Vec<Tag>` with `into`.
+pub type Tags = SmallVec<[Tag; 4]>;
+
+pub(crate) type TagExtractor<K, V> = Arc<dyn Fn(&K, &V) -> Tags + Send + Sync + 'static>;
+
+/// A secondary index from tags to the keys of the entries having the tags.
+///
+/// An entry is added to the index when it is inserted or updated, so the index
+/// also contains the entries whose write operations are still pending. Until the
+/// cache applies the pending write operations, a key may also be indexed under
+/// the tags of its previous values. Therefore, the callers must check the tags of
+/// the current value of a key returned by [`keys`](#method.keys).
+pub(crate) struct TagIndex<K, V> {
+    extractor: TagExtractor<K, V>,
+    inner: Mutex<IndexInner<K>>,
+}
+
+struct IndexInner<K> {
+    keys_by_tag: HashMap<Tag, HashSet<Arc<K>>>,
+    tags_by_key: HashMap<Arc<K>, (TrioArc<EntryInfo<K>>, Tags)>,
+}
+
+impl<K, V> TagIndex<K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(extractor: TagExtractor<K, V>) -> Self {
+        Self {
+            extractor,
+            inner: Mutex::new(IndexInner {
+                keys_by_tag: HashMap::default(),
+                tags_by_key: HashMap::default(),
+            }),
+        }
+    }
+
+    /// Returns `true` if the extractor attaches the tag to the key and value.
+    pub(crate) fn has_tag(&self, key: &K, value: &V, tag: &str) -> bool {
+        (self.extractor)(key, value).iter().any(|t| &**t == tag)
+    }
+
+    /// Returns the keys indexed under the tag.
+    pub(crate) fn keys(&self, tag: &str) -> Vec<Arc<K>> {
+        self.inner
+            .lock()
+            .keys_by_tag
+            .get(tag)
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds the key of the inserted or updated entry under the tags of its value,
+    /// keeping the tags of the previous values of the same key. Called before the
+    /// entry is admitted to the cache policies.
+    pub(crate) fn add(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        let info = entry.entry_info();
+        let key = &info.key_hash().key;
+        let new_tags = (self.extractor)(key, &entry.value);
+
+        let mut inner = self.inner.lock();
+        for tag in &new_tags {
+            inner
+                .keys_by_tag
+                .entry(Arc::clone(tag))
+                .or_default()
+                .insert(Arc::clone(key));
+        }
+        let (indexed, tags) = inner
+            .tags_by_key
+            .entry(Arc::clone(key))
+            .or_insert_with(|| (TrioArc::clone(info), Tags::new()));
+        *indexed = TrioArc::clone(info);
+        for tag in new_tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    /// Indexes the entry under the tags of its current value, replacing the tags
+    /// of the previous value of the same key.
+    pub(crate) fn index(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        let info = entry.entry_info();
+        let key = &info.key_hash().key;
+        let mut tags = (self.extractor)(key, &entry.value);
+        tags.sort_unstable();
+        tags.dedup();
+
+        let mut inner = self.inner.lock();
+        inner.remove(key);
+        for tag in &tags {
+            inner
+                .keys_by_tag
+                .entry(Arc::clone(tag))
+                .or_default()
+                .insert(Arc::clone(key));
+        }
+        inner
+            .tags_by_key
+            .insert(Arc::clone(key), (TrioArc::clone(info), tags));
+    }
+
+    /// Removes the entry from the index. Does nothing if the key is now indexed
+    /// for a different entry.
+    pub(crate) fn unindex(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        let info = entry.entry_info();
+        let key = &info.key_hash().key;
+
+        let mut inner = self.inner.lock();
+        match inner.tags_by_key.get(key) {
+            Some((indexed, _)) if TrioArc::ptr_eq(indexed, info) => inner.remove(key),
+            _ => (),
+        }
+    }
+}
+
+impl<K> IndexInner<K>
+where
+    K: Hash + Eq,
+{
+    fn remove(&mut self, key: &Arc<K>) {
+        let Some((_, tags)) = self.tags_by_key.remove(key) else {
+            return;
+        };
+        for tag in tags {
+            if let Some(keys) = self.keys_by_tag.get_mut(&tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.keys_by_tag.remove(&tag);
+                }
+            }
+        }
+    }
+}
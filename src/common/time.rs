@@ -1,14 +1,83 @@
-use std::time::Duration;
+use crate::clock::CacheClock;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant as StdInstant, SystemTime},
+};
 
 #[cfg_attr(feature = "quanta", path = "time/clock_quanta.rs")]
 #[cfg_attr(not(feature = "quanta"), path = "time/clock_compat.rs")]
 pub(crate) mod clock;
 
-pub(crate) use clock::Clock;
-
 #[cfg(test)]
 pub(crate) use clock::Mock;
 
+/// The expiration clock of a cache.
+pub(crate) enum Clock {
+    /// The clock from the `clock` module. Used only by the tests.
+    #[cfg(test)]
+    Default(clock::Clock),
+    /// A clock set by the `clock` method of a cache builder.
+    Custom {
+        clock: Arc<dyn CacheClock>,
+        /// The time (`clock::Instant`) when this `Clock` was created.
+        origin: clock::Instant,
+        /// The time of the custom clock when this `Clock` was created.
+        origin_std: StdInstant,
+    },
+}
+
+impl Clock {
+    pub(crate) fn custom(clock: Arc<dyn CacheClock>) -> Self {
+        let origin_std = clock.now();
+        Self::Custom {
+            clock,
+            origin: clock::Instant::now(),
+            origin_std,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn mock() -> (Self, Arc<Mock>) {
+        let (clock, mock) = clock::Clock::mock();
+        (Self::Default(clock), mock)
+    }
+
+    pub(crate) fn now(&self) -> clock::Instant {
+        match self {
+            #[cfg(test)]
+            Self::Default(clock) => clock.now(),
+            // The custom clock cannot go back before its origin.
+            Self::Custom {
+                clock,
+                origin,
+                origin_std,
+            } => {
+                let elapsed = clock.now().saturating_duration_since(*origin_std);
+                origin.checked_add(elapsed).unwrap_or(*origin)
+            }
+        }
+    }
+
+    /// Returns the current time of this clock as a `std::time::Instant`.
+    pub(crate) fn std_now(&self) -> StdInstant {
+        match self {
+            #[cfg(test)]
+            Self::Default(_) => StdInstant::now(),
+            Self::Custom { clock, .. } => clock.now(),
+        }
+    }
+
+    /// Returns the current wall-clock time of this clock.
+    pub(crate) fn system_now(&self) -> SystemTime {
+        match self {
+            #[cfg(test)]
+            Self::Default(_) => SystemTime::now(),
+            Self::Custom { clock, .. } => clock.system_now(),
+        }
+    }
+}
+
 /// a wrapper type over Instant to force checked additions and prevent
 /// unintentional overflow. The type preserve the Copy semantics for the wrapped
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
@@ -126,6 +126,14 @@ impl<K> TimerNode<K> {
         }
     }
 
+    fn set_timer_node_in_deq_nodes(&self, node: NonNull<DeqNode<TimerNode<K>>>) {
+        if let Self::Entry { deq_nodes, .. } = &self {
+            deq_nodes.lock().set_timer_node(Some(node));
+        } else {
+            unreachable!();
+        }
+    }
+
     fn unset_timer_node_in_deq_nodes(&self) {
         if let Self::Entry { deq_nodes, .. } = &self {
             deq_nodes.lock().set_timer_node(None);
@@ -186,6 +194,19 @@ impl<K> TimerWheel<K> {
         !self.wheels.is_empty()
     }
 
+    /// Returns the number of bytes allocated for the buckets and the nodes,
+    /// including the sentinel nodes.
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.wheels
+            .iter()
+            .flat_map(|wheel| wheel.iter())
+            .map(|bucket| {
+                std::mem::size_of::<Bucket<K>>()
+                    + bucket.len() * std::mem::size_of::<DeqNode<TimerNode<K>>>()
+            })
+            .sum()
+    }
+
     pub(crate) fn enable(&mut self) {
         assert!(!self.is_enabled());
 
@@ -255,6 +276,21 @@ impl<K> TimerWheel<K> {
         }
     }
 
+    /// Schedules an expired timer event, returned by `advance`, again for the
+    /// current tick. The next `advance` to a later tick will return it as expired
+    /// again.
+    pub(crate) fn schedule_expired(&mut self, mut node: Box<DeqNode<TimerNode<K>>>) {
+        debug_assert!(self.is_enabled());
+
+        let (level, index) = self.bucket_indices(self.current);
+        node.element.set_position(level, index);
+        let node_p = self.wheels[level][index].push_back(node);
+        // SAFETY: The node is owned by this timer wheel, and we have `&mut self`.
+        unsafe { node_p.as_ref() }
+            .element
+            .set_timer_node_in_deq_nodes(node_p);
+    }
+
     /// Reschedules an active timer event for the node.
     pub(crate) fn reschedule(
         &mut self,
@@ -308,6 +344,38 @@ impl<K> TimerWheel<K> {
         TimerEventsIter::new(self, previous_time, current_time)
     }
 
+    /// Returns the earliest time when advancing this timer wheel may fire a timer
+    /// event, or `None` if there is no timer event.
+    ///
+    /// The time is the start of the tick of the earliest non-empty bucket, so it may
+    /// be earlier than the actual expiration time of the events in the bucket.
+    /// The events in the overflow queue are not considered.
+    pub(crate) fn next_expiration_time(&self) -> Option<Instant> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let current_nanos = self.time_nanos(self.current);
+        let mut earliest_nanos = None;
+        for level in 0..=NUM_LEVELS {
+            let ticks = current_nanos >> SHIFT[level];
+            let count = BUCKET_COUNTS[level];
+            // A bucket has a sentinel node even if it is empty.
+            let offset = (0..count).find(|i| {
+                let index = (ticks + i) & (count - 1);
+                self.wheels[level][index as usize].len() > 1
+            });
+            if let Some(offset) = offset {
+                // The bucket for the current tick will be processed when the timer
+                // wheel is advanced to the next tick.
+                let nanos = (ticks + offset.max(1)) << SHIFT[level];
+                earliest_nanos = Some(earliest_nanos.map_or(nanos, |e: u64| e.min(nanos)));
+            }
+        }
+
+        earliest_nanos.and_then(|nanos| self.origin.checked_add(Duration::from_nanos(nanos)))
+    }
+
     /// Returns a pointer to the timer event (cache entry) at the front of the queue.
     /// Returns `None` if the front node is a sentinel.
     fn pop_timer_node(&mut self, level: usize, index: usize) -> Option<Box<DeqNode<TimerNode<K>>>> {
@@ -560,10 +628,11 @@ mod tests {
 
     use super::{TimerEvent, TimerWheel, SPANS};
     use crate::common::{
-        concurrent::{entry_info::EntryInfo, KeyHash},
+        concurrent::{entry_info::EntryInfo, DeqNodes, KeyHash},
         time::{CheckedTimeOps, Clock, Instant, Mock},
     };
 
+    use parking_lot::Mutex;
     use triomphe::Arc as TrioArc;
 
     #[test]
@@ -793,6 +862,84 @@ mod tests {
         drop(expired_entries);
     }
 
+    #[test]
+    fn test_schedule_expired() {
+        let (clock, mock) = Clock::mock();
+        let now = advance_clock(&clock, &mock, s2d(10));
+
+        let mut timer = TimerWheel::<u32>::new(now);
+        timer.enable();
+
+        let key_hash = KeyHash::new(Arc::new(1), 1);
+        let entry_info = TrioArc::new(EntryInfo::new(key_hash, now, 0));
+        entry_info.set_expiration_time(Some(now.checked_add(s2d(1)).unwrap()));
+        let deq_nodes: TrioArc<Mutex<DeqNodes<u32>>> = Default::default();
+        let timer_node = timer.schedule(entry_info, TrioArc::clone(&deq_nodes));
+        deq_nodes.lock().set_timer_node(timer_node);
+
+        let take_expired = |timer: &mut TimerWheel<u32>, now| {
+            let mut events = timer.advance(now).filter_map(|event| match event {
+                TimerEvent::Expired(node) => Some(node),
+                _ => None,
+            });
+            let node = events.next();
+            assert!(events.next().is_none());
+            node
+        };
+
+        let now = advance_clock(&clock, &mock, s2d(2));
+        let node = take_expired(&mut timer, now).expect("Not expired");
+        assert!(deq_nodes.lock().timer_node().is_none());
+
+        // Schedule the expired node again. It is returned again by the next advance
+        // to a later tick.
+        timer.schedule_expired(node);
+        assert!(deq_nodes.lock().timer_node().is_some());
+        assert!(take_expired(&mut timer, now).is_none());
+
+        let now = advance_clock(&clock, &mock, s2d(2));
+        let node = take_expired(&mut timer, now).expect("Not expired");
+        assert_eq!(*node.element.entry_info().key_hash().key, 1);
+        assert!(deq_nodes.lock().timer_node().is_none());
+    }
+
+    #[test]
+    fn test_next_expiration_time() {
+        fn schedule_timer(timer: &mut TimerWheel<u32>, key: u32, now: Instant, ttl: Duration) {
+            let key_hash = KeyHash::new(Arc::new(key), key as u64);
+            let entry_info = TrioArc::new(EntryInfo::new(key_hash, now, 0));
+            entry_info.set_expiration_time(Some(now.checked_add(ttl).unwrap()));
+            let deq_nodes = Default::default();
+            let timer_node = timer.schedule(entry_info, TrioArc::clone(&deq_nodes));
+            deq_nodes.lock().set_timer_node(timer_node);
+        }
+
+        let (clock, mock) = Clock::mock();
+        let now = advance_clock(&clock, &mock, s2d(10));
+
+        let mut timer = TimerWheel::<u32>::new(now);
+        timer.enable();
+        assert_eq!(timer.next_expiration_time(), None);
+
+        // A timer in the level 1 wheel (roughly minutes).
+        schedule_timer(&mut timer, 1, now, s2d(130));
+        let t = timer.next_expiration_time().expect("No expiration time");
+        assert!(t > now.checked_add(s2d(60)).unwrap());
+        assert!(t <= now.checked_add(s2d(130)).unwrap());
+
+        // A timer in the level 0 wheel (roughly seconds).
+        schedule_timer(&mut timer, 2, now, s2d(5));
+        let t = timer.next_expiration_time().expect("No expiration time");
+        assert!(t > now.checked_add(s2d(3)).unwrap());
+        assert!(t <= now.checked_add(s2d(5)).unwrap());
+
+        let now = advance_clock(&clock, &mock, s2d(6));
+        let expired = timer.advance(now).count();
+        assert_eq!(expired, 1);
+        let t = timer.next_expiration_time().expect("No expiration time");
+        assert!(t <= now.checked_add(s2d(124)).unwrap());
+    }
+
     //
     // Utility functions
     //
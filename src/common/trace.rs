@@ -0,0 +1,184 @@
+//! Macros to emit `tracing` spans. They expand to nothing unless the `tracing`
+//! feature is enabled.
+
+/// Creates a span with the given level and enters it. The span is exited when
+/// the returned guard is dropped.
+///
+/// Do not hold the guard across an `.await`; use `instrument!` instead.
+#[cfg(feature = "sync")]
+macro_rules! enter_span {
+    ($lvl:ident, $($args:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let entered = tracing::span!(tracing::Level::$lvl, $($args)+).entered();
+        #[cfg(not(feature = "tracing"))]
+        let entered = $crate::common::trace::NoSpan;
+        entered
+    }};
+}
+
+/// A placeholder of the guard returned by `enter_span!` when the `tracing`
+/// feature is disabled.
+#[cfg(all(feature = "sync", not(feature = "tracing")))]
+pub(crate) struct NoSpan;
+
+/// Wraps the given future so that it runs in a span with the given level.
+#[cfg(feature = "future")]
+macro_rules! instrument {
+    ($fut:expr, $lvl:ident, $($args:tt)+) => {{
+        let fut = $fut;
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(
+            fut,
+            tracing::span!(tracing::Level::$lvl, $($args)+),
+        );
+        fut
+    }};
+}
+
+#[cfg(feature = "sync")]
+pub(crate) use enter_span;
+#[cfg(feature = "future")]
+pub(crate) use instrument;
+
+/// Creates a span for a value load of the `get_with` family methods.
+#[cfg(feature = "tracing")]
+pub(crate) fn load_span(cache_name: Option<&str>, key_hash: u64) -> tracing::Span {
+    tracing::debug_span!("load", cache = cache_name.unwrap_or_default(), key_hash)
+}
+
+#[cfg(all(test, feature = "tracing", feature = "sync"))]
+mod tests {
+    use crate::sync::Cache;
+
+    use parking_lot::Mutex;
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    type Fields = HashMap<&'static str, String>;
+
+    /// The name and the fields of a span.
+    type SpanRecord = (&'static str, Fields);
+
+    /// A single threaded subscriber that records the spans and the messages of the
+    /// events.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        next_id: Arc<AtomicU64>,
+        spans: Arc<Mutex<HashMap<u64, SpanRecord>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+        events: Arc<Mutex<Vec<Fields>>>,
+    }
+
+    impl Recorder {
+        fn spans_named(&self, name: &str) -> Vec<HashMap<&'static str, String>> {
+            let spans = self.spans.lock();
+            let mut ids = spans
+                .iter()
+                .filter(|(_, (n, _))| *n == name)
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids.into_iter().map(|id| spans[&id].1.clone()).collect()
+        }
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<&'static str, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name(), format!("{value:?}"));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut fields = HashMap::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            self.spans
+                .lock()
+                .insert(id, (attrs.metadata().name(), fields));
+            span::Id::from_u64(id)
+        }
+
+        fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+            if let Some((_, fields)) = self.spans.lock().get_mut(&id.into_u64()) {
+                values.record(&mut FieldVisitor(fields));
+            }
+        }
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        /// Records the fields of the event and the name of the entered span.
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = HashMap::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            if let Some(id) = self.entered.lock().last() {
+                fields.insert("span", self.spans.lock()[id].0.to_string());
+            }
+            self.events.lock().push(fields);
+        }
+
+        fn enter(&self, span: &span::Id) {
+            self.entered.lock().push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &span::Id) {
+            self.entered.lock().pop();
+        }
+    }
+
+    #[test]
+    fn trace_loads_and_maintenance() {
+        let recorder = Recorder::default();
+        let cache = Cache::builder().name("traced").max_capacity(1).build();
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert_eq!(cache.get_with(1, || "a"), "a");
+            // This will not create a load span as the value exists.
+            assert_eq!(cache.get_with(1, || "b"), "a");
+            assert!(cache.try_get_with(2, || Err("error")).is_err());
+            cache.run_pending_tasks();
+        });
+
+        let loads = recorder.spans_named("load");
+        assert_eq!(loads.len(), 2);
+        assert_eq!(loads[0]["cache"], "traced");
+
+        let outcomes = recorder
+            .events
+            .lock()
+            .iter()
+            .filter(|fields| fields.get("span").map(String::as_str) == Some("load"))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0]["outcome"], "initialized");
+        assert_eq!(outcomes[0]["waiters"], "0");
+        assert!(outcomes[0].contains_key("elapsed"));
+        assert_eq!(outcomes[1]["outcome"], "init_error");
+
+        let runs = recorder.spans_named("run_pending_tasks");
+        assert!(!runs.is_empty());
+        assert_eq!(runs[0]["cache"], "traced");
+        assert!(!recorder.spans_named("apply_writes").is_empty());
+    }
+}
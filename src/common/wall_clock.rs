@@ -0,0 +1,150 @@
+use super::{
+    concurrent::entry_info::EntryInfo,
+    time::{CheckedTimeOps, Instant},
+};
+
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use triomphe::Arc as TrioArc;
+
+/// The difference between the elapsed wall-clock time and the elapsed cache time
+/// above which the wall clock is considered to have jumped.
+const JUMP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// The wall-clock (`SystemTime`) deadlines of the entries inserted by
+/// `insert_expiring_at`.
+///
+/// The deadlines are converted into the per-entry expiration times (`Instant`s of
+/// the cache) at insertion. When the wall clock jumps, the expiration times no
+/// longer match the deadlines, so they are converted again.
+pub(crate) struct WallClockDeadlines<K> {
+    inner: Mutex<DeadlinesInner<K>>,
+    is_empty: AtomicBool,
+}
+
+struct DeadlinesInner<K> {
+    deadlines: HashMap<Arc<K>, Deadline<K>>,
+    /// The wall-clock time and the cache time when the deadlines were converted
+    /// last time.
+    anchor: Option<(SystemTime, Instant)>,
+}
+
+struct Deadline<K> {
+    expires_at: SystemTime,
+    entry_info: TrioArc<EntryInfo<K>>,
+    /// The generation of the entry inserted with the deadline. If the entry has
+    /// been updated since then, the deadline no longer applies.
+    entry_gen: u16,
+}
+
+/// An entry whose expiration time has to be converted again from its deadline.
+pub(crate) struct Reanchored<K> {
+    pub(crate) entry_info: TrioArc<EntryInfo<K>>,
+    pub(crate) expires_at: SystemTime,
+}
+
+impl<K> Default for WallClockDeadlines<K> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(DeadlinesInner {
+                deadlines: HashMap::default(),
+                anchor: None,
+            }),
+            is_empty: AtomicBool::new(true),
+        }
+    }
+}
+
+impl<K> WallClockDeadlines<K>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn is_empty(&self) -> bool {
+        self.is_empty.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn register(
+        &self,
+        key: Arc<K>,
+        expires_at: SystemTime,
+        entry_info: &TrioArc<EntryInfo<K>>,
+        entry_gen: u16,
+        anchor: (SystemTime, Instant),
+    ) {
+        let mut inner = self.inner.lock();
+        let deadline = Deadline {
+            expires_at,
+            entry_info: TrioArc::clone(entry_info),
+            entry_gen,
+        };
+        inner.deadlines.insert(key, deadline);
+        inner.anchor.get_or_insert(anchor);
+        self.is_empty.store(false, Ordering::Release);
+    }
+
+    /// Removes the deadline of the removed entry, if any.
+    pub(crate) fn unregister(&self, entry_info: &TrioArc<EntryInfo<K>>) {
+        if self.is_empty() {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        let key = &entry_info.key_hash().key;
+        let is_same_entry = inner
+            .deadlines
+            .get(key)
+            .map_or(false, |d| TrioArc::ptr_eq(&d.entry_info, entry_info));
+        if is_same_entry {
+            inner.deadlines.remove(key);
+            self.is_empty
+                .store(inner.deadlines.is_empty(), Ordering::Release);
+        }
+    }
+
+    /// Checks whether the wall clock has jumped since the last check. If so,
+    /// returns the entries whose expiration times have to be converted again from
+    /// their deadlines, and drops the deadlines of the updated entries.
+    pub(crate) fn reanchor(&self, system_now: SystemTime, now: Instant) -> Vec<Reanchored<K>> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut inner = self.inner.lock();
+        let jumped = match inner.anchor.replace((system_now, now)) {
+            None => false,
+            Some((last_system, last)) => {
+                let elapsed = now.checked_duration_since(last).unwrap_or_default();
+                let diff = match system_now.duration_since(last_system) {
+                    Ok(wall_elapsed) if wall_elapsed >= elapsed => wall_elapsed - elapsed,
+                    Ok(wall_elapsed) => elapsed - wall_elapsed,
+                    Err(e) => e.duration() + elapsed,
+                };
+                diff > JUMP_THRESHOLD
+            }
+        };
+        if !jumped {
+            return Vec::new();
+        }
+
+        inner
+            .deadlines
+            .retain(|_, d| d.entry_info.entry_gen() == d.entry_gen);
+        self.is_empty
+            .store(inner.deadlines.is_empty(), Ordering::Release);
+        inner
+            .deadlines
+            .values()
+            .map(|d| Reanchored {
+                entry_info: TrioArc::clone(&d.entry_info),
+                expires_at: d.expires_at,
+            })
+            .collect()
+    }
+}
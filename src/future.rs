@@ -10,19 +10,28 @@ use std::{future::Future, hash::Hash, sync::Arc};
 use crate::common::{concurrent::WriteOp, time::Instant};
 
 mod base_cache;
+mod blocking;
 mod builder;
 mod cache;
 mod entry_selector;
 mod housekeeper;
 mod invalidator;
 mod key_lock;
+mod maintenance;
 mod notifier;
+mod segment;
+mod stream;
 mod value_initializer;
 
 pub use {
+    blocking::{BlockingOp, BlockingOwnedKeyEntrySelector, BlockingRefKeyEntrySelector},
     builder::CacheBuilder,
     cache::Cache,
     entry_selector::{OwnedKeyEntrySelector, RefKeyEntrySelector},
+    key_lock::KeyGuard,
+    maintenance::MaintenanceScheduler,
+    segment::SegmentedCache,
+    stream::{EntryStream, KeyStream},
 };
 
 /// The type of the unique ID to identify a predicate used by
@@ -75,6 +84,78 @@ where
     }
 }
 
+/// Iterator visiting all entries in a cache with their metadata in arbitrary
+/// order.
+///
+/// Call [`Cache::iter_with_metadata`](./struct.Cache.html#method.iter_with_metadata)
+/// method to obtain an `IterWithMetadata`.
+pub struct IterWithMetadata<'i, K, V>(crate::sync_base::iter::IterWithMetadata<'i, K, V>);
+
+impl<'i, K, V> IterWithMetadata<'i, K, V> {
+    pub(crate) fn new(inner: crate::sync_base::iter::IterWithMetadata<'i, K, V>) -> Self {
+        Self(inner)
+    }
+}
+
+impl<K, V> Iterator for IterWithMetadata<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V, crate::EntryMetadata);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// Iterator visiting all keys in a cache in arbitrary order.
+///
+/// Call [`Cache::keys`](./struct.Cache.html#method.keys) method to obtain a `Keys`.
+pub struct Keys<'i, K, V>(crate::sync_base::iter::Keys<'i, K, V>);
+
+impl<'i, K, V> Keys<'i, K, V> {
+    pub(crate) fn new(inner: crate::sync_base::iter::Keys<'i, K, V>) -> Self {
+        Self(inner)
+    }
+}
+
+impl<K, V> Iterator for Keys<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = Arc<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// Iterator visiting all values in a cache in arbitrary order.
+///
+/// Call [`Cache::values`](./struct.Cache.html#method.values) method to obtain a
+/// `Values`.
+pub struct Values<'i, K, V>(crate::sync_base::iter::Values<'i, K, V>);
+
+impl<'i, K, V> Values<'i, K, V> {
+    pub(crate) fn new(inner: crate::sync_base::iter::Values<'i, K, V>) -> Self {
+        Self(inner)
+    }
+}
+
+impl<K, V> Iterator for Values<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
 /// Operation that has been interrupted (stopped polling) by async cancellation.
 pub(crate) enum InterruptedOp<K, V> {
     CallEvictionListener {
@@ -148,3 +229,11 @@ impl<K, V> Drop for CancelGuard<'_, K, V> {
             .expect("Failed to send a pending op");
     }
 }
+
+/// A write op that has been applied to the hash table but not yet scheduled. The
+/// cancel guard holds a clone of the op until it is scheduled.
+struct PendingOp<'a, K, V> {
+    op: WriteOp<K, V>,
+    ts: Instant,
+    cancel_guard: CancelGuard<'a, K, V>,
+}
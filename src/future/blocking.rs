@@ -0,0 +1,535 @@
+use crate::{ops::compute, Entry};
+
+use super::{Cache, OwnedKeyEntrySelector, RefKeyEntrySelector};
+
+use std::{
+    borrow::Borrow,
+    future::Future,
+    hash::{BuildHasher, Hash},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// Provides blocking versions of the methods of a [`Cache`][cache-struct], so that
+/// the cache can be shared between asynchronous tasks and plain synchronous code.
+///
+/// `BlockingOp` is constructed from the [`blocking`][blocking-method] method on the
+/// cache. Each method drives the corresponding `async` method of the cache to
+/// completion on the current thread, so no async runtime is required. It can be
+/// called from any thread that is not driving an async runtime, e.g. a thread
+/// spawned by `std::thread::spawn` or the blocking thread pool of a runtime.
+///
+/// Do not call these methods from within an asynchronous context (e.g. from an
+/// `async fn` running on a runtime worker thread). They block the current thread
+/// until the operation completes, and may deadlock if the operation waits for a
+/// task scheduled on the same thread. Also, if the cache has an async eviction
+/// listener, its futures will be driven by the calling thread, so they must not
+/// depend on a runtime-specific feature such as a timer of the runtime.
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [blocking-method]: ./struct.Cache.html#method.blocking
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = "0.12", features = ["future"] }
+/// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+///
+/// use moka2::future::Cache;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cache: Cache<u32, String> = Cache::new(100);
+///     cache.insert(0, "zero".to_string()).await;
+///
+///     // Access the same cache from a synchronous plugin callback running on
+///     // a plain OS thread.
+///     let cache2 = cache.clone();
+///     std::thread::spawn(move || {
+///         let blocking = cache2.blocking();
+///         assert_eq!(blocking.get(&0), Some("zero".to_string()));
+///         let value = blocking.get_with(1, || "one".to_string());
+///         assert_eq!(value, "one");
+///     })
+///     .join()
+///     .unwrap();
+///
+///     assert_eq!(cache.get(&1).await, Some("one".to_string()));
+/// }
+/// ```
+pub struct BlockingOp<'a, K, V, S>(&'a Cache<K, V, S>);
+
+impl<'a, K, V, S> BlockingOp<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(cache: &'a Cache<K, V, S>) -> Self {
+        Self(cache)
+    }
+
+    /// Blocking [`get`](./struct.Cache.html#method.get)
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        block_on(self.0.get(key))
+    }
+
+    /// Blocking [`get_entry`](./struct.Cache.html#method.get_entry)
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        block_on(self.0.get_entry(key))
+    }
+
+    /// Blocking [`get_with`](./struct.Cache.html#method.get_with), taking a
+    /// synchronous `init` closure instead of a future.
+    ///
+    /// Concurrent calls on the same not-existing key are coalesced into one
+    /// evaluation of an `init` closure or future, whether the other callers use the
+    /// blocking or the async API.
+    pub fn get_with(&self, key: K, init: impl FnOnce() -> V) -> V {
+        block_on(self.0.get_with(key, async move { init() }))
+    }
+
+    /// Blocking [`get_with_by_ref`](./struct.Cache.html#method.get_with_by_ref),
+    /// taking a synchronous `init` closure instead of a future.
+    pub fn get_with_by_ref<Q>(&self, key: &Q, init: impl FnOnce() -> V) -> V
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        block_on(self.0.get_with_by_ref(key, async move { init() }))
+    }
+
+    /// Blocking [`optionally_get_with`](./struct.Cache.html#method.optionally_get_with),
+    /// taking a synchronous `init` closure instead of a future.
+    pub fn optionally_get_with(&self, key: K, init: impl FnOnce() -> Option<V>) -> Option<V> {
+        block_on(self.0.optionally_get_with(key, async move { init() }))
+    }
+
+    /// Blocking [`try_get_with`](./struct.Cache.html#method.try_get_with), taking a
+    /// synchronous `init` closure instead of a future.
+    pub fn try_get_with<E>(&self, key: K, init: impl FnOnce() -> Result<V, E>) -> Result<V, Arc<E>>
+    where
+        E: Send + Sync + 'static,
+    {
+        block_on(self.0.try_get_with(key, async move { init() }))
+    }
+
+    /// Blocking [`insert`](./struct.Cache.html#method.insert)
+    pub fn insert(&self, key: K, value: V) {
+        block_on(self.0.insert(key, value))
+    }
+
+    /// Blocking [`invalidate`](./struct.Cache.html#method.invalidate)
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        block_on(self.0.invalidate(key))
+    }
+
+    /// Blocking [`remove`](./struct.Cache.html#method.remove)
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        block_on(self.0.remove(key))
+    }
+
+    /// Blocking [`entry`](./struct.Cache.html#method.entry)
+    pub fn entry(&self, key: K) -> BlockingOwnedKeyEntrySelector<'a, K, V, S> {
+        BlockingOwnedKeyEntrySelector(self.0.entry(key))
+    }
+
+    /// Blocking [`entry_by_ref`](./struct.Cache.html#method.entry_by_ref)
+    pub fn entry_by_ref<Q>(&self, key: &'a Q) -> BlockingRefKeyEntrySelector<'a, K, Q, V, S>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        BlockingRefKeyEntrySelector(self.0.entry_by_ref(key))
+    }
+
+    /// Blocking [`run_pending_tasks`](./struct.Cache.html#method.run_pending_tasks)
+    pub fn run_pending_tasks(&self) {
+        block_on(self.0.run_pending_tasks())
+    }
+}
+
+/// Provides blocking versions of the methods of an
+/// [`OwnedKeyEntrySelector`][owned-key-entry-selector].
+///
+/// `BlockingOwnedKeyEntrySelector` is constructed from the
+/// [`BlockingOp::entry`][entry-method] method. The closures passed to its methods
+/// are synchronous versions of the futures taken by `OwnedKeyEntrySelector`.
+///
+/// [owned-key-entry-selector]: ./struct.OwnedKeyEntrySelector.html
+/// [entry-method]: ./struct.BlockingOp.html#method.entry
+pub struct BlockingOwnedKeyEntrySelector<'a, K, V, S>(OwnedKeyEntrySelector<'a, K, V, S>);
+
+impl<K, V, S> BlockingOwnedKeyEntrySelector<'_, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Blocking [`and_compute_with`](./struct.OwnedKeyEntrySelector.html#method.and_compute_with)
+    pub fn and_compute_with<F>(self, f: F) -> compute::CompResult<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> compute::Op<V>,
+    {
+        block_on(
+            self.0
+                .and_compute_with(|entry| std::future::ready(f(entry))),
+        )
+    }
+
+    /// Blocking [`and_upsert_with`](./struct.OwnedKeyEntrySelector.html#method.and_upsert_with)
+    pub fn and_upsert_with<F>(self, f: F) -> Entry<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> V,
+    {
+        block_on(self.0.and_upsert_with(|entry| std::future::ready(f(entry))))
+    }
+
+    /// Blocking [`or_default`](./struct.OwnedKeyEntrySelector.html#method.or_default)
+    pub fn or_default(self) -> Entry<K, V>
+    where
+        V: Default,
+    {
+        block_on(self.0.or_default())
+    }
+
+    /// Blocking [`or_insert`](./struct.OwnedKeyEntrySelector.html#method.or_insert)
+    pub fn or_insert(self, default: V) -> Entry<K, V> {
+        block_on(self.0.or_insert(default))
+    }
+
+    /// Blocking [`or_insert_with`](./struct.OwnedKeyEntrySelector.html#method.or_insert_with)
+    pub fn or_insert_with(self, init: impl FnOnce() -> V) -> Entry<K, V> {
+        block_on(self.0.or_insert_with(async move { init() }))
+    }
+
+    /// Blocking [`or_optionally_insert_with`](./struct.OwnedKeyEntrySelector.html#method.or_optionally_insert_with)
+    pub fn or_optionally_insert_with(
+        self,
+        init: impl FnOnce() -> Option<V>,
+    ) -> Option<Entry<K, V>> {
+        block_on(self.0.or_optionally_insert_with(async move { init() }))
+    }
+
+    /// Blocking [`or_try_insert_with`](./struct.OwnedKeyEntrySelector.html#method.or_try_insert_with)
+    pub fn or_try_insert_with<F, E>(self, init: F) -> Result<Entry<K, V>, Arc<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
+    {
+        block_on(self.0.or_try_insert_with(async move { init() }))
+    }
+}
+
+/// Provides blocking versions of the methods of a
+/// [`RefKeyEntrySelector`][ref-key-entry-selector].
+///
+/// `BlockingRefKeyEntrySelector` is constructed from the
+/// [`BlockingOp::entry_by_ref`][entry-by-ref-method] method. The closures passed to
+/// its methods are synchronous versions of the futures taken by
+/// `RefKeyEntrySelector`.
+///
+/// [ref-key-entry-selector]: ./struct.RefKeyEntrySelector.html
+/// [entry-by-ref-method]: ./struct.BlockingOp.html#method.entry_by_ref
+pub struct BlockingRefKeyEntrySelector<'a, K, Q, V, S>(RefKeyEntrySelector<'a, K, Q, V, S>)
+where
+    Q: ?Sized;
+
+impl<K, Q, V, S> BlockingRefKeyEntrySelector<'_, K, Q, V, S>
+where
+    K: Borrow<Q> + Hash + Eq + Send + Sync + 'static,
+    Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Blocking [`and_compute_with`](./struct.RefKeyEntrySelector.html#method.and_compute_with)
+    pub fn and_compute_with<F>(self, f: F) -> compute::CompResult<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> compute::Op<V>,
+    {
+        block_on(
+            self.0
+                .and_compute_with(|entry| std::future::ready(f(entry))),
+        )
+    }
+
+    /// Blocking [`and_upsert_with`](./struct.RefKeyEntrySelector.html#method.and_upsert_with)
+    pub fn and_upsert_with<F>(self, f: F) -> Entry<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> V,
+    {
+        block_on(self.0.and_upsert_with(|entry| std::future::ready(f(entry))))
+    }
+
+    /// Blocking [`or_default`](./struct.RefKeyEntrySelector.html#method.or_default)
+    pub fn or_default(self) -> Entry<K, V>
+    where
+        V: Default,
+    {
+        block_on(self.0.or_default())
+    }
+
+    /// Blocking [`or_insert`](./struct.RefKeyEntrySelector.html#method.or_insert)
+    pub fn or_insert(self, default: V) -> Entry<K, V> {
+        block_on(self.0.or_insert(default))
+    }
+
+    /// Blocking [`or_insert_with`](./struct.RefKeyEntrySelector.html#method.or_insert_with)
+    pub fn or_insert_with(self, init: impl FnOnce() -> V) -> Entry<K, V> {
+        block_on(self.0.or_insert_with(async move { init() }))
+    }
+
+    /// Blocking [`or_optionally_insert_with`](./struct.RefKeyEntrySelector.html#method.or_optionally_insert_with)
+    pub fn or_optionally_insert_with(
+        self,
+        init: impl FnOnce() -> Option<V>,
+    ) -> Option<Entry<K, V>> {
+        block_on(self.0.or_optionally_insert_with(async move { init() }))
+    }
+
+    /// Blocking [`or_try_insert_with`](./struct.RefKeyEntrySelector.html#method.or_try_insert_with)
+    pub fn or_try_insert_with<F, E>(self, init: F) -> Result<Entry<K, V>, Arc<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
+    {
+        block_on(self.0.or_try_insert_with(async move { init() }))
+    }
+}
+
+/// Wakes up the thread blocked in `block_on`.
+///
+/// The `woken` flag is owned by a single `block_on` call, so a wake-up is not lost
+/// even when a nested `block_on` (e.g. one called from an `init` closure) consumes
+/// the unpark token of the thread.
+struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Drives the future to completion on the current thread, parking the thread while
+/// the future is pending.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    futures_util::pin_mut!(fut);
+
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        woken: AtomicBool::new(false),
+    });
+    let waker = Waker::from(Arc::clone(&thread_waker));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        while !thread_waker.woken.swap(false, Ordering::Acquire) {
+            thread::park();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        future::{Cache, FutureExt},
+        notification::{ListenerFuture, RemovalCause},
+        ops::compute::{CompResult, Op},
+    };
+
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn basic_ops_without_runtime() {
+        let actual = Arc::new(Mutex::new(Vec::new()));
+
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().unwrap().push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        let cache = Cache::builder()
+            .max_capacity(100)
+            .async_eviction_listener(listener)
+            .build();
+        let blocking = cache.blocking();
+
+        blocking.insert("a", "alice");
+        blocking.insert("b", "bob");
+        assert_eq!(blocking.get(&"a"), Some("alice"));
+        assert_eq!(
+            blocking.get_entry(&"b").map(|e| e.into_value()),
+            Some("bob")
+        );
+
+        blocking.invalidate(&"a");
+        assert_eq!(blocking.get(&"a"), None);
+        assert_eq!(blocking.remove(&"b"), Some("bob"));
+        assert_eq!(blocking.remove(&"b"), None);
+
+        blocking.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(
+            *actual.lock().unwrap(),
+            vec![
+                (Arc::new("a"), "alice", RemovalCause::Explicit),
+                (Arc::new("b"), "bob", RemovalCause::Explicit),
+            ]
+        );
+    }
+
+    #[test]
+    fn get_with_family() {
+        let cache: Cache<u32, String> = Cache::new(100);
+        let blocking = cache.blocking();
+
+        assert_eq!(blocking.get_with(0, || "zero".to_string()), "zero");
+        assert_eq!(blocking.get_with(0, || unreachable!()), "zero");
+        assert_eq!(blocking.get_with_by_ref(&1, || "one".to_string()), "one");
+
+        assert_eq!(blocking.optionally_get_with(2, || None), None);
+        assert_eq!(
+            blocking.optionally_get_with(2, || Some("two".to_string())),
+            Some("two".to_string())
+        );
+
+        let err = blocking
+            .try_get_with(3, || Err::<String, _>("error"))
+            .unwrap_err();
+        assert_eq!(*err, "error");
+        assert_eq!(
+            blocking.try_get_with(3, || Ok::<_, &str>("three".to_string())),
+            Ok("three".to_string())
+        );
+        assert_eq!(blocking.get(&3), Some("three".to_string()));
+    }
+
+    #[test]
+    fn entry_api() {
+        let cache: Cache<String, u64> = Cache::new(100);
+        let blocking = cache.blocking();
+        let key = "counter".to_string();
+
+        let entry = blocking.entry(key.clone()).or_insert_with(|| 1);
+        assert!(entry.is_fresh());
+        assert_eq!(entry.into_value(), 1);
+
+        let entry = blocking.entry_by_ref(&key).or_insert(5);
+        assert!(!entry.is_fresh());
+        assert_eq!(entry.into_value(), 1);
+
+        let entry = blocking
+            .entry_by_ref(&key)
+            .and_upsert_with(|e| e.map_or(1, |e| e.into_value() + 1));
+        assert_eq!(entry.into_value(), 2);
+
+        let result = blocking.entry(key.clone()).and_compute_with(|_| Op::Remove);
+        assert!(matches!(result, CompResult::Removed(e) if *e.value() == 2));
+
+        let entry = blocking.entry(key.clone()).or_default();
+        assert_eq!(entry.into_value(), 0);
+
+        assert!(blocking
+            .entry("none".to_string())
+            .or_optionally_insert_with(|| None)
+            .is_none());
+        assert!(blocking
+            .entry_by_ref("err")
+            .or_try_insert_with(|| Err("error"))
+            .is_err());
+    }
+
+    // Blocking and async callers of `get_with` on the same key are coalesced into
+    // one evaluation of the `init` closure or future.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn get_with_mixed_with_async_callers() {
+        let cache: Cache<u32, String> = Cache::new(100);
+        let init_count = Arc::new(AtomicU32::new(0));
+
+        let handles = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let count = Arc::clone(&init_count);
+                thread::spawn(move || {
+                    cache.blocking().get_with(0, || {
+                        count.fetch_add(1, Ordering::AcqRel);
+                        thread::sleep(Duration::from_millis(200));
+                        "blocking".to_string()
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let async_value = cache
+            .get_with(0, async {
+                init_count.fetch_add(1, Ordering::AcqRel);
+                "async".to_string()
+            })
+            .await;
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), async_value);
+        }
+        assert_eq!(init_count.load(Ordering::Acquire), 1);
+        assert!(cache.is_waiter_map_empty());
+    }
+
+    // A nested `block_on` must not swallow the wake-up for the outer one.
+    #[test]
+    fn nested_blocking_calls() {
+        let cache: Cache<u32, u32> = Cache::new(100);
+        let cache2 = cache.clone();
+
+        let value = cache.blocking().get_with(0, || {
+            cache2.blocking().insert(1, 10);
+            cache2.blocking().get_with(2, || 20) + 1
+        });
+        assert_eq!(value, 21);
+        assert_eq!(cache.blocking().get(&1), Some(10));
+    }
+}
@@ -1,9 +1,10 @@
-use super::{Cache, FutureExt};
+use super::{Cache, FutureExt, MaintenanceScheduler, SegmentedCache};
 use crate::{
-    common::{builder_utils, concurrent::Weigher, HousekeeperConfig},
+    clock::CacheClock,
+    common::{builder_utils, concurrent::Weigher, tag::TagExtractor, HousekeeperConfig},
     notification::{AsyncEvictionListener, ListenerFuture, RemovalCause},
-    policy::{EvictionPolicy, ExpirationPolicy},
-    Expiry,
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    Expiry, Tags,
 };
 
 use std::{
@@ -14,9 +15,11 @@ use std::{
     time::Duration,
 };
 
-/// Builds a [`Cache`][cache-struct] with various configuration knobs.
+/// Builds a [`Cache`][cache-struct] or [`SegmentedCache`][seg-cache-struct]
+/// with various configuration knobs.
 ///
 /// [cache-struct]: ./struct.Cache.html
+/// [seg-cache-struct]: ./struct.SegmentedCache.html
 ///
 /// # Example: Expirations
 ///
@@ -59,12 +62,18 @@ pub struct CacheBuilder<K, V, C> {
     name: Option<String>,
     max_capacity: Option<u64>,
     initial_capacity: Option<usize>,
+    num_segments: Option<usize>,
     weigher: Option<Weigher<K, V>>,
     eviction_policy: EvictionPolicy,
     eviction_listener: Option<AsyncEvictionListener<K, V>>,
+    callback_panic_policy: CallbackPanicPolicy,
     expiration_policy: ExpirationPolicy<K, V>,
     housekeeper_config: HousekeeperConfig,
     invalidator_enabled: bool,
+    key_locks_enabled: bool,
+    tag_extractor: Option<TagExtractor<K, V>>,
+    clock: Option<Arc<dyn CacheClock>>,
+    maintenance_scheduler: Option<MaintenanceScheduler>,
     cache_type: PhantomData<C>,
 }
 
@@ -78,12 +87,18 @@ where
             name: None,
             max_capacity: None,
             initial_capacity: None,
+            num_segments: None,
             weigher: None,
             eviction_policy: EvictionPolicy::default(),
             eviction_listener: None,
+            callback_panic_policy: CallbackPanicPolicy::default(),
             expiration_policy: ExpirationPolicy::default(),
             housekeeper_config: HousekeeperConfig::default(),
             invalidator_enabled: false,
+            key_locks_enabled: false,
+            tag_extractor: None,
+            clock: None,
+            maintenance_scheduler: None,
             cache_type: PhantomData,
         }
     }
@@ -94,8 +109,8 @@ where
     K: Eq + Hash + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
 {
-    /// Construct a new `CacheBuilder` that will be used to build a `Cache` holding
-    /// up to `max_capacity` entries.
+    /// Construct a new `CacheBuilder` that will be used to build a `Cache` or
+    /// `SegmentedCache` holding up to `max_capacity` entries.
     pub fn new(max_capacity: u64) -> Self {
         Self {
             max_capacity: Some(max_capacity),
@@ -103,17 +118,60 @@ where
         }
     }
 
+    /// Sets the number of segments of the cache.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is zero.
+    pub fn segments(
+        self,
+        num_segments: usize,
+    ) -> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>> {
+        assert!(num_segments != 0);
+
+        CacheBuilder {
+            name: self.name,
+            max_capacity: self.max_capacity,
+            initial_capacity: self.initial_capacity,
+            num_segments: Some(num_segments),
+            weigher: self.weigher,
+            eviction_policy: self.eviction_policy,
+            eviction_listener: self.eviction_listener,
+            callback_panic_policy: self.callback_panic_policy,
+            expiration_policy: self.expiration_policy,
+            housekeeper_config: self.housekeeper_config,
+            invalidator_enabled: self.invalidator_enabled,
+            key_locks_enabled: self.key_locks_enabled,
+            tag_extractor: self.tag_extractor,
+            clock: self.clock,
+            maintenance_scheduler: self.maintenance_scheduler,
+            cache_type: PhantomData,
+        }
+    }
+
     /// Builds a `Cache<K, V>`.
     ///
+    /// If you want to build a `SegmentedCache<K, V>`, call `segments` method before
+    /// calling this method.
+    ///
     /// # Panics
     ///
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build(self) -> Cache<K, V, RandomState> {
         let build_hasher = RandomState::default();
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         Cache::with_everything(
             self.name,
             self.max_capacity,
@@ -122,9 +180,14 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 
@@ -206,12 +269,21 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build_with_hasher<S>(self, hasher: S) -> Cache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         Cache::with_everything(
             self.name,
             self.max_capacity,
@@ -220,9 +292,135 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
+        )
+    }
+}
+
+impl<K, V> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Builds a `SegmentedCache<K, V>`.
+    ///
+    /// If you want to build a `Cache<K, V>`, do not call `segments` method before
+    /// calling this method.
+    ///
+    /// # Panics
+    ///
+    /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
+    /// 1000 years. This is done to protect against overflow when computing key
+    /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
+    pub fn build(self) -> SegmentedCache<K, V, RandomState> {
+        let build_hasher = RandomState::default();
+        let exp = &self.expiration_policy;
+        builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
+        SegmentedCache::with_everything(
+            self.name,
+            self.max_capacity,
+            self.initial_capacity,
+            self.num_segments.unwrap(),
+            build_hasher,
+            self.weigher,
+            self.eviction_policy,
+            self.eviction_listener,
+            self.callback_panic_policy,
+            self.expiration_policy,
+            self.housekeeper_config,
+            self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
+        )
+    }
+
+    /// Builds a `SegmentedCache<K, V, S>` with the given `hasher`.
+    ///
+    /// # Examples
+    ///
+    /// This example uses AHash hasher from [AHash][ahash-crate] crate.
+    ///
+    /// [ahash-crate]: https://crates.io/crates/ahash
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// // [dependencies]
+    /// // ahash = "0.8"
+    /// // moka = { version = ..., features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::future::SegmentedCache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     // The type of this cache is: SegmentedCache<i32, String, ahash::RandomState>
+    ///     let cache = SegmentedCache::builder(4)
+    ///         .max_capacity(100)
+    ///         .build_with_hasher(ahash::RandomState::default());
+    ///     cache.insert(1, "one".to_string()).await;
+    /// }
+    /// ```
+    ///
+    /// Note: If you need to add a type annotation to your cache, you must use the
+    /// form of `SegmentedCache<K, V, S>` instead of `SegmentedCache<K, V>`. That `S`
+    /// is the type of the build hasher, whose default is the `RandomState` from
+    /// `std::collections::hash_map` module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
+    /// 1000 years. This is done to protect against overflow when computing key
+    /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
+    pub fn build_with_hasher<S>(self, hasher: S) -> SegmentedCache<K, V, S>
+    where
+        S: BuildHasher + Clone + Send + Sync + 'static,
+    {
+        let exp = &self.expiration_policy;
+        builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
+        SegmentedCache::with_everything(
+            self.name,
+            self.max_capacity,
+            self.initial_capacity,
+            self.num_segments.unwrap(),
+            hasher,
+            self.weigher,
+            self.eviction_policy,
+            self.eviction_listener,
+            self.callback_panic_policy,
+            self.expiration_policy,
+            self.housekeeper_config,
+            self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 }
@@ -298,7 +496,9 @@ impl<K, V, C> CacheBuilder<K, V, C> {
     /// It is very important to make the listener closure not to panic. Otherwise,
     /// the cache will stop calling the listener after a panic. This is an intended
     /// behavior because the cache cannot know whether it is memory safe or not to
-    /// call the panicked listener again.
+    /// call the panicked listener again. Use the
+    /// [`callback_panic_policy`](#method.callback_panic_policy) method to change
+    /// this behavior.
     ///
     /// [removal-cause]: ../notification/enum.RemovalCause.html
     /// [example]: ./struct.Cache.html#per-entry-expiration-policy
@@ -338,7 +538,9 @@ impl<K, V, C> CacheBuilder<K, V, C> {
     /// It is very important to make the listener closure not to panic. Otherwise,
     /// the cache will stop calling the listener after a panic. This is an intended
     /// behavior because the cache cannot know whether it is memory safe or not to
-    /// call the panicked listener again.
+    /// call the panicked listener again. Use the
+    /// [`callback_panic_policy`](#method.callback_panic_policy) method to change
+    /// this behavior.
     ///
     /// [removal-cause]: ../notification/enum.RemovalCause.html
     /// [listener-future]: ../notification/type.ListenerFuture.html
@@ -353,6 +555,22 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the policy for handling panics raised by the eviction listener, the
+    /// weigher, the expiry and the `init` closures.
+    ///
+    /// The default policy is [`CallbackPanicPolicy::disable`][disable], which
+    /// disables the eviction listener once it panicked. See
+    /// [`CallbackPanicPolicy`][callback-panic-policy] for the other policies.
+    ///
+    /// [disable]: ../policy/struct.CallbackPanicPolicy.html#method.disable
+    /// [callback-panic-policy]: ../policy/struct.CallbackPanicPolicy.html
+    pub fn callback_panic_policy(self, policy: CallbackPanicPolicy) -> Self {
+        Self {
+            callback_panic_policy: policy,
+            ..self
+        }
+    }
+
     /// Sets the time to live of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from
@@ -419,6 +637,170 @@ impl<K, V, C> CacheBuilder<K, V, C> {
             ..self
         }
     }
+
+    /// Enables support for [`Cache::lock_key`][cache-lock-key] method.
+    ///
+    /// The cache will lock the key of an entry while inserting, updating or
+    /// removing the entry, so that these operations are serialized with the ones
+    /// done while holding the lock returned by `lock_key`. This adds some overhead
+    /// to the write operations.
+    ///
+    /// The key locks are always enabled if the cache has an eviction listener.
+    ///
+    /// [cache-lock-key]: ./struct.Cache.html#method.lock_key
+    pub fn support_key_locks(self) -> Self {
+        Self {
+            key_locks_enabled: true,
+            ..self
+        }
+    }
+
+    /// Sets the tag extractor closure to the cache, and enables
+    /// [`Cache::invalidate_tag`][cache-invalidate-tag] method.
+    ///
+    /// The closure returns the [`Tags`][tags] of a cached entry. The cache will
+    /// maintain an index from the tags to the keys of the entries, so that
+    /// `invalidate_tag` can discard all entries having a tag without scanning the
+    /// whole cache.
+    ///
+    /// The closure is called every time an entry is inserted or updated, and when
+    /// `invalidate_tag` checks the entries. It should be cheap and must return the
+    /// same tags for the same key and value.
+    ///
+    /// [cache-invalidate-tag]: ./struct.Cache.html#method.invalidate_tag
+    /// [tags]: ../type.Tags.html
+    pub fn tags(self, extractor: impl Fn(&K, &V) -> Tags + Send + Sync + 'static) -> Self {
+        Self {
+            tag_extractor: Some(Arc::new(extractor)),
+            ..self
+        }
+    }
+
+    /// Sets the clock the cache uses to measure the time for the expiration
+    /// policies, such as time-to-live, time-to-idle and per-entry expiration.
+    ///
+    /// By default, the cache uses a monotonic clock of the system. A custom clock
+    /// is useful to test code depending on the expiration without waiting for the
+    /// real time to pass. See [`MockClock`][mock-clock], which is available when
+    /// the `testing` feature is enabled.
+    ///
+    /// The clock must be monotonic; the cache treats a clock going backward as not
+    /// advancing.
+    ///
+    /// [mock-clock]: ../clock/struct.MockClock.html
+    pub fn clock(self, clock: impl CacheClock) -> Self {
+        Self {
+            clock: Some(Arc::new(clock)),
+            ..self
+        }
+    }
+
+    /// Sets the [`MaintenanceScheduler`][scheduler] to run the maintenance tasks of
+    /// the cache in the background.
+    ///
+    /// Without a scheduler, the maintenance tasks, such as removing expired entries
+    /// and calling the eviction listener, run only while the cache is being used.
+    ///
+    /// [scheduler]: ./struct.MaintenanceScheduler.html
+    pub fn maintenance_scheduler(self, scheduler: MaintenanceScheduler) -> Self {
+        Self {
+            maintenance_scheduler: Some(scheduler),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of a single run of the maintenance tasks.
+    ///
+    /// The maintenance tasks may be run by a cache read or write. When an eviction
+    /// listener is set, the tasks call it for the removed entries, so a slow
+    /// listener could block the read or write for a long time. The tasks stop when
+    /// the timeout is reached, and the remaining work is done by the next run. Only
+    /// used when an eviction listener is set.
+    ///
+    /// The default value is 100 milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `timeout` is zero.
+    pub fn maintenance_task_timeout(self, timeout: Duration) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.maintenance_task_timeout = timeout;
+        builder
+    }
+
+    /// Sets the maximum number of times a single run of the maintenance tasks
+    /// drains the read and write logs while they keep filling up.
+    ///
+    /// The default value is 4.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `repeats` is zero.
+    pub fn max_log_sync_repeats(self, repeats: u32) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.max_log_sync_repeats = repeats;
+        builder
+    }
+
+    /// Sets the maximum number of entries processed at once by each step of the
+    /// maintenance tasks, such as removing expired entries.
+    ///
+    /// A smaller value makes the maintenance tasks check the timeout more often.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `batch_size` is
+    /// zero.
+    pub fn eviction_batch_size(self, batch_size: u32) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.eviction_batch_size = batch_size;
+        builder
+    }
+
+    /// Sets the capacity of the read log, where the cache records the reads to
+    /// apply them to the cache policy later.
+    ///
+    /// When the log is full, reads are not recorded. This is counted as
+    /// [`read_ops_dropped`][read-ops-dropped] in the maintenance statistics.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `capacity` is smaller
+    /// than 64, the number of the recorded reads that triggers the maintenance
+    /// tasks.
+    ///
+    /// [read-ops-dropped]: ../struct.MaintenanceStats.html#method.read_ops_dropped
+    pub fn read_log_capacity(self, capacity: usize) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.read_log_capacity = capacity;
+        builder
+    }
+
+    /// Sets the capacity of the write log, where the cache records the writes to
+    /// apply them to the cache policy later.
+    ///
+    /// When the log is full, writes wait for the maintenance tasks to drain it.
+    /// This is counted as [`write_op_retries`][write-op-retries] in the maintenance
+    /// statistics.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `capacity` is smaller
+    /// than 64, the number of the recorded writes that triggers the maintenance
+    /// tasks.
+    ///
+    /// [write-op-retries]: ../struct.MaintenanceStats.html#method.write_op_retries
+    pub fn write_log_capacity(self, capacity: usize) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.write_log_capacity = capacity;
+        builder
+    }
 }
 
 #[cfg(test)]
@@ -456,6 +838,41 @@ mod tests {
         assert_eq!(cache.get(&'a').await, Some("Alice"));
     }
 
+    #[tokio::test]
+    async fn build_segmented_cache() {
+        // SegmentCache<char, String>
+        let cache = CacheBuilder::new(100).segments(15).build();
+        let policy = cache.policy();
+
+        assert_eq!(policy.max_capacity(), Some(100));
+        assert!(policy.time_to_live().is_none());
+        assert!(policy.time_to_idle().is_none());
+        assert_eq!(policy.num_segments(), 16_usize.next_power_of_two());
+
+        cache.insert('b', "Bob").await;
+        assert_eq!(cache.get(&'b').await, Some("Bob"));
+
+        let builder = CacheBuilder::new(400)
+            .time_to_live(Duration::from_secs(45 * 60))
+            .time_to_idle(Duration::from_secs(15 * 60))
+            .name("tracked_sessions")
+            // Call segments() at the end to check all field values in the current
+            // builder struct are copied to the new builder.
+            .segments(24);
+
+        let cache = builder.build();
+        let policy = cache.policy();
+
+        assert_eq!(policy.max_capacity(), Some(400));
+        assert_eq!(policy.time_to_live(), Some(Duration::from_secs(45 * 60)));
+        assert_eq!(policy.time_to_idle(), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(policy.num_segments(), 24_usize.next_power_of_two());
+        assert_eq!(cache.name(), Some("tracked_sessions"));
+
+        cache.insert('b', "Bob").await;
+        assert_eq!(cache.get(&'b').await, Some("Bob"));
+    }
+
     #[tokio::test]
     #[should_panic(expected = "time_to_live is longer than 1000 years")]
     async fn build_cache_too_long_ttl() {
@@ -477,4 +894,87 @@ mod tests {
             .time_to_idle(duration + Duration::from_secs(1))
             .build();
     }
+
+    #[tokio::test]
+    async fn build_cache_with_maintenance_options() {
+        let builder = CacheBuilder::new(100)
+            .maintenance_task_timeout(Duration::from_millis(10))
+            .max_log_sync_repeats(2)
+            .eviction_batch_size(100)
+            .read_log_capacity(64)
+            .write_log_capacity(1024);
+        let conf = &builder.housekeeper_config;
+        assert_eq!(conf.maintenance_task_timeout, Duration::from_millis(10));
+        assert_eq!(conf.max_log_sync_repeats, 2);
+        assert_eq!(conf.eviction_batch_size, 100);
+        assert_eq!(conf.read_log_capacity, 64);
+        assert_eq!(conf.write_log_capacity, 1024);
+
+        let cache = builder.build();
+        cache.insert('a', "Alice").await;
+        assert_eq!(cache.get(&'a').await, Some("Alice"));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "write_log_capacity must be at least 64")]
+    async fn build_cache_too_small_write_log() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.write_log_capacity(0).build();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "the interval of the maintenance scheduler must not be zero")]
+    async fn build_cache_zero_maintenance_interval() {
+        use crate::future::MaintenanceScheduler;
+
+        let scheduler = MaintenanceScheduler::spawn_with(|task| {
+            tokio::spawn(task);
+        });
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder
+            .maintenance_scheduler(scheduler.interval(Duration::ZERO))
+            .build();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "maintenance_task_timeout must not be zero")]
+    async fn build_cache_zero_maintenance_task_timeout() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.maintenance_task_timeout(Duration::ZERO).build();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "max_log_sync_repeats must not be zero")]
+    async fn build_cache_zero_max_log_sync_repeats() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.max_log_sync_repeats(0).build();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "eviction_batch_size must not be zero")]
+    async fn build_cache_zero_eviction_batch_size() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.eviction_batch_size(0).build();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "read_log_capacity must be at least 64")]
+    async fn build_cache_too_small_read_log() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.read_log_capacity(63).build();
+    }
+
+    #[tokio::test]
+    async fn build_cache_with_overridden_maintenance_options() {
+        // The options are validated by `build`, so an invalid value can be
+        // overridden by a valid one before that.
+        let cache = CacheBuilder::new(100)
+            .eviction_batch_size(0)
+            .eviction_batch_size(1)
+            .read_log_capacity(0)
+            .read_log_capacity(64)
+            .build();
+        cache.insert('a', "Alice").await;
+        assert_eq!(cache.get(&'a').await, Some("Alice"));
+    }
 }
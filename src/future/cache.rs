@@ -1,20 +1,31 @@
 use super::{
     base_cache::BaseCache,
+    blocking::BlockingOp,
     value_initializer::{InitResult, ValueInitializer},
-    CacheBuilder, CancelGuard, Iter, OwnedKeyEntrySelector, PredicateId, RefKeyEntrySelector,
-    WriteOp,
+    CacheBuilder, CancelGuard, EntryStream, Iter, IterWithMetadata, KeyGuard, KeyStream, Keys,
+    MaintenanceScheduler, OwnedKeyEntrySelector, PendingOp, PredicateId, RefKeyEntrySelector,
+    Values, WriteOp,
 };
 use crate::{
-    common::{concurrent::Weigher, HousekeeperConfig},
-    notification::AsyncEvictionListener,
+    clock::CacheClock,
+    common::{
+        callback_panic::CallbackPanicReporter,
+        concurrent::{KvEntry, Weigher},
+        tag::TagExtractor,
+        time::Instant,
+        HousekeeperConfig,
+    },
+    notification::{AsyncEvictionListener, RemovalCause},
     ops::compute::{self, CompResult},
-    policy::{EvictionPolicy, ExpirationPolicy},
-    Entry, Policy, PredicateError,
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    Entry, HeapSize, KeyLockError, MaintenanceStats, MemoryReport, Policy, PredicateError,
+    PredicateStatus,
 };
 
 #[cfg(feature = "unstable-debug-counters")]
 use crate::common::concurrent::debug_counters::CacheDebugStats;
 
+use crossbeam_channel::TrySendError;
 use std::{
     borrow::Borrow,
     collections::hash_map::RandomState,
@@ -23,6 +34,7 @@ use std::{
     hash::{BuildHasher, Hash},
     pin::Pin,
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 #[cfg(test)]
@@ -706,6 +718,16 @@ impl<K, V, S> Cache<K, V, S> {
         self.base.policy()
     }
 
+    /// Returns `true` if the eviction listener is set and has not been disabled.
+    ///
+    /// Under the default [`CallbackPanicPolicy`][callback-panic-policy], the cache
+    /// disables the listener once it panicked. This method lets you detect it.
+    ///
+    /// [callback-panic-policy]: ../policy/struct.CallbackPanicPolicy.html
+    pub fn listener_enabled(&self) -> bool {
+        self.base.is_listener_enabled()
+    }
+
     /// Returns an approximate number of entries in this cache.
     ///
     /// The value returned is _an estimate_; the actual count may differ if there are
@@ -790,9 +812,14 @@ where
             None,
             EvictionPolicy::default(),
             None,
+            CallbackPanicPolicy::default(),
             ExpirationPolicy::default(),
             HousekeeperConfig::default(),
             false,
+            false,
+            None,
+            None,
+            None,
         )
     }
 
@@ -821,11 +848,17 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<AsyncEvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
         expiration_policy: ExpirationPolicy<K, V>,
         housekeeper_config: HousekeeperConfig,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
     ) -> Self {
-        Self {
+        let panic_reporter = CallbackPanicReporter::new(callback_panic_policy, name.as_deref());
+        let mut cache = Self {
             base: BaseCache::new(
                 name,
                 max_capacity,
@@ -834,15 +867,27 @@ where
                 weigher,
                 eviction_policy,
                 eviction_listener,
+                panic_reporter.clone(),
                 expiration_policy,
                 housekeeper_config,
                 invalidator_enabled,
+                key_locks_enabled,
+                tag_extractor,
+                clock,
             ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            value_initializer: Arc::new(ValueInitializer::new(build_hasher, panic_reporter)),
 
             #[cfg(test)]
             schedule_write_op_should_block: Default::default(), // false
+        };
+        if let Some(scheduler) = maintenance_scheduler {
+            let (handle, waker) = scheduler.start(vec![cache.base.maintenance_job()]);
+            cache.base.set_maintenance_handle(handle);
+            if let Some(waker) = waker {
+                cache.base.set_maintenance_waker(waker);
+            }
         }
+        cache
     }
 
     /// Returns `true` if the cache contains a value for the key.
@@ -876,14 +921,109 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let ignore_if = None as Option<&mut fn(&V) -> bool>;
+        self.get_with_hash(key, self.base.hash(key), false)
+            .await
+            .map(Entry::into_value)
+    }
 
+    pub(crate) async fn get_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_key: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.base
-            .get_with_hash(key, self.base.hash(key), ignore_if, false, true)
+            .get_with_hash(key, hash, never_ignore(), need_key, true)
             .await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key without affecting
+    /// the cache policies.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read operation,
+    /// so it does not update the historic popularity estimator, move the entry in
+    /// the access order queue, or reset the idle timer for the key. The
+    /// `expire_after_read` method of the [`Expiry`](../policy/trait.Expiry.html) is
+    /// not called either. This is useful for monitoring or dumping the cache
+    /// without skewing its eviction policy.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::future::Cache;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder()
+    ///         .time_to_idle(Duration::from_secs(60))
+    ///         .build();
+    ///     cache.insert("key", "value").await;
+    ///
+    ///     let last_accessed = |cache: &Cache<_, _>| {
+    ///         cache.peek_entry(&"key").unwrap().metadata().unwrap().last_accessed()
+    ///     };
+    ///     let before = last_accessed(&cache);
+    ///     assert_eq!(cache.peek(&"key"), Some("value"));
+    ///     assert_eq!(last_accessed(&cache), before);
+    /// }
+    /// ```
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base
+            .peek_with_hash(key, self.base.hash(key), false)
             .map(Entry::into_value)
     }
 
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the
+    /// [`EntryMetadata`](../struct.EntryMetadata.html) of the entry, without
+    /// affecting the cache policies.
+    ///
+    /// See [`peek`](#method.peek) for the difference from the `get` family of
+    /// methods.
+    pub fn peek_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.peek_with_hash(key, self.base.hash(key), true)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the
+    /// [`EntryMetadata`](../struct.EntryMetadata.html) of the entry.
+    ///
+    /// This is considered a cache read operation, same as the `get` method.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn get_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base
+            .get_entry_with_hash(key, self.base.hash(key))
+            .await
+    }
+
     /// Takes a key `K` and returns an [`OwnedKeyEntrySelector`] that can be used to
     /// select or insert an entry.
     ///
@@ -965,6 +1105,197 @@ where
         RefKeyEntrySelector::new(key, hash, self)
     }
 
+    /// Performs a compute operation on multiple keys atomically.
+    ///
+    /// This method locks all the keys, and calls the `f` closure with the current
+    /// entries for the keys, in the same order as `keys`. The closure returns a
+    /// future resolving to a `compute::Op` for each key, also in the same order,
+    /// and the ops are applied before the keys are unlocked:
+    ///
+    /// - `Op::Put(V)`: Puts the new value `V` to the cache.
+    /// - `Op::Remove`: Removes the current entry from the cache.
+    /// - `Op::Nop`: Does nothing.
+    ///
+    /// This method returns a `compute::CompResult` for each key. See
+    /// [`and_compute_with`][compute-with] for the variants.
+    ///
+    /// The keys are locked in the same way as [`lock_key`](#method.lock_key), in a
+    /// deterministic order so that the concurrent calls with overlapping keys do
+    /// not deadlock. While the keys are locked, the other tasks inserting,
+    /// updating or removing the entries for the keys wait for this method to
+    /// return. The methods reading the entries, such as `get`, do not wait; they
+    /// may see some of the ops applied and the others not yet applied.
+    ///
+    /// The future returned by the closure must not insert, update or remove the
+    /// entries for the keys through the cache. Such calls wait for the keys to be
+    /// unlocked, which never happens while the closure is running.
+    ///
+    /// If a key is given more than once, the closure gets the same entry for each
+    /// occurrence, and the ops for the key are applied in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::{future::Cache, ops::compute::Op};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+    ///     cache.insert("alice", 100).await;
+    ///     cache.insert("bob", 20).await;
+    ///
+    ///     // Transfer 30 from alice to bob.
+    ///     let results = cache
+    ///         .compute_many(["alice", "bob"], |entries| async move {
+    ///             let alice = entries[0].as_ref().map_or(0, |e| *e.value());
+    ///             let bob = entries[1].as_ref().map_or(0, |e| *e.value());
+    ///             if alice < 30 {
+    ///                 return [Op::Nop, Op::Nop];
+    ///             }
+    ///             [Op::Put(alice - 30), Op::Put(bob + 30)]
+    ///         })
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(results.len(), 2);
+    ///     assert_eq!(cache.get(&"alice").await, Some(70));
+    ///     assert_eq!(cache.get(&"bob").await, Some(50));
+    /// }
+    /// ```
+    ///
+    /// [compute-with]: ./struct.OwnedKeyEntrySelector.html#method.and_compute_with
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub async fn compute_many<const N: usize, F, Fut>(
+        &self,
+        keys: [K; N],
+        f: F,
+    ) -> Result<Vec<compute::CompResult<K, V>>, KeyLockError>
+    where
+        F: FnOnce([Option<Entry<K, V>>; N]) -> Fut,
+        Fut: Future<Output = [compute::Op<V>; N]>,
+    {
+        let keys = keys.map(Arc::new);
+        let hashes: [u64; N] = std::array::from_fn(|i| self.base.hash(&keys[i]));
+        let guards = self.base.lock_keys(&keys).await?;
+
+        let (results, pending_ops, removed_keys) = {
+            let mut entries = std::array::from_fn(|_| None);
+            for ((entry, k), &h) in entries.iter_mut().zip(&keys).zip(&hashes) {
+                let ignore_if = None as Option<&mut fn(&V) -> bool>;
+                *entry = self.base.get_with_hash(k, h, ignore_if, true, true).await;
+            }
+            let ops = f(entries).await;
+
+            let mut results = Vec::with_capacity(N);
+            let mut pending_ops = Vec::new();
+            let mut removed_keys = Vec::new();
+
+            for ((key, hash), op) in keys.into_iter().zip(hashes).zip(ops) {
+                // Get the current value, which reflects the ops already applied for
+                // the same key.
+                let ignore_if = None as Option<&mut fn(&V) -> bool>;
+                let maybe_value = self
+                    .base
+                    .get_with_hash(&key, hash, ignore_if, false, false)
+                    .await
+                    .map(Entry::into_value);
+
+                let result = match op {
+                    compute::Op::Nop => {
+                        if let Some(value) = maybe_value {
+                            let md = self.base.entry_metadata_with_hash(&key, hash);
+                            let entry =
+                                Entry::new(Some(key), value, false, false).with_metadata(md);
+                            CompResult::Unchanged(entry)
+                        } else {
+                            CompResult::StillNone(key)
+                        }
+                    }
+                    compute::Op::Put(value) => {
+                        let prev_v = if self.base.is_map_disabled() {
+                            None
+                        } else {
+                            let (op, ts, prev) = self
+                                .base
+                                .do_insert_with_hash_locked(
+                                    Arc::clone(&key),
+                                    hash,
+                                    value.clone(),
+                                    None,
+                                )
+                                .await;
+                            pending_ops.push(self.pending_op(op, ts));
+                            prev.map(|entry| entry.value.clone())
+                        };
+                        let md = self.base.entry_metadata_with_hash(&key, hash);
+                        if maybe_value.is_some() {
+                            let entry = Entry::new(Some(key), value, true, true)
+                                .with_old_value(prev_v)
+                                .with_metadata(md);
+                            CompResult::ReplacedWith(entry)
+                        } else {
+                            let entry = Entry::new(Some(key), value, true, false).with_metadata(md);
+                            CompResult::Inserted(entry)
+                        }
+                    }
+                    compute::Op::Remove => {
+                        let removed = self
+                            .remove_and_notify_locked(RemovalCause::Explicit, || {
+                                self.base.remove_entry(&*key, hash)
+                            })
+                            .await;
+                        if let Some((kv, pending_op)) = removed {
+                            pending_ops.push(pending_op);
+                            let prev_v = kv.entry.value.clone();
+                            removed_keys.push(kv.key);
+                            CompResult::Removed(Entry::new(Some(key), prev_v, false, false))
+                        } else {
+                            CompResult::StillNone(key)
+                        }
+                    }
+                };
+                results.push(result);
+            }
+            (results, pending_ops, removed_keys)
+        };
+
+        // Unlock the keys before scheduling the write ops to avoid a potential
+        // dead lock. (See `remove_and_notify_without_scheduling`)
+        std::mem::drop(guards);
+
+        if !pending_ops.is_empty() {
+            for pending_op in pending_ops {
+                self.schedule_pending_op(pending_op).await;
+            }
+            crossbeam_epoch::pin().flush();
+        }
+        for key in removed_keys {
+            self.invalidate_dependents(&key).await;
+        }
+
+        Ok(results)
+    }
+
+    /// Returns a [`BlockingOp`] for this cache. It provides blocking versions of
+    /// some methods of the cache, so the cache can also be accessed from
+    /// synchronous code running outside of an async runtime.
+    ///
+    /// [`BlockingOp`]: ./struct.BlockingOp.html
+    pub fn blocking(&self) -> BlockingOp<'_, K, V, S> {
+        BlockingOp::new(self)
+    }
+
     /// Returns a _clone_ of the value corresponding to the key. If the value does
     /// not exist, resolve the `init` future and inserts the output.
     ///
@@ -1344,582 +1675,2014 @@ where
         self.insert_with_hash(key, hash, value).await;
     }
 
-    /// Discards any cached value for the key.
+    /// Inserts a key-value pair into the cache, and makes the entry expire after
+    /// the given duration.
     ///
-    /// If you need to get the value that has been discarded, use the
-    /// [`remove`](#method.remove) method instead.
+    /// If the cache has this key present, the value is updated and its expiration
+    /// time is reset to the given duration from now.
     ///
-    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
-    /// on the borrowed form _must_ match those for the key type.
-    pub async fn invalidate<Q>(&self, key: &Q)
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.base.hash(key);
-        self.invalidate_with_hash(key, hash, false).await;
-    }
-
-    /// Discards any cached value for the key and returns a _clone_ of the value.
+    /// The duration takes precedence over the
+    /// [`Expiry`](../policy/trait.Expiry.html) configured to the cache, which is not
+    /// called for this insertion. The time-to-live and time-to-idle policies of the
+    /// cache still apply, so the entry will expire at whichever comes first.
     ///
-    /// If you do not need to get the value that has been discarded, use the
-    /// [`invalidate`](#method.invalidate) method instead.
+    /// # Example
     ///
-    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
-    /// on the borrowed form _must_ match those for the key type.
-    pub async fn remove<Q>(&self, key: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let hash = self.base.hash(key);
-        self.invalidate_with_hash(key, hash, true).await
-    }
-
-    /// Discards all cached values.
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
     ///
-    /// This method returns immediately and a background thread will evict all the
-    /// cached values inserted before the time when this method was called. It is
-    /// guaranteed that the `get` method must not return these invalidated values
-    /// even if they have not been evicted.
+    /// use moka2::future::Cache;
+    /// use std::time::Duration;
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
-    pub fn invalidate_all(&self) {
-        self.base.invalidate_all();
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache
+    ///         .insert_with_ttl("key", "value", Duration::from_secs(30))
+    ///         .await;
+    ///
+    ///     let remaining = cache.expires_in(&"key").unwrap();
+    ///     assert!(remaining <= Duration::from_secs(30));
+    /// }
+    /// ```
+    pub async fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_ttl(key, hash, value, Some(ttl))
+            .await;
     }
 
-    /// Discards cached values that satisfy a predicate.
-    ///
-    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
-    /// method returns immediately and a background thread will apply the closure to
-    /// each cached value inserted before the time when `invalidate_entries_if` was
-    /// called. If the closure returns `true` on a value, that value will be evicted
-    /// from the cache.
-    ///
-    /// Also the `get` method will apply the closure to a value to determine if it
-    /// should have been invalidated. Therefore, it is guaranteed that the `get`
-    /// method must not return invalidated values.
-    ///
-    /// Note that you must call
-    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
-    /// at the cache creation time as the cache needs to maintain additional internal
-    /// data structures to support this method. Otherwise, calling this method will
-    /// fail with a
-    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
-    ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given deadline.
     ///
-    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
-    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
-    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
-    where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
-    {
-        self.base.invalidate_entries_if(Arc::new(predicate))
+    /// If the deadline has already passed, the entry is inserted but will never be
+    /// returned by the cache. See [`insert_with_ttl`](#method.insert_with_ttl) for
+    /// how the deadline interacts with other expiration policies.
+    pub async fn insert_with_deadline(&self, key: K, value: V, deadline: std::time::Instant) {
+        let ttl = self.base.duration_until(deadline);
+        self.insert_with_ttl(key, value, ttl).await;
     }
 
-    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
-    /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
-    /// value.
-    ///
-    /// Iterators do not block concurrent reads and writes on the cache. An entry can
-    /// be inserted to, invalidated or evicted from a cache while iterators are alive
-    /// on the same cache.
-    ///
-    /// Unlike the `get` method, visiting entries via an iterator do not update the
-    /// historic popularity estimator or reset idle timers for keys.
-    ///
-    /// # Guarantees
-    ///
-    /// In order to allow concurrent access to the cache, iterator's `next` method
-    /// does _not_ guarantee the following:
-    ///
-    /// - It does not guarantee to return a key-value pair (an entry) if its key has
-    ///   been inserted to the cache _after_ the iterator was created.
-    ///   - Such an entry may or may not be returned depending on key's hash and
-    ///     timing.
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given wall-clock time, such as the time of an HTTP `Expires` header or the
+    /// `notAfter` field of a certificate.
     ///
-    /// and the `next` method guarantees the followings:
+    /// Returns `false` without inserting the entry if `expires_at` has already
+    /// passed. In that case, the existing entry for the key, if any, is left as it
+    /// is.
     ///
-    /// - It guarantees not to return the same entry more than once.
-    /// - It guarantees not to return an entry if it has been removed from the cache
-    ///   after the iterator was created.
-    ///     - Note: An entry can be removed by following reasons:
-    ///         - Manually invalidated.
-    ///         - Expired (e.g. time-to-live).
-    ///         - Evicted as the cache capacity exceeded.
+    /// The deadline is converted into a duration from now at insertion. If the
+    /// system clock jumps afterward, the expiration time of the entry is converted
+    /// again from the deadline when the cache runs its pending tasks. Updating the
+    /// entry by other methods discards the deadline. See
+    /// [`insert_with_ttl`](#method.insert_with_ttl) for how the deadline interacts
+    /// with other expiration policies.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```rust
     /// // Cargo.toml
     /// //
     /// // [dependencies]
-    /// // moka = { version = "0.12", features = ["future"] }
+    /// // moka2 = { version = "0.13", features = ["future"] }
     /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
     /// use moka2::future::Cache;
+    /// use std::time::{Duration, SystemTime};
     ///
     /// #[tokio::main]
     /// async fn main() {
     ///     let cache = Cache::new(100);
-    ///     cache.insert("Julia", 14).await;
     ///
-    ///     let mut iter = cache.iter();
-    ///     let (k, v) = iter.next().unwrap(); // (Arc<K>, V)
-    ///     assert_eq!(*k, "Julia");
-    ///     assert_eq!(v, 14);
+    ///     let expires_at = SystemTime::now() + Duration::from_secs(60);
+    ///     assert!(cache.insert_expiring_at("fresh", "value", expires_at).await);
+    ///     assert_eq!(cache.get(&"fresh").await, Some("value"));
     ///
-    ///     assert!(iter.next().is_none());
+    ///     // A deadline in the past is refused.
+    ///     let expired_at = SystemTime::now() - Duration::from_secs(1);
+    ///     assert!(!cache.insert_expiring_at("stale", "value", expired_at).await);
+    ///     assert!(!cache.contains_key(&"stale"));
     /// }
     /// ```
-    ///
-    pub fn iter(&self) -> Iter<'_, K, V> {
-        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet};
-
-        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
-        Iter::new(inner)
+    pub async fn insert_expiring_at(&self, key: K, value: V, expires_at: SystemTime) -> bool {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_expiring_at(key, hash, value, expires_at)
+            .await
     }
 
-    /// Performs any pending maintenance operations needed by the cache.
-    pub async fn run_pending_tasks(&self) {
-        if let Some(hk) = &self.base.housekeeper {
-            self.base.retry_interrupted_ops().await;
-            hk.run_pending_tasks(Arc::clone(&self.base.inner)).await;
-        }
-    }
-}
-
-impl<'a, K, V, S> IntoIterator for &'a Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    type Item = (Arc<K>, V);
-
-    type IntoIter = Iter<'a, K, V>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
-    }
-}
-
-//
-// private methods
-//
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    pub(crate) async fn get_or_insert_with_hash_and_fun(
+    pub(crate) async fn insert_with_hash_expiring_at(
         &self,
         key: Arc<K>,
         hash: u64,
-        init: Pin<&mut impl Future<Output = V>>,
-        mut replace_if: Option<impl FnMut(&V) -> bool + Send>,
-        need_key: bool,
-    ) -> Entry<K, V> {
-        let maybe_entry = self
-            .base
-            .get_with_hash(&key, hash, replace_if.as_mut(), need_key, true)
-            .await;
-        if let Some(entry) = maybe_entry {
-            entry
-        } else {
-            self.insert_with_hash_and_fun(key, hash, init, replace_if, need_key)
-                .await
+        value: V,
+        expires_at: SystemTime,
+    ) -> bool {
+        if self.base.is_map_disabled() {
+            return false;
         }
-    }
+        let Some(ttl) = self.base.duration_until_system_time(expires_at) else {
+            return false;
+        };
 
-    pub(crate) async fn get_or_insert_with_hash_by_ref_and_fun<Q>(
-        &self,
-        key: &Q,
-        hash: u64,
-        init: Pin<&mut impl Future<Output = V>>,
-        mut replace_if: Option<impl FnMut(&V) -> bool + Send>,
-        need_key: bool,
-    ) -> Entry<K, V>
-    where
-        K: Borrow<Q>,
-        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
-    {
-        let maybe_entry = self
+        let (op, ts, _) = self
             .base
-            .get_with_hash(key, hash, replace_if.as_mut(), need_key, true)
+            .do_insert_with_hash(Arc::clone(&key), hash, value, Some(ttl))
             .await;
-        if let Some(entry) = maybe_entry {
-            entry
-        } else {
-            let key = Arc::new(key.to_owned());
-            self.insert_with_hash_and_fun(key, hash, init, replace_if, need_key)
-                .await
-        }
+        self.base.register_wall_clock_deadline(key, expires_at, &op);
+        self.schedule_upsert_op(op, ts).await;
+        true
     }
 
-    async fn insert_with_hash_and_fun(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: Pin<&mut impl Future<Output = V>>,
-        replace_if: Option<impl FnMut(&V) -> bool + Send>,
-        need_key: bool,
-    ) -> Entry<K, V> {
-        let k = if need_key {
-            Some(Arc::clone(&key))
-        } else {
-            None
-        };
-
-        let type_id = ValueInitializer::<K, V, S>::type_id_for_get_with();
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_get_with;
-
-        match self
-            .value_initializer
-            .try_init_or_read(&key, hash, type_id, self, replace_if, init, post_init)
-            .await
-        {
-            InitResult::Initialized(v) => {
-                crossbeam_epoch::pin().flush();
-                Entry::new(k, v, true, false)
-            }
-            InitResult::ReadExisting(v) => Entry::new(k, v, false, false),
-            InitResult::InitErr(_) => unreachable!(),
-        }
+    /// Inserts a key-value pair into the cache, and returns a _clone_ of the value
+    /// that was replaced, if any.
+    ///
+    /// Unlike calling [`get`](#method.get) and then [`insert`](#method.insert),
+    /// the previous value is the one actually replaced by this insertion. `None` is
+    /// returned if the cache did not have a live entry for the key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///
+    ///     assert_eq!(cache.swap("key", "alice").await, None);
+    ///     assert_eq!(cache.swap("key", "bob").await, Some("alice"));
+    ///     assert_eq!(cache.get(&"key").await, Some("bob"));
+    /// }
+    /// ```
+    pub async fn swap(&self, key: K, value: V) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.swap_with_hash(key, hash, value).await
     }
 
-    pub(crate) async fn get_or_insert_with_hash(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: impl FnOnce() -> V,
-    ) -> Entry<K, V> {
-        match self
-            .base
-            .get_with_hash(&key, hash, never_ignore(), true, true)
-            .await
-        {
-            Some(entry) => entry,
-            None => {
-                let value = init();
-                self.insert_with_hash(Arc::clone(&key), hash, value.clone())
-                    .await;
-                Entry::new(Some(key), value, true, false)
-            }
-        }
+    /// Inserts a key-value pair into the cache only if the cache does not have a
+    /// live entry for the key.
+    ///
+    /// Returns `None` if the value was inserted. Otherwise, returns a _clone_ of
+    /// the existing value, and the given value is discarded.
+    ///
+    /// Unlike [`get_with`](#method.get_with), this method does not wait for
+    /// concurrent calls for the same key; it is an atomic operation on the
+    /// internal hash table.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///
+    ///     assert_eq!(cache.insert_if_absent("key", "alice").await, None);
+    ///     assert_eq!(cache.insert_if_absent("key", "bob").await, Some("alice"));
+    ///     assert_eq!(cache.get(&"key").await, Some("alice"));
+    /// }
+    /// ```
+    pub async fn insert_if_absent(&self, key: K, value: V) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_if_absent_with_hash(key, hash, value).await
     }
 
-    pub(crate) async fn get_or_insert_with_hash_by_ref<Q>(
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key and `condition` returns `true` for the current value.
+    ///
+    /// Returns a _clone_ of the previous value if the value was replaced.
+    /// Otherwise, returns `None`.
+    ///
+    /// The check and the replacement are done atomically; no other update to the
+    /// entry can happen between them. `condition` may be called more than once
+    /// when it conflicts with concurrent updates to the same entry.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn replace_if<Q>(
         &self,
         key: &Q,
-        hash: u64,
-        init: impl FnOnce() -> V,
-    ) -> Entry<K, V>
+        value: V,
+        condition: impl FnMut(&V) -> bool + Send,
+    ) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        Q: Hash + Eq + ?Sized,
     {
-        match self
-            .base
-            .get_with_hash(key, hash, never_ignore(), true, true)
+        let hash = self.base.hash(key);
+        self.replace_with_hash_if(key, hash, value, condition)
             .await
-        {
-            Some(entry) => entry,
-            None => {
-                let key = Arc::new(key.to_owned());
-                let value = init();
-                self.insert_with_hash(Arc::clone(&key), hash, value.clone())
-                    .await;
-                Entry::new(Some(key), value, true, false)
-            }
-        }
+            .ok()
     }
 
-    pub(crate) async fn get_or_optionally_insert_with_hash_and_fun<F>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Option<Entry<K, V>>
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key.
+    ///
+    /// Returns a _clone_ of the previous value if the value was replaced.
+    /// Otherwise, returns `None` and the key will not be inserted.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn replace_if_present<Q>(&self, key: &Q, value: V) -> Option<V>
     where
-        F: Future<Output = Option<V>>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        let entry = self
-            .base
-            .get_with_hash(&key, hash, never_ignore(), need_key, true)
-            .await;
-        if entry.is_some() {
-            return entry;
-        }
+        self.replace_if(key, value, |_| true).await
+    }
 
-        self.optionally_insert_with_hash_and_fun(key, hash, init, need_key)
+    /// Replaces the value for the key only if the current value is equal to
+    /// `expected`.
+    ///
+    /// Returns `Ok` with a _clone_ of the previous value if the value was
+    /// replaced. Otherwise, returns `Err` with a _clone_ of the current value, or
+    /// `Err(None)` if the cache does not have a live entry for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn replace<Q>(&self, key: &Q, expected: &V, new: V) -> Result<V, Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        let hash = self.base.hash(key);
+        self.replace_with_hash_if(key, hash, new, |v| v == expected)
             .await
     }
 
-    pub(crate) async fn get_or_optionally_insert_with_hash_by_ref_and_fun<F, Q>(
-        &self,
-        key: &Q,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Option<Entry<K, V>>
+    /// Sets the expiration of the existing entry for the key to the given duration
+    /// from now. Returns `false` if the cache does not contain a value for the
+    /// key.
+    ///
+    /// Like [`insert_with_ttl`](#method.insert_with_ttl), this overrides the
+    /// per-entry expiration time set by an insertion or by the
+    /// [`Expiry`](../policy/trait.Expiry.html), but the time-to-live and
+    /// time-to-idle policies of the cache still apply.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn set_expiry<Q>(&self, key: &Q, ttl: Duration) -> bool
     where
-        F: Future<Output = Option<V>>,
         K: Borrow<Q>,
-        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        Q: Hash + Eq + ?Sized,
     {
-        let entry = self
-            .base
-            .get_with_hash(key, hash, never_ignore(), need_key, true)
-            .await;
-        if entry.is_some() {
-            return entry;
-        }
-
-        let key = Arc::new(key.to_owned());
-        self.optionally_insert_with_hash_and_fun(key, hash, init, need_key)
+        self.set_expiry_with_hash(key, self.base.hash(key), ttl)
             .await
     }
 
-    async fn optionally_insert_with_hash_and_fun<F>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Option<Entry<K, V>>
+    pub(crate) async fn set_expiry_with_hash<Q>(&self, key: &Q, hash: u64, ttl: Duration) -> bool
     where
-        F: Future<Output = Option<V>>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        let k = if need_key {
-            Some(Arc::clone(&key))
-        } else {
-            None
+        let Some((op, ts)) = self.base.set_expiry_with_hash(key, hash, ttl) else {
+            return false;
         };
+        // The write op channel is disconnected only when the cache is dropped. The
+        // new expiration time has been set to the entry anyway, so ignore the error.
+        let _ = self.try_schedule_pending_op(self.pending_op(op, ts)).await;
+        true
+    }
 
-        let type_id = ValueInitializer::<K, V, S>::type_id_for_optionally_get_with();
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_optionally_get_with;
-
-        match self
-            .value_initializer
-            .try_init_or_read(&key, hash, type_id, self, never_ignore(), init, post_init)
-            .await
-        {
-            InitResult::Initialized(v) => {
-                crossbeam_epoch::pin().flush();
-                Some(Entry::new(k, v, true, false))
-            }
-            InitResult::ReadExisting(v) => Some(Entry::new(k, v, false, false)),
-            InitResult::InitErr(_) => None,
-        }
-    }
-
-    pub(super) async fn get_or_try_insert_with_hash_and_fun<F, E>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Result<Entry<K, V>, Arc<E>>
+    /// Records a read access to the entry for the key. Returns `false` if the cache
+    /// does not contain a value for the key.
+    ///
+    /// This is the same as the `get` method except that it does not return the
+    /// value. It resets the idle timer of the entry and calls `expire_after_read`
+    /// of the [`Expiry`](../policy/trait.Expiry.html) if any.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn touch<Q>(&self, key: &Q) -> bool
     where
-        F: Future<Output = Result<V, E>>,
-        E: Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        if let Some(entry) = self
-            .base
-            .get_with_hash(&key, hash, never_ignore(), need_key, true)
-            .await
-        {
-            return Ok(entry);
-        }
-
-        self.try_insert_with_hash_and_fun(key, hash, init, need_key)
+        self.get_with_hash(key, self.base.hash(key), false)
             .await
+            .is_some()
     }
 
-    pub(super) async fn get_or_try_insert_with_hash_by_ref_and_fun<F, E, Q>(
-        &self,
-        key: &Q,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Result<Entry<K, V>, Arc<E>>
+    /// Returns the remaining time until the entry for the key expires.
+    ///
+    /// The returned duration is the earliest of the per-entry expiration time, the
+    /// time-to-live and the time-to-idle. Returns `None` if the cache does not
+    /// contain a value for the key or the entry does not expire.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn expires_in<Q>(&self, key: &Q) -> Option<Duration>
     where
-        F: Future<Output = Result<V, E>>,
-        E: Send + Sync + 'static,
         K: Borrow<Q>,
-        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+        Q: Hash + Eq + ?Sized,
     {
-        if let Some(entry) = self
-            .base
-            .get_with_hash(key, hash, never_ignore(), need_key, true)
-            .await
-        {
-            return Ok(entry);
-        }
-        let key = Arc::new(key.to_owned());
-        self.try_insert_with_hash_and_fun(key, hash, init, need_key)
-            .await
+        self.base.expires_in_with_hash(key, self.base.hash(key))
     }
 
-    async fn try_insert_with_hash_and_fun<F, E>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        init: Pin<&mut F>,
-        need_key: bool,
-    ) -> Result<Entry<K, V>, Arc<E>>
+    /// Discards any cached value for the key.
+    ///
+    /// If you need to get the value that has been discarded, use the
+    /// [`remove`](#method.remove) method instead.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn invalidate<Q>(&self, key: &Q)
     where
-        F: Future<Output = Result<V, E>>,
-        E: Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        let k = if need_key {
-            Some(Arc::clone(&key))
-        } else {
-            None
-        };
-
-        let type_id = ValueInitializer::<K, V, S>::type_id_for_try_get_with::<E>();
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_get_with;
-
-        match self
-            .value_initializer
-            .try_init_or_read(&key, hash, type_id, self, never_ignore(), init, post_init)
-            .await
-        {
-            InitResult::Initialized(v) => {
-                crossbeam_epoch::pin().flush();
-                Ok(Entry::new(k, v, true, false))
-            }
-            InitResult::ReadExisting(v) => Ok(Entry::new(k, v, false, false)),
-            InitResult::InitErr(e) => {
-                crossbeam_epoch::pin().flush();
-                Err(e)
-            }
-        }
+        let hash = self.base.hash(key);
+        self.invalidate_with_hash(key, hash, false).await;
     }
 
-    pub(crate) async fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
-        if self.base.is_map_disabled() {
-            return;
-        }
-
-        let (op, ts) = self.base.do_insert_with_hash(key, hash, value).await;
-        let mut cancel_guard = CancelGuard::new(&self.base.interrupted_op_ch_snd, ts);
-        cancel_guard.set_op(op.clone());
-
-        let should_block;
-        #[cfg(not(test))]
-        {
-            should_block = false;
-        }
-        #[cfg(test)]
-        {
-            should_block = self.schedule_write_op_should_block.load(Ordering::Acquire);
-        }
-
-        let hk = self.base.housekeeper.as_ref();
-        let event = self.base.write_op_ch_ready_event();
-
-        BaseCache::<K, V, S>::schedule_write_op(
-            &self.base.inner,
-            &self.base.write_op_ch,
-            event,
-            op,
-            ts,
-            hk,
-            should_block,
-        )
-        .await
-        .expect("Failed to schedule write op for insert");
-        cancel_guard.clear();
+    /// Discards any cached value for the key and returns a _clone_ of the value.
+    ///
+    /// If you do not need to get the value that has been discarded, use the
+    /// [`invalidate`](#method.invalidate) method instead.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.base.hash(key);
+        self.invalidate_with_hash(key, hash, true).await
     }
 
-    pub(crate) async fn compute_with_hash_and_fun<F, Fut>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        f: F,
-    ) -> compute::CompResult<K, V>
+    /// Discards the cached value for the key only if `condition` returns `true`
+    /// for the value, and returns a _clone_ of the discarded value.
+    ///
+    /// The check and the removal are done atomically; no other update to the
+    /// entry can happen between them. `condition` may be called more than once
+    /// when it conflicts with concurrent updates to the same entry.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn remove_if<Q>(&self, key: &Q, condition: impl FnMut(&V) -> bool + Send) -> Option<V>
     where
-        F: FnOnce(Option<Entry<K, V>>) -> Fut,
-        Fut: Future<Output = compute::Op<V>>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_compute_with;
-        match self
-            .value_initializer
-            .try_compute(key, hash, self, f, post_init, true)
-            .await
-        {
-            Ok(result) => result,
-            Err(_) => unreachable!(),
-        }
+        let hash = self.base.hash(key);
+        self.remove_with_hash_if(key, hash, condition).await
     }
 
-    pub(crate) async fn try_compute_with_hash_and_fun<F, Fut, E>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        f: F,
-    ) -> Result<compute::CompResult<K, V>, E>
+    /// Locks the key and returns a [`KeyGuard`] that unlocks it when dropped.
+    ///
+    /// While a task holds the `KeyGuard`, the other tasks inserting, updating or
+    /// removing the entry for the key wait until the guard is dropped. The
+    /// maintenance tasks do not wait for the lock; they defer the eviction and
+    /// expiration of the entry until the guard is dropped. This is useful for a
+    /// read-modify-write flow that spans several `.await`s and does not fit into a
+    /// single [`and_compute_with`][compute-with] closure.
+    ///
+    /// The lock is not reentrant. The task holding the guard must update the entry
+    /// through [`compute_with_guard`](#method.compute_with_guard). The other
+    /// methods writing the key wait for the guard to be dropped, so they never
+    /// complete if the holder calls them.
+    ///
+    /// The methods reading the entry, such as `get`, do not wait for the lock. The
+    /// compute methods, such as `and_compute_with`, hold the lock while they read
+    /// the entry, evaluate the closure and write the result.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::{future::Cache, ops::compute::Op};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder().support_key_locks().build();
+    ///     cache.insert("balance", 100).await;
+    ///
+    ///     {
+    ///         let guard = cache.lock_key(&"balance").await.unwrap();
+    ///         // No other task can update the balance until the guard is dropped.
+    ///         let balance = cache.get(&"balance").await.unwrap();
+    ///         cache
+    ///             .compute_with_guard(&guard, |_| async move { Op::Put(balance - 30) })
+    ///             .await
+    ///             .unwrap();
+    ///     }
+    ///
+    ///     assert_eq!(cache.get(&"balance").await, Some(70));
+    /// }
+    /// ```
+    ///
+    /// [compute-with]: ./struct.OwnedKeyEntrySelector.html#method.and_compute_with
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub async fn lock_key<Q>(&self, key: &Q) -> Result<KeyGuard<'_, K, S>, KeyLockError>
     where
-        F: FnOnce(Option<Entry<K, V>>) -> Fut,
-        Fut: Future<Output = Result<compute::Op<V>, E>>,
-        E: Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
     {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_compute_with;
-        self.value_initializer
-            .try_compute(key, hash, self, f, post_init, true)
-            .await
+        self.base.lock_key(Arc::new(key.to_owned())).await
     }
 
-    pub(crate) async fn upsert_with_hash_and_fun<F, Fut>(
+    /// Performs a compute operation on the entry for the key locked by `guard`.
+    ///
+    /// This method works in the same way as [`and_compute_with`][compute-with],
+    /// except that it does not lock the key; the key is already locked by the
+    /// caller holding `guard`. Use this method to update the entry while holding
+    /// a [`KeyGuard`] returned by [`lock_key`](#method.lock_key).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::ForeignKeyGuard`] if `guard` was not returned by
+    /// this cache.
+    ///
+    /// [compute-with]: ./struct.OwnedKeyEntrySelector.html#method.and_compute_with
+    pub async fn compute_with_guard<F, Fut>(
         &self,
-        key: Arc<K>,
-        hash: u64,
+        guard: &KeyGuard<'_, K, S>,
         f: F,
-    ) -> Entry<K, V>
+    ) -> Result<compute::CompResult<K, V>, KeyLockError>
     where
         F: FnOnce(Option<Entry<K, V>>) -> Fut,
-        Fut: Future<Output = V>,
+        Fut: Future<Output = compute::Op<V>>,
     {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_upsert_with;
+        if !self.base.is_own_key_guard(guard) {
+            return Err(KeyLockError::ForeignKeyGuard);
+        }
+
+        let key = Arc::clone(guard.key());
+        let hash = self.base.hash(&key);
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_compute_with;
         match self
             .value_initializer
-            .try_compute(key, hash, self, f, post_init, false)
+            .try_compute_locked(key, hash, self, f, post_init, true)
             .await
         {
-            Ok(CompResult::Inserted(entry) | CompResult::ReplacedWith(entry)) => entry,
-            _ => unreachable!(),
+            Ok(result) => Ok(result),
+            Err(_) => unreachable!(),
         }
     }
 
-    pub(crate) async fn invalidate_with_hash<Q>(
-        &self,
-        key: &Q,
-        hash: u64,
-        need_value: bool,
-    ) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        use futures_util::FutureExt;
-
-        self.base.retry_interrupted_ops().await;
-
-        // Lock the key for removal if blocking removal notification is enabled.
-        let mut kl = None;
-        let mut klg = None;
-        if self.base.is_removal_notifier_enabled() {
-            // To lock the key, we have to get Arc<K> for key (&Q).
-            //
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    pub fn invalidate_all(&self) {
+        self.base.invalidate_all();
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
+    /// method returns immediately and a background thread will apply the closure to
+    /// each cached value inserted before the time when `invalidate_entries_if` was
+    /// called. If the closure returns `true` on a value, that value will be evicted
+    /// from the cache.
+    ///
+    /// Also the `get` method will apply the closure to a value to determine if it
+    /// should have been invalidated. Therefore, it is guaranteed that the `get`
+    /// method must not return invalidated values.
+    ///
+    /// Note that you must call
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
+    /// at the cache creation time as the cache needs to maintain additional internal
+    /// data structures to support this method. Otherwise, calling this method will
+    /// fail with a
+    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
+    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(Arc::new(predicate))
+    }
+
+    /// Returns the status of a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method.
+    ///
+    /// Returns `None` if there is no predicate with the given ID. The status of a
+    /// finished (completed or cancelled) predicate is kept for a while after it has
+    /// finished; the cache remembers the status of the most recent 1024 finished
+    /// predicates.
+    ///
+    /// See [`PredicateStatus`][predicate-status] for the details of the status.
+    ///
+    /// [predicate-status]: ../enum.PredicateStatus.html
+    pub fn predicate_status(&self, id: &str) -> Option<PredicateStatus> {
+        self.base.predicate_status(id)
+    }
+
+    /// Cancels a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method.
+    ///
+    /// After the cancellation, the predicate will no longer be applied to the
+    /// cached entries. Entries that have already been invalidated by the predicate
+    /// are not restored.
+    ///
+    /// Returns `true` if the predicate was pending or in progress and has been
+    /// cancelled. Returns `false` if there is no such predicate or it has already
+    /// finished.
+    pub fn cancel_predicate(&self, id: &str) -> bool {
+        self.base.cancel_predicate(id)
+    }
+
+    /// Waits until a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method has
+    /// finished, and returns its final status.
+    ///
+    /// This method runs the pending maintenance tasks of the cache until the scan
+    /// for the predicate is completed, so it can take a while if the cache has many
+    /// entries. Returns `None` if there is no predicate with the given ID.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::{future::Cache, PredicateStatus};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let cache = Cache::builder()
+    ///         .max_capacity(100)
+    ///         .support_invalidation_closures()
+    ///         .build();
+    ///     for i in 0..10 {
+    ///         cache.insert(i, i * 10).await;
+    ///     }
+    ///
+    ///     let id = cache.invalidate_entries_if(|_k, v| v % 20 == 0)?;
+    ///
+    ///     let status = cache.wait_predicate(&id).await;
+    ///     assert_eq!(
+    ///         status,
+    ///         Some(PredicateStatus::Completed {
+    ///             scanned: 10,
+    ///             removed: 5
+    ///         })
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn wait_predicate(&self, id: &str) -> Option<PredicateStatus> {
+        loop {
+            match self.predicate_status(id) {
+                Some(status) if !status.is_finished() => self.run_pending_tasks().await,
+                status => return status,
+            }
+        }
+    }
+
+    /// Discards all cached entries having the given tag.
+    ///
+    /// The tags of the entries are given by the tag extractor closure set by
+    /// [`CacheBuilder::tags`][builder-tags] method. This method does nothing if the
+    /// cache does not have a tag extractor.
+    ///
+    /// Unlike the [`invalidate_entries_if`](#method.invalidate_entries_if) method,
+    /// this method looks up the entries in an index maintained by the cache, and
+    /// discards them before returning. It takes time proportional to the number of
+    /// the entries having the tag. The index includes the entries that have been
+    /// just inserted, so they are discarded even if the pending maintenance tasks
+    /// have not been run yet. The eviction listener is called for every discarded
+    /// entry with `RemovalCause::Explicit`.
+    ///
+    /// Entries inserted or updated concurrently with this method may or may not
+    /// be discarded.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     // The keys are `(tenant, record)` pairs.
+    ///     let cache: Cache<(&str, u32), String> = Cache::builder()
+    ///         .max_capacity(100)
+    ///         .tags(|(tenant, _record), _value| vec![format!("tenant:{tenant}").into()].into())
+    ///         .build();
+    ///
+    ///     cache.insert(("alice", 0), "a0".to_string()).await;
+    ///     cache.insert(("alice", 1), "a1".to_string()).await;
+    ///     cache.insert(("bob", 0), "b0".to_string()).await;
+    ///
+    ///     cache.invalidate_tag("tenant:alice").await;
+    ///
+    ///     assert!(cache.get(&("alice", 0)).await.is_none());
+    ///     assert!(cache.get(&("alice", 1)).await.is_none());
+    ///     assert_eq!(cache.get(&("bob", 0)).await, Some("b0".to_string()));
+    /// }
+    /// ```
+    ///
+    /// [builder-tags]: ./struct.CacheBuilder.html#method.tags
+    pub async fn invalidate_tag(&self, tag: &str) {
+        let Some(keys) = self.base.keys_for_tag(tag) else {
+            return;
+        };
+        for key in keys {
+            let hash = self.base.hash(&*key);
+            self.remove_with_hash_if(&*key, hash, |v| self.base.has_tag(&key, v, tag))
+                .await;
+        }
+    }
+
+    /// Declares that the entry for `key` depends on the entries for
+    /// `dependencies`.
+    ///
+    /// When any of the dependencies is invalidated, expired or evicted, the entry
+    /// for `key` is discarded too, and so are the entries depending on it. The
+    /// eviction listener is called for these entries with
+    /// `RemovalCause::Cascaded`. Dependency cycles are allowed; every entry in a
+    /// cycle is discarded at most once.
+    ///
+    /// Returns `false` if the cache does not have `key`, or if it does not have
+    /// some of the dependencies. In the latter case, the entry for `key` is
+    /// discarded immediately as if the missing dependencies had just been
+    /// invalidated.
+    ///
+    /// The dependencies are also recorded automatically for the `get_with` family
+    /// of methods: the entries read from this cache (by `get`, `get_with` and so
+    /// on) while the `init` future is being polled become the dependencies of the
+    /// inserted entry.
+    ///
+    /// Dependencies are held per key. Updating the value of a key does not
+    /// discard its dependents, and does not drop its own dependencies.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert("config", "v1".to_string()).await;
+    ///
+    ///     // Explicitly declare the dependency.
+    ///     cache.insert("page1", "page1 rendered with v1".to_string()).await;
+    ///     assert!(cache.add_dependencies("page1", ["config"]).await);
+    ///
+    ///     // Or let `get_with` record it automatically.
+    ///     let page2 = cache
+    ///         .get_with("page2", async {
+    ///             let config = cache.get("config").await.unwrap();
+    ///             format!("page2 rendered with {config}")
+    ///         })
+    ///         .await;
+    ///     assert_eq!(page2, "page2 rendered with v1");
+    ///
+    ///     cache.invalidate("config").await;
+    ///
+    ///     assert!(cache.get("page1").await.is_none());
+    ///     assert!(cache.get("page2").await.is_none());
+    /// }
+    /// ```
+    pub async fn add_dependencies<'a, Q>(
+        &self,
+        key: &Q,
+        dependencies: impl IntoIterator<Item = &'a Q>,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+    {
+        let hash = self.base.hash(key);
+        let Some(key) = self.base.get_key_with_hash(key, hash) else {
+            return false;
+        };
+
+        let mut deps = Vec::new();
+        let mut missing = false;
+        for dep in dependencies {
+            match self.base.get_key_with_hash(dep, self.base.hash(dep)) {
+                Some(dep) => deps.push(dep),
+                None => {
+                    missing = true;
+                    break;
+                }
+            }
+        }
+        if missing {
+            self.invalidate_cascaded(&key, hash).await;
+            return false;
+        }
+        self.add_dependencies_with_hash(&key, hash, deps).await
+    }
+
+    async fn add_dependencies_with_hash(&self, key: &Arc<K>, hash: u64, deps: Vec<Arc<K>>) -> bool {
+        if deps.is_empty() {
+            return true;
+        }
+        self.base.add_dependencies(key, &deps);
+
+        // A dependency may have been removed before the edges were added. Then its
+        // dependents have already been taken, so discard the entry here.
+        let all_present = deps.iter().all(|dep| {
+            self.base
+                .contains_key_with_hash(&**dep, self.base.hash(&**dep))
+        });
+        if !all_present {
+            self.invalidate_cascaded(key, hash).await;
+        }
+        all_present
+    }
+
+    async fn invalidate_cascaded(&self, key: &Arc<K>, hash: u64) {
+        self.remove_and_notify(&**key, hash, RemovalCause::Cascaded, || {
+            self.base.remove_entry(&**key, hash)
+        })
+        .await;
+        self.invalidate_dependents(key).await;
+    }
+
+    /// Makes the inserted entry depend on the keys read by its `init` future, and
+    /// records the key as read by the enclosing `init` future, if any.
+    pub(super) async fn add_tracked_dependencies(
+        &self,
+        key: &Arc<K>,
+        hash: u64,
+        deps: Vec<Arc<K>>,
+    ) {
+        self.base.record_read(key);
+        self.add_dependencies_with_hash(key, hash, deps).await;
+    }
+
+    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
+    /// value.
+    ///
+    /// Iterators do not block concurrent reads and writes on the cache. An entry can
+    /// be inserted to, invalidated or evicted from a cache while iterators are alive
+    /// on the same cache.
+    ///
+    /// Unlike the `get` method, visiting entries via an iterator do not update the
+    /// historic popularity estimator or reset idle timers for keys.
+    ///
+    /// # Guarantees
+    ///
+    /// In order to allow concurrent access to the cache, iterator's `next` method
+    /// does _not_ guarantee the following:
+    ///
+    /// - It does not guarantee to return a key-value pair (an entry) if its key has
+    ///   been inserted to the cache _after_ the iterator was created.
+    ///   - Such an entry may or may not be returned depending on key's hash and
+    ///     timing.
+    ///
+    /// and the `next` method guarantees the followings:
+    ///
+    /// - It guarantees not to return the same entry more than once.
+    /// - It guarantees not to return an entry if it has been removed from the cache
+    ///   after the iterator was created.
+    ///     - Note: An entry can be removed by following reasons:
+    ///         - Manually invalidated.
+    ///         - Expired (e.g. time-to-live).
+    ///         - Evicted as the cache capacity exceeded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert("Julia", 14).await;
+    ///
+    ///     let mut iter = cache.iter();
+    ///     let (k, v) = iter.next().unwrap(); // (Arc<K>, V)
+    ///     assert_eq!(*k, "Julia");
+    ///     assert_eq!(v, 14);
+    ///
+    ///     assert!(iter.next().is_none());
+    /// }
+    /// ```
+    ///
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet};
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        Iter::new(inner)
+    }
+
+    /// Creates an iterator visiting all entries in arbitrary order, yielding a
+    /// snapshot of the metadata of each entry along with the key and value. The
+    /// iterator element type is `(Arc<K>, V, EntryMetadata)`.
+    ///
+    /// Like the [`iter`](#method.iter) method, iterating does not update the
+    /// historic popularity estimator or reset the idle timer of the entries.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    ///
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert("Julia", 14).await;
+    ///
+    ///     let mut iter = cache.iter_with_metadata();
+    ///     let (k, v, metadata) = iter.next().unwrap();
+    ///     assert_eq!(*k, "Julia");
+    ///     assert_eq!(v, 14);
+    ///     assert_eq!(metadata.policy_weight(), 1);
+    ///
+    ///     assert!(iter.next().is_none());
+    /// }
+    /// ```
+    pub fn iter_with_metadata(&self) -> IterWithMetadata<'_, K, V> {
+        use crate::sync_base::iter::{
+            Iter as InnerIter, IterWithMetadata as InnerIterWithMetadata, ScanningGet,
+        };
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        IterWithMetadata::new(InnerIterWithMetadata::new(inner))
+    }
+
+    /// Creates an iterator visiting all keys in arbitrary order. The iterator
+    /// element type is `Arc<K>`.
+    ///
+    /// Unlike the [`iter`](#method.iter) method, this does not clone the values.
+    /// The same guarantees about concurrently inserted or removed entries apply.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        use crate::sync_base::iter::{Iter as InnerIter, Keys as InnerKeys, ScanningGet};
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        Keys::new(InnerKeys::new(inner))
+    }
+
+    /// Creates an iterator visiting all values in arbitrary order. The iterator
+    /// element type is `V`, a _clone_ of the cached value.
+    ///
+    /// The same guarantees as the [`iter`](#method.iter) method about
+    /// concurrently inserted or removed entries apply.
+    pub fn values(&self) -> Values<'_, K, V> {
+        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet, Values as InnerValues};
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        Values::new(InnerValues::new(inner))
+    }
+
+    /// Retains only the entries for which `f` returns `true`, and discards the
+    /// others immediately.
+    ///
+    /// Unlike the [`invalidate_entries_if`](#method.invalidate_entries_if) method,
+    /// this method scans the cache and discards the entries before the returned
+    /// future completes, and does not require the cache to support invalidation
+    /// closures. The eviction listener is called for every discarded entry with
+    /// `RemovalCause::Explicit`.
+    ///
+    /// Each entry is checked and discarded atomically, but the scan is not a
+    /// snapshot of the whole cache; entries inserted or updated during the scan
+    /// may or may not be visited.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     for i in 0..10 {
+    ///         cache.insert(i, i * 10).await;
+    ///     }
+    ///
+    ///     cache.retain(|k, _v| k % 2 == 0).await;
+    ///
+    ///     assert_eq!(cache.get(&2).await, Some(20));
+    ///     assert!(cache.get(&3).await.is_none());
+    /// }
+    /// ```
+    pub async fn retain(&self, mut f: impl FnMut(&K, &V) -> bool + Send) {
+        self.retain_and(|k, v| f(k, v), |_, _| ()).await;
+    }
+
+    /// Discards all entries from the cache immediately, and returns them as a
+    /// `Vec` of `(Arc<K>, V)` in arbitrary order.
+    ///
+    /// Like the [`retain`](#method.retain) method, the eviction listener is called
+    /// for every discarded entry with `RemovalCause::Explicit`. Entries that have
+    /// already expired are not returned.
+    pub async fn drain(&self) -> Vec<(Arc<K>, V)> {
+        let mut drained = Vec::new();
+        self.retain_and(|_, _| false, |k, v| drained.push((k, v)))
+            .await;
+        drained
+    }
+
+    async fn retain_and(
+        &self,
+        mut f: impl FnMut(&K, &V) -> bool + Send,
+        mut on_remove: impl FnMut(Arc<K>, V) + Send,
+    ) {
+        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet};
+
+        let mut iter =
+            InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        while let Some(key) = iter.next_key() {
+            let hash = self.base.hash(&*key);
+            if let Some(v) = self.remove_with_hash_if(&*key, hash, |v| !f(&key, v)).await {
+                on_remove(key, v);
+            }
+        }
+    }
+
+    /// Creates a [`Stream`][stream] visiting all key-value pairs in arbitrary
+    /// order. The stream element type is `(Arc<K>, V)`.
+    ///
+    /// Unlike the [`iter`](#method.iter) method, the returned stream yields to the
+    /// async runtime every time it has scanned a certain number of entries (128 by
+    /// default), so it is suitable for scanning a large cache in an async task.
+    /// The stream never holds a lock or a guard of the internal hash table across
+    /// an await point.
+    ///
+    /// Like the `iter` method, streaming does not update the historic popularity
+    /// estimator or reset the idle timer of the entries, and the same guarantees
+    /// about concurrently inserted or removed entries apply.
+    ///
+    /// [stream]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // futures-util = "0.3"
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use futures_util::StreamExt;
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert("Julia", 14).await;
+    ///
+    ///     let mut stream = cache.stream().yield_every(64);
+    ///     let (k, v) = stream.next().await.unwrap(); // (Arc<K>, V)
+    ///     assert_eq!(*k, "Julia");
+    ///     assert_eq!(v, 14);
+    ///
+    ///     assert!(stream.next().await.is_none());
+    /// }
+    /// ```
+    pub fn stream(&self) -> EntryStream<'_, K, V> {
+        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet};
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        EntryStream::new(inner)
+    }
+
+    /// Creates a [`Stream`][stream] visiting all keys in arbitrary order. The
+    /// stream element type is `Arc<K>`.
+    ///
+    /// This is the same as the [`stream`](#method.stream) method, but it does not
+    /// clone the values.
+    ///
+    /// [stream]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+    pub fn keys_stream(&self) -> KeyStream<'_, K, V> {
+        use crate::sync_base::iter::{Iter as InnerIter, ScanningGet};
+
+        let inner = InnerIter::with_single_cache_segment(&self.base, self.base.num_cht_segments());
+        KeyStream::new(inner)
+    }
+
+    /// Runs the given async closure for every key-value pair in the cache, with up
+    /// to `limit` closures running concurrently. `None` means no limit.
+    ///
+    /// This is a shorthand for calling `for_each_concurrent` of
+    /// [`StreamExt`][stream-ext] on the stream returned by the
+    /// [`stream`](#method.stream) method.
+    ///
+    /// [stream-ext]: https://docs.rs/futures/0.3/futures/stream/trait.StreamExt.html#method.for_each_concurrent
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.12", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    /// use std::sync::atomic::{AtomicU32, Ordering};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     for i in 0..10 {
+    ///         cache.insert(i, i).await;
+    ///     }
+    ///
+    ///     let sum = AtomicU32::new(0);
+    ///     cache
+    ///         .for_each_concurrent(4, |(_k, v)| {
+    ///             sum.fetch_add(v, Ordering::Relaxed);
+    ///             async {}
+    ///         })
+    ///         .await;
+    ///     assert_eq!(sum.load(Ordering::Relaxed), 45);
+    /// }
+    /// ```
+    pub async fn for_each_concurrent<F, Fut>(&self, limit: impl Into<Option<usize>>, f: F)
+    where
+        F: FnMut((Arc<K>, V)) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        use futures_util::StreamExt;
+
+        self.stream().for_each_concurrent(limit, f).await;
+    }
+
+    /// Performs any pending maintenance operations needed by the cache.
+    pub async fn run_pending_tasks(&self) {
+        if let Some(hk) = &self.base.housekeeper {
+            self.base.retry_interrupted_ops().await;
+            hk.run_pending_tasks(Arc::clone(&self.base.inner)).await;
+        }
+    }
+
+    /// Shrinks the internal concurrent hash table to fit the current number of
+    /// entries, releasing the memory of the bucket arrays that are no longer
+    /// needed.
+    ///
+    /// The hash table only grows while entries are inserted. The maintenance tasks
+    /// shrink it when it has become mostly empty, but leave some room for the
+    /// entries to be inserted again. This method shrinks it further, e.g. after the
+    /// cache has been cleared and will not be refilled soon.
+    ///
+    /// The entries discarded by `invalidate_all` and `invalidate_entries_if` are
+    /// removed from the hash table by the maintenance tasks, so call
+    /// [`run_pending_tasks`](#method.run_pending_tasks) before this method to
+    /// release the memory used for them. Other threads can keep reading and
+    /// writing the cache while the hash table is being shrunk.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100_000);
+    ///     for i in 0..50_000 {
+    ///         cache.insert(i, i).await;
+    ///     }
+    ///     cache.run_pending_tasks().await;
+    ///     let grown = cache.memory_usage().await.hash_table();
+    ///
+    ///     cache.invalidate_all();
+    ///     cache.run_pending_tasks().await;
+    ///     cache.shrink_to_fit();
+    ///     assert!(cache.memory_usage().await.hash_table() < grown / 4);
+    /// }
+    /// ```
+    pub fn shrink_to_fit(&self) {
+        self.base.shrink_to_fit();
+    }
+
+    /// Returns the earliest time when an entry with per-entry expiration will
+    /// expire, or `None` if there is no such entry.
+    ///
+    /// The entries are expired by the timers, which have a resolution of about one
+    /// second. The returned time is the start of the timer tick containing the
+    /// expiration time, so it may be up to one tick earlier than the actual
+    /// expiration time. The time is given by the clock of the cache.
+    ///
+    /// Only the entries whose insertions have been processed by the maintenance
+    /// tasks are considered. Call [`run_pending_tasks`](#method.run_pending_tasks)
+    /// first to include the recently inserted entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::{future::Cache, Expiry};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// struct MyExpiry;
+    ///
+    /// impl Expiry<&'static str, &'static str> for MyExpiry {
+    ///     fn expire_after_create(
+    ///         &self,
+    ///         _key: &&'static str,
+    ///         _value: &&'static str,
+    ///         _created_at: Instant,
+    ///     ) -> Option<Duration> {
+    ///         Some(Duration::from_secs(30))
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder().expire_after(MyExpiry).build();
+    ///     assert_eq!(cache.next_expiration().await, None);
+    ///
+    ///     cache.insert("key", "value").await;
+    ///     cache.run_pending_tasks().await;
+    ///     let next = cache.next_expiration().await.unwrap();
+    ///     assert!(next <= Instant::now() + Duration::from_secs(30));
+    /// }
+    /// ```
+    pub async fn next_expiration(&self) -> Option<std::time::Instant> {
+        self.base.next_expiration().await
+    }
+
+    /// Returns a snapshot of the statistics of the maintenance tasks of the cache.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert("key", "value").await;
+    ///     cache.get(&"key").await;
+    ///     cache.run_pending_tasks().await;
+    ///
+    ///     let stats = cache.maintenance_stats();
+    ///     assert!(stats.runs() >= 1);
+    ///     assert_eq!(stats.writes_applied(), 1);
+    ///     assert_eq!(stats.reads_applied(), 1);
+    ///     assert_eq!(stats.run_duration().count(), stats.runs());
+    /// }
+    /// ```
+    pub fn maintenance_stats(&self) -> MaintenanceStats {
+        self.base.maintenance_stats()
+    }
+
+    /// Writes the metrics of the cache in the [Prometheus text exposition
+    /// format][prometheus-format], labeled with `cache="<name>"`.
+    ///
+    /// The metrics are the number of entries, the weighted size and the maximum
+    /// capacity of the cache, the numbers of hits, misses, evictions (by cause) and
+    /// loads (by result), and a histogram of the load durations. Loads are the
+    /// calls to the `get_with` family methods that resolved their `init` futures.
+    ///
+    /// This method does not need any crate feature. To export the same metrics to
+    /// the [`metrics`][metrics-crate] crate facade instead, enable the `metrics`
+    /// feature.
+    ///
+    /// [prometheus-format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+    /// [metrics-crate]: https://crates.io/crates/metrics
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::builder().name("users").max_capacity(100).build();
+    ///     cache.get_with(1, async { "alice" }).await;
+    ///     cache.get(&1).await;
+    ///
+    ///     let mut buf = Vec::new();
+    ///     cache.render_prometheus(&mut buf).unwrap();
+    ///     let text = String::from_utf8(buf).unwrap();
+    ///     assert!(text.contains("moka_cache_hits_total{cache=\"users\"} 1"));
+    /// }
+    /// ```
+    pub fn render_prometheus(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        self.base
+            .metrics_snapshot()
+            .render_prometheus(self.name(), w)
+    }
+
+    /// Returns an approximate breakdown of the memory used by the cache, such as
+    /// the concurrent hash table, the cache entries, the deques, the timer wheel
+    /// and the frequency sketch. See [`MemoryReport`][memory-report] for the
+    /// details.
+    ///
+    /// The report does not include the heap memory owned by the keys and values.
+    /// Use [`memory_usage_with_heap_size`](#method.memory_usage_with_heap_size) to
+    /// include it.
+    ///
+    /// This method briefly takes the internal locks used by the maintenance tasks,
+    /// so avoid calling it too frequently.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert(1, "one".to_string()).await;
+    ///     cache.run_pending_tasks().await;
+    ///
+    ///     let report = cache.memory_usage().await;
+    ///     assert!(report.entries() > 0);
+    ///     assert!(report.total() > report.hash_table() + report.entries());
+    ///     assert_eq!(report.key_value_heap(), None);
+    /// }
+    /// ```
+    ///
+    /// [memory-report]: ../struct.MemoryReport.html
+    pub async fn memory_usage(&self) -> MemoryReport {
+        let mut report = self.base.memory_report().await;
+        self.value_initializer.record_memory_usage(&mut report);
+        report
+    }
+
+    /// Returns the same report as [`memory_usage`](#method.memory_usage), but also
+    /// includes the heap memory owned by the keys and values, reported by their
+    /// [`HeapSize`][heap-size] implementations.
+    ///
+    /// This method visits all entries in the cache, so it takes time proportional
+    /// to the number of entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka2 = { version = "0.13", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka2::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert(1, "a".repeat(64)).await;
+    ///
+    ///     let report = cache.memory_usage_with_heap_size().await;
+    ///     assert_eq!(report.key_value_heap(), Some(64));
+    /// }
+    /// ```
+    ///
+    /// [heap-size]: ../trait.HeapSize.html
+    pub async fn memory_usage_with_heap_size(&self) -> MemoryReport
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let mut report = self.memory_usage().await;
+        report.key_value_heap = Some(self.base.heap_size_of_entries());
+        report
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+//
+// private methods
+//
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) async fn get_or_insert_with_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut impl Future<Output = V>>,
+        mut replace_if: Option<impl FnMut(&V) -> bool + Send>,
+        need_key: bool,
+    ) -> Entry<K, V> {
+        let maybe_entry = self
+            .base
+            .get_with_hash(&key, hash, replace_if.as_mut(), need_key, true)
+            .await;
+        if let Some(entry) = maybe_entry {
+            entry
+        } else {
+            self.insert_with_hash_and_fun(key, hash, init, replace_if, need_key)
+                .await
+        }
+    }
+
+    pub(crate) async fn get_or_insert_with_hash_by_ref_and_fun<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        init: Pin<&mut impl Future<Output = V>>,
+        mut replace_if: Option<impl FnMut(&V) -> bool + Send>,
+        need_key: bool,
+    ) -> Entry<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let maybe_entry = self
+            .base
+            .get_with_hash(key, hash, replace_if.as_mut(), need_key, true)
+            .await;
+        if let Some(entry) = maybe_entry {
+            entry
+        } else {
+            let key = Arc::new(key.to_owned());
+            self.insert_with_hash_and_fun(key, hash, init, replace_if, need_key)
+                .await
+        }
+    }
+
+    async fn insert_with_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut impl Future<Output = V>>,
+        replace_if: Option<impl FnMut(&V) -> bool + Send>,
+        need_key: bool,
+    ) -> Entry<K, V> {
+        let k = if need_key {
+            Some(Arc::clone(&key))
+        } else {
+            None
+        };
+
+        let type_id = ValueInitializer::<K, V, S>::type_id_for_get_with();
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_get_with;
+
+        let load = self
+            .value_initializer
+            .try_init_or_read(&key, hash, type_id, self, replace_if, init, post_init);
+        #[cfg(feature = "tracing")]
+        let load = tracing::Instrument::instrument(
+            load,
+            crate::common::trace::load_span(self.base.name(), hash),
+        );
+
+        match load.await {
+            InitResult::Initialized(v) => {
+                crossbeam_epoch::pin().flush();
+                Entry::new(k, v, true, false)
+            }
+            InitResult::ReadExisting(v) => Entry::new(k, v, false, false),
+            InitResult::InitErr(_) => unreachable!(),
+        }
+    }
+
+    pub(crate) async fn get_or_insert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> V,
+    ) -> Entry<K, V> {
+        match self
+            .base
+            .get_with_hash(&key, hash, never_ignore(), true, true)
+            .await
+        {
+            Some(entry) => entry,
+            None => {
+                let value = init();
+                self.insert_with_hash(Arc::clone(&key), hash, value.clone())
+                    .await;
+                Entry::new(Some(key), value, true, false)
+            }
+        }
+    }
+
+    pub(crate) async fn get_or_insert_with_hash_by_ref<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        init: impl FnOnce() -> V,
+    ) -> Entry<K, V>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        match self
+            .base
+            .get_with_hash(key, hash, never_ignore(), true, true)
+            .await
+        {
+            Some(entry) => entry,
+            None => {
+                let key = Arc::new(key.to_owned());
+                let value = init();
+                self.insert_with_hash(Arc::clone(&key), hash, value.clone())
+                    .await;
+                Entry::new(Some(key), value, true, false)
+            }
+        }
+    }
+
+    pub(crate) async fn get_or_optionally_insert_with_hash_and_fun<F>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        F: Future<Output = Option<V>>,
+    {
+        let entry = self
+            .base
+            .get_with_hash(&key, hash, never_ignore(), need_key, true)
+            .await;
+        if entry.is_some() {
+            return entry;
+        }
+
+        self.optionally_insert_with_hash_and_fun(key, hash, init, need_key)
+            .await
+    }
+
+    pub(crate) async fn get_or_optionally_insert_with_hash_by_ref_and_fun<F, Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        F: Future<Output = Option<V>>,
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let entry = self
+            .base
+            .get_with_hash(key, hash, never_ignore(), need_key, true)
+            .await;
+        if entry.is_some() {
+            return entry;
+        }
+
+        let key = Arc::new(key.to_owned());
+        self.optionally_insert_with_hash_and_fun(key, hash, init, need_key)
+            .await
+    }
+
+    async fn optionally_insert_with_hash_and_fun<F>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        F: Future<Output = Option<V>>,
+    {
+        let k = if need_key {
+            Some(Arc::clone(&key))
+        } else {
+            None
+        };
+
+        let type_id = ValueInitializer::<K, V, S>::type_id_for_optionally_get_with();
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_optionally_get_with;
+
+        let load = self.value_initializer.try_init_or_read(
+            &key,
+            hash,
+            type_id,
+            self,
+            never_ignore(),
+            init,
+            post_init,
+        );
+        #[cfg(feature = "tracing")]
+        let load = tracing::Instrument::instrument(
+            load,
+            crate::common::trace::load_span(self.base.name(), hash),
+        );
+
+        match load.await {
+            InitResult::Initialized(v) => {
+                crossbeam_epoch::pin().flush();
+                Some(Entry::new(k, v, true, false))
+            }
+            InitResult::ReadExisting(v) => Some(Entry::new(k, v, false, false)),
+            InitResult::InitErr(_) => None,
+        }
+    }
+
+    pub(super) async fn get_or_try_insert_with_hash_and_fun<F, E>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Result<Entry<K, V>, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+    {
+        if let Some(entry) = self
+            .base
+            .get_with_hash(&key, hash, never_ignore(), need_key, true)
+            .await
+        {
+            return Ok(entry);
+        }
+
+        self.try_insert_with_hash_and_fun(key, hash, init, need_key)
+            .await
+    }
+
+    pub(super) async fn get_or_try_insert_with_hash_by_ref_and_fun<F, E, Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Result<Entry<K, V>, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        if let Some(entry) = self
+            .base
+            .get_with_hash(key, hash, never_ignore(), need_key, true)
+            .await
+        {
+            return Ok(entry);
+        }
+        let key = Arc::new(key.to_owned());
+        self.try_insert_with_hash_and_fun(key, hash, init, need_key)
+            .await
+    }
+
+    async fn try_insert_with_hash_and_fun<F, E>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: Pin<&mut F>,
+        need_key: bool,
+    ) -> Result<Entry<K, V>, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+    {
+        let k = if need_key {
+            Some(Arc::clone(&key))
+        } else {
+            None
+        };
+
+        let type_id = ValueInitializer::<K, V, S>::type_id_for_try_get_with::<E>();
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_get_with;
+
+        let load = self.value_initializer.try_init_or_read(
+            &key,
+            hash,
+            type_id,
+            self,
+            never_ignore(),
+            init,
+            post_init,
+        );
+        #[cfg(feature = "tracing")]
+        let load = tracing::Instrument::instrument(
+            load,
+            crate::common::trace::load_span(self.base.name(), hash),
+        );
+
+        match load.await {
+            InitResult::Initialized(v) => {
+                crossbeam_epoch::pin().flush();
+                Ok(Entry::new(k, v, true, false))
+            }
+            InitResult::ReadExisting(v) => Ok(Entry::new(k, v, false, false)),
+            InitResult::InitErr(e) => {
+                crossbeam_epoch::pin().flush();
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) async fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        self.insert_with_hash_and_ttl(key, hash, value, None).await;
+    }
+
+    pub(crate) async fn insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        ttl: Option<Duration>,
+    ) {
+        self.do_insert_with_hash_and_ttl(key, hash, value, ttl, false)
+            .await;
+    }
+
+    pub(crate) async fn swap_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> Option<V> {
+        self.do_insert_with_hash_and_ttl(key, hash, value, None, true)
+            .await
+    }
+
+    /// Same as `swap_with_hash`, but does not lock the key. The caller must hold
+    /// the key lock if key locks are enabled.
+    pub(crate) async fn swap_with_hash_locked(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Option<V> {
+        if self.base.is_map_disabled() {
+            return None;
+        }
+
+        let (op, ts, prev) = self
+            .base
+            .do_insert_with_hash_locked(key, hash, value, None)
+            .await;
+        self.schedule_upsert_op(op, ts).await;
+        prev.map(|entry| entry.value.clone())
+    }
+
+    async fn do_insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        ttl: Option<Duration>,
+        need_prev: bool,
+    ) -> Option<V> {
+        if self.base.is_map_disabled() {
+            return None;
+        }
+
+        let (op, ts, prev) = self.base.do_insert_with_hash(key, hash, value, ttl).await;
+        self.schedule_upsert_op(op, ts).await;
+
+        if need_prev {
+            prev.map(|entry| entry.value.clone())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) async fn insert_if_absent_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Option<V> {
+        if self.base.is_map_disabled() {
+            return None;
+        }
+
+        match self
+            .base
+            .do_insert_if_absent_with_hash(key, hash, value)
+            .await
+        {
+            Ok((op, ts)) => {
+                self.schedule_upsert_op(op, ts).await;
+                None
+            }
+            Err(current) => Some(current),
+        }
+    }
+
+    pub(crate) async fn replace_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        value: V,
+        condition: impl FnMut(&V) -> bool + Send,
+    ) -> Result<V, Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.base.is_map_disabled() {
+            return Err(None);
+        }
+
+        let (op, ts, prev) = self
+            .base
+            .do_replace_with_hash_if(key, hash, value, condition)
+            .await?;
+        self.schedule_upsert_op(op, ts).await;
+        Ok(prev)
+    }
+
+    async fn schedule_upsert_op(&self, op: WriteOp<K, V>, ts: Instant) {
+        self.schedule_pending_op(self.pending_op(op, ts)).await;
+    }
+
+    /// Wraps the write op with a cancel guard, which saves the op to the
+    /// interrupted op channel if the caller is cancelled before scheduling it.
+    fn pending_op(&self, op: WriteOp<K, V>, ts: Instant) -> PendingOp<'_, K, V> {
+        let mut cancel_guard = CancelGuard::new(&self.base.interrupted_op_ch_snd, ts);
+        cancel_guard.set_op(op.clone());
+        PendingOp {
+            op,
+            ts,
+            cancel_guard,
+        }
+    }
+
+    pub(crate) async fn compute_with_hash_and_fun<F, Fut>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: F,
+    ) -> compute::CompResult<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Fut,
+        Fut: Future<Output = compute::Op<V>>,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_compute_with;
+        match self
+            .value_initializer
+            .try_compute(key, hash, self, f, post_init, true)
+            .await
+        {
+            Ok(result) => result,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    pub(crate) async fn try_compute_with_hash_and_fun<F, Fut, E>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: F,
+    ) -> Result<compute::CompResult<K, V>, E>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Fut,
+        Fut: Future<Output = Result<compute::Op<V>, E>>,
+        E: Send + Sync + 'static,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_compute_with;
+        self.value_initializer
+            .try_compute(key, hash, self, f, post_init, true)
+            .await
+    }
+
+    pub(crate) async fn upsert_with_hash_and_fun<F, Fut>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: F,
+    ) -> Entry<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_upsert_with;
+        match self
+            .value_initializer
+            .try_compute(key, hash, self, f, post_init, false)
+            .await
+        {
+            Ok(CompResult::Inserted(entry) | CompResult::ReplacedWith(entry)) => entry,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Same as `invalidate_with_hash`, but does not lock the key. The caller must
+    /// hold the key lock if key locks are enabled.
+    pub(crate) async fn invalidate_with_hash_locked(
+        &self,
+        key: &Arc<K>,
+        hash: u64,
+        need_value: bool,
+    ) -> Option<V> {
+        let (kv, pending_op) = self
+            .remove_and_notify_locked(RemovalCause::Explicit, || {
+                self.base.remove_entry(&**key, hash)
+            })
+            .await?;
+        self.schedule_pending_op(pending_op).await;
+        crossbeam_epoch::pin().flush();
+        self.invalidate_dependents(&kv.key).await;
+        if need_value {
+            Some(kv.entry.value.clone())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) async fn invalidate_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_value: bool,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_invalidate_with_hash(key, hash, need_value, || self.base.remove_entry(key, hash))
+            .await
+    }
+
+    pub(crate) async fn remove_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        condition: impl FnMut(&V) -> bool + Send,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_invalidate_with_hash(key, hash, true, || {
+            self.base.remove_entry_if(key, hash, condition)
+        })
+        .await
+    }
+
+    async fn do_invalidate_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_value: bool,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let kv = self
+            .remove_and_notify(key, hash, RemovalCause::Explicit, remove)
+            .await?;
+        self.invalidate_dependents(&kv.key).await;
+        if need_value {
+            Some(kv.entry.value.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Invalidates the entries depending on the removed key, the entries depending
+    /// on them, and so on.
+    async fn invalidate_dependents(&self, key: &K) {
+        let mut keys = self.base.take_dependents(key);
+        while let Some(key) = keys.pop() {
+            let hash = self.base.hash(&*key);
+            self.remove_and_notify(&*key, hash, RemovalCause::Cascaded, || {
+                self.base.remove_entry(&*key, hash)
+            })
+            .await;
+            keys.extend(self.base.take_dependents(&key));
+        }
+    }
+
+    /// Removes the entry, notifies the removal and schedules the write op. Returns
+    /// the removed entry.
+    async fn remove_and_notify<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        cause: RemovalCause,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<KvEntry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (kv, pending_op) = self
+            .remove_and_notify_without_scheduling(key, hash, cause, remove)
+            .await?;
+        self.schedule_pending_op(pending_op).await;
+        crossbeam_epoch::pin().flush();
+        Some(kv)
+    }
+
+    /// Removes the entry and notifies the removal. Returns the removed entry and
+    /// the write op to schedule.
+    async fn remove_and_notify_without_scheduling<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        cause: RemovalCause,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<(KvEntry<K, V>, PendingOp<'_, K, V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.retry_interrupted_ops().await;
+
+        // Lock the key for removal if blocking removal notification or key locks
+        // are enabled.
+        let mut kl = None;
+        let mut klg = None;
+        if self.base.has_key_locks() {
+            // To lock the key, we have to get Arc<K> for key (&Q).
+            //
             // TODO: Enhance this if possible. This is rather hack now because
             // it cannot prevent race conditions like this:
             //
@@ -1933,362 +3696,1990 @@ where
                 klg = if let Some(lock) = &kl {
                     Some(lock.lock().await)
                 } else {
-                    None
+                    None
+                };
+            }
+        }
+
+        let removed = self.remove_and_notify_locked(cause, remove).await;
+
+        // Drop the locks before scheduling write op to avoid a potential dead lock.
+        // (Scheduling write can do spin lock when the queue is full, and queue will
+        // be drained by the housekeeping thread that can lock the same key)
+        std::mem::drop(klg);
+        std::mem::drop(kl);
+
+        removed
+    }
+
+    /// Same as `remove_and_notify_without_scheduling`, but does not lock the key.
+    /// The caller must hold the key lock if key locks are enabled.
+    async fn remove_and_notify_locked(
+        &self,
+        cause: RemovalCause,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<(KvEntry<K, V>, PendingOp<'_, K, V>)> {
+        use futures_util::FutureExt;
+
+        match remove() {
+            None => None,
+            Some(kv) => {
+                let now = self.base.current_time_from_expiration_clock();
+
+                let info = kv.entry.entry_info();
+                let entry_gen = info.incr_entry_gen();
+
+                let op: WriteOp<K, V> = WriteOp::Remove {
+                    kv_entry: kv.clone(),
+                    entry_gen,
+                };
+
+                // Async Cancellation Safety: To ensure the below future should be
+                // executed even if our caller async task is cancelled, we create a
+                // cancel guard for the future (and the op). If our caller is
+                // cancelled while we are awaiting for the future, the cancel guard
+                // will save the future and the op to the interrupted_op_ch channel,
+                // so that we can resume/retry later.
+                let mut cancel_guard = CancelGuard::new(&self.base.interrupted_op_ch_snd, now);
+
+                if self.base.is_removal_notifier_enabled() {
+                    let future = self
+                        .base
+                        .notify_invalidate(&kv.key, &kv.entry, cause)
+                        .boxed()
+                        .shared();
+                    cancel_guard.set_future_and_op(future.clone(), op.clone());
+                    // Send notification to the eviction listener.
+                    future.await;
+                    cancel_guard.unset_future();
+                } else {
+                    cancel_guard.set_op(op.clone());
+                }
+
+                let pending_op = PendingOp {
+                    op,
+                    ts: now,
+                    cancel_guard,
                 };
+                Some((kv, pending_op))
+            }
+        }
+    }
+
+    /// Schedules the write op of an insert or a removal that has been applied to
+    /// the hash table.
+    async fn schedule_pending_op(&self, pending_op: PendingOp<'_, K, V>) {
+        self.try_schedule_pending_op(pending_op)
+            .await
+            .expect("Failed to schedule write op");
+    }
+
+    /// Same as `schedule_pending_op`, but returns an error instead of panicking if
+    /// the write op channel has been disconnected.
+    async fn try_schedule_pending_op(
+        &self,
+        pending_op: PendingOp<'_, K, V>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let PendingOp {
+            op,
+            ts,
+            mut cancel_guard,
+        } = pending_op;
+
+        let should_block;
+        #[cfg(not(test))]
+        {
+            should_block = false;
+        }
+        #[cfg(test)]
+        {
+            should_block = self.schedule_write_op_should_block.load(Ordering::Acquire);
+        }
+
+        let event = self.base.write_op_ch_ready_event();
+        let hk = self.base.housekeeper.as_ref();
+
+        let result = BaseCache::<K, V, S>::schedule_write_op(
+            &self.base.inner,
+            &self.base.write_op_ch,
+            event,
+            op,
+            ts,
+            hk,
+            should_block,
+        )
+        .await;
+        cancel_guard.clear();
+        result
+    }
+}
+
+// For unit tests.
+// For unit tests.
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S> {
+    pub(crate) fn is_table_empty(&self) -> bool {
+        self.entry_count() == 0
+    }
+
+    pub(crate) fn is_waiter_map_empty(&self) -> bool {
+        self.value_initializer.waiter_count() == 0
+    }
+}
+
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn invalidation_predicate_count(&self) -> usize {
+        self.base.invalidation_predicate_count()
+    }
+
+    pub(crate) async fn reconfigure_for_testing(&mut self) {
+        self.base.reconfigure_for_testing().await;
+    }
+
+    pub(crate) async fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
+        self.base.set_expiration_clock(clock).await;
+    }
+
+    pub(crate) fn key_locks_map_is_empty(&self) -> bool {
+        self.base.key_locks_map_is_empty()
+    }
+
+    fn run_pending_tasks_initiation_count(&self) -> usize {
+        self.base
+            .housekeeper
+            .as_ref()
+            .map(|hk| hk.start_count.load(Ordering::Acquire))
+            .expect("housekeeper is not set")
+    }
+
+    fn run_pending_tasks_completion_count(&self) -> usize {
+        self.base
+            .housekeeper
+            .as_ref()
+            .map(|hk| hk.complete_count.load(Ordering::Acquire))
+            .expect("housekeeper is not set")
+    }
+}
+
+// AS of Rust 1.71, we cannot make this function into a `const fn` because mutable
+// references are not allowed.
+// See [#57349](https://github.com/rust-lang/rust/issues/57349).
+#[inline]
+fn never_ignore<'a, V>() -> Option<&'a mut fn(&V) -> bool> {
+    None
+}
+
+// To see the debug prints, run test as `cargo test -- --nocapture`
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use crate::{
+        common::{time::Clock, HousekeeperConfig},
+        future::FutureExt,
+        notification::{ListenerFuture, RemovalCause},
+        ops::compute,
+        policy::{test_utils::ExpiryCallCounters, EvictionPolicy},
+        Expiry, PredicateStatus,
+    };
+
+    use async_lock::{Barrier, Mutex};
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicU32, AtomicU8, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant as StdInstant, SystemTime},
+        vec,
+    };
+    use tokio::time::sleep;
+
+    #[test]
+    fn futures_are_send() {
+        let cache = Cache::new(0);
+
+        fn is_send(_: impl Send) {}
+
+        // pub fns
+        is_send(cache.add_dependencies(&(), [&()]));
+        is_send(cache.compute_many([()], |_| async { [compute::Op::Nop] }));
+        is_send(cache.drain());
+        is_send(cache.for_each_concurrent(None, |_| async {}));
+        is_send(cache.get(&()));
+        is_send(cache.get_entry(&()));
+        is_send(cache.get_with((), async {}));
+        is_send(cache.get_with_by_ref(&(), async {}));
+        #[allow(deprecated)]
+        is_send(cache.get_with_if((), async {}, |_| false));
+        is_send(cache.insert((), ()));
+        is_send(cache.insert_expiring_at((), (), SystemTime::now()));
+        is_send(cache.insert_if_absent((), ()));
+        is_send(cache.insert_with_deadline((), (), StdInstant::now()));
+        is_send(cache.insert_with_ttl((), (), Duration::ZERO));
+        is_send(cache.invalidate(&()));
+        is_send(cache.invalidate_tag(""));
+        is_send(cache.lock_key(&()));
+        is_send(cache.next_expiration());
+        is_send(cache.optionally_get_with((), async { None }));
+        is_send(cache.optionally_get_with_by_ref(&(), async { None }));
+        is_send(cache.remove(&()));
+        is_send(cache.remove_if(&(), |_| false));
+        is_send(cache.replace(&(), &(), ()));
+        is_send(cache.replace_if(&(), (), |_| false));
+        is_send(cache.replace_if_present(&(), ()));
+        is_send(cache.retain(|_, _| true));
+        is_send(cache.run_pending_tasks());
+        is_send(cache.set_expiry(&(), Duration::ZERO));
+        is_send(cache.swap((), ()));
+        is_send(cache.touch(&()));
+        is_send(cache.try_get_with((), async { Err(()) }));
+        is_send(cache.try_get_with_by_ref(&(), async { Err(()) }));
+        is_send(cache.wait_predicate(""));
+
+        // entry fns
+        is_send(
+            cache
+                .entry(())
+                .and_compute_with(|_| async { compute::Op::Nop }),
+        );
+        is_send(
+            cache
+                .entry(())
+                .and_try_compute_with(|_| async { Ok(compute::Op::Nop) as Result<_, Infallible> }),
+        );
+        is_send(cache.entry(()).and_upsert_with(|_| async {}));
+        is_send(cache.entry(()).or_default());
+        is_send(cache.entry(()).or_insert(()));
+        is_send(cache.entry(()).or_insert_with(async {}));
+        is_send(cache.entry(()).or_insert_with_if(async {}, |_| false));
+        is_send(cache.entry(()).or_optionally_insert_with(async { None }));
+        is_send(cache.entry(()).or_try_insert_with(async { Err(()) }));
+
+        // entry_by_ref fns
+        is_send(
+            cache
+                .entry_by_ref(&())
+                .and_compute_with(|_| async { compute::Op::Nop }),
+        );
+        is_send(
+            cache
+                .entry_by_ref(&())
+                .and_try_compute_with(|_| async { Ok(compute::Op::Nop) as Result<_, Infallible> }),
+        );
+        is_send(cache.entry_by_ref(&()).and_upsert_with(|_| async {}));
+        is_send(cache.entry_by_ref(&()).or_default());
+        is_send(cache.entry_by_ref(&()).or_insert(()));
+        is_send(cache.entry_by_ref(&()).or_insert_with(async {}));
+        is_send(
+            cache
+                .entry_by_ref(&())
+                .or_insert_with_if(async {}, |_| false),
+        );
+        is_send(
+            cache
+                .entry_by_ref(&())
+                .or_optionally_insert_with(async { None }),
+        );
+        is_send(
+            cache
+                .entry_by_ref(&())
+                .or_try_insert_with(async { Err(()) }),
+        );
+    }
+
+    #[tokio::test]
+    async fn max_capacity_zero() {
+        let mut cache = Cache::new(0);
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, ()).await;
+
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).await.is_none());
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).await.is_none());
+        assert_eq!(cache.entry_count(), 0)
+    }
+
+    #[tokio::test]
+    async fn basic_single_async_task() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        cache.run_pending_tasks().await;
+        // counts: a -> 1, b -> 1
+
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
+        assert!(cache.contains_key(&"c"));
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks().await;
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks().await;
+        // counts: a -> 2, b -> 2, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "david").await; //   count: d -> 0
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None); //   d -> 1
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", "david").await;
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d").await, None); //   d -> 2
+
+        // "d" should be admitted and "c" should be evicted
+        // because d's frequency is higher than c's.
+        cache.insert("d", "dennis").await;
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"c").await, None);
+        assert_eq!(cache.get(&"d").await, Some("dennis"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        cache.invalidate(&"b").await;
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"b").await, None);
+        assert!(!cache.contains_key(&"b"));
+
+        assert!(cache.remove(&"b").await.is_none());
+        assert_eq!(cache.remove(&"d").await, Some("dennis"));
+        expected.push((Arc::new("d"), "dennis", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None);
+        assert!(!cache.contains_key(&"d"));
+
+        verify_notification_vec(&cache, actual, &expected).await;
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[tokio::test]
+    async fn basic_lru_single_thread() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .eviction_policy(EvictionPolicy::lru())
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        cache.run_pending_tasks().await;
+        // a -> b
+
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
+        assert!(cache.contains_key(&"c"));
+        cache.run_pending_tasks().await;
+        // a -> b -> c
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks().await;
+        // c -> a -> b
+
+        // "d" should be admitted because the cache uses the LRU strategy.
+        cache.insert("d", "david").await;
+        // "c" is the LRU and should have be evicted.
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"c").await, None);
+        assert_eq!(cache.get(&"d").await, Some("david"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+        cache.run_pending_tasks().await;
+        // a -> b -> d
+
+        cache.invalidate(&"b").await;
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        // a -> d
+        assert_eq!(cache.get(&"b").await, None);
+        assert!(!cache.contains_key(&"b"));
+
+        assert!(cache.remove(&"b").await.is_none());
+        assert_eq!(cache.remove(&"d").await, Some("david"));
+        expected.push((Arc::new("d"), "david", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        // a
+        assert_eq!(cache.get(&"d").await, None);
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("e", "emily").await;
+        cache.insert("f", "frank").await;
+        // "a" should be evicted because it is the LRU.
+        cache.insert("g", "gina").await;
+        expected.push((Arc::new("a"), "alice", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        // e -> f -> g
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"e").await, Some("emily"));
+        assert_eq!(cache.get(&"f").await, Some("frank"));
+        assert_eq!(cache.get(&"g").await, Some("gina"));
+        assert!(!cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"e"));
+        assert!(cache.contains_key(&"f"));
+        assert!(cache.contains_key(&"g"));
+
+        verify_notification_vec(&cache, actual, &expected).await;
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[tokio::test]
+    async fn size_aware_eviction() {
+        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
+
+        let alice = ("alice", 10);
+        let bob = ("bob", 15);
+        let bill = ("bill", 20);
+        let cindy = ("cindy", 5);
+        let david = ("david", 15);
+        let dennis = ("dennis", 15);
+
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(31)
+            .weigher(weigher)
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", alice).await;
+        cache.insert("b", bob).await;
+        assert_eq!(cache.get(&"a").await, Some(alice));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b").await, Some(bob));
+        cache.run_pending_tasks().await;
+        // order (LRU -> MRU) and counts: a -> 1, b -> 1
+
+        cache.insert("c", cindy).await;
+        assert_eq!(cache.get(&"c").await, Some(cindy));
+        assert!(cache.contains_key(&"c"));
+        // order and counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks().await;
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a").await, Some(alice));
+        assert_eq!(cache.get(&"b").await, Some(bob));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks().await;
+        // order and counts: c -> 1, a -> 2, b -> 2
+
+        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
+        // "d" must have higher count than 3, which is the aggregated count
+        // of "a" and "c".
+        cache.insert("d", david).await; //   count: d -> 0
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None); //   d -> 1
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", david).await;
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d").await, None); //   d -> 2
+
+        cache.insert("d", david).await;
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None); //   d -> 3
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", david).await;
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d").await, None); //   d -> 4
+
+        // Finally "d" should be admitted by evicting "c" and "a".
+        cache.insert("d", dennis).await;
+        expected.push((Arc::new("c"), cindy, RemovalCause::Size));
+        expected.push((Arc::new("a"), alice, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"b").await, Some(bob));
+        assert_eq!(cache.get(&"c").await, None);
+        assert_eq!(cache.get(&"d").await, Some(dennis));
+        assert!(!cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
+        cache.insert("b", bill).await;
+        expected.push((Arc::new("b"), bob, RemovalCause::Replaced));
+        expected.push((Arc::new("d"), dennis, RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"b").await, Some(bill));
+        assert_eq!(cache.get(&"d").await, None);
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"d"));
+
+        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
+        cache.insert("a", alice).await;
+        cache.insert("b", bob).await;
+        expected.push((Arc::new("b"), bill, RemovalCause::Replaced));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"a").await, Some(alice));
+        assert_eq!(cache.get(&"b").await, Some(bob));
+        assert_eq!(cache.get(&"d").await, None);
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"d"));
+
+        // Verify the sizes.
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.weighted_size(), 25);
+
+        verify_notification_vec(&cache, actual, &expected).await;
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[tokio::test]
+    async fn basic_multi_async_tasks() {
+        let num_tasks = 2;
+        let num_threads = 2;
+
+        let cache = Cache::new(100);
+        let barrier = Arc::new(Barrier::new(num_tasks + num_threads as usize));
+
+        let tasks = (0..num_tasks)
+            .map(|id| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+
+                tokio::spawn(async move {
+                    barrier.wait().await;
+
+                    cache.insert(10, format!("{id}-100")).await;
+                    cache.get(&10).await;
+                    cache.insert(20, format!("{id}-200")).await;
+                    cache.invalidate(&10).await;
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let threads = (0..num_threads)
+            .map(|id| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                let rt = tokio::runtime::Handle::current();
+
+                std::thread::spawn(move || {
+                    rt.block_on(barrier.wait());
+
+                    rt.block_on(cache.insert(10, format!("{id}-100")));
+                    rt.block_on(cache.get(&10));
+                    rt.block_on(cache.insert(20, format!("{id}-200")));
+                    rt.block_on(cache.invalidate(&10));
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let _ = futures_util::future::join_all(tasks).await;
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        assert!(cache.get(&10).await.is_none());
+        assert!(cache.get(&20).await.is_some());
+        assert!(!cache.contains_key(&10));
+        assert!(cache.contains_key(&20));
+    }
+
+    #[tokio::test]
+    async fn invalidate_all() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(cache.contains_key(&"c"));
+
+        // `cache.run_pending_tasks().await` is no longer needed here before invalidating. The last
+        // modified timestamp of the entries were updated when they were inserted.
+        // https://github.com/moka-rs/moka/issues/155
+
+        cache.invalidate_all();
+        expected.push((Arc::new("a"), "alice", RemovalCause::Explicit));
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+
+        cache.insert("d", "david").await;
+        cache.run_pending_tasks().await;
+
+        assert!(cache.get(&"a").await.is_none());
+        assert!(cache.get(&"b").await.is_none());
+        assert!(cache.get(&"c").await.is_none());
+        assert_eq!(cache.get(&"d").await, Some("david"));
+        assert!(!cache.contains_key(&"a"));
+        assert!(!cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        verify_notification_vec(&cache, actual, &expected).await;
+    }
+
+    // This test is for https://github.com/moka-rs/moka/issues/155
+    #[tokio::test]
+    async fn invalidate_all_without_running_pending_tasks() {
+        let cache = Cache::new(1024);
+
+        assert_eq!(cache.get(&0).await, None);
+        cache.insert(0, 1).await;
+        assert_eq!(cache.get(&0).await, Some(1));
+
+        cache.invalidate_all();
+        assert_eq!(cache.get(&0).await, None);
+    }
+
+    #[tokio::test]
+    async fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        use std::collections::HashSet;
+
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice").await;
+        cache.insert(1, "bob").await;
+        cache.insert(2, "alex").await;
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks().await;
+
+        assert_eq!(cache.get(&0).await, Some("alice"));
+        assert_eq!(cache.get(&1).await, Some("bob"));
+        assert_eq!(cache.get(&2).await, Some("alex"));
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+
+        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
+        let id = cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
+        assert_eq!(cache.invalidation_predicate_count(), 1);
+        expected.push((Arc::new(0), "alice", RemovalCause::Explicit));
+        expected.push((Arc::new(2), "alex", RemovalCause::Explicit));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        cache.insert(3, "alice").await;
+
+        // Run the invalidation task and wait for it to finish.
+        assert!(matches!(
+            cache.wait_predicate(&id).await,
+            Some(PredicateStatus::Completed { .. })
+        ));
+
+        assert!(cache.get(&0).await.is_none());
+        assert!(cache.get(&2).await.is_none());
+        assert_eq!(cache.get(&1).await, Some("bob"));
+        // This should survive as it was inserted after calling invalidate_entries_if.
+        assert_eq!(cache.get(&3).await, Some("alice"));
+
+        assert!(!cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+
+        let id1 = cache.invalidate_entries_if(|_k, &v| v == "alice")?;
+        let id2 = cache.invalidate_entries_if(|_k, &v| v == "bob")?;
+        assert_eq!(cache.invalidation_predicate_count(), 2);
+        // key 1 was inserted before key 3.
+        expected.push((Arc::new(1), "bob", RemovalCause::Explicit));
+        expected.push((Arc::new(3), "alice", RemovalCause::Explicit));
+
+        // Run the invalidation task and wait for it to finish.
+        assert!(matches!(
+            cache.wait_predicate(&id1).await,
+            Some(PredicateStatus::Completed { .. })
+        ));
+        assert!(matches!(
+            cache.wait_predicate(&id2).await,
+            Some(PredicateStatus::Completed { .. })
+        ));
+
+        assert!(cache.get(&1).await.is_none());
+        assert!(cache.get(&3).await.is_none());
+
+        assert!(!cache.contains_key(&1));
+        assert!(!cache.contains_key(&3));
+
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        verify_notification_vec(&cache, actual, &expected).await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalidate_tag() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Tag each entry with its value.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .tags(|_k, v: &&str| vec![(*v).into()].into())
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(1, "red").await;
+        cache.insert(2, "red").await;
+        cache.insert(3, "blue").await;
+        cache.run_pending_tasks().await;
+
+        // Change the tag of key 2 without running the pending tasks.
+        cache.insert(2, "blue").await;
+        expected.push((Arc::new(2), "red", RemovalCause::Replaced));
+
+        cache.invalidate_tag("red").await;
+        expected.push((Arc::new(1), "red", RemovalCause::Explicit));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.get(&2).await, Some("blue"));
+
+        cache.invalidate_tag("green").await;
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 2);
+
+        cache.invalidate_tag("blue").await;
+        expected.push((Arc::new(2), "blue", RemovalCause::Explicit));
+        expected.push((Arc::new(3), "blue", RemovalCause::Explicit));
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        // A removed entry is no longer indexed.
+        cache.insert(4, "red").await;
+        cache.invalidate(&4).await;
+        expected.push((Arc::new(4), "red", RemovalCause::Explicit));
+        cache.insert(5, "yellow").await;
+        cache.run_pending_tasks().await;
+        cache.invalidate_tag("red").await;
+        assert_eq!(cache.entry_count(), 1);
+
+        // The entries are visited in arbitrary order, so sort the notifications
+        // for the "blue" tag.
+        {
+            let mut a = actual.lock().await;
+            a[2..4].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
+        }
+        verify_notification_vec(&cache, actual, &expected).await;
+
+        // A cache without a tag extractor does nothing.
+        let cache = Cache::new(100);
+        cache.insert(1, "red").await;
+        cache.invalidate_tag("red").await;
+        assert_eq!(cache.get(&1).await, Some("red"));
+    }
+
+    #[tokio::test]
+    async fn invalidate_tag_before_maintenance() {
+        // Tag each entry with its value.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .tags(|_k, v: &&str| vec![(*v).into()].into())
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // None of the entries are admitted to the cache policies yet. Key 3 is
+        // updated from "red" to "blue".
+        cache.insert(1, "red").await;
+        cache.insert(2, "blue").await;
+        cache.insert(3, "red").await;
+        cache.insert(3, "blue").await;
+        assert_eq!(cache.base.write_op_ch.len(), 4);
+
+        cache.invalidate_tag("red").await;
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+
+        // The pending write ops were not applied. (One op was added for the
+        // removal)
+        assert_eq!(cache.base.write_op_ch.len(), 5);
+
+        cache.invalidate_tag("blue").await;
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+
+        // The removed entries are no longer indexed.
+        assert_eq!(cache.base.keys_for_tag("red"), Some(vec![]));
+        assert_eq!(cache.base.keys_for_tag("blue"), Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn dependencies() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 1..=6 {
+            cache.insert(i, i * 10).await;
+        }
+        cache.run_pending_tasks().await;
+
+        // 3 -> 2 -> 1 -> 3 (cycle)
+        assert!(cache.add_dependencies(&2, [&1]).await);
+        assert!(cache.add_dependencies(&3, [&2]).await);
+        assert!(cache.add_dependencies(&1, [&3]).await);
+
+        cache.invalidate(&1).await;
+        expected.push((Arc::new(1), 10, RemovalCause::Explicit));
+        expected.push((Arc::new(2), 20, RemovalCause::Cascaded));
+        expected.push((Arc::new(3), 30, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        // A missing dependency discards the dependent immediately.
+        assert!(!cache.add_dependencies(&4, [&5, &99]).await);
+        expected.push((Arc::new(4), 40, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&4));
+
+        // A missing dependent is ignored.
+        assert!(!cache.add_dependencies(&100, [&5]).await);
+        assert!(cache.contains_key(&5));
+
+        // The dependencies are recorded by `get_with`. 8 depends on 7, and 7
+        // depends on 6.
+        let v = cache
+            .get_with(8, async {
+                cache
+                    .get_with(7, async { cache.get(&6).await.unwrap() + 1 })
+                    .await
+                    + 1
+            })
+            .await;
+        assert_eq!(v, 62);
+
+        cache.invalidate(&6).await;
+        expected.push((Arc::new(6), 60, RemovalCause::Explicit));
+        expected.push((Arc::new(7), 61, RemovalCause::Cascaded));
+        expected.push((Arc::new(8), 62, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&7));
+        assert!(!cache.contains_key(&8));
+
+        // Expiration cascades to the dependents.
+        mock.increment(Duration::from_secs(5)); // 5 secs.
+        let v = cache
+            .get_with(9, async { cache.get(&5).await.unwrap() + 1 })
+            .await;
+        assert_eq!(v, 51);
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(6)); // 11 secs.
+        cache.run_pending_tasks().await;
+        expected.push((Arc::new(5), 50, RemovalCause::Expired));
+        expected.push((Arc::new(9), 51, RemovalCause::Cascaded));
+        assert_eq!(cache.entry_count(), 0);
+
+        verify_notification_vec(&cache, actual, &expected).await;
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn custom_clock() {
+        use crate::clock::{CacheClock, MockClock};
+
+        // An expiry that checks the current time given by the cache.
+        struct MyExpiry {
+            clock: MockClock,
+        }
+
+        impl Expiry<&str, &str> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                _value: &&str,
+                current_time: StdInstant,
+            ) -> Option<Duration> {
+                assert_eq!(current_time, self.clock.now());
+                Some(Duration::from_secs(10))
             }
         }
 
-        match self.base.remove_entry(key, hash) {
-            None => None,
-            Some(kv) => {
-                let now = self.base.current_time_from_expiration_clock();
+        let clock = MockClock::new();
+        let start = clock.now();
+        let cache = Cache::builder()
+            .max_capacity(100)
+            .expire_after(MyExpiry {
+                clock: clock.clone(),
+            })
+            .clock(clock.clone())
+            .build();
+
+        cache.insert("a", "alice").await;
+        clock.increment(Duration::from_secs(9)); // 9 secs from the start.
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+
+        cache.insert("b", "bob").await;
+        clock.increment(Duration::from_secs(1)); // 10 secs.
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+
+        // Setting the time backward does not bring expired entries back.
+        clock.set(start);
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+
+        clock.set(start + Duration::from_secs(19)); // 19 secs.
+        assert_eq!(cache.get(&"b").await, None);
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn insert_expiring_at() {
+        use crate::clock::{CacheClock, MockClock};
+
+        let clock = MockClock::new();
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .clock(clock.clone())
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let start = clock.system_now();
+        let secs = Duration::from_secs;
+
+        assert!(
+            cache
+                .insert_expiring_at("a", "alice", start + secs(10))
+                .await
+        );
+        assert!(cache.insert_expiring_at("b", "bob", start + secs(20)).await);
+        // Deadlines that have already passed are refused.
+        assert!(
+            !cache
+                .insert_expiring_at("c", "cindy", start - secs(1))
+                .await
+        );
+        assert!(!cache.insert_expiring_at("d", "david", start).await);
+        assert!(!cache.contains_key(&"c"));
+        assert!(!cache.contains_key(&"d"));
+
+        // The deadline of "e" is discarded by the following update.
+        assert!(
+            cache
+                .insert_expiring_at("e", "emily", start + secs(10))
+                .await
+        );
+        cache.insert("e", "eve").await;
+        cache.run_pending_tasks().await;
+
+        clock.increment(secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+
+        // The wall clock jumps 10 secs forward.
+        clock.set_system_time(start + secs(15));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"e").await, Some("eve"));
+
+        clock.increment(secs(4)); // 19 secs by the wall clock.
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        clock.increment(secs(1)); // 20 secs.
+        assert_eq!(cache.get(&"b").await, None);
+
+        // The wall clock jumps backward.
+        assert!(
+            cache
+                .insert_expiring_at("f", "frank", start + secs(30))
+                .await
+        );
+        cache.run_pending_tasks().await;
+        clock.set_system_time(start);
+        cache.run_pending_tasks().await;
+        clock.increment(secs(20)); // 20 secs by the wall clock.
+        assert_eq!(cache.get(&"f").await, Some("frank"));
+        clock.increment(secs(10)); // 30 secs.
+        assert_eq!(cache.get(&"f").await, None);
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn maintenance_scheduler() {
+        use crate::future::MaintenanceScheduler;
+
+        // Waits for the scheduled task without asserting how soon it runs. The long
+        // timeout only matters when the test fails.
+        async fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+            let deadline = StdInstant::now() + Duration::from_secs(30);
+            while StdInstant::now() < deadline {
+                if condition() {
+                    return true;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+            condition()
+        }
+
+        // Keep the join handles of the spawned tasks.
+        let tasks = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let t1 = Arc::clone(&tasks);
+        let runtime = tokio::runtime::Handle::current();
+        let scheduler = MaintenanceScheduler::spawn_with(move |task| {
+            t1.lock().unwrap().push(runtime.spawn(task));
+        })
+        .interval(Duration::from_millis(20));
+
+        // The following `Vec` will hold actual notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener and the scheduler.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .async_eviction_listener(listener)
+            .maintenance_scheduler(scheduler.clone())
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        mock.increment(Duration::from_secs(10));
+
+        // The expired entry is removed without calling any method of the cache.
+        assert!(wait_until(|| actual.try_lock().map_or(false, |a| !a.is_empty())).await);
+        assert_eq!(
+            *actual.lock().await,
+            vec![(Arc::new("a"), "alice", RemovalCause::Expired)]
+        );
+        assert_eq!(cache.entry_count(), 0);
+
+        // A single task has been spawned for all the runs.
+        assert_eq!(tasks.lock().unwrap().len(), 1);
+
+        // The scheduler does not keep the cache alive, and the task finishes when
+        // the cache is dropped.
+        let value = Arc::new(());
+        let cache = Cache::builder().maintenance_scheduler(scheduler).build();
+        cache.insert(0, Arc::clone(&value)).await;
+        drop(cache);
+        assert!(wait_until(|| Arc::strong_count(&value) == 1).await);
+        let task = tasks.lock().unwrap().pop().unwrap();
+        tokio::time::timeout(Duration::from_secs(30), task)
+            .await
+            .expect("The task did not finish")
+            .expect("The task panicked");
+    }
+
+    #[tokio::test]
+    async fn growing_hash_table_is_not_shrunk() {
+        const INITIAL_CAPACITY: usize = 50_000;
+        const NUM_KEYS: u32 = 1_000;
+
+        let cache = Cache::builder().initial_capacity(INITIAL_CAPACITY).build();
+        let capacity = cache.base.inner.cache.capacity();
+
+        // The load factor of the hash table stays below the shrink threshold, but
+        // the maintenance tasks do not rehash it as no entry has been removed.
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i).await;
+            if i % 100 == 0 {
+                cache.run_pending_tasks().await;
+            }
+        }
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.base.inner.cache.capacity(), capacity);
+
+        // Updating the entries does not count as removals either.
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i + 1).await;
+        }
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.base.inner.cache.capacity(), capacity);
+
+        // The hash table is shrunk once most of the entries have been removed.
+        for i in 10..NUM_KEYS {
+            cache.invalidate(&i).await;
+        }
+        cache.run_pending_tasks().await;
+        assert!(cache.base.inner.cache.capacity() < capacity);
+        assert_eq!(cache.entry_count(), 10);
+    }
+
+    #[tokio::test]
+    async fn shrink_hash_table() {
+        const NUM_KEYS: u32 = 50_000;
+        const NUM_RETAINED: u32 = 1_000;
+
+        let cache = Cache::new(NUM_KEYS as u64);
+
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i).await;
+        }
+        cache.run_pending_tasks().await;
+        let grown = cache.memory_usage().await.hash_table();
+
+        // The maintenance tasks shrink the hash table after most of the entries
+        // have been removed.
+        for i in NUM_RETAINED..NUM_KEYS {
+            cache.invalidate(&i).await;
+        }
+        cache.run_pending_tasks().await;
+        let shrunk = cache.memory_usage().await.hash_table();
+        assert!(shrunk < grown / 4);
+
+        // `shrink_to_fit` shrinks it further.
+        cache.shrink_to_fit();
+        assert!(cache.memory_usage().await.hash_table() <= shrunk);
+        assert_eq!(cache.entry_count(), NUM_RETAINED as u64);
+        for i in 0..NUM_RETAINED {
+            assert_eq!(cache.get(&i).await, Some(i));
+        }
+
+        cache.invalidate_all();
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+        cache.shrink_to_fit();
+        assert!(cache.memory_usage().await.hash_table() <= shrunk);
+    }
 
-                let maybe_v = if need_value {
-                    Some(kv.entry.value.clone())
-                } else {
-                    None
-                };
+    #[tokio::test]
+    async fn lock_key() {
+        use crate::{ops::compute::Op, KeyLockError};
+        use std::sync::atomic::AtomicBool;
 
-                let info = kv.entry.entry_info();
-                let entry_gen = info.incr_entry_gen();
+        let cache = Cache::new(100);
+        cache.insert(0, 0).await;
+        assert!(matches!(
+            cache.lock_key(&0).await,
+            Err(KeyLockError::KeyLocksDisabled)
+        ));
+
+        let cache = Cache::builder().support_key_locks().build();
+        cache.insert(0, 0).await;
+
+        let guard = cache.lock_key(&0).await.unwrap();
+        assert_eq!(**guard.key(), 0);
+
+        // Another task waits for the guard to be dropped before updating the key.
+        let updated = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn({
+            let cache = cache.clone();
+            let updated = Arc::clone(&updated);
+            async move {
+                cache.insert(0, 10).await;
+                updated.store(true, Ordering::Release);
+            }
+        });
+        sleep(Duration::from_millis(200)).await;
+        assert!(!updated.load(Ordering::Acquire));
 
-                let op: WriteOp<K, V> = WriteOp::Remove {
-                    kv_entry: kv.clone(),
-                    entry_gen,
-                };
+        // The task holding the guard can update the key through
+        // `compute_with_guard`, and the other keys are not locked.
+        let v = cache.get(&0).await.unwrap();
+        cache
+            .compute_with_guard(&guard, |_| async move { Op::Put(v + 1) })
+            .await
+            .unwrap();
+        assert_eq!(cache.get(&0).await, Some(1));
 
-                // Async Cancellation Safety: To ensure the below future should be
-                // executed even if our caller async task is cancelled, we create a
-                // cancel guard for the future (and the op). If our caller is
-                // cancelled while we are awaiting for the future, the cancel guard
-                // will save the future and the op to the interrupted_op_ch channel,
-                // so that we can resume/retry later.
-                let mut cancel_guard = CancelGuard::new(&self.base.interrupted_op_ch_snd, now);
+        // A guard of another cache is rejected.
+        let other_cache = Cache::builder().support_key_locks().build();
+        assert!(matches!(
+            other_cache
+                .compute_with_guard(&guard, |_| async { Op::Put(1) })
+                .await,
+            Err(KeyLockError::ForeignKeyGuard)
+        ));
+        cache.insert(1, 1).await;
+        cache.invalidate(&1).await;
 
-                if self.base.is_removal_notifier_enabled() {
-                    let future = self
-                        .base
-                        .notify_invalidate(&kv.key, &kv.entry)
-                        .boxed()
-                        .shared();
-                    cancel_guard.set_future_and_op(future.clone(), op.clone());
-                    // Send notification to the eviction listener.
-                    future.await;
-                    cancel_guard.unset_future();
-                } else {
-                    cancel_guard.set_op(op.clone());
-                }
+        drop(guard);
+        handle.await.expect("Failed to join");
+        assert!(updated.load(Ordering::Acquire));
+        assert_eq!(cache.get(&0).await, Some(10));
+        assert!(cache.key_locks_map_is_empty());
+    }
 
-                // Drop the locks before scheduling write op to avoid a potential
-                // dead lock. (Scheduling write can do spin lock when the queue is
-                // full, and queue will be drained by the housekeeping thread that
-                // can lock the same key)
-                std::mem::drop(klg);
-                std::mem::drop(kl);
-
-                let should_block;
-                #[cfg(not(test))]
-                {
-                    should_block = false;
-                }
-                #[cfg(test)]
-                {
-                    should_block = self.schedule_write_op_should_block.load(Ordering::Acquire);
-                }
+    #[tokio::test]
+    async fn lock_key_excludes_futures_polled_on_the_same_thread() {
+        use crate::ops::compute::Op;
+        use std::sync::atomic::AtomicBool;
 
-                let event = self.base.write_op_ch_ready_event();
-                let hk = self.base.housekeeper.as_ref();
+        let cache = Cache::builder().support_key_locks().build();
+        cache.insert(0, 0).await;
 
-                BaseCache::<K, V, S>::schedule_write_op(
-                    &self.base.inner,
-                    &self.base.write_op_ch,
-                    event,
-                    op,
-                    now,
-                    hk,
-                    should_block,
-                )
+        let guard = cache.lock_key(&0).await.unwrap();
+        let updated = AtomicBool::new(false);
+
+        // `join!` polls both futures on the same thread. The unrelated insert must
+        // still wait for the guard to be dropped.
+        let holder = async {
+            sleep(Duration::from_millis(200)).await;
+            assert!(!updated.load(Ordering::Acquire));
+            cache
+                .compute_with_guard(&guard, |_| async { Op::Put(1) })
                 .await
-                .expect("Failed to schedule write op for remove");
-                cancel_guard.clear();
+                .unwrap();
+            assert_eq!(cache.get(&0).await, Some(1));
+            drop(guard);
+        };
+        let other = async {
+            cache.insert(0, 10).await;
+            updated.store(true, Ordering::Release);
+        };
+        futures_util::join!(holder, other);
 
-                crossbeam_epoch::pin().flush();
-                maybe_v
-            }
-        }
+        assert!(updated.load(Ordering::Acquire));
+        assert_eq!(cache.get(&0).await, Some(10));
+        assert!(cache.key_locks_map_is_empty());
     }
-}
 
-// For unit tests.
-// For unit tests.
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S> {
-    pub(crate) fn is_table_empty(&self) -> bool {
-        self.entry_count() == 0
-    }
+    #[tokio::test]
+    async fn lock_key_does_not_block_maintenance() {
+        const WRITE_LOG_CAPACITY: usize = 64;
 
-    pub(crate) fn is_waiter_map_empty(&self) -> bool {
-        self.value_initializer.waiter_count() == 0
-    }
-}
+        let cache = Cache::builder()
+            .max_capacity(10)
+            .eviction_policy(EvictionPolicy::lru())
+            .write_log_capacity(WRITE_LOG_CAPACITY)
+            .support_key_locks()
+            .build();
+        cache.insert(0, 0).await;
+        cache.run_pending_tasks().await;
 
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    fn invalidation_predicate_count(&self) -> usize {
-        self.base.invalidation_predicate_count()
-    }
+        let guard = cache.lock_key(&0).await.unwrap();
 
-    async fn reconfigure_for_testing(&mut self) {
-        self.base.reconfigure_for_testing().await;
-    }
+        // Another task inserts more entries than the write log can hold. The key 0
+        // is the first victim of the eviction, but the maintenance tasks must skip
+        // it instead of waiting for the guard. Otherwise, the inserts will wait
+        // forever for the write log to have room.
+        let handle = tokio::spawn({
+            let cache = cache.clone();
+            async move {
+                for i in 1..=(WRITE_LOG_CAPACITY as u32 * 4) {
+                    cache.insert(i, i).await;
+                }
+                cache.run_pending_tasks().await;
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(30), handle)
+            .await
+            .expect("Inserts were blocked by the key lock")
+            .expect("Failed to join");
 
-    async fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
-        self.base.set_expiration_clock(clock).await;
+        // The locked key has not been evicted.
+        assert_eq!(cache.get(&0).await, Some(0));
+
+        drop(guard);
+        cache.run_pending_tasks().await;
+        assert!(cache.entry_count() <= 10);
+        assert!(cache.key_locks_map_is_empty());
     }
 
-    fn key_locks_map_is_empty(&self) -> bool {
-        self.base.key_locks_map_is_empty()
+    #[tokio::test]
+    async fn lock_key_keeps_oversize_candidate_out_of_policy() {
+        use crate::ops::compute::Op;
+
+        const MAX_CAPACITY: u64 = 10;
+
+        let cache = Cache::builder()
+            .max_capacity(MAX_CAPACITY)
+            .weigher(|_k, v: &u32| *v)
+            .support_key_locks()
+            .build();
+        cache.insert(0, 5).await;
+        cache.run_pending_tasks().await;
+
+        // Insert a value heavier than the max capacity while holding the lock. The
+        // candidate cannot be rejected yet, but it must not be admitted either.
+        let guard = cache.lock_key(&1).await.unwrap();
+        cache
+            .compute_with_guard(&guard, |_| async { Op::Put(20) })
+            .await
+            .unwrap();
+        cache.run_pending_tasks().await;
+        assert!(cache.weighted_size() <= MAX_CAPACITY);
+        assert_eq!(cache.get(&0).await, Some(5));
+
+        // The candidate is rejected once the lock is released.
+        drop(guard);
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.weighted_size(), 5);
+        assert!(cache.key_locks_map_is_empty());
     }
 
-    fn run_pending_tasks_initiation_count(&self) -> usize {
-        self.base
-            .housekeeper
-            .as_ref()
-            .map(|hk| hk.start_count.load(Ordering::Acquire))
-            .expect("housekeeper is not set")
+    #[tokio::test]
+    async fn lock_key_and_invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        let cache = Cache::builder()
+            .support_invalidation_closures()
+            .support_key_locks()
+            .build();
+        for i in 0..10 {
+            cache.insert(i, i).await;
+        }
+        cache.run_pending_tasks().await;
+
+        // The locked entry is skipped, and the predicate is kept to retry it.
+        let guard = cache.lock_key(&0).await?;
+        cache.invalidate_entries_if(|_k, _v| true)?;
+        for _ in 0..3 {
+            cache.run_pending_tasks().await;
+            assert_eq!(cache.entry_count(), 1);
+            assert_eq!(cache.invalidation_predicate_count(), 1);
+        }
+
+        drop(guard);
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        Ok(())
     }
 
-    fn run_pending_tasks_completion_count(&self) -> usize {
-        self.base
-            .housekeeper
-            .as_ref()
-            .map(|hk| hk.complete_count.load(Ordering::Acquire))
-            .expect("housekeeper is not set")
+    #[tokio::test]
+    async fn lock_key_and_compute_with() {
+        use crate::ops::compute::Op;
+        use tokio::task::yield_now;
+
+        const NUM_UPDATES: u32 = 500;
+
+        let cache = Cache::builder().support_key_locks().build();
+        cache.insert(0, 0).await;
+
+        // Two tasks increment the value under a `KeyGuard`, and the other two tasks
+        // increment it by `and_compute_with`. No increment must be lost.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    for _ in 0..NUM_UPDATES {
+                        if i % 2 == 0 {
+                            let guard = cache.lock_key(&0).await.unwrap();
+                            let v = cache.get(&0).await.unwrap();
+                            yield_now().await;
+                            cache
+                                .compute_with_guard(&guard, |_| async move { Op::Put(v + 1) })
+                                .await
+                                .unwrap();
+                        } else {
+                            cache
+                                .entry(0)
+                                .and_compute_with(|entry| async move {
+                                    let v = *entry.unwrap().value();
+                                    yield_now().await;
+                                    Op::Put(v + 1)
+                                })
+                                .await;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.await.expect("Failed to join");
+        }
+
+        assert_eq!(cache.get(&0).await, Some(NUM_UPDATES * 4));
+        assert!(cache.key_locks_map_is_empty());
     }
-}
 
-// AS of Rust 1.71, we cannot make this function into a `const fn` because mutable
-// references are not allowed.
-// See [#57349](https://github.com/rust-lang/rust/issues/57349).
-#[inline]
-fn never_ignore<'a, V>() -> Option<&'a mut fn(&V) -> bool> {
-    None
-}
+    #[tokio::test]
+    async fn compute_many() {
+        use crate::{
+            ops::compute::{CompResult, Op},
+            KeyLockError,
+        };
 
-// To see the debug prints, run test as `cargo test -- --nocapture`
-#[cfg(test)]
-mod tests {
-    use super::Cache;
-    use crate::{
-        common::{time::Clock, HousekeeperConfig},
-        future::FutureExt,
-        notification::{ListenerFuture, RemovalCause},
-        ops::compute,
-        policy::{test_utils::ExpiryCallCounters, EvictionPolicy},
-        Expiry,
-    };
+        let cache = Cache::new(100);
+        assert!(matches!(
+            cache.compute_many([0], |_| async { [Op::Put(0)] }).await,
+            Err(KeyLockError::KeyLocksDisabled)
+        ));
+
+        let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+        cache.insert("a", 1000).await;
+        cache.insert("b", 0).await;
+
+        // Transfer 1 from "a" to "b" on four tasks. Half of them give the keys in
+        // the reverse order, which must not deadlock.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    let keys = if i % 2 == 0 { ["a", "b"] } else { ["b", "a"] };
+                    let (a, b) = if i % 2 == 0 { (0, 1) } else { (1, 0) };
+                    for _ in 0..100 {
+                        cache
+                            .compute_many(keys, |entries| async move {
+                                // Yield while holding the locks.
+                                tokio::task::yield_now().await;
+                                let mut ops = [Op::Nop, Op::Nop];
+                                ops[a] = Op::Put(*entries[a].as_ref().unwrap().value() - 1);
+                                ops[b] = Op::Put(*entries[b].as_ref().unwrap().value() + 1);
+                                ops
+                            })
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.await.expect("Failed to join");
+        }
+        assert_eq!(cache.get(&"a").await, Some(600));
+        assert_eq!(cache.get(&"b").await, Some(400));
 
-    use async_lock::{Barrier, Mutex};
-    use std::{
-        convert::Infallible,
-        sync::{
-            atomic::{AtomicU32, AtomicU8, Ordering},
-            Arc,
-        },
-        time::{Duration, Instant as StdInstant},
-        vec,
-    };
-    use tokio::time::sleep;
+        let results = cache
+            .compute_many(["a", "c", "b"], |entries| async move {
+                assert!(entries[1].is_none());
+                [Op::Remove, Op::Put(5), Op::Nop]
+            })
+            .await
+            .unwrap();
+        assert!(matches!(&results[0], CompResult::Removed(e) if *e.value() == 600));
+        assert!(matches!(&results[1], CompResult::Inserted(e) if *e.value() == 5));
+        assert!(matches!(&results[2], CompResult::Unchanged(e) if *e.value() == 400));
+        assert_eq!(cache.get(&"a").await, None);
+        assert_eq!(cache.get(&"c").await, Some(5));
+
+        // The ops for the same key are applied in order, and the key is locked only
+        // once.
+        let results = cache
+            .compute_many(["c", "c"], |entries| async move {
+                let v = *entries[0].as_ref().unwrap().value();
+                [Op::Put(v + 1), Op::Remove]
+            })
+            .await
+            .unwrap();
+        assert!(matches!(&results[0], CompResult::ReplacedWith(e) if *e.value() == 6));
+        assert!(matches!(&results[1], CompResult::Removed(e) if *e.value() == 6));
+        assert_eq!(cache.get(&"c").await, None);
 
-    #[test]
-    fn futures_are_send() {
-        let cache = Cache::new(0);
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 1);
+        assert!(cache.key_locks_map_is_empty());
+    }
 
-        fn is_send(_: impl Send) {}
+    #[tokio::test]
+    async fn compute_many_and_compute_with() {
+        use crate::ops::compute::Op;
+        use tokio::task::yield_now;
 
-        // pub fns
-        is_send(cache.get(&()));
-        is_send(cache.get_with((), async {}));
-        is_send(cache.get_with_by_ref(&(), async {}));
-        #[allow(deprecated)]
-        is_send(cache.get_with_if((), async {}, |_| false));
-        is_send(cache.insert((), ()));
-        is_send(cache.invalidate(&()));
-        is_send(cache.optionally_get_with((), async { None }));
-        is_send(cache.optionally_get_with_by_ref(&(), async { None }));
-        is_send(cache.remove(&()));
-        is_send(cache.run_pending_tasks());
-        is_send(cache.try_get_with((), async { Err(()) }));
-        is_send(cache.try_get_with_by_ref(&(), async { Err(()) }));
+        let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+        cache.insert("a", 1000).await;
+        cache.insert("b", 0).await;
 
-        // entry fns
-        is_send(
-            cache
-                .entry(())
-                .and_compute_with(|_| async { compute::Op::Nop }),
-        );
-        is_send(
-            cache
-                .entry(())
-                .and_try_compute_with(|_| async { Ok(compute::Op::Nop) as Result<_, Infallible> }),
-        );
-        is_send(cache.entry(()).and_upsert_with(|_| async {}));
-        is_send(cache.entry(()).or_default());
-        is_send(cache.entry(()).or_insert(()));
-        is_send(cache.entry(()).or_insert_with(async {}));
-        is_send(cache.entry(()).or_insert_with_if(async {}, |_| false));
-        is_send(cache.entry(()).or_optionally_insert_with(async { None }));
-        is_send(cache.entry(()).or_try_insert_with(async { Err(()) }));
+        // Two tasks transfer 1 from "a" to "b" by `compute_many`, and the other two
+        // tasks rewrite the value of "a" or "b" by `and_compute_with`. If
+        // `and_compute_with` wrote back a value read before a transfer, the sum
+        // would change.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    for _ in 0..200 {
+                        if i % 2 == 0 {
+                            cache
+                                .compute_many(["a", "b"], |entries| {
+                                    let a = *entries[0].as_ref().unwrap().value();
+                                    let b = *entries[1].as_ref().unwrap().value();
+                                    async move {
+                                        yield_now().await;
+                                        [Op::Put(a - 1), Op::Put(b + 1)]
+                                    }
+                                })
+                                .await
+                                .unwrap();
+                        } else {
+                            let key = if i == 1 { "a" } else { "b" };
+                            cache
+                                .entry(key)
+                                .and_compute_with(|entry| async move {
+                                    let v = *entry.unwrap().value();
+                                    yield_now().await;
+                                    Op::Put(v)
+                                })
+                                .await;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.await.expect("Failed to join");
+        }
+
+        assert_eq!(cache.get(&"a").await, Some(600));
+        assert_eq!(cache.get(&"b").await, Some(400));
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[tokio::test]
+    async fn maintenance_stats() {
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .eviction_policy(EvictionPolicy::lru())
+            .time_to_live(Duration::from_secs(10))
+            .read_log_capacity(64)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing().await;
 
-        // entry_by_ref fns
-        is_send(
-            cache
-                .entry_by_ref(&())
-                .and_compute_with(|_| async { compute::Op::Nop }),
-        );
-        is_send(
-            cache
-                .entry_by_ref(&())
-                .and_try_compute_with(|_| async { Ok(compute::Op::Nop) as Result<_, Infallible> }),
-        );
-        is_send(cache.entry_by_ref(&()).and_upsert_with(|_| async {}));
-        is_send(cache.entry_by_ref(&()).or_default());
-        is_send(cache.entry_by_ref(&()).or_insert(()));
-        is_send(cache.entry_by_ref(&()).or_insert_with(async {}));
-        is_send(
-            cache
-                .entry_by_ref(&())
-                .or_insert_with_if(async {}, |_| false),
-        );
-        is_send(
-            cache
-                .entry_by_ref(&())
-                .or_optionally_insert_with(async { None }),
-        );
-        is_send(
-            cache
-                .entry_by_ref(&())
-                .or_try_insert_with(async { Err(()) }),
-        );
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..5 {
+            cache.insert(i, i).await;
+        }
+        cache.run_pending_tasks().await;
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.runs(), 1);
+        assert_eq!(stats.run_duration().count(), 1);
+        assert_eq!(stats.writes_applied(), 5);
+        assert_eq!(stats.entries_evicted(), 2);
+        assert_eq!(cache.entry_count(), 3);
+
+        // The reads exceeding the capacity of the read log are dropped.
+        for _ in 0..100 {
+            cache.get(&4).await;
+        }
+        cache.run_pending_tasks().await;
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.reads_applied(), 64);
+        assert_eq!(stats.read_ops_dropped(), 36);
+
+        cache.invalidate_entries_if(|k, _| *k == 4).unwrap();
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.maintenance_stats().entries_invalidated(), 1);
+
+        mock.increment(Duration::from_secs(10));
+        cache.run_pending_tasks().await;
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.entries_expired(), 2);
+        assert_eq!(stats.runs(), 4);
+        assert_eq!(stats.run_duration().count(), 4);
+        assert_eq!(stats.write_op_retries(), 0);
+        assert_eq!(stats.timeouts(), 0);
+        assert_eq!(cache.entry_count(), 0);
     }
 
+    #[cfg(feature = "testing")]
     #[tokio::test]
-    async fn max_capacity_zero() {
-        let mut cache = Cache::new(0);
+    async fn next_expiration() {
+        use crate::clock::{CacheClock, MockClock};
+
+        // The resolution of the timer wheel.
+        const TICK: Duration = Duration::from_nanos(1 << 30);
+
+        struct MyExpiry;
+
+        impl Expiry<&str, u64> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                value: &u64,
+                _current_time: StdInstant,
+            ) -> Option<Duration> {
+                Some(Duration::from_secs(*value))
+            }
+        }
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut cache = Cache::builder()
+            .expire_after(MyExpiry)
+            .clock(clock.clone())
+            .build();
         cache.reconfigure_for_testing().await;
 
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert(0, ()).await;
+        assert_eq!(cache.next_expiration().await, None);
 
-        assert!(!cache.contains_key(&0));
-        assert!(cache.get(&0).await.is_none());
+        cache.insert("a", 10).await;
+        cache.insert("b", 20).await;
         cache.run_pending_tasks().await;
-        assert!(!cache.contains_key(&0));
-        assert!(cache.get(&0).await.is_none());
-        assert_eq!(cache.entry_count(), 0)
+        let next = cache.next_expiration().await.expect("No next expiration");
+        assert!(next >= start + Duration::from_secs(10) - TICK);
+        assert!(next <= start + Duration::from_secs(10) + TICK);
+
+        // After "a" expires, the next expiration is the one of "b".
+        clock.increment(Duration::from_secs(11));
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&"a"));
+        let next = cache.next_expiration().await.expect("No next expiration");
+        assert!(next >= start + Duration::from_secs(20) - TICK);
+        assert!(next <= start + Duration::from_secs(20) + TICK);
+
+        cache.invalidate(&"b").await;
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.next_expiration().await, None);
     }
 
     #[tokio::test]
-    async fn basic_single_async_task() {
-        // The following `Vec`s will hold actual and expected notifications.
+    async fn proactive_expiration() {
+        use crate::future::MaintenanceScheduler;
+
+        const TTL: Duration = Duration::from_millis(500);
+
+        struct MyExpiry;
+
+        impl Expiry<&str, &str> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                _value: &&str,
+                _current_time: StdInstant,
+            ) -> Option<Duration> {
+                Some(TTL)
+            }
+        }
+
+        // Use a long interval so that only the expiration timers can remove the
+        // entry.
+        let runtime = tokio::runtime::Handle::current();
+        let scheduler = MaintenanceScheduler::spawn_with(move |task| {
+            runtime.spawn(task);
+        })
+        .interval(Duration::from_secs(3600))
+        .proactive_expiration(true);
+
+        // The following `Vec` will hold actual notifications and their times.
         let actual = Arc::new(Mutex::new(Vec::new()));
-        let mut expected = Vec::new();
 
         // Create an eviction listener.
         let a1 = Arc::clone(&actual);
         let listener = move |k, v, cause| -> ListenerFuture {
             let a2 = Arc::clone(&a1);
             async move {
-                a2.lock().await.push((k, v, cause));
+                a2.lock().await.push((k, v, cause, StdInstant::now()));
             }
             .boxed()
         };
 
-        // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(3)
+            .expire_after(MyExpiry)
             .async_eviction_listener(listener)
+            .maintenance_scheduler(scheduler)
             .build();
         cache.reconfigure_for_testing().await;
 
         // Make the cache exterior immutable.
         let cache = cache;
 
+        assert_eq!(cache.next_expiration().await, None);
+
+        let inserted_at = StdInstant::now();
         cache.insert("a", "alice").await;
-        cache.insert("b", "bob").await;
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
         cache.run_pending_tasks().await;
-        // counts: a -> 1, b -> 1
+        assert!(cache.next_expiration().await.is_some());
+
+        // The eviction listener is called without calling any method of the cache,
+        // long before the next maintenance interval. Do not assert how soon it is
+        // called, as it depends on the load of the machine. The long timeout only
+        // matters when the test fails.
+        let deadline = StdInstant::now() + Duration::from_secs(30);
+        while actual.lock().await.is_empty() && StdInstant::now() < deadline {
+            sleep(Duration::from_millis(10)).await;
+        }
+        let actual = actual.lock().await;
+        assert_eq!(actual.len(), 1);
+        let (k, v, cause, notified_at) = &actual[0];
+        assert_eq!(
+            (k, v, cause),
+            (&Arc::new("a"), &"alice", &RemovalCause::Expired)
+        );
+        // The entry is not removed before it expires.
+        assert!(*notified_at >= inserted_at + TTL);
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.next_expiration().await, None);
+    }
 
-        cache.insert("c", "cindy").await;
-        assert_eq!(cache.get(&"c").await, Some("cindy"));
-        assert!(cache.contains_key(&"c"));
-        // counts: a -> 1, b -> 1, c -> 1
-        cache.run_pending_tasks().await;
+    #[tokio::test]
+    async fn predicate_status_cancel_and_wait() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing().await;
 
-        assert!(cache.contains_key(&"a"));
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert!(cache.contains_key(&"b"));
-        cache.run_pending_tasks().await;
-        // counts: a -> 2, b -> 2, c -> 1
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
 
-        // "d" should not be admitted because its frequency is too low.
-        cache.insert("d", "david").await; //   count: d -> 0
-        expected.push((Arc::new("d"), "david", RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"d").await, None); //   d -> 1
-        assert!(!cache.contains_key(&"d"));
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        cache.insert("d", "david").await;
-        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        for i in 0..10 {
+            cache.insert(i, i * 10).await;
+        }
         cache.run_pending_tasks().await;
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d").await, None); //   d -> 2
 
-        // "d" should be admitted and "c" should be evicted
-        // because d's frequency is higher than c's.
-        cache.insert("d", "dennis").await;
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert_eq!(cache.get(&"c").await, None);
-        assert_eq!(cache.get(&"d").await, Some("dennis"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
 
-        cache.invalidate(&"b").await;
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"b").await, None);
-        assert!(!cache.contains_key(&"b"));
+        let id = cache.invalidate_entries_if(|_k, &v| v % 20 == 0)?;
+        assert_eq!(cache.predicate_status(&id), Some(PredicateStatus::Pending));
+
+        let status = cache.wait_predicate(&id).await;
+        assert_eq!(
+            status,
+            Some(PredicateStatus::Completed {
+                scanned: 10,
+                removed: 5
+            })
+        );
+        // The status of a finished predicate can still be queried.
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        assert_eq!(cache.entry_count(), 5);
+        assert!(!cache.cancel_predicate(&id));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        // Cancel a predicate before the cache starts scanning for it.
+        let id = cache.invalidate_entries_if(|_k, _v| true)?;
+        assert!(cache.cancel_predicate(&id));
+        assert!(!cache.cancel_predicate(&id));
+        let status = Some(PredicateStatus::Cancelled {
+            scanned: 0,
+            removed: 0,
+        });
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.wait_predicate(&id).await, status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
 
-        assert!(cache.remove(&"b").await.is_none());
-        assert_eq!(cache.remove(&"d").await, Some("dennis"));
-        expected.push((Arc::new("d"), "dennis", RemovalCause::Explicit));
         cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"d").await, None);
-        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 5);
+        assert_eq!(cache.get(&1).await, Some(10));
 
-        verify_notification_vec(&cache, actual, &expected).await;
-        assert!(cache.key_locks_map_is_empty());
+        assert!(cache.predicate_status("no-such-predicate").is_none());
+        assert!(cache.wait_predicate("no-such-predicate").await.is_none());
+        assert!(!cache.cancel_predicate("no-such-predicate"));
+
+        Ok(())
     }
 
     #[tokio::test]
-    async fn basic_lru_single_thread() {
+    async fn time_to_live() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2305,100 +5696,113 @@ mod tests {
 
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(3)
-            .eviction_policy(EvictionPolicy::lru())
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
             .async_eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing().await;
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
         // Make the cache exterior immutable.
         let cache = cache;
 
         cache.insert("a", "alice").await;
-        cache.insert("b", "bob").await;
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
         cache.run_pending_tasks().await;
-        // a -> b
 
-        cache.insert("c", "cindy").await;
-        assert_eq!(cache.get(&"c").await, Some("cindy"));
-        assert!(cache.contains_key(&"c"));
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
         cache.run_pending_tasks().await;
-        // a -> b -> c
 
-        assert!(cache.contains_key(&"a"));
         assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert!(cache.contains_key(&"b"));
+        assert!(cache.contains_key(&"a"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        expected.push((Arc::new("a"), "alice", RemovalCause::Expired));
+        assert_eq!(cache.get(&"a").await, None);
+        assert!(!cache.contains_key(&"a"));
+
+        assert_eq!(cache.iter().count(), 0);
+
         cache.run_pending_tasks().await;
-        // c -> a -> b
+        assert!(cache.is_table_empty());
 
-        // "d" should be admitted because the cache uses the LRU strategy.
-        cache.insert("d", "david").await;
-        // "c" is the LRU and should have be evicted.
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.insert("b", "bob").await;
+        cache.run_pending_tasks().await;
+
+        assert_eq!(cache.entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
         cache.run_pending_tasks().await;
 
-        assert_eq!(cache.get(&"a").await, Some("alice"));
         assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert_eq!(cache.get(&"c").await, None);
-        assert_eq!(cache.get(&"d").await, Some("david"));
-        assert!(cache.contains_key(&"a"));
         assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
-        cache.run_pending_tasks().await;
-        // a -> b -> d
+        assert_eq!(cache.entry_count(), 1);
 
-        cache.invalidate(&"b").await;
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.insert("b", "bill").await;
+        expected.push((Arc::new("b"), "bob", RemovalCause::Replaced));
         cache.run_pending_tasks().await;
-        // a -> d
-        assert_eq!(cache.get(&"b").await, None);
-        assert!(!cache.contains_key(&"b"));
 
-        assert!(cache.remove(&"b").await.is_none());
-        assert_eq!(cache.remove(&"d").await, Some("david"));
-        expected.push((Arc::new("d"), "david", RemovalCause::Explicit));
+        mock.increment(Duration::from_secs(5)); // 20 secs
         cache.run_pending_tasks().await;
-        // a
-        assert_eq!(cache.get(&"d").await, None);
-        assert!(!cache.contains_key(&"d"));
 
-        cache.insert("e", "emily").await;
-        cache.insert("f", "frank").await;
-        // "a" should be evicted because it is the LRU.
-        cache.insert("g", "gina").await;
-        expected.push((Arc::new("a"), "alice", RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        // e -> f -> g
+        assert_eq!(cache.get(&"b").await, Some("bill"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 25 secs
+        expected.push((Arc::new("b"), "bill", RemovalCause::Expired));
+
         assert_eq!(cache.get(&"a").await, None);
-        assert_eq!(cache.get(&"e").await, Some("emily"));
-        assert_eq!(cache.get(&"f").await, Some("frank"));
-        assert_eq!(cache.get(&"g").await, Some("gina"));
+        assert_eq!(cache.get(&"b").await, None);
         assert!(!cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"e"));
-        assert!(cache.contains_key(&"f"));
-        assert!(cache.contains_key(&"g"));
+        assert!(!cache.contains_key(&"b"));
+
+        assert_eq!(cache.iter().count(), 0);
+
+        cache.run_pending_tasks().await;
+        assert!(cache.is_table_empty());
 
         verify_notification_vec(&cache, actual, &expected).await;
-        assert!(cache.key_locks_map_is_empty());
     }
 
     #[tokio::test]
-    async fn size_aware_eviction() {
-        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
+    async fn set_expiry_when_read_log_is_full() {
+        const READ_LOG_CAPACITY: usize = 64;
 
-        let alice = ("alice", 10);
-        let bob = ("bob", 15);
-        let bill = ("bill", 20);
-        let cindy = ("cindy", 5);
-        let david = ("david", 15);
-        let dennis = ("dennis", 15);
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .read_log_capacity(READ_LOG_CAPACITY)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
 
+        cache
+            .insert_with_ttl("a", "alice", Duration::from_secs(5))
+            .await;
+        cache.run_pending_tasks().await;
+
+        // Fill the read log, so that the read ops will be discarded.
+        for _ in 0..(READ_LOG_CAPACITY * 2) {
+            assert_eq!(cache.get(&"b").await, None);
+        }
+
+        // The new expiration time must not be discarded with the read ops.
+        assert!(cache.set_expiry(&"a", Duration::from_secs(1)).await);
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(2));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn per_entry_expiration() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2415,236 +5819,208 @@ mod tests {
 
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(31)
-            .weigher(weigher)
+            .max_capacity(100)
             .async_eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing().await;
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", alice).await;
-        cache.insert("b", bob).await;
-        assert_eq!(cache.get(&"a").await, Some(alice));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b").await, Some(bob));
-        cache.run_pending_tasks().await;
-        // order (LRU -> MRU) and counts: a -> 1, b -> 1
-
-        cache.insert("c", cindy).await;
-        assert_eq!(cache.get(&"c").await, Some(cindy));
-        assert!(cache.contains_key(&"c"));
-        // order and counts: a -> 1, b -> 1, c -> 1
+        cache
+            .insert_with_ttl("a", "alice", Duration::from_secs(5))
+            .await;
+        cache.insert("b", "bob").await;
+        let deadline = std::time::Instant::now() + Duration::from_secs(8);
+        cache.insert_with_deadline("c", "cindy", deadline).await;
         cache.run_pending_tasks().await;
 
-        assert!(cache.contains_key(&"a"));
-        assert_eq!(cache.get(&"a").await, Some(alice));
-        assert_eq!(cache.get(&"b").await, Some(bob));
-        assert!(cache.contains_key(&"b"));
-        cache.run_pending_tasks().await;
-        // order and counts: c -> 1, a -> 2, b -> 2
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(5)));
+        assert_eq!(cache.expires_in(&"b"), None);
+        assert!(cache.expires_in(&"c").is_some());
+        assert_eq!(cache.expires_in(&"d"), None);
 
-        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
-        // "d" must have higher count than 3, which is the aggregated count
-        // of "a" and "c".
-        cache.insert("d", david).await; //   count: d -> 0
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"d").await, None); //   d -> 1
-        assert!(!cache.contains_key(&"d"));
+        mock.increment(Duration::from_secs(3)); // 3 secs from the start.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(2)));
 
-        cache.insert("d", david).await;
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        assert!(cache.set_expiry(&"a", Duration::from_secs(10)).await);
+        assert!(cache.set_expiry(&"b", Duration::from_secs(4)).await);
+        assert!(!cache.set_expiry(&"d", Duration::from_secs(4)).await);
         cache.run_pending_tasks().await;
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d").await, None); //   d -> 2
 
-        cache.insert("d", david).await;
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"d").await, None); //   d -> 3
-        assert!(!cache.contains_key(&"d"));
+        mock.increment(Duration::from_secs(4)); // 7 secs.
+        expected.push((Arc::new("b"), "bob", RemovalCause::Expired));
+        assert_eq!(cache.get(&"b").await, None);
+        assert_eq!(cache.expires_in(&"b"), None);
+        assert!(!cache.set_expiry(&"b", Duration::from_secs(4)).await);
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(6)));
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
 
-        cache.insert("d", david).await;
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
         cache.run_pending_tasks().await;
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d").await, None); //   d -> 4
+        assert_eq!(cache.entry_count(), 2);
 
-        // Finally "d" should be admitted by evicting "c" and "a".
-        cache.insert("d", dennis).await;
-        expected.push((Arc::new("c"), cindy, RemovalCause::Size));
-        expected.push((Arc::new("a"), alice, RemovalCause::Size));
-        cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"a").await, None);
-        assert_eq!(cache.get(&"b").await, Some(bob));
+        mock.increment(Duration::from_secs(2)); // 9 secs.
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Expired));
         assert_eq!(cache.get(&"c").await, None);
-        assert_eq!(cache.get(&"d").await, Some(dennis));
-        assert!(!cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
 
-        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
-        cache.insert("b", bill).await;
-        expected.push((Arc::new("b"), bob, RemovalCause::Replaced));
-        expected.push((Arc::new("d"), dennis, RemovalCause::Size));
         cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"b").await, Some(bill));
-        assert_eq!(cache.get(&"d").await, None);
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 1);
 
-        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
-        cache.insert("a", alice).await;
-        cache.insert("b", bob).await;
-        expected.push((Arc::new("b"), bill, RemovalCause::Replaced));
+        // Updating the value will not change the per-entry expiration time.
+        cache.insert("a", "anna").await;
+        expected.push((Arc::new("a"), "alice", RemovalCause::Replaced));
         cache.run_pending_tasks().await;
-        assert_eq!(cache.get(&"a").await, Some(alice));
-        assert_eq!(cache.get(&"b").await, Some(bob));
-        assert_eq!(cache.get(&"d").await, None);
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(4)));
 
-        // Verify the sizes.
-        assert_eq!(cache.entry_count(), 2);
-        assert_eq!(cache.weighted_size(), 25);
+        mock.increment(Duration::from_secs(4)); // 13 secs.
+        expected.push((Arc::new("a"), "anna", RemovalCause::Expired));
+        assert_eq!(cache.get(&"a").await, None);
+
+        cache.run_pending_tasks().await;
+        assert!(cache.is_table_empty());
 
         verify_notification_vec(&cache, actual, &expected).await;
-        assert!(cache.key_locks_map_is_empty());
     }
 
     #[tokio::test]
-    async fn basic_multi_async_tasks() {
-        let num_tasks = 2;
-        let num_threads = 2;
+    async fn per_entry_expiration_with_time_to_idle() {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing().await;
 
-        let cache = Cache::new(100);
-        let barrier = Arc::new(Barrier::new(num_tasks + num_threads as usize));
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
 
-        let tasks = (0..num_tasks)
-            .map(|id| {
-                let cache = cache.clone();
-                let barrier = Arc::clone(&barrier);
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-                tokio::spawn(async move {
-                    barrier.wait().await;
+        cache
+            .insert_with_ttl("a", "alice", Duration::from_secs(15))
+            .await;
+        cache
+            .insert_with_ttl("b", "bob", Duration::from_secs(5))
+            .await;
+        cache.run_pending_tasks().await;
 
-                    cache.insert(10, format!("{id}-100")).await;
-                    cache.get(&10).await;
-                    cache.insert(20, format!("{id}-200")).await;
-                    cache.invalidate(&10).await;
-                })
-            })
-            .collect::<Vec<_>>();
+        // The earlier of the per-entry expiration time and the time-to-idle wins.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.expires_in(&"b"), Some(Duration::from_secs(5)));
 
-        let threads = (0..num_threads)
-            .map(|id| {
-                let cache = cache.clone();
-                let barrier = Arc::clone(&barrier);
-                let rt = tokio::runtime::Handle::current();
+        mock.increment(Duration::from_secs(4)); // 4 secs from the start.
+        assert!(cache.touch(&"a").await);
+        assert!(cache.touch(&"b").await);
+        assert!(!cache.touch(&"c").await);
+        cache.run_pending_tasks().await;
 
-                std::thread::spawn(move || {
-                    rt.block_on(barrier.wait());
+        // Touching resets the idle timer but not the per-entry expiration time.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.expires_in(&"b"), Some(Duration::from_secs(1)));
 
-                    rt.block_on(cache.insert(10, format!("{id}-100")));
-                    rt.block_on(cache.get(&10));
-                    rt.block_on(cache.insert(20, format!("{id}-200")));
-                    rt.block_on(cache.invalidate(&10));
-                })
-            })
-            .collect::<Vec<_>>();
+        mock.increment(Duration::from_secs(8)); // 12 secs.
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, None);
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(3)));
 
-        let _ = futures_util::future::join_all(tasks).await;
-        threads.into_iter().for_each(|t| t.join().unwrap());
+        mock.increment(Duration::from_secs(3)); // 15 secs.
+        assert_eq!(cache.get(&"a").await, None);
 
-        assert!(cache.get(&10).await.is_none());
-        assert!(cache.get(&20).await.is_some());
-        assert!(!cache.contains_key(&10));
-        assert!(cache.contains_key(&20));
+        cache.run_pending_tasks().await;
+        assert!(cache.is_table_empty());
     }
 
     #[tokio::test]
-    async fn invalidate_all() {
-        // The following `Vec`s will hold actual and expected notifications.
-        let actual = Arc::new(Mutex::new(Vec::new()));
-        let mut expected = Vec::new();
-
-        // Create an eviction listener.
-        let a1 = Arc::clone(&actual);
-        let listener = move |k, v, cause| -> ListenerFuture {
-            let a2 = Arc::clone(&a1);
-            async move {
-                a2.lock().await.push((k, v, cause));
-            }
-            .boxed()
-        };
+    async fn entry_metadata() {
+        use crate::{ops::compute::CompResult, EntryRegion};
 
-        // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .async_eviction_listener(listener)
+            .weigher(|_k, v: &&str| v.len() as u32)
+            .time_to_live(Duration::from_secs(10))
             .build();
         cache.reconfigure_for_testing().await;
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
         // Make the cache exterior immutable.
         let cache = cache;
 
         cache.insert("a", "alice").await;
-        cache.insert("b", "bob").await;
-        cache.insert("c", "cindy").await;
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert_eq!(cache.get(&"c").await, Some("cindy"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(cache.contains_key(&"c"));
-
-        // `cache.run_pending_tasks().await` is no longer needed here before invalidating. The last
-        // modified timestamp of the entries were updated when they were inserted.
-        // https://github.com/moka-rs/moka/issues/155
-
-        cache.invalidate_all();
-        expected.push((Arc::new("a"), "alice", RemovalCause::Explicit));
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Explicit));
-        cache.run_pending_tasks().await;
-
-        cache.insert("d", "david").await;
         cache.run_pending_tasks().await;
+        mock.increment(Duration::from_secs(3)); // 3 secs from the start.
+
+        let entry = cache.get_entry(&"a").await.expect("Entry not found");
+        assert_eq!(entry.key(), &"a");
+        assert_eq!(entry.value(), &"alice");
+        let md = entry.metadata().expect("Metadata not found");
+        let t0 = md.last_modified().expect("Last modified not set");
+        assert_eq!(md.last_accessed(), Some(t0 + Duration::from_secs(3)));
+        assert_eq!(md.expiration_time(), Some(t0 + Duration::from_secs(10)));
+        assert_eq!(md.policy_weight(), 5);
+        assert_eq!(md.region(), Some(EntryRegion::MainProbation));
+        assert!(cache.get_entry(&"b").await.is_none());
+
+        let (k, v, md) = cache.iter_with_metadata().next().expect("Entry not found");
+        assert_eq!((*k, v), ("a", "alice"));
+        assert_eq!(md.last_accessed(), Some(t0 + Duration::from_secs(3)));
+
+        match cache
+            .entry("a")
+            .and_compute_with(|_| async { compute::Op::Put("anna") })
+            .await
+        {
+            CompResult::ReplacedWith(entry) => {
+                let md = entry.metadata().expect("Metadata not found");
+                assert_eq!(md.last_modified(), Some(t0 + Duration::from_secs(3)));
+                assert_eq!(md.policy_weight(), 4);
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
 
-        assert!(cache.get(&"a").await.is_none());
-        assert!(cache.get(&"b").await.is_none());
-        assert!(cache.get(&"c").await.is_none());
-        assert_eq!(cache.get(&"d").await, Some("david"));
-        assert!(!cache.contains_key(&"a"));
-        assert!(!cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
+    #[tokio::test]
+    async fn peek() {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing().await;
 
-        verify_notification_vec(&cache, actual, &expected).await;
-    }
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
 
-    // This test is for https://github.com/moka-rs/moka/issues/155
-    #[tokio::test]
-    async fn invalidate_all_without_running_pending_tasks() {
-        let cache = Cache::new(1024);
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        assert_eq!(cache.get(&0).await, None);
-        cache.insert(0, 1).await;
-        assert_eq!(cache.get(&0).await, Some(1));
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.run_pending_tasks().await;
 
-        cache.invalidate_all();
-        assert_eq!(cache.get(&0).await, None);
+        mock.increment(Duration::from_secs(6)); // 6 secs from the start.
+        assert_eq!(cache.peek(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.peek(&"c"), None);
+
+        let entry = cache.peek_entry(&"a").expect("Entry not found");
+        assert_eq!(entry.key(), &"a");
+        let md = entry.metadata().expect("Metadata not found");
+        assert_eq!(md.last_accessed(), md.last_modified());
+        cache.run_pending_tasks().await;
+
+        // Peeking did not reset the idle timer of "a".
+        mock.increment(Duration::from_secs(6)); // 12 secs.
+        assert_eq!(cache.peek(&"a"), None);
+        assert!(cache.peek_entry(&"a").is_none());
+        assert_eq!(cache.peek(&"b"), Some("bob"));
     }
 
     #[tokio::test]
-    async fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
-        use std::collections::HashSet;
-
+    async fn conditional_mutations() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2662,7 +6038,7 @@ mod tests {
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .support_invalidation_closures()
+            .time_to_live(Duration::from_secs(10))
             .async_eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing().await;
@@ -2673,82 +6049,104 @@ mod tests {
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert(0, "alice").await;
-        cache.insert(1, "bob").await;
-        cache.insert(2, "alex").await;
-        cache.run_pending_tasks().await;
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.insert_if_absent("a", "alice").await, None);
+        assert_eq!(cache.insert_if_absent("a", "anna").await, Some("alice"));
+        assert_eq!(cache.replace_if_present(&"b", "bob").await, None);
+        assert!(cache.get(&"b").await.is_none());
         cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 1);
 
-        assert_eq!(cache.get(&0).await, Some("alice"));
-        assert_eq!(cache.get(&1).await, Some("bob"));
-        assert_eq!(cache.get(&2).await, Some("alex"));
-        assert!(cache.contains_key(&0));
-        assert!(cache.contains_key(&1));
-        assert!(cache.contains_key(&2));
+        assert_eq!(
+            cache.replace(&"a", &"anna", "amy").await,
+            Err(Some("alice"))
+        );
+        assert_eq!(cache.replace(&"a", &"alice", "amy").await, Ok("alice"));
+        expected.push((Arc::new("a"), "alice", RemovalCause::Replaced));
+        assert_eq!(cache.replace(&"b", &"bob", "bill").await, Err(None));
 
-        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
-        cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
-        assert_eq!(cache.invalidation_predicate_count(), 1);
-        expected.push((Arc::new(0), "alice", RemovalCause::Explicit));
-        expected.push((Arc::new(2), "alex", RemovalCause::Explicit));
+        assert_eq!(
+            cache.replace_if(&"a", "anna", |v| v.starts_with('b')).await,
+            None
+        );
+        assert_eq!(
+            cache.replace_if(&"a", "anna", |v| *v == "amy").await,
+            Some("amy")
+        );
+        expected.push((Arc::new("a"), "amy", RemovalCause::Replaced));
+        assert_eq!(cache.get(&"a").await, Some("anna"));
 
-        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+        assert_eq!(cache.remove_if(&"a", |v| *v == "amy").await, None);
+        assert_eq!(cache.remove_if(&"a", |v| *v == "anna").await, Some("anna"));
+        expected.push((Arc::new("a"), "anna", RemovalCause::Explicit));
+        assert!(cache.get(&"a").await.is_none());
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 0);
 
-        cache.insert(3, "alice").await;
+        cache.insert("c", "cindy").await;
+        cache.run_pending_tasks().await;
 
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.run_pending_tasks().await; // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.run_pending_tasks().await; // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
+        // An expired entry is treated as absent even if it has not been evicted.
+        mock.increment(Duration::from_secs(11)); // 11 secs from the start.
+        assert_eq!(cache.replace_if_present(&"c", "carl").await, None);
+        assert_eq!(cache.remove_if(&"c", |_| true).await, None);
+        assert_eq!(cache.insert_if_absent("c", "carol").await, None);
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Expired));
+        assert_eq!(cache.get(&"c").await, Some("carol"));
 
-        assert!(cache.get(&0).await.is_none());
-        assert!(cache.get(&2).await.is_none());
-        assert_eq!(cache.get(&1).await, Some("bob"));
-        // This should survive as it was inserted after calling invalidate_entries_if.
-        assert_eq!(cache.get(&3).await, Some("alice"));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 1);
 
-        assert!(!cache.contains_key(&0));
-        assert!(cache.contains_key(&1));
-        assert!(!cache.contains_key(&2));
-        assert!(cache.contains_key(&3));
+        verify_notification_vec(&cache, actual, &expected).await;
+    }
 
-        assert_eq!(cache.entry_count(), 2);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+    #[tokio::test]
+    async fn swap_and_old_value() {
+        use crate::ops::compute;
 
-        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing().await;
 
-        cache.invalidate_entries_if(|_k, &v| v == "alice")?;
-        cache.invalidate_entries_if(|_k, &v| v == "bob")?;
-        assert_eq!(cache.invalidation_predicate_count(), 2);
-        // key 1 was inserted before key 3.
-        expected.push((Arc::new(1), "bob", RemovalCause::Explicit));
-        expected.push((Arc::new(3), "alice", RemovalCause::Explicit));
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
 
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.run_pending_tasks().await; // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.run_pending_tasks().await; // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        assert!(cache.get(&1).await.is_none());
-        assert!(cache.get(&3).await.is_none());
+        assert_eq!(cache.swap("a", "alice").await, None);
+        assert_eq!(cache.swap("a", "anna").await, Some("alice"));
+        assert_eq!(cache.get(&"a").await, Some("anna"));
 
-        assert!(!cache.contains_key(&1));
-        assert!(!cache.contains_key(&3));
+        let res = cache
+            .entry("a")
+            .and_compute_with(|_| async { compute::Op::Put("amy") })
+            .await;
+        let compute::CompResult::ReplacedWith(entry) = res else {
+            panic!("Expected `ReplacedWith`. Got {res:?}")
+        };
+        assert_eq!(entry.old_value(), Some(&"anna"));
+        assert_eq!(entry.into_value_and_old_value(), ("amy", Some("anna")));
 
-        assert_eq!(cache.entry_count(), 0);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+        let entry = cache
+            .entry_by_ref(&"a")
+            .and_upsert_with(|_| async { "ada" })
+            .await;
+        assert_eq!(entry.old_value(), Some(&"amy"));
 
-        verify_notification_vec(&cache, actual, &expected).await;
+        let entry = cache.entry("b").and_upsert_with(|_| async { "bob" }).await;
+        assert!(entry.old_value().is_none());
+        cache.run_pending_tasks().await;
 
-        Ok(())
+        // An expired value is not returned as the previous value.
+        mock.increment(Duration::from_secs(11)); // 11 secs from the start.
+        assert_eq!(cache.swap("a", "alma").await, None);
+        assert_eq!(cache.get(&"a").await, Some("alma"));
     }
 
     #[tokio::test]
-    async fn time_to_live() {
+    async fn keys_values_retain_drain() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2763,75 +6161,59 @@ mod tests {
             .boxed()
         };
 
-        // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .time_to_live(Duration::from_secs(10))
             .async_eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing().await;
 
-        let (clock, mock) = Clock::mock();
-        cache.set_expiration_clock(Some(clock)).await;
-
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", "alice").await;
-        cache.run_pending_tasks().await;
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.run_pending_tasks().await;
-
-        assert_eq!(cache.get(&"a").await, Some("alice"));
-        assert!(cache.contains_key(&"a"));
-
-        mock.increment(Duration::from_secs(5)); // 10 secs.
-        expected.push((Arc::new("a"), "alice", RemovalCause::Expired));
-        assert_eq!(cache.get(&"a").await, None);
-        assert!(!cache.contains_key(&"a"));
-
-        assert_eq!(cache.iter().count(), 0);
-
-        cache.run_pending_tasks().await;
-        assert!(cache.is_table_empty());
-
-        cache.insert("b", "bob").await;
-        cache.run_pending_tasks().await;
-
-        assert_eq!(cache.entry_count(), 1);
-
-        mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.run_pending_tasks().await;
-
-        assert_eq!(cache.get(&"b").await, Some("bob"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.entry_count(), 1);
-
-        cache.insert("b", "bill").await;
-        expected.push((Arc::new("b"), "bob", RemovalCause::Replaced));
-        cache.run_pending_tasks().await;
-
-        mock.increment(Duration::from_secs(5)); // 20 secs
-        cache.run_pending_tasks().await;
-
-        assert_eq!(cache.get(&"b").await, Some("bill"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.entry_count(), 1);
+        for i in 0..10 {
+            cache.insert(i, i * 10).await;
+        }
 
-        mock.increment(Duration::from_secs(5)); // 25 secs
-        expected.push((Arc::new("b"), "bill", RemovalCause::Expired));
+        let mut keys = cache.keys().map(|k| *k).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
 
-        assert_eq!(cache.get(&"a").await, None);
-        assert_eq!(cache.get(&"b").await, None);
-        assert!(!cache.contains_key(&"a"));
-        assert!(!cache.contains_key(&"b"));
+        let mut values = cache.values().collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, (0..10).map(|i| i * 10).collect::<Vec<_>>());
 
-        assert_eq!(cache.iter().count(), 0);
+        // Discard the entries with odd keys.
+        cache.retain(|k, _v| k % 2 == 0).await;
+        for i in (1..10).step_by(2) {
+            expected.push((Arc::new(i), i * 10, RemovalCause::Explicit));
+        }
+        assert!(cache.contains_key(&0));
+        assert!(!cache.contains_key(&1));
 
-        cache.run_pending_tasks().await;
-        assert!(cache.is_table_empty());
+        let mut drained = cache
+            .drain()
+            .await
+            .into_iter()
+            .map(|(k, v)| (*k, v))
+            .collect::<Vec<_>>();
+        drained.sort_unstable();
+        assert_eq!(
+            drained,
+            (0..10).step_by(2).map(|i| (i, i * 10)).collect::<Vec<_>>()
+        );
+        for i in (0..10).step_by(2) {
+            expected.push((Arc::new(i), i * 10, RemovalCause::Explicit));
+        }
+        assert_eq!(cache.keys().count(), 0);
+        assert!(cache.drain().await.is_empty());
 
+        // The entries are visited in arbitrary order, so sort the notifications
+        // within each of the retain and drain calls.
+        {
+            let mut a = actual.lock().await;
+            a[..5].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
+            a[5..].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
+        }
         verify_notification_vec(&cache, actual, &expected).await;
     }
 
@@ -3371,6 +6753,85 @@ mod tests {
         assert_eq!(key_set.len(), NUM_KEYS);
     }
 
+    #[tokio::test]
+    async fn test_stream() {
+        use futures_util::{task::noop_waker_ref, Stream, StreamExt};
+        use std::{
+            collections::HashSet,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        const NUM_KEYS: usize = 50;
+
+        fn make_value(key: usize) -> String {
+            format!("val: {key}")
+        }
+
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock)).await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, make_value(key)).await;
+        }
+
+        // Ensure there are no missing or duplicate keys in the stream.
+        let mut key_set = HashSet::new();
+        let mut stream = cache.stream().yield_every(7);
+        while let Some((key, value)) = stream.next().await {
+            assert_eq!(value, make_value(*key));
+            assert!(key_set.insert(*key));
+        }
+        assert_eq!(key_set.len(), NUM_KEYS);
+
+        let keys = cache.keys_stream().collect::<Vec<_>>().await;
+        assert_eq!(keys.len(), NUM_KEYS);
+        assert_eq!(
+            keys.into_iter().map(|k| *k).collect::<HashSet<_>>(),
+            key_set
+        );
+
+        // Ensure the stream yields to the runtime every 7 scanned entries.
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut stream = cache.stream().yield_every(7);
+        let (mut ready, mut pending) = (0, 0);
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(_)) => ready += 1,
+                Poll::Ready(None) => break,
+                Poll::Pending => pending += 1,
+            }
+        }
+        assert_eq!(ready, NUM_KEYS);
+        assert_eq!(pending, NUM_KEYS / 7);
+
+        // Expired entries are skipped.
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.insert(0, make_value(0)).await;
+        mock.increment(Duration::from_secs(6)); // 11 secs.
+        assert_eq!(cache.stream().count().await, 1);
+        assert_eq!(*cache.keys_stream().next().await.unwrap(), 0);
+
+        let count = std::sync::atomic::AtomicUsize::new(0);
+        cache
+            .for_each_concurrent(4, |(key, _)| {
+                assert_eq!(*key, 0);
+                count.fetch_add(1, Ordering::Relaxed);
+                async {}
+            })
+            .await;
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
     /// Runs 16 async tasks at the same time and ensures no deadlock occurs.
     ///
     /// - Eight of the task will update key-values in the cache.
@@ -5081,7 +8542,7 @@ mod tests {
     async fn no_batch_size_limit_on_eviction() {
         const MAX_CAPACITY: u64 = 20;
 
-        const EVICTION_TIMEOUT: Duration = Duration::from_nanos(0);
+        const EVICTION_TIMEOUT: Duration = Duration::from_nanos(1);
         const MAX_LOG_SYNC_REPEATS: u32 = 1;
         const EVICTION_BATCH_SIZE: u32 = 1;
 
@@ -5280,6 +8741,7 @@ mod tests {
         // value "panic now!" so the eviction listener will panic.
         cache.insert("alice", "a2").await;
         cache.run_pending_tasks().await;
+        assert!(!cache.listener_enabled());
         // No more removal notification should be sent.
 
         // Invalidate the okay value.
@@ -5289,6 +8751,57 @@ mod tests {
         verify_notification_vec(&cache, actual, &expected).await;
     }
 
+    #[tokio::test]
+    async fn log_and_continue_after_panicking_eviction_listener() {
+        use crate::policy::CallbackPanicPolicy;
+
+        #[cfg(feature = "logging")]
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener that panics when it see
+        // a value "panic now!".
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                if v == "panic now!" {
+                    panic!("Panic now!");
+                }
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        let mut cache = Cache::builder()
+            .name("My Future Cache")
+            .async_eviction_listener(listener)
+            .callback_panic_policy(CallbackPanicPolicy::log_and_continue())
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("alice", "panic now!").await;
+        cache.run_pending_tasks().await;
+
+        // Replace the value "panic now!" so the eviction listener will panic.
+        cache.insert("alice", "a1").await;
+        cache.run_pending_tasks().await;
+        assert!(cache.listener_enabled());
+
+        // The listener keeps receiving notifications.
+        cache.invalidate(&"alice").await;
+        expected.push((Arc::new("alice"), "a1", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+
+        verify_notification_vec(&cache, actual, &expected).await;
+    }
+
     #[tokio::test]
     async fn cancel_future_while_running_pending_tasks() {
         use crate::future::FutureExt;
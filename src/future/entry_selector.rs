@@ -59,7 +59,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -195,7 +195,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -699,7 +699,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -835,7 +835,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
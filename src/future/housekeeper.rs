@@ -4,6 +4,7 @@ use crate::common::{
         constants::{LOG_SYNC_INTERVAL_MILLIS, READ_LOG_FLUSH_POINT, WRITE_LOG_FLUSH_POINT},
     },
     time::{CheckedTimeOps, Instant},
+    trace::instrument,
     HousekeeperConfig,
 };
 
@@ -174,9 +175,14 @@ impl Housekeeper {
             let batch_size = self.eviction_batch_size;
             // Create a new maintenance task and await it.
             let task = async move {
-                cache
-                    .do_run_pending_tasks(timeout, repeats, batch_size)
-                    .await
+                let run = cache.do_run_pending_tasks(timeout, repeats, batch_size);
+                instrument!(
+                    run,
+                    DEBUG,
+                    "run_pending_tasks",
+                    cache = cache.name().unwrap_or_default()
+                )
+                .await
             }
             .boxed()
             .shared();
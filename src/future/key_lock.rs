@@ -1,16 +1,19 @@
 use std::{
+    fmt,
     hash::{BuildHasher, Hash},
     sync::Arc,
 };
 
-use crate::cht::SegmentedHashMap;
+use crate::{
+    cht::SegmentedHashMap,
+    common::memory_report::{arc_alloc_size, MemoryReport},
+};
 
-use async_lock::{Mutex, MutexGuard};
-use triomphe::Arc as TrioArc;
+use async_lock::{Mutex, MutexGuard, MutexGuardArc};
 
 const LOCK_MAP_NUM_SEGMENTS: usize = 64;
 
-type LockMap<K, S> = SegmentedHashMap<Arc<K>, TrioArc<Mutex<()>>, S>;
+type LockMap<K, S> = SegmentedHashMap<Arc<K>, Arc<Mutex<()>>, S>;
 
 // We need the `where` clause here because of the Drop impl.
 pub(crate) struct KeyLock<'a, K, S>
@@ -21,7 +24,7 @@ where
     map: &'a LockMap<K, S>,
     key: Arc<K>,
     hash: u64,
-    lock: TrioArc<Mutex<()>>,
+    lock: Arc<Mutex<()>>,
 }
 
 impl<K, S> Drop for KeyLock<'_, K, S>
@@ -30,11 +33,11 @@ where
     S: BuildHasher,
 {
     fn drop(&mut self) {
-        if TrioArc::count(&self.lock) <= 2 {
+        if Arc::strong_count(&self.lock) <= 2 {
             self.map.remove_if(
                 self.hash,
                 |k| k == &self.key,
-                |_k, v| TrioArc::count(v) <= 2,
+                |_k, v| Arc::strong_count(v) <= 2,
             );
         }
     }
@@ -45,7 +48,7 @@ where
     K: Eq + Hash,
     S: BuildHasher,
 {
-    fn new(map: &'a LockMap<K, S>, key: &Arc<K>, hash: u64, lock: TrioArc<Mutex<()>>) -> Self {
+    fn new(map: &'a LockMap<K, S>, key: &Arc<K>, hash: u64, lock: Arc<Mutex<()>>) -> Self {
         Self {
             map,
             key: Arc::clone(key),
@@ -57,6 +60,31 @@ where
     pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
         self.lock.lock().await
     }
+
+    /// Returns the order to acquire the lock in. The hash orders the locks, and
+    /// the address of the lock breaks ties between the keys with the same hash.
+    fn lock_order(&self) -> (u64, usize) {
+        (self.hash, Arc::as_ptr(&self.lock) as usize)
+    }
+
+    pub(crate) async fn into_guard(self) -> KeyGuard<'a, K, S> {
+        KeyGuard {
+            _guard: self.lock.lock_arc().await,
+            key_lock: self,
+        }
+    }
+
+    /// Locks the key without waiting. Returns the `KeyLock` back if another task
+    /// holds the lock.
+    pub(crate) fn try_into_guard(self) -> Result<KeyGuard<'a, K, S>, Self> {
+        match self.lock.try_lock_arc() {
+            Some(guard) => Ok(KeyGuard {
+                _guard: guard,
+                key_lock: self,
+            }),
+            None => Err(self),
+        }
+    }
 }
 
 pub(crate) struct KeyLockMap<K, S> {
@@ -76,15 +104,35 @@ where
 
     pub(crate) fn key_lock(&self, key: &Arc<K>) -> KeyLock<'_, K, S> {
         let hash = self.locks.hash(key);
-        let kl = TrioArc::new(Mutex::new(()));
+        let kl = Arc::new(Mutex::new(()));
         match self
             .locks
-            .insert_if_not_present(Arc::clone(key), hash, kl.clone())
+            .insert_if_not_present(Arc::clone(key), hash, Arc::clone(&kl))
         {
             None => KeyLock::new(&self.locks, key, hash, kl),
             Some(existing_kl) => KeyLock::new(&self.locks, key, hash, existing_kl),
         }
     }
+
+    /// Locks the keys in a deterministic order, so that the callers locking
+    /// overlapping sets of keys do not deadlock each other. A key given more than
+    /// once is locked only once.
+    pub(crate) async fn lock_keys(&self, keys: &[Arc<K>]) -> Vec<KeyGuard<'_, K, S>> {
+        let mut kls = keys.iter().map(|k| self.key_lock(k)).collect::<Vec<_>>();
+        kls.sort_unstable_by_key(|kl| kl.lock_order());
+        kls.dedup_by_key(|kl| kl.lock_order());
+
+        let mut guards = Vec::with_capacity(kls.len());
+        for kl in kls {
+            guards.push(kl.into_guard().await);
+        }
+        guards
+    }
+
+    pub(crate) fn record_memory_usage(&self, report: &mut MemoryReport) {
+        let usage = self.locks.memory_usage();
+        report.key_locks = report.add_hash_table(&usage, arc_alloc_size::<Mutex<()>>());
+    }
 }
 
 #[cfg(test)]
@@ -93,3 +141,56 @@ impl<K, S> KeyLockMap<K, S> {
         self.locks.len() == 0
     }
 }
+
+/// A lock on a key of a [`Cache`][cache-struct], returned by the
+/// [`Cache::lock_key`][lock-key-method] method.
+///
+/// While a task holds a `KeyGuard`, the other tasks cannot insert, update or
+/// remove the entry for the key; they wait until the `KeyGuard` is dropped.
+///
+/// The lock is not reentrant. The task holding a `KeyGuard` must update the entry
+/// through [`Cache::compute_with_guard`][compute-with-guard]. The other methods of
+/// the cache writing the key wait for the `KeyGuard` to be dropped, which never
+/// happens if they are called by the holder.
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [lock-key-method]: ./struct.Cache.html#method.lock_key
+/// [compute-with-guard]: ./struct.Cache.html#method.compute_with_guard
+#[must_use = "the key is unlocked as soon as the guard is dropped"]
+pub struct KeyGuard<'a, K, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    // Declared before `key_lock` to unlock before removing the lock from the map.
+    _guard: MutexGuardArc<()>,
+    key_lock: KeyLock<'a, K, S>,
+}
+
+impl<K, S> KeyGuard<'_, K, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Returns the locked key.
+    pub fn key(&self) -> &Arc<K> {
+        &self.key_lock.key
+    }
+
+    /// Returns `true` if this guard was returned by the given lock map.
+    pub(crate) fn is_from(&self, map: &KeyLockMap<K, S>) -> bool {
+        std::ptr::eq(self.key_lock.map, &map.locks)
+    }
+}
+
+impl<K, S> fmt::Debug for KeyGuard<'_, K, S>
+where
+    K: fmt::Debug + Eq + Hash,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyGuard")
+            .field("key", &self.key_lock.key)
+            .finish()
+    }
+}
@@ -0,0 +1,301 @@
+use crate::common::concurrent::{
+    constants::DEFAULT_MAINTENANCE_INTERVAL_MILLIS,
+    maintenance::{MaintenanceHandle, MaintenanceWaker},
+};
+
+use crossbeam_channel::{Receiver, TryRecvError};
+use futures_util::{future::BoxFuture, task::AtomicWaker};
+use parking_lot::{Condvar, Mutex};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    task::Poll,
+    thread,
+    time::{Duration, Instant},
+};
+
+type SpawnFn = Arc<dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static>;
+
+/// Runs the maintenance tasks of a cache in the background.
+///
+/// By default, a cache runs its maintenance tasks, such as removing expired
+/// entries and calling the eviction listener, only while it is being read or
+/// written, or when [`run_pending_tasks`][run-pending-tasks] is called. An idle
+/// cache keeps its expired entries, and the eviction listener is not called for
+/// them until the cache is used again.
+///
+/// Set a `MaintenanceScheduler` to the cache by the `maintenance_scheduler` method
+/// of the [`CacheBuilder`][builder] to run the tasks in the background. The
+/// scheduler runs them when the next entry will expire or after the interval,
+/// whichever comes first. It stops when the cache and all of its clones are
+/// dropped.
+///
+/// The maintenance tasks run in a future spawned by a function you provide, so the
+/// scheduler works with any async runtime. The future sleeps between the runs
+/// without using a timer of the runtime; a single thread shared by all the caches
+/// wakes it up when the next run is due.
+///
+/// By default, the eviction listener is called for an expired entry at the next
+/// run of the tasks, so it can be called up to the interval later than the entry
+/// has expired. Enable [`proactive_expiration`][proactive-expiration] to call it
+/// closer to the expiration time of the entries with per-entry expiration.
+///
+/// [run-pending-tasks]: ./struct.Cache.html#method.run_pending_tasks
+/// [builder]: ./struct.CacheBuilder.html
+/// [proactive-expiration]: #method.proactive_expiration
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka2 = { version = "0.13", features = ["future"] }
+/// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+/// use moka2::future::{Cache, MaintenanceScheduler};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // The spawn function is called when the cache is built, which may be
+///     // outside of the runtime, so use a handle to the runtime rather than
+///     // `tokio::spawn`.
+///     let runtime = tokio::runtime::Handle::current();
+///     let scheduler = MaintenanceScheduler::spawn_with(move |task| {
+///         runtime.spawn(task);
+///     });
+///
+///     let cache = Cache::builder()
+///         .time_to_live(Duration::from_secs(30))
+///         .maintenance_scheduler(scheduler.interval(Duration::from_secs(5)))
+///         .build();
+///
+///     cache.insert("key", "value").await;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MaintenanceScheduler {
+    spawn: SpawnFn,
+    pub(super) interval: Duration,
+    proactive_expiration: bool,
+}
+
+impl fmt::Debug for MaintenanceScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaintenanceScheduler")
+            .field("interval", &self.interval)
+            .field("proactive_expiration", &self.proactive_expiration)
+            .finish()
+    }
+}
+
+impl MaintenanceScheduler {
+    /// Creates a scheduler that runs the maintenance tasks in a future spawned by
+    /// the given function.
+    ///
+    /// The function is called once for each cache when the cache is built. The
+    /// spawned future runs until the cache is dropped. The function should spawn
+    /// it through a handle to the runtime if the cache may be built outside of the
+    /// runtime.
+    pub fn spawn_with(
+        spawn: impl Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            spawn: Arc::new(spawn),
+            interval: Duration::from_millis(DEFAULT_MAINTENANCE_INTERVAL_MILLIS),
+            proactive_expiration: false,
+        }
+    }
+
+    /// Sets the maximum interval between two runs of the maintenance tasks.
+    ///
+    /// The default value is one second.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `interval` is zero.
+    pub fn interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Sets whether to remove the entries with per-entry expiration (set by an
+    /// [`Expiry`][expiry] or `insert_with_ttl`) at their expiration times.
+    ///
+    /// When enabled, the scheduler sleeps until the earliest of these entries
+    /// expires, and then removes the expired entries and calls the eviction
+    /// listener for them. It does not run the other maintenance tasks until the
+    /// interval has elapsed. The listener is called within about one second (the
+    /// resolution of the timers) after the expiration time.
+    ///
+    /// An entry gets its timer when its insertion is processed by the maintenance
+    /// tasks, so an entry inserted to an idle cache may expire before that.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [expiry]: ../policy/trait.Expiry.html
+    pub fn proactive_expiration(self, enabled: bool) -> Self {
+        Self {
+            proactive_expiration: enabled,
+            ..self
+        }
+    }
+
+    /// Spawns a future that runs the given maintenance jobs. A job returns a task
+    /// to run, or `None` if its cache has been dropped. The task resolves to the
+    /// delay until the next run. The tasks of all jobs run concurrently.
+    ///
+    /// Returns a waker for the future if the expiration timers should run
+    /// proactively.
+    pub(crate) fn start<F>(&self, mut jobs: Vec<F>) -> (MaintenanceHandle, Option<MaintenanceWaker>)
+    where
+        F: FnMut(Duration) -> Option<BoxFuture<'static, Duration>> + Send + 'static,
+    {
+        let interval = self.interval;
+        let (handle, stop) = MaintenanceHandle::new();
+        let timer = MaintenanceTimer::new();
+        let waker = {
+            let timer = Arc::clone(&timer);
+            MaintenanceWaker::new(move || timer.wake())
+        };
+
+        (self.spawn)(Box::pin(async move {
+            let mut delay = interval;
+            while timer.sleep(delay, &stop).await {
+                let Some(tasks) = jobs
+                    .iter_mut()
+                    .map(|job| job(interval))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    break;
+                };
+                let delays = futures_util::future::join_all(tasks).await;
+                delay = delays.into_iter().fold(interval, Duration::min);
+            }
+        }));
+
+        (handle, self.proactive_expiration.then_some(waker))
+    }
+}
+
+/// The timer of the future spawned by a `MaintenanceScheduler`. The future sleeps
+/// on it until the deadline has passed or it is woken up by a `MaintenanceWaker`.
+struct MaintenanceTimer {
+    deadline: Mutex<Option<Instant>>,
+    is_woken: AtomicBool,
+    task: AtomicWaker,
+}
+
+impl MaintenanceTimer {
+    fn new() -> Arc<Self> {
+        let timer = Arc::new(Self {
+            deadline: Mutex::new(None),
+            is_woken: AtomicBool::new(false),
+            task: AtomicWaker::new(),
+        });
+        TIMER_THREAD.register(&timer);
+        timer
+    }
+
+    fn wake(&self) {
+        self.is_woken.store(true, Ordering::Release);
+        self.task.wake();
+    }
+
+    /// Sleeps until the delay has elapsed or the timer is woken up. Returns `false`
+    /// if the future should stop because the handle has been dropped.
+    async fn sleep(&self, delay: Duration, stop: &Receiver<()>) -> bool {
+        let deadline = Instant::now().checked_add(delay);
+        TIMER_THREAD.set_deadline(self, deadline);
+
+        futures_util::future::poll_fn(|cx| {
+            self.task.register(cx.waker());
+            // Nothing is sent to the stop channel. It gets disconnected when the
+            // handle is dropped.
+            if let Err(TryRecvError::Disconnected) = stop.try_recv() {
+                Poll::Ready(false)
+            } else if self.is_woken.swap(false, Ordering::AcqRel)
+                || deadline.map_or(false, |d| d <= Instant::now())
+            {
+                Poll::Ready(true)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// The thread that wakes up the futures sleeping on the `MaintenanceTimer`s when
+/// their deadlines have passed. It is shared by all the caches, and started when
+/// the first timer is registered.
+static TIMER_THREAD: TimerThread = TimerThread {
+    state: parking_lot::const_mutex(TimerThreadState {
+        timers: Vec::new(),
+        is_started: false,
+    }),
+    cond: Condvar::new(),
+};
+
+struct TimerThread {
+    state: Mutex<TimerThreadState>,
+    cond: Condvar,
+}
+
+struct TimerThreadState {
+    timers: Vec<Weak<MaintenanceTimer>>,
+    is_started: bool,
+}
+
+impl TimerThread {
+    fn register(&'static self, timer: &Arc<MaintenanceTimer>) {
+        let mut state = self.state.lock();
+        state.timers.push(Arc::downgrade(timer));
+        if !state.is_started {
+            thread::Builder::new()
+                .name("moka2-maintenance-timer".to_string())
+                .spawn(move || self.run())
+                .expect("Failed to spawn the maintenance timer thread");
+            state.is_started = true;
+        }
+    }
+
+    fn set_deadline(&self, timer: &MaintenanceTimer, deadline: Option<Instant>) {
+        // Hold the state lock so that the thread does not miss the notification
+        // between reading the deadlines and starting to wait.
+        let _state = self.state.lock();
+        *timer.deadline.lock() = deadline;
+        self.cond.notify_one();
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock();
+        loop {
+            state.timers.retain(|timer| timer.strong_count() > 0);
+
+            let now = Instant::now();
+            let mut next_deadline: Option<Instant> = None;
+            for timer in state.timers.iter().filter_map(Weak::upgrade) {
+                let mut deadline = timer.deadline.lock();
+                match *deadline {
+                    Some(d) if d <= now => {
+                        *deadline = None;
+                        timer.task.wake();
+                    }
+                    Some(d) => next_deadline = Some(next_deadline.map_or(d, |n| n.min(d))),
+                    None => (),
+                }
+            }
+
+            if let Some(d) = next_deadline {
+                self.cond.wait_until(&mut state, d);
+            } else {
+                self.cond.wait(&mut state);
+            }
+        }
+    }
+}
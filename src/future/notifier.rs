@@ -1,46 +1,56 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    any::Any,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use futures_util::FutureExt;
 
-use crate::notification::{AsyncEvictionListener, RemovalCause};
+use crate::{
+    common::callback_panic::CallbackPanicReporter,
+    notification::{AsyncEvictionListener, RemovalCause},
+    policy::CallbackKind,
+};
 
 pub(crate) struct RemovalNotifier<K, V> {
     listener: AsyncEvictionListener<K, V>,
     is_enabled: AtomicBool,
-    #[cfg(feature = "logging")]
-    cache_name: Option<String>,
+    panic_reporter: CallbackPanicReporter,
 }
 
 impl<K, V> RemovalNotifier<K, V> {
-    pub(crate) fn new(listener: AsyncEvictionListener<K, V>, _cache_name: Option<String>) -> Self {
+    pub(crate) fn new(
+        listener: AsyncEvictionListener<K, V>,
+        panic_reporter: CallbackPanicReporter,
+    ) -> Self {
         Self {
             listener,
             is_enabled: AtomicBool::new(true),
-            #[cfg(feature = "logging")]
-            cache_name: _cache_name,
+            panic_reporter,
         }
     }
 
-    pub(crate) async fn notify(&self, key: Arc<K>, value: V, cause: RemovalCause) {
-        use std::panic::{catch_unwind, AssertUnwindSafe};
+    /// Returns `false` if the listener has been disabled because it panicked.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.is_enabled.load(Ordering::Acquire)
+    }
 
+    pub(crate) async fn notify(&self, key: Arc<K>, value: V, cause: RemovalCause) {
         if !self.is_enabled.load(Ordering::Acquire) {
             return;
         }
 
         // This macro unwraps the result of the catch_unwind call if it is Ok. And
-        // disable the notifier and do early return if the listener panicked.
-        macro_rules! try_or_disable {
+        // handles the panic and do early return if the listener panicked.
+        macro_rules! try_or_handle_panic {
             ($match_expr:expr) => {
                 match $match_expr {
                     Ok(v) => v,
-                    Err(_payload) => {
-                        self.is_enabled.store(false, Ordering::Release);
-                        #[cfg(feature = "logging")]
-                        log_panic(&*_payload, self.cache_name.as_deref());
+                    Err(payload) => {
+                        self.handle_panic(payload);
                         return;
                     }
                 }
@@ -49,30 +59,22 @@ impl<K, V> RemovalNotifier<K, V> {
 
         let listener_clo = || (self.listener)(key, value, cause);
 
-        // Safety: It is safe to assert unwind safety here because we will not
-        // call the listener again if it has been panicked.
-        let fut = try_or_disable!(catch_unwind(AssertUnwindSafe(listener_clo)));
-        try_or_disable!(AssertUnwindSafe(fut).catch_unwind().await);
+        // Safety: It is safe to assert unwind safety here because, by default, we
+        // will not call the listener again if it has been panicked. The other
+        // panic policies keep calling it as the user opted in.
+        let fut = try_or_handle_panic!(catch_unwind(AssertUnwindSafe(listener_clo)));
+        try_or_handle_panic!(AssertUnwindSafe(fut).catch_unwind().await);
     }
-}
-
-#[cfg(feature = "logging")]
-fn log_panic(payload: &(dyn std::any::Any + Send + 'static), cache_name: Option<&str>) {
-    // Try to downcast the payload into &str or String.
-    //
-    // NOTE: Clippy will complain if we use `if let Some(_)` here.
-    // https://rust-lang.github.io/rust-clippy/master/index.html#manual_map
-    let message: Option<std::borrow::Cow<'_, str>> =
-        (payload.downcast_ref::<&str>().map(|s| (*s).into()))
-            .or_else(|| payload.downcast_ref::<String>().map(Into::into));
 
-    let cn = cache_name
-        .map(|name| format!("[{name}] "))
-        .unwrap_or_default();
-
-    if let Some(m) = message {
-        log::error!("{cn}Disabled the eviction listener because it panicked at '{m}'");
-    } else {
-        log::error!("{cn}Disabled the eviction listener because it panicked");
+    fn handle_panic(&self, payload: Box<dyn Any + Send>) {
+        let reporter = &self.panic_reporter;
+        if reporter.propagates_panics() {
+            resume_unwind(payload);
+        } else if reporter.catches_panics() {
+            reporter.report(CallbackKind::EvictionListener, &*payload);
+        } else {
+            self.is_enabled.store(false, Ordering::Release);
+            reporter.report_disabled_listener(&*payload);
+        }
     }
 }
@@ -0,0 +1,1991 @@
+use super::{
+    cache::Cache, CacheBuilder, EntryStream, Iter, IterWithMetadata, KeyGuard, KeyStream, Keys,
+    MaintenanceScheduler, OwnedKeyEntrySelector, PredicateId, RefKeyEntrySelector, Values,
+};
+use crate::common::{
+    cache_metrics::MetricsSnapshot,
+    concurrent::{maintenance::MaintenanceHandle, Weigher},
+    tag::TagExtractor,
+};
+use crate::{
+    clock::CacheClock,
+    common::HousekeeperConfig,
+    notification::AsyncEvictionListener,
+    ops::compute,
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    sync_base::iter::{
+        Iter as InnerIter, IterWithMetadata as InnerIterWithMetadata, Keys as InnerKeys,
+        ScanningGet, Values as InnerValues,
+    },
+    Entry, HeapSize, KeyLockError, MaintenanceStats, MemoryReport, Policy, PredicateError,
+    PredicateStatus,
+};
+
+use super::invalidator::PredicateFun;
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// A thread-safe, futures-aware concurrent in-memory cache, with multiple internal
+/// segments.
+///
+/// `SegmentedCache` has multiple internal [`Cache`][cache-struct] instances for
+/// increased concurrent update performance. Each segment has its own maintenance
+/// tasks and read/write logs, so the tasks of different segments do not contend
+/// with each other. However, it has little overheads on retrievals and updates for
+/// managing these segments.
+///
+/// For usage examples, see the document of the [`Cache`][cache-struct].
+///
+/// [cache-struct]: ./struct.Cache.html
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = ..., features = ["future"] }
+/// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+/// use moka2::future::SegmentedCache;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // A cache with 8 segments, holding up to 10,000 entries in total.
+///     let cache = SegmentedCache::builder(8).max_capacity(10_000).build();
+///
+///     cache.insert(0, "zero").await;
+///     assert_eq!(cache.get(&0).await, Some("zero"));
+///     assert_eq!(cache.get_with(1, async { "one" }).await, "one");
+///
+///     // Runs the pending tasks of all segments concurrently.
+///     cache.run_pending_tasks().await;
+///     assert_eq!(cache.entry_count(), 2);
+/// }
+/// ```
+///
+pub struct SegmentedCache<K, V, S = RandomState> {
+    inner: Arc<Inner<K, V, S>>,
+}
+
+// TODO: https://github.com/moka-rs/moka/issues/54
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<K, V, S> Send for SegmentedCache<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send,
+{
+}
+
+unsafe impl<K, V, S> Sync for SegmentedCache<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Sync,
+{
+}
+
+impl<K, V, S> Clone for SegmentedCache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for SegmentedCache<K, V, S>
+where
+    K: fmt::Debug + Eq + Hash + Send + Sync + 'static,
+    V: fmt::Debug + Clone + Send + Sync + 'static,
+    // TODO: Remove these bounds from S.
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d_map = f.debug_map();
+
+        for (k, v) in self {
+            d_map.entry(&k, &v);
+        }
+
+        d_map.finish()
+    }
+}
+
+impl<K, V> SegmentedCache<K, V, RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Constructs a new `SegmentedCache<K, V>` that has multiple internal
+    /// segments and will store up to the `max_capacity`.
+    ///
+    /// To adjust various configuration knobs such as `initial_capacity` or
+    /// `time_to_live`, use the [`CacheBuilder`][builder-struct].
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn new(max_capacity: u64, num_segments: usize) -> Self {
+        let build_hasher = RandomState::default();
+        Self::with_everything(
+            None,
+            Some(max_capacity),
+            None,
+            num_segments,
+            build_hasher,
+            None,
+            EvictionPolicy::default(),
+            None,
+            CallbackPanicPolicy::default(),
+            ExpirationPolicy::default(),
+            HousekeeperConfig::default(),
+            false,
+            false,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Returns a [`CacheBuilder`][builder-struct], which can builds a
+    /// `SegmentedCache` with various configuration knobs.
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    pub fn builder(num_segments: usize) -> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>> {
+        CacheBuilder::default().segments(num_segments)
+    }
+}
+
+impl<K, V, S> SegmentedCache<K, V, S> {
+    /// Returns cache’s name.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.segments[0].name()
+    }
+
+    /// Returns a read-only cache policy of this cache.
+    ///
+    /// At this time, cache policy cannot be modified after cache creation.
+    /// A future version may support to modify it.
+    pub fn policy(&self) -> Policy {
+        let mut policy = self.inner.segments[0].policy();
+        policy.set_max_capacity(self.inner.desired_capacity);
+        policy.set_num_segments(self.inner.segments.len());
+        policy
+    }
+
+    /// Returns `true` if the eviction listener is set and has not been disabled in
+    /// any segment.
+    ///
+    /// See [`Cache::listener_enabled`](./struct.Cache.html#method.listener_enabled)
+    /// for more details.
+    pub fn listener_enabled(&self) -> bool {
+        self.inner.segments.iter().all(Cache::listener_enabled)
+    }
+
+    /// Returns an approximate number of entries in this cache.
+    ///
+    /// The value returned is _an estimate_; the actual count may differ if there are
+    /// concurrent insertions or removals, or if some entries are pending removal due
+    /// to expiration. This inaccuracy can be mitigated by calling
+    /// `run_pending_tasks` first.
+    pub fn entry_count(&self) -> u64 {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.entry_count())
+            .sum()
+    }
+
+    /// Returns an approximate total weighted size of entries in this cache.
+    ///
+    /// The value returned is _an estimate_; the actual size may differ if there are
+    /// concurrent insertions or removals, or if some entries are pending removal due
+    /// to expiration. This inaccuracy can be mitigated by calling
+    /// `run_pending_tasks` first.
+    pub fn weighted_size(&self) -> u64 {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.weighted_size())
+            .sum()
+    }
+}
+
+impl<K, V, S> SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn with_everything(
+        name: Option<String>,
+        max_capacity: Option<u64>,
+        initial_capacity: Option<usize>,
+        num_segments: usize,
+        build_hasher: S,
+        weigher: Option<Weigher<K, V>>,
+        eviction_policy: EvictionPolicy,
+        eviction_listener: Option<AsyncEvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
+        expiration_policy: ExpirationPolicy<K, V>,
+        housekeeper_config: HousekeeperConfig,
+        invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner::new(
+                name,
+                max_capacity,
+                initial_capacity,
+                num_segments,
+                build_hasher,
+                weigher,
+                eviction_policy,
+                eviction_listener,
+                callback_panic_policy,
+                expiration_policy,
+                housekeeper_config,
+                invalidator_enabled,
+                key_locks_enabled,
+                tag_extractor,
+                clock,
+                maintenance_scheduler,
+            )),
+        }
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read operation,
+    /// so it does not update the historic popularity estimator or reset the idle
+    /// timer for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .base
+            .contains_key_with_hash(key, hash)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// If you want to store values that will be expensive to clone, wrap them by
+    /// `std::sync::Arc` before storing in a cache. [`Arc`][rustdoc-std-arc] is a
+    /// thread-safe reference-counted pointer and its `clone()` method is cheap.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// [rustdoc-std-arc]: https://doc.rust-lang.org/stable/std/sync/struct.Arc.html
+    pub async fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .get_with_hash(key, hash, false)
+            .await
+            .map(Entry::into_value)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key without affecting
+    /// the cache policies.
+    ///
+    /// See [`Cache::peek`](./struct.Cache.html#method.peek) for more details.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .base
+            .peek_with_hash(key, hash, false)
+            .map(Entry::into_value)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the metadata of the entry,
+    /// without affecting the cache policies.
+    ///
+    /// See [`Cache::peek_entry`](./struct.Cache.html#method.peek_entry) for more
+    /// details.
+    pub fn peek_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).base.peek_with_hash(key, hash, true)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the metadata of the entry.
+    ///
+    /// See [`Cache::get_entry`](./struct.Cache.html#method.get_entry) for more
+    /// details.
+    pub async fn get_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .base
+            .get_entry_with_hash(key, hash)
+            .await
+    }
+
+    /// Takes a key `K` and returns an [`OwnedKeyEntrySelector`] that can be used to
+    /// select or insert an entry.
+    ///
+    /// See [`Cache::entry`](./struct.Cache.html#method.entry) for more details.
+    ///
+    /// [`OwnedKeyEntrySelector`]: ./struct.OwnedKeyEntrySelector.html
+    pub fn entry(&self, key: K) -> OwnedKeyEntrySelector<'_, K, V, S>
+    where
+        K: Hash + Eq,
+    {
+        let hash = self.inner.hash(&key);
+        let cache = self.inner.select(hash);
+        OwnedKeyEntrySelector::new(key, hash, cache)
+    }
+
+    /// Takes a reference `&Q` of a key and returns an [`RefKeyEntrySelector`] that
+    /// can be used to select or insert an entry.
+    ///
+    /// See [`Cache::entry_by_ref`](./struct.Cache.html#method.entry_by_ref) for
+    /// more details.
+    ///
+    /// [`RefKeyEntrySelector`]: ./struct.RefKeyEntrySelector.html
+    pub fn entry_by_ref<'a, Q>(&'a self, key: &'a Q) -> RefKeyEntrySelector<'a, K, Q, V, S>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        let cache = self.inner.select(hash);
+        RefKeyEntrySelector::new(key, hash, cache)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the value does
+    /// not exist, resolve the `init` future and inserts the output.
+    ///
+    /// See [`Cache::get_with`](./struct.Cache.html#method.get_with) for more
+    /// details, including the concurrent calls for the same key.
+    pub async fn get_with(&self, key: K, init: impl Future<Output = V>) -> V {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        let replace_if = None as Option<fn(&V) -> bool>;
+        self.inner
+            .select(hash)
+            .get_or_insert_with_hash_and_fun(key, hash, init, replace_if, false)
+            .await
+            .into_value()
+    }
+
+    /// Similar to [`get_with`](#method.get_with), but instead of passing an owned
+    /// key, you can pass a reference to the key. If the key does not exist in the
+    /// cache, the key will be cloned to create new entry in the cache.
+    pub async fn get_with_by_ref<Q>(&self, key: &Q, init: impl Future<Output = V>) -> V
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(key);
+        let replace_if = None as Option<fn(&V) -> bool>;
+        self.inner
+            .select(hash)
+            .get_or_insert_with_hash_by_ref_and_fun(key, hash, init, replace_if, false)
+            .await
+            .into_value()
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the value does
+    /// not exist, resolves the `init` future, and inserts the value if `Some(value)`
+    /// was returned. If `None` was returned from the future, this method does not
+    /// insert a value and returns `None`.
+    ///
+    /// See [`Cache::optionally_get_with`](./struct.Cache.html#method.optionally_get_with)
+    /// for more details.
+    pub async fn optionally_get_with<F>(&self, key: K, init: F) -> Option<V>
+    where
+        F: Future<Output = Option<V>>,
+    {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .get_or_optionally_insert_with_hash_and_fun(key, hash, init, false)
+            .await
+            .map(Entry::into_value)
+    }
+
+    /// Similar to [`optionally_get_with`](#method.optionally_get_with), but instead
+    /// of passing an owned key, you can pass a reference to the key. If the key
+    /// does not exist in the cache, the key will be cloned to create new entry in
+    /// the cache.
+    pub async fn optionally_get_with_by_ref<F, Q>(&self, key: &Q, init: F) -> Option<V>
+    where
+        F: Future<Output = Option<V>>,
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .get_or_optionally_insert_with_hash_by_ref_and_fun(key, hash, init, false)
+            .await
+            .map(Entry::into_value)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key. If the value does
+    /// not exist, resolves the `init` future, and inserts the value if `Ok(value)`
+    /// was returned. If `Err(_)` was returned from the future, this method does not
+    /// insert a value and returns the `Err` wrapped by [`std::sync::Arc`][std-arc].
+    ///
+    /// See [`Cache::try_get_with`](./struct.Cache.html#method.try_get_with) for
+    /// more details.
+    ///
+    /// [std-arc]: https://doc.rust-lang.org/stable/std/sync/struct.Arc.html
+    pub async fn try_get_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+    {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .get_or_try_insert_with_hash_and_fun(key, hash, init, false)
+            .await
+            .map(Entry::into_value)
+    }
+
+    /// Similar to [`try_get_with`](#method.try_get_with), but instead of passing an
+    /// owned key, you can pass a reference to the key. If the key does not exist in
+    /// the cache, the key will be cloned to create new entry in the cache.
+    pub async fn try_get_with_by_ref<F, E, Q>(&self, key: &Q, init: F) -> Result<V, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        futures_util::pin_mut!(init);
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .get_or_try_insert_with_hash_by_ref_and_fun(key, hash, init, false)
+            .await
+            .map(Entry::into_value)
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub async fn insert(&self, key: K, value: V) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash(key, hash, value)
+            .await;
+    }
+
+    /// Inserts a key-value pair into the cache with its own time-to-live.
+    ///
+    /// See [`Cache::insert_with_ttl`](./struct.Cache.html#method.insert_with_ttl)
+    /// for more details.
+    pub async fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash_and_ttl(key, hash, value, Some(ttl))
+            .await;
+    }
+
+    /// Inserts a key-value pair into the cache, which will expire at the given
+    /// deadline.
+    ///
+    /// See [`Cache::insert_with_deadline`](./struct.Cache.html#method.insert_with_deadline)
+    /// for more details.
+    pub async fn insert_with_deadline(&self, key: K, value: V, deadline: std::time::Instant) {
+        let hash = self.inner.hash(&key);
+        let segment = self.inner.select(hash);
+        let ttl = segment.base.duration_until(deadline);
+        segment
+            .insert_with_hash_and_ttl(Arc::new(key), hash, value, Some(ttl))
+            .await;
+    }
+
+    /// Inserts a key-value pair into the cache, which will expire at the given
+    /// wall-clock time.
+    ///
+    /// See [`Cache::insert_expiring_at`](./struct.Cache.html#method.insert_expiring_at)
+    /// for more details.
+    pub async fn insert_expiring_at(&self, key: K, value: V, expires_at: SystemTime) -> bool {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash_expiring_at(key, hash, value, expires_at)
+            .await
+    }
+
+    /// Inserts a key-value pair into the cache, and returns a _clone_ of the value
+    /// that was replaced, if any.
+    ///
+    /// See [`Cache::swap`](./struct.Cache.html#method.swap) for more details.
+    pub async fn swap(&self, key: K, value: V) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .swap_with_hash(key, hash, value)
+            .await
+    }
+
+    /// Inserts a key-value pair into the cache only if the key is not present, and
+    /// returns a _clone_ of the current value if it is present.
+    ///
+    /// See [`Cache::insert_if_absent`](./struct.Cache.html#method.insert_if_absent)
+    /// for more details.
+    pub async fn insert_if_absent(&self, key: K, value: V) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_if_absent_with_hash(key, hash, value)
+            .await
+    }
+
+    /// Replaces the value of the key with the given `value` if the key is present
+    /// and the `condition` returns `true` for its current value. Returns a _clone_
+    /// of the replaced value.
+    ///
+    /// See [`Cache::replace_if`](./struct.Cache.html#method.replace_if) for more
+    /// details.
+    pub async fn replace_if<Q>(
+        &self,
+        key: &Q,
+        value: V,
+        condition: impl FnMut(&V) -> bool + Send,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .replace_with_hash_if(key, hash, value, condition)
+            .await
+            .ok()
+    }
+
+    /// Replaces the value of the key with the given `value` if the key is present.
+    /// Returns a _clone_ of the replaced value.
+    pub async fn replace_if_present<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.replace_if(key, value, |_| true).await
+    }
+
+    /// Replaces the value of the key with `new` if its current value is equal to
+    /// `expected`.
+    ///
+    /// See [`Cache::replace`](./struct.Cache.html#method.replace) for more details.
+    pub async fn replace<Q>(&self, key: &Q, expected: &V, new: V) -> Result<V, Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .replace_with_hash_if(key, hash, new, |v| v == expected)
+            .await
+    }
+
+    /// Sets the time-to-live of the entry for the key, without changing its value.
+    ///
+    /// See [`Cache::set_expiry`](./struct.Cache.html#method.set_expiry) for more
+    /// details.
+    pub async fn set_expiry<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .set_expiry_with_hash(key, hash, ttl)
+            .await
+    }
+
+    /// Records a read of the entry for the key without cloning its value. Returns
+    /// `true` if the key is present.
+    ///
+    /// See [`Cache::touch`](./struct.Cache.html#method.touch) for more details.
+    pub async fn touch<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .get_with_hash(key, hash, false)
+            .await
+            .is_some()
+    }
+
+    /// Returns the remaining time until the entry for the key expires.
+    ///
+    /// See [`Cache::expires_in`](./struct.Cache.html#method.expires_in) for more
+    /// details.
+    pub fn expires_in<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).base.expires_in_with_hash(key, hash)
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// If you need to get the value that has been discarded, use the
+    /// [`remove`](#method.remove) method instead.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .invalidate_with_hash(key, hash, false)
+            .await;
+    }
+
+    /// Discards any cached value for the key and returns a _clone_ of the value.
+    ///
+    /// If you do not need to get the value that has been discarded, use the
+    /// [`invalidate`](#method.invalidate) method instead.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .invalidate_with_hash(key, hash, true)
+            .await
+    }
+
+    /// Discards the cached value for the key if the `condition` returns `true` for
+    /// it, and returns a _clone_ of the removed value.
+    ///
+    /// See [`Cache::remove_if`](./struct.Cache.html#method.remove_if) for more
+    /// details.
+    pub async fn remove_if<Q>(&self, key: &Q, condition: impl FnMut(&V) -> bool + Send) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .remove_with_hash_if(key, hash, condition)
+            .await
+    }
+
+    /// Locks the key and returns a [`KeyGuard`] that unlocks it when dropped.
+    ///
+    /// See [`Cache::lock_key`] for the details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub async fn lock_key<Q>(&self, key: &Q) -> Result<KeyGuard<'_, K, S>, KeyLockError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).lock_key(key).await
+    }
+
+    /// Performs a compute operation on the entry for the key locked by `guard`.
+    ///
+    /// See [`Cache::compute_with_guard`] for the details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::ForeignKeyGuard`] if `guard` was not returned by
+    /// this cache.
+    pub async fn compute_with_guard<F, Fut>(
+        &self,
+        guard: &KeyGuard<'_, K, S>,
+        f: F,
+    ) -> Result<compute::CompResult<K, V>, KeyLockError>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Fut,
+        Fut: Future<Output = compute::Op<V>>,
+    {
+        let hash = self.inner.hash(&**guard.key());
+        self.inner.select(hash).compute_with_guard(guard, f).await
+    }
+
+    /// Performs a compute operation on the entries for multiple keys, while
+    /// holding the locks of all the keys.
+    ///
+    /// The keys may belong to different segments. The keys are locked segment by
+    /// segment in the order of the segments, so that the concurrent calls with
+    /// overlapping keys do not deadlock.
+    ///
+    /// See [`Cache::compute_many`](./struct.Cache.html#method.compute_many) for
+    /// more details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub async fn compute_many<const N: usize, F, Fut>(
+        &self,
+        keys: [K; N],
+        f: F,
+    ) -> Result<Vec<compute::CompResult<K, V>>, KeyLockError>
+    where
+        F: FnOnce([Option<Entry<K, V>>; N]) -> Fut,
+        Fut: Future<Output = [compute::Op<V>; N]>,
+    {
+        let keys = keys.map(Arc::new);
+        let hashes: [u64; N] = std::array::from_fn(|i| self.inner.hash(&*keys[i]));
+        let indices = hashes.map(|h| self.inner.segment_index_from_hash(h));
+
+        let mut guards = Vec::with_capacity(N);
+        for (index, segment) in self.inner.segments.iter().enumerate() {
+            let seg_keys = keys
+                .iter()
+                .zip(&indices)
+                .filter(|(_, &i)| i == index)
+                .map(|(k, _)| Arc::clone(k))
+                .collect::<Vec<_>>();
+            if !seg_keys.is_empty() {
+                guards.extend(segment.base.lock_keys(&seg_keys).await?);
+            }
+        }
+
+        let mut entries = std::array::from_fn(|_| None);
+        for (((entry, k), &h), &i) in entries.iter_mut().zip(&keys).zip(&hashes).zip(&indices) {
+            *entry = self.inner.segments[i].get_with_hash(&**k, h, true).await;
+        }
+        let ops = f(entries).await;
+
+        let mut results = Vec::with_capacity(N);
+        for ((key, i), op) in keys.iter().zip(indices).zip(ops) {
+            let guard = guards
+                .iter()
+                .find(|g| g.key() == key)
+                .expect("the key should have been locked");
+            let result = self.inner.segments[i]
+                .compute_with_guard(guard, |_| async { op })
+                .await?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately by just setting the current time as the
+    /// invalidation time. `get` and other retrieval methods are guaranteed not to
+    /// return the entries inserted before or at the invalidation time.
+    ///
+    /// The actual removal of the invalidated entries is done as a maintenance task
+    /// driven by a user thread. For more details, see
+    /// [the Maintenance Tasks section](../index.html#maintenance-tasks) in the crate
+    /// level documentation.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    pub fn invalidate_all(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_all();
+        }
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. The
+    /// closure is called against each cached entry inserted before or at the time
+    /// when this method was called. If the closure returns `true` that entry will be
+    /// evicted from the cache.
+    ///
+    /// This method returns immediately by not actually removing the invalidated
+    /// entries. Instead, it just sets the predicate to the cache with the time when
+    /// this method was called. The actual removal of the invalidated entries is done
+    /// as a maintenance task driven by a user thread. For more details, see
+    /// [the Maintenance Tasks section](../index.html#maintenance-tasks) in the crate
+    /// level documentation.
+    ///
+    /// Also the `get` and other retrieval methods will apply the closure to a cached
+    /// entry to determine if it should have been invalidated. Therefore, it is
+    /// guaranteed that these methods must not return invalidated values.
+    ///
+    /// Returns the ID of the predicate, which is shared by all segments. Pass it to
+    /// [`predicate_status`](#method.predicate_status) and others to track the scan.
+    ///
+    /// Note that you must call
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
+    /// at the cache creation time as the cache needs to maintain additional internal
+    /// data structures to support this method. Otherwise, calling this method will
+    /// fail with a
+    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// [support-invalidation-closures]:
+    ///     ./struct.CacheBuilder.html#method.support_invalidation_closures
+    /// [invalidation-disabled-error]:
+    ///     ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        let pred: PredicateFun<K, V> = Arc::new(predicate);
+        // Register the predicate to all segments under the ID given by the first
+        // segment, so that the ID can be used with `predicate_status` and others.
+        let (first, rest) = self
+            .inner
+            .segments
+            .split_first()
+            .expect("a SegmentedCache has at least one segment");
+        let id = first.base.invalidate_entries_if(Arc::clone(&pred))?;
+        for segment in rest {
+            segment
+                .base
+                .invalidate_entries_if_with_id(&id, Arc::clone(&pred))?;
+        }
+        Ok(id)
+    }
+
+    /// Returns the status of a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method.
+    ///
+    /// The status is combined from the segments: the predicate is `Pending` until
+    /// any segment starts scanning for it, and it is finished when all segments
+    /// have finished. `scanned` and `removed` are summed over the segments.
+    ///
+    /// See [`Cache::predicate_status`](./struct.Cache.html#method.predicate_status)
+    /// for more details.
+    pub fn predicate_status(&self, id: &str) -> Option<PredicateStatus> {
+        let (mut total_scanned, mut total_removed) = (0, 0);
+        let (mut is_known, mut is_pending, mut is_finished, mut is_cancelled) =
+            (false, true, true, false);
+
+        for segment in self.inner.segments.iter() {
+            // A segment may have forgotten the status of a finished predicate.
+            let Some(status) = segment.predicate_status(id) else {
+                continue;
+            };
+            is_known = true;
+            let (scanned, removed) = match status {
+                PredicateStatus::Pending => {
+                    is_finished = false;
+                    continue;
+                }
+                PredicateStatus::InProgress { scanned, removed } => {
+                    is_finished = false;
+                    (scanned, removed)
+                }
+                PredicateStatus::Completed { scanned, removed } => (scanned, removed),
+                PredicateStatus::Cancelled { scanned, removed } => {
+                    is_cancelled = true;
+                    (scanned, removed)
+                }
+            };
+            is_pending = false;
+            total_scanned += scanned;
+            total_removed += removed;
+        }
+
+        let (scanned, removed) = (total_scanned, total_removed);
+        let status = if !is_known {
+            return None;
+        } else if is_finished && is_cancelled {
+            PredicateStatus::Cancelled { scanned, removed }
+        } else if is_finished {
+            PredicateStatus::Completed { scanned, removed }
+        } else if is_pending {
+            PredicateStatus::Pending
+        } else {
+            PredicateStatus::InProgress { scanned, removed }
+        };
+        Some(status)
+    }
+
+    /// Cancels a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method in all
+    /// segments.
+    ///
+    /// Returns `true` if the predicate was pending or in progress in any segment
+    /// and has been cancelled.
+    ///
+    /// See [`Cache::cancel_predicate`](./struct.Cache.html#method.cancel_predicate)
+    /// for more details.
+    pub fn cancel_predicate(&self, id: &str) -> bool {
+        // Do not short-circuit; cancel the predicate in all segments.
+        let mut cancelled = false;
+        for segment in self.inner.segments.iter() {
+            cancelled |= segment.cancel_predicate(id);
+        }
+        cancelled
+    }
+
+    /// Waits until a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method has
+    /// finished in all segments, and returns its final status.
+    ///
+    /// See [`Cache::wait_predicate`](./struct.Cache.html#method.wait_predicate) for
+    /// more details.
+    pub async fn wait_predicate(&self, id: &str) -> Option<PredicateStatus> {
+        for segment in self.inner.segments.iter() {
+            segment.wait_predicate(id).await;
+        }
+        self.predicate_status(id)
+    }
+
+    /// Discards all cached entries having the given tag.
+    ///
+    /// See [`Cache::invalidate_tag`](./struct.Cache.html#method.invalidate_tag) for
+    /// more details.
+    pub async fn invalidate_tag(&self, tag: &str) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_tag(tag).await;
+        }
+    }
+
+    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
+    /// value.
+    ///
+    /// Iterators do not block concurrent reads and writes on the cache. An entry can
+    /// be inserted to, invalidated or evicted from a cache while iterators are alive
+    /// on the same cache.
+    ///
+    /// Unlike the `get` method, visiting entries via an iterator do not update the
+    /// historic popularity estimator or reset idle timers for keys.
+    ///
+    /// # Guarantees
+    ///
+    /// In order to allow concurrent access to the cache, iterator's `next` method
+    /// does _not_ guarantee the following:
+    ///
+    /// - It does not guarantee to return a key-value pair (an entry) if its key has
+    ///   been inserted to the cache _after_ the iterator was created.
+    ///   - Such an entry may or may not be returned depending on key's hash and
+    ///     timing.
+    ///
+    /// and the `next` method guarantees the followings:
+    ///
+    /// - It guarantees not to return the same entry more than once.
+    /// - It guarantees not to return an entry if it has been removed from the cache
+    ///   after the iterator was created.
+    ///     - Note: An entry can be removed by following reasons:
+    ///         - Manually invalidated.
+    ///         - Expired (e.g. time-to-live).
+    ///         - Evicted as the cache capacity exceeded.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(self.inner_iter())
+    }
+
+    /// Creates an iterator visiting all entries with their metadata in arbitrary
+    /// order.
+    ///
+    /// See [`Cache::iter_with_metadata`](./struct.Cache.html#method.iter_with_metadata)
+    /// for more details.
+    pub fn iter_with_metadata(&self) -> IterWithMetadata<'_, K, V> {
+        IterWithMetadata::new(InnerIterWithMetadata::new(self.inner_iter()))
+    }
+
+    /// Creates an iterator visiting all keys in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(InnerKeys::new(self.inner_iter()))
+    }
+
+    /// Creates an iterator visiting all values in arbitrary order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(InnerValues::new(self.inner_iter()))
+    }
+
+    /// Creates a [`Stream`][stream] visiting all key-value pairs in arbitrary
+    /// order.
+    ///
+    /// See [`Cache::stream`](./struct.Cache.html#method.stream) for more details.
+    ///
+    /// [stream]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+    pub fn stream(&self) -> EntryStream<'_, K, V> {
+        EntryStream::new(self.inner_iter())
+    }
+
+    /// Creates a [`Stream`][stream] visiting all keys in arbitrary order.
+    ///
+    /// [stream]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+    pub fn keys_stream(&self) -> KeyStream<'_, K, V> {
+        KeyStream::new(self.inner_iter())
+    }
+
+    fn inner_iter(&self) -> InnerIter<'_, K, V> {
+        let num_cht_segments = self.inner.segments[0].base.num_cht_segments();
+        let segments = self
+            .inner
+            .segments
+            .iter()
+            .map(|c| &c.base as &dyn ScanningGet<_, _>)
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        InnerIter::with_multiple_cache_segments(segments, num_cht_segments)
+    }
+
+    /// Retains only the entries for which the closure `f` returns `true`.
+    ///
+    /// See [`Cache::retain`](./struct.Cache.html#method.retain) for more details.
+    pub async fn retain(&self, mut f: impl FnMut(&K, &V) -> bool + Send) {
+        for segment in self.inner.segments.iter() {
+            segment.retain(&mut f).await;
+        }
+    }
+
+    /// Removes all entries from the cache, and returns them.
+    ///
+    /// See [`Cache::drain`](./struct.Cache.html#method.drain) for more details.
+    pub async fn drain(&self) -> Vec<(Arc<K>, V)> {
+        let mut drained = Vec::new();
+        for segment in self.inner.segments.iter() {
+            drained.extend(segment.drain().await);
+        }
+        drained
+    }
+
+    /// Performs any pending maintenance operations needed by the cache.
+    ///
+    /// The pending operations of the segments run concurrently.
+    pub async fn run_pending_tasks(&self) {
+        let tasks = self.inner.segments.iter().map(Cache::run_pending_tasks);
+        futures_util::future::join_all(tasks).await;
+    }
+
+    /// Shrinks the internal concurrent hash tables of the segments to fit their
+    /// current number of entries.
+    ///
+    /// See [`Cache::shrink_to_fit`](./struct.Cache.html#method.shrink_to_fit) for
+    /// more details.
+    pub fn shrink_to_fit(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.shrink_to_fit();
+        }
+    }
+
+    /// Returns the time when the earliest entry in any segment will expire.
+    ///
+    /// See [`Cache::next_expiration`](./struct.Cache.html#method.next_expiration)
+    /// for more details.
+    pub async fn next_expiration(&self) -> Option<std::time::Instant> {
+        let mut next = None;
+        for segment in self.inner.segments.iter() {
+            if let Some(t) = segment.next_expiration().await {
+                next = Some(next.map_or(t, |n: std::time::Instant| n.min(t)));
+            }
+        }
+        next
+    }
+
+    /// Returns the statistics of the maintenance tasks, summed over all segments.
+    ///
+    /// See [`Cache::maintenance_stats`](./struct.Cache.html#method.maintenance_stats)
+    /// for more details.
+    pub fn maintenance_stats(&self) -> MaintenanceStats {
+        let mut stats = MaintenanceStats::default();
+        for segment in self.inner.segments.iter() {
+            stats.merge(&segment.maintenance_stats());
+        }
+        stats
+    }
+
+    /// Writes the metrics of this cache, summed over all segments, in the
+    /// Prometheus text exposition format.
+    ///
+    /// See [`Cache::render_prometheus`](./struct.Cache.html#method.render_prometheus)
+    /// for more details.
+    pub fn render_prometheus(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        let mut metrics = MetricsSnapshot::default();
+        for segment in self.inner.segments.iter() {
+            metrics.merge(&segment.base.metrics_snapshot());
+        }
+        metrics.max_capacity = self.policy().max_capacity();
+        metrics.render_prometheus(self.name(), w)
+    }
+
+    /// Returns an approximate breakdown of the memory used by this cache, summed
+    /// over all segments.
+    ///
+    /// See [`Cache::memory_usage`](./struct.Cache.html#method.memory_usage) for
+    /// more details.
+    pub async fn memory_usage(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        for segment in self.inner.segments.iter() {
+            report.merge(&segment.memory_usage().await);
+        }
+        report
+    }
+
+    /// Returns the same report as [`memory_usage`](#method.memory_usage), but also
+    /// includes the heap memory owned by the keys and values.
+    ///
+    /// See [`Cache::memory_usage_with_heap_size`][with-heap-size] for more details.
+    ///
+    /// [with-heap-size]: ./struct.Cache.html#method.memory_usage_with_heap_size
+    pub async fn memory_usage_with_heap_size(&self) -> MemoryReport
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let mut report = MemoryReport::default();
+        for segment in self.inner.segments.iter() {
+            report.merge(&segment.memory_usage_with_heap_size().await);
+        }
+        report
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// For unit tests.
+#[cfg(test)]
+impl<K, V, S> SegmentedCache<K, V, S> {
+    fn is_waiter_map_empty(&self) -> bool {
+        self.inner.segments.iter().all(Cache::is_waiter_map_empty)
+    }
+}
+
+#[cfg(test)]
+impl<K, V, S> SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn invalidation_predicate_count(&self) -> usize {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.invalidation_predicate_count())
+            .sum()
+    }
+
+    async fn reconfigure_for_testing(&mut self) {
+        let inner = Arc::get_mut(&mut self.inner)
+            .expect("There are other strong reference to self.inner Arc");
+
+        for segment in inner.segments.iter_mut() {
+            segment.reconfigure_for_testing().await;
+        }
+    }
+
+    async fn create_mock_expiration_clock(&self) -> MockExpirationClock {
+        let mut exp_clock = MockExpirationClock::default();
+
+        for segment in self.inner.segments.iter() {
+            let (clock, mock) = crate::common::time::Clock::mock();
+            segment.set_expiration_clock(Some(clock)).await;
+            exp_clock.mocks.push(mock);
+        }
+
+        exp_clock
+    }
+
+    fn key_locks_map_is_empty(&self) -> bool {
+        self.inner
+            .segments
+            .iter()
+            .all(|seg| seg.key_locks_map_is_empty())
+    }
+}
+
+// For unit tests.
+#[cfg(test)]
+#[derive(Default)]
+struct MockExpirationClock {
+    mocks: Vec<Arc<crate::common::time::Mock>>,
+}
+
+#[cfg(test)]
+impl MockExpirationClock {
+    fn increment(&mut self, duration: Duration) {
+        for mock in &mut self.mocks {
+            mock.increment(duration);
+        }
+    }
+}
+
+struct Inner<K, V, S> {
+    desired_capacity: Option<u64>,
+    segments: Box<[Cache<K, V, S>]>,
+    build_hasher: S,
+    segment_shift: u32,
+    _maintenance: Option<MaintenanceHandle>,
+}
+
+impl<K, V, S> Inner<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: Option<String>,
+        max_capacity: Option<u64>,
+        initial_capacity: Option<usize>,
+        num_segments: usize,
+        build_hasher: S,
+        weigher: Option<Weigher<K, V>>,
+        eviction_policy: EvictionPolicy,
+        eviction_listener: Option<AsyncEvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
+        expiration_policy: ExpirationPolicy<K, V>,
+        housekeeper_config: HousekeeperConfig,
+        invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
+    ) -> Self {
+        assert!(num_segments > 0);
+
+        let actual_num_segments = num_segments.next_power_of_two();
+        let segment_shift = 64 - actual_num_segments.trailing_zeros();
+        let seg_max_capacity =
+            max_capacity.map(|n| (n as f64 / actual_num_segments as f64).ceil() as u64);
+        let seg_init_capacity =
+            initial_capacity.map(|cap| (cap as f64 / actual_num_segments as f64).ceil() as usize);
+        // The async eviction listener is a `Box`, so share it between the segments
+        // through an `Arc`.
+        let eviction_listener = eviction_listener.map(Arc::new);
+        // NOTE: We cannot initialize the segments as `vec![cache; actual_num_segments]`
+        // because Cache::clone() does not clone its inner but shares the same inner.
+        let segments = (0..actual_num_segments)
+            .map(|_| {
+                let listener = eviction_listener.clone().map(|listener| {
+                    Box::new(move |k, v, cause| listener(k, v, cause))
+                        as AsyncEvictionListener<K, V>
+                });
+                Cache::with_everything(
+                    name.clone(),
+                    seg_max_capacity,
+                    seg_init_capacity,
+                    build_hasher.clone(),
+                    weigher.clone(),
+                    eviction_policy.clone(),
+                    listener,
+                    callback_panic_policy.clone(),
+                    expiration_policy.clone(),
+                    housekeeper_config.clone(),
+                    invalidator_enabled,
+                    key_locks_enabled,
+                    tag_extractor.clone(),
+                    clock.clone(),
+                    None,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Run the maintenance tasks of all segments concurrently.
+        let maintenance = maintenance_scheduler.map(|scheduler| {
+            let jobs = segments.iter().map(|c| c.base.maintenance_job()).collect();
+            let (handle, waker) = scheduler.start(jobs);
+            if let Some(waker) = waker {
+                for segment in &segments {
+                    segment.base.set_maintenance_waker(waker.clone());
+                }
+            }
+            handle
+        });
+
+        Self {
+            desired_capacity: max_capacity,
+            segments: segments.into_boxed_slice(),
+            build_hasher,
+            segment_shift,
+            _maintenance: maintenance,
+        }
+    }
+
+    #[inline]
+    fn hash<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut hasher = self.build_hasher.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[inline]
+    fn select(&self, hash: u64) -> &Cache<K, V, S> {
+        let index = self.segment_index_from_hash(hash);
+        &self.segments[index]
+    }
+
+    #[inline]
+    fn segment_index_from_hash(&self, hash: u64) -> usize {
+        if self.segment_shift == 64 {
+            0
+        } else {
+            (hash >> self.segment_shift) as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentedCache;
+    use crate::{
+        future::{FutureExt, MaintenanceScheduler},
+        notification::{ListenerFuture, RemovalCause},
+    };
+
+    use async_lock::Mutex;
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn max_capacity_zero() {
+        let mut cache = SegmentedCache::new(0, 1);
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, ()).await;
+
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).await.is_none());
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).await.is_none());
+        assert_eq!(cache.entry_count(), 0)
+    }
+
+    #[tokio::test]
+    async fn basic_single_async_task() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.push((k, v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = SegmentedCache::builder(1)
+            .max_capacity(3)
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        cache.run_pending_tasks().await;
+        // counts: a -> 1, b -> 1
+
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
+        assert!(cache.contains_key(&"c"));
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks().await;
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks().await;
+        // counts: a -> 2, b -> 2, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "david").await; //   count: d -> 0
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None); //   d -> 1
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", "david").await;
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d").await, None); //   d -> 2
+
+        // "d" should be admitted and "c" should be evicted
+        // because d's frequency is higher than c's.
+        cache.insert("d", "dennis").await;
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"c").await, None);
+        assert_eq!(cache.get(&"d").await, Some("dennis"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        cache.invalidate(&"b").await;
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"b").await, None);
+        assert!(!cache.contains_key(&"b"));
+
+        assert!(cache.remove(&"b").await.is_none());
+        assert_eq!(cache.remove(&"d").await, Some("dennis"));
+        expected.push((Arc::new("d"), "dennis", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.get(&"d").await, None);
+        assert!(!cache.contains_key(&"d"));
+
+        assert_eq!(*actual.lock().await, expected);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[tokio::test]
+    async fn non_power_of_two_segments() {
+        let mut cache = SegmentedCache::new(100, 5);
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.policy().num_segments(), 8);
+        assert_eq!(cache.iter().count(), 0);
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.insert("c", "cindy").await;
+
+        assert_eq!(cache.iter().count(), 3);
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.iter().count(), 3);
+        assert_eq!(cache.entry_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn invalidate_all() {
+        // The following `HashMap`s will hold actual and expected notifications.
+        // Note: We use `HashMap` here as the order of invalidations is non-deterministic.
+        let actual = Arc::new(Mutex::new(HashMap::new()));
+        let mut expected = HashMap::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.insert(k, (v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = SegmentedCache::builder(4)
+            .max_capacity(100)
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.insert("c", "cindy").await;
+        assert_eq!(cache.get(&"a").await, Some("alice"));
+        assert_eq!(cache.get(&"b").await, Some("bob"));
+        assert_eq!(cache.get(&"c").await, Some("cindy"));
+
+        cache.invalidate_all();
+        expected.insert(Arc::new("a"), ("alice", RemovalCause::Explicit));
+        expected.insert(Arc::new("b"), ("bob", RemovalCause::Explicit));
+        expected.insert(Arc::new("c"), ("cindy", RemovalCause::Explicit));
+        cache.run_pending_tasks().await;
+
+        cache.insert("d", "david").await;
+        cache.run_pending_tasks().await;
+
+        assert!(cache.get(&"a").await.is_none());
+        assert!(cache.get(&"b").await.is_none());
+        assert!(cache.get(&"c").await.is_none());
+        assert_eq!(cache.get(&"d").await, Some("david"));
+        assert_eq!(cache.entry_count(), 1);
+
+        assert_eq!(*actual.lock().await, expected);
+    }
+
+    #[tokio::test]
+    async fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        use std::collections::HashSet;
+
+        const SEGMENTS: usize = 4;
+
+        // The following `HashMap`s will hold actual and expected notifications.
+        // Note: We use `HashMap` here as the order of invalidations is non-deterministic.
+        let actual = Arc::new(Mutex::new(HashMap::new()));
+        let mut expected = HashMap::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.insert(k, (v, cause));
+            }
+            .boxed()
+        };
+
+        // Create a cache with the eviction listener.
+        let mut cache = SegmentedCache::builder(SEGMENTS)
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .async_eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let mut mock = cache.create_mock_expiration_clock().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice").await;
+        cache.insert(1, "bob").await;
+        cache.insert(2, "alex").await;
+        cache.run_pending_tasks().await;
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks().await;
+
+        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
+        cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
+        assert_eq!(cache.invalidation_predicate_count(), SEGMENTS);
+        expected.insert(Arc::new(0), ("alice", RemovalCause::Explicit));
+        expected.insert(Arc::new(2), ("alex", RemovalCause::Explicit));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        cache.insert(3, "alice").await;
+        cache.run_pending_tasks().await;
+
+        assert!(cache.get(&0).await.is_none());
+        assert!(cache.get(&2).await.is_none());
+        assert_eq!(cache.get(&1).await, Some("bob"));
+        // This should survive as it was inserted after calling invalidate_entries_if.
+        assert_eq!(cache.get(&3).await, Some("alice"));
+
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        assert_eq!(*actual.lock().await, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn predicate_status_cancel_and_wait() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::PredicateStatus;
+
+        let mut cache = SegmentedCache::builder(4)
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let mut mock = cache.create_mock_expiration_clock().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 10).await;
+        }
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+
+        // The predicate is registered to all segments under the same ID.
+        let id = cache.invalidate_entries_if(|_k, &v| v % 20 == 0)?;
+        assert_eq!(cache.predicate_status(&id), Some(PredicateStatus::Pending));
+
+        // The status is summed over the segments.
+        let status = cache.wait_predicate(&id).await;
+        assert_eq!(
+            status,
+            Some(PredicateStatus::Completed {
+                scanned: 10,
+                removed: 5
+            })
+        );
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        assert_eq!(cache.entry_count(), 5);
+        assert!(!cache.cancel_predicate(&id));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        // Cancel a predicate in all segments before they start scanning for it.
+        let id = cache.invalidate_entries_if(|_k, _v| true)?;
+        assert!(cache.cancel_predicate(&id));
+        assert!(!cache.cancel_predicate(&id));
+        let status = Some(PredicateStatus::Cancelled {
+            scanned: 0,
+            removed: 0,
+        });
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.wait_predicate(&id).await, status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 5);
+        assert_eq!(cache.get(&1).await, Some(10));
+
+        assert!(cache.predicate_status("no-such-predicate").is_none());
+        assert!(cache.wait_predicate("no-such-predicate").await.is_none());
+        assert!(!cache.cancel_predicate("no-such-predicate"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_iter() {
+        const NUM_KEYS: usize = 50;
+
+        fn make_value(key: usize) -> String {
+            format!("val: {key}")
+        }
+
+        let cache = SegmentedCache::builder(4)
+            .max_capacity(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, make_value(key)).await;
+        }
+
+        let mut key_set = std::collections::HashSet::new();
+
+        for (key, value) in &cache {
+            assert_eq!(value, make_value(*key));
+
+            key_set.insert(*key);
+        }
+
+        // Ensure there are no missing or duplicate keys in the iteration.
+        assert_eq!(key_set.len(), NUM_KEYS);
+        assert_eq!(cache.keys().count(), NUM_KEYS);
+
+        let drained = cache.drain().await;
+        assert_eq!(drained.len(), NUM_KEYS);
+        assert_eq!(cache.iter().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn get_with() {
+        let cache = SegmentedCache::new(100, 4);
+        const KEY: u32 = 0;
+
+        // Task1 will be the first task to call `get_with` for a key, so its async
+        // block will be evaluated and then a &str value "task1" will be inserted to
+        // the cache.
+        let task1 = {
+            let cache1 = cache.clone();
+            async move {
+                let v = cache1
+                    .get_with(KEY, async {
+                        // Wait for 300 ms and return a &str value.
+                        sleep(Duration::from_millis(300)).await;
+                        "task1"
+                    })
+                    .await;
+                assert_eq!(v, "task1");
+            }
+        };
+
+        // Task2 will call `get_with` for the same key while task1's async block is
+        // still running, so it will wait for and get the value inserted by task1.
+        let task2 = {
+            let cache2 = cache.clone();
+            async move {
+                sleep(Duration::from_millis(100)).await;
+                let v = cache2.get_with(KEY, async { unreachable!() }).await;
+                assert_eq!(v, "task1");
+            }
+        };
+
+        // Task3 will call `get` for the same key while task1's async block is still
+        // running, so it will get none for the key.
+        let task3 = {
+            let cache3 = cache.clone();
+            async move {
+                sleep(Duration::from_millis(200)).await;
+                assert!(cache3.get(&KEY).await.is_none());
+            }
+        };
+
+        futures_util::join!(task1, task2, task3);
+
+        assert_eq!(cache.get(&KEY).await, Some("task1"));
+        assert!(cache.is_waiter_map_empty());
+    }
+
+    #[tokio::test]
+    async fn maintenance_scheduler() {
+        async fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+            for _ in 0..200 {
+                if condition() {
+                    return true;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+            false
+        }
+
+        const NUM_KEYS: usize = 16;
+
+        let runtime = tokio::runtime::Handle::current();
+        let scheduler = MaintenanceScheduler::spawn_with(move |task| {
+            runtime.spawn(task);
+        })
+        .interval(Duration::from_millis(20));
+
+        let actual = Arc::new(Mutex::new(HashMap::new()));
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| -> ListenerFuture {
+            let a2 = Arc::clone(&a1);
+            async move {
+                a2.lock().await.insert(k, (v, cause));
+            }
+            .boxed()
+        };
+
+        let mut cache = SegmentedCache::builder(4)
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .async_eviction_listener(listener)
+            .maintenance_scheduler(scheduler)
+            .build();
+        cache.reconfigure_for_testing().await;
+
+        let mut mock = cache.create_mock_expiration_clock().await;
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, key).await;
+        }
+        mock.increment(Duration::from_secs(10));
+
+        // The expired entries in all segments are removed without calling any
+        // method of the cache.
+        assert!(wait_until(|| actual.try_lock().map_or(false, |a| a.len() == NUM_KEYS)).await);
+        for key in 0..NUM_KEYS {
+            assert_eq!(
+                actual.lock().await.get(&key),
+                Some(&(key, RemovalCause::Expired))
+            );
+        }
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn compute_with_guard() {
+        use crate::{ops::compute::Op, KeyLockError};
+
+        let cache = SegmentedCache::builder(4).support_key_locks().build();
+        let other_cache = SegmentedCache::builder(4).support_key_locks().build();
+        for key in 0..8 {
+            cache.insert(key, key).await;
+        }
+
+        // The guard is accepted by the segment that returned it, and rejected by
+        // another cache.
+        for key in 0..8 {
+            let guard = cache.lock_key(&key).await.unwrap();
+            cache
+                .compute_with_guard(&guard, |entry| async move {
+                    Op::Put(*entry.unwrap().value() + 10)
+                })
+                .await
+                .unwrap();
+            assert!(matches!(
+                other_cache
+                    .compute_with_guard(&guard, |_| async { Op::Put(0) })
+                    .await,
+                Err(KeyLockError::ForeignKeyGuard)
+            ));
+        }
+        for key in 0..8 {
+            assert_eq!(cache.get(&key).await, Some(key + 10));
+        }
+    }
+
+    #[tokio::test]
+    async fn compute_many() {
+        use crate::{
+            ops::compute::{CompResult, Op},
+            KeyLockError,
+        };
+
+        let cache: SegmentedCache<u32, u32> = SegmentedCache::new(100, 4);
+        assert!(matches!(
+            cache.compute_many([0], |_| async { [Op::Nop] }).await,
+            Err(KeyLockError::KeyLocksDisabled)
+        ));
+
+        let cache = SegmentedCache::builder(4).support_key_locks().build();
+        for key in 0..8 {
+            cache.insert(key, key * 10).await;
+        }
+        cache.insert(8, 0).await;
+
+        // The keys are spread over the segments.
+        let keys = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let results = cache
+            .compute_many(keys, |entries| async move {
+                let total = entries.iter().flatten().map(|e| *e.value()).sum();
+                let mut ops = entries.map(|_| Op::Remove);
+                ops[8] = Op::Put(total);
+                ops
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 9);
+        assert!(results[..8]
+            .iter()
+            .all(|r| matches!(r, CompResult::Removed(_))));
+        assert!(matches!(&results[8], CompResult::ReplacedWith(e) if *e.value() == 280));
+
+        for key in 0..8 {
+            assert!(cache.get(&key).await.is_none());
+        }
+        assert_eq!(cache.get(&8).await, Some(280));
+
+        // The ops for a key given more than once are applied in order.
+        let results = cache
+            .compute_many([9, 9], |entries| async move {
+                assert!(entries.iter().all(Option::is_none));
+                [Op::Put(1), Op::Put(2)]
+            })
+            .await
+            .unwrap();
+        assert!(matches!(&results[0], CompResult::Inserted(e) if *e.value() == 1));
+        assert!(matches!(&results[1], CompResult::ReplacedWith(e) if *e.value() == 2));
+        assert_eq!(cache.get(&9).await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_debug_format() {
+        let cache = SegmentedCache::new(10, 4);
+        cache.insert('a', "alice").await;
+        cache.insert('b', "bob").await;
+        cache.insert('c', "cindy").await;
+
+        let debug_str = format!("{cache:?}");
+        assert!(debug_str.starts_with('{'));
+        assert!(debug_str.contains(r#"'a': "alice""#));
+        assert!(debug_str.contains(r#"'b': "bob""#));
+        assert!(debug_str.contains(r#"'c': "cindy""#));
+        assert!(debug_str.ends_with('}'));
+    }
+}
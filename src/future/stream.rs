@@ -0,0 +1,164 @@
+use crate::sync_base::iter::Iter;
+
+use futures_util::stream::Stream;
+use std::{
+    hash::Hash,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// The default number of entries that a stream scans before yielding to the async
+/// runtime.
+pub(crate) const DEFAULT_YIELD_INTERVAL: usize = 128;
+
+/// A `Stream` visiting all key-value pairs in a cache in arbitrary order.
+///
+/// Call [`Cache::stream`](./struct.Cache.html#method.stream) method to obtain an
+/// `EntryStream`.
+///
+/// The stream yields to the async runtime (returns `Poll::Pending` after waking
+/// the task) every time it has scanned a certain number of entries, so that a
+/// scan over a large cache does not starve other tasks. The number can be changed
+/// by the [`yield_every`](#method.yield_every) method.
+///
+/// The stream never holds a lock or a guard of the internal hash table between
+/// polls.
+pub struct EntryStream<'i, K, V> {
+    scanner: Scanner<'i, K, V>,
+}
+
+impl<'i, K, V> EntryStream<'i, K, V> {
+    pub(crate) fn new(iter: Iter<'i, K, V>) -> Self {
+        Self {
+            scanner: Scanner::new(iter),
+        }
+    }
+
+    /// Sets the number of entries to scan before yielding to the async runtime.
+    /// The default is 128.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn yield_every(mut self, interval: usize) -> Self {
+        self.scanner.set_yield_interval(interval);
+        self
+    }
+}
+
+impl<K, V> Stream for EntryStream<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.scanner.poll_next(cx, |iter, key| {
+            iter.cache().scanning_get(&key).map(|v| (key, v))
+        })
+    }
+}
+
+/// A `Stream` visiting all keys in a cache in arbitrary order.
+///
+/// Call [`Cache::keys_stream`](./struct.Cache.html#method.keys_stream) method to
+/// obtain a `KeyStream`.
+///
+/// Unlike [`EntryStream`](./struct.EntryStream.html), this stream does not clone
+/// the values. See `EntryStream` for how it yields to the async runtime.
+pub struct KeyStream<'i, K, V> {
+    scanner: Scanner<'i, K, V>,
+}
+
+impl<'i, K, V> KeyStream<'i, K, V> {
+    pub(crate) fn new(iter: Iter<'i, K, V>) -> Self {
+        Self {
+            scanner: Scanner::new(iter),
+        }
+    }
+
+    /// Sets the number of entries to scan before yielding to the async runtime.
+    /// The default is 128.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn yield_every(mut self, interval: usize) -> Self {
+        self.scanner.set_yield_interval(interval);
+        self
+    }
+}
+
+impl<K, V> Stream for KeyStream<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = Arc<K>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.scanner.poll_next(cx, |iter, key| {
+            iter.cache().scanning_contains_key(&key).then_some(key)
+        })
+    }
+}
+
+struct Scanner<'i, K, V> {
+    iter: Iter<'i, K, V>,
+    yield_interval: usize,
+    scanned: usize,
+    is_done: bool,
+}
+
+impl<'i, K, V> Scanner<'i, K, V> {
+    fn new(iter: Iter<'i, K, V>) -> Self {
+        Self {
+            iter,
+            yield_interval: DEFAULT_YIELD_INTERVAL,
+            scanned: 0,
+            is_done: false,
+        }
+    }
+
+    fn set_yield_interval(&mut self, interval: usize) {
+        assert!(interval > 0, "yield interval must be greater than zero");
+        self.yield_interval = interval;
+    }
+}
+
+impl<K, V> Scanner<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn poll_next<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut scan: impl FnMut(&Iter<'_, K, V>, Arc<K>) -> Option<T>,
+    ) -> Poll<Option<T>> {
+        if self.is_done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            if self.scanned >= self.yield_interval {
+                // Yield to the async runtime, and ask it to poll us again.
+                self.scanned = 0;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            let Some(key) = self.iter.next_key() else {
+                self.is_done = true;
+                return Poll::Ready(None);
+            };
+
+            self.scanned += 1;
+            if let Some(item) = scan(&self.iter, key) {
+                return Poll::Ready(Some(item));
+            }
+        }
+    }
+}
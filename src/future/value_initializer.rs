@@ -1,5 +1,5 @@
 use async_lock::{RwLock, RwLockWriteGuard};
-use futures_util::FutureExt;
+use futures_util::{future::poll_fn, FutureExt};
 use std::{
     any::{Any, TypeId},
     fmt,
@@ -11,7 +11,13 @@ use std::{
 use triomphe::Arc as TrioArc;
 
 use crate::{
+    common::{
+        cache_metrics::{LoadOutcome, LoadRecorder},
+        callback_panic::CallbackPanicReporter,
+        memory_report::{trio_arc_alloc_size, MemoryReport},
+    },
     ops::compute::{CompResult, Op},
+    policy::CallbackKind,
     Entry,
 };
 
@@ -117,6 +123,7 @@ pub(crate) struct ValueInitializer<K, V, S> {
     // can always downcast the trait object ErrorObject (in Waiter<V>) into its
     // concrete type.
     waiters: TrioArc<WaiterMap<K, V, S>>,
+    panic_reporter: CallbackPanicReporter,
 }
 
 impl<K, V, S> ValueInitializer<K, V, S>
@@ -125,15 +132,22 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn with_hasher(hasher: S) -> Self {
+    pub(crate) fn new(hasher: S, panic_reporter: CallbackPanicReporter) -> Self {
         Self {
             waiters: TrioArc::new(crate::cht::SegmentedHashMap::with_num_segments_and_hasher(
                 WAITER_MAP_NUM_SEGMENTS,
                 hasher,
             )),
+            panic_reporter,
         }
     }
 
+    pub(crate) fn record_memory_usage(&self, report: &mut MemoryReport) {
+        let usage = self.waiters.memory_usage();
+        report.waiters =
+            report.add_hash_table(&usage, trio_arc_alloc_size::<RwLock<WaiterValue<V>>>());
+    }
+
     //
     // NOTES: We use `Pin<&mut impl Future>` instead of `impl Future` here for the
     // `init` argument. This is because we want to avoid the future size inflation
@@ -170,12 +184,16 @@ where
         const MAX_RETRIES: usize = 200;
         let mut retries = 0;
 
+        let recorder = LoadRecorder::start(cache.base.metrics_counters());
         let (w_key, w_hash) = waiter_key_hash(&self.waiters, c_key, type_id);
 
         let waiter = TrioArc::new(RwLock::new(WaiterValue::Computing));
         // NOTE: We have to acquire a write lock before `try_insert_waiter`,
         // so that any concurrent attempt will get our lock and wait on it.
         let lock = waiter.write().await;
+        // The number of the other callers waiting for our waiter. (Our waiter is
+        // also referenced by the waiter map)
+        let num_waiters = || TrioArc::count(&waiter) - 2;
 
         loop {
             let Some(existing_waiter) =
@@ -188,8 +206,14 @@ where
             // Somebody else's waiter already exists, so wait for its result to become available.
             let waiter_result = existing_waiter.read().await;
             match &*waiter_result {
-                WaiterValue::Ready(Ok(value)) => return ReadExisting(value.clone()),
-                WaiterValue::Ready(Err(e)) => return InitErr(Arc::clone(e).downcast().unwrap()),
+                WaiterValue::Ready(Ok(value)) => {
+                    recorder.finish(LoadOutcome::Coalesced, 0);
+                    return ReadExisting(value.clone());
+                }
+                WaiterValue::Ready(Err(e)) => {
+                    recorder.finish(LoadOutcome::Coalesced, 0);
+                    return InitErr(Arc::clone(e).downcast().unwrap());
+                }
                 // Somebody else's init future has been panicked.
                 WaiterValue::InitFuturePanicked => {
                     retries += 1;
@@ -229,31 +253,46 @@ where
         {
             // Yes. Set the waiter value, remove our waiter, and return
             // the existing value.
+            recorder.finish(LoadOutcome::ReadExisting, num_waiters());
             waiter_guard.set_waiter_value(WaiterValue::Ready(Ok(value.clone())));
             return ReadExisting(value);
         }
 
         // The value still does note exist. Let's resolve the init
-        // future. Catching panic is safe here as we do not try to
-        // resolve the future again.
-        match AssertUnwindSafe(init).catch_unwind().await {
+        // future, while recording the keys read from the cache by each poll of
+        // it. Catching panic is safe here as we do not try to resolve the future
+        // again.
+        let mut deps = Vec::new();
+        let mut init = init;
+        let tracked_init = poll_fn(|cx| {
+            let (poll, keys) = cache.base.track_reads(|| init.as_mut().poll(cx));
+            deps.extend(keys);
+            poll
+        });
+        let result = AssertUnwindSafe(tracked_init).catch_unwind().await;
+        match result {
             // Resolved.
             Ok(value) => match post_init(value) {
                 Ok(value) => {
                     cache
                         .insert_with_hash(Arc::clone(c_key), c_hash, value.clone())
                         .await;
+                    cache.add_tracked_dependencies(c_key, c_hash, deps).await;
+                    recorder.finish(LoadOutcome::Initialized, num_waiters());
                     waiter_guard.set_waiter_value(WaiterValue::Ready(Ok(value.clone())));
                     Initialized(value)
                 }
                 Err(e) => {
                     let err: ErrorObject = Arc::new(e);
+                    recorder.finish(LoadOutcome::InitError, num_waiters());
                     waiter_guard.set_waiter_value(WaiterValue::Ready(Err(Arc::clone(&err))));
                     InitErr(err.downcast().unwrap())
                 }
             },
             // Panicked.
             Err(payload) => {
+                self.panic_reporter.report(CallbackKind::Init, &*payload);
+                recorder.finish(LoadOutcome::Panicked, num_waiters());
                 waiter_guard.set_waiter_value(WaiterValue::InitFuturePanicked);
                 resume_unwind(payload);
             }
@@ -272,6 +311,39 @@ where
         post_init: fn(O) -> Result<Op<V>, E>,
         allow_nop: bool,
     ) -> Result<CompResult<K, V>, E>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Fut,
+        Fut: Future<Output = O> + 'a,
+        E: Send + Sync + 'static,
+    {
+        // Hold the key lock (if enabled) across the read, the closure and the write,
+        // so that they do not interleave with the updates made under a `KeyGuard`.
+        // Lock it before inserting our waiter, because the holder of the key lock
+        // may be waiting for the waiter.
+        let kl = cache.base.maybe_key_lock(&c_key);
+        let _klg = if let Some(lock) = &kl {
+            Some(lock.lock().await)
+        } else {
+            None
+        };
+        self.try_compute_locked(c_key, c_hash, cache, f, post_init, allow_nop)
+            .await
+    }
+
+    /// Same as `try_compute`, but does not lock the key. The caller must hold the
+    /// key lock if key locks are enabled.
+    ///
+    /// # Panics
+    /// Panics if the `init` future has been panicked.
+    pub(crate) async fn try_compute_locked<'a, F, Fut, O, E>(
+        &'a self,
+        c_key: Arc<K>,
+        c_hash: u64,
+        cache: &Cache<K, V, S>,
+        f: F,
+        post_init: fn(O) -> Result<Op<V>, E>,
+        allow_nop: bool,
+    ) -> Result<CompResult<K, V>, E>
     where
         F: FnOnce(Option<Entry<K, V>>) -> Fut,
         Fut: Future<Output = O> + 'a,
@@ -337,6 +409,7 @@ where
             Ok(fut) => fut,
             // Panicked.
             Err(payload) => {
+                self.panic_reporter.report(CallbackKind::Init, &*payload);
                 waiter_guard.set_waiter_value(WaiterValue::InitFuturePanicked);
                 resume_unwind(payload);
             }
@@ -352,6 +425,7 @@ where
             }
             // Panicked.
             Err(payload) => {
+                self.panic_reporter.report(CallbackKind::Init, &*payload);
                 waiter_guard.set_waiter_value(WaiterValue::InitFuturePanicked);
                 resume_unwind(payload);
             }
@@ -360,31 +434,33 @@ where
         match post_init(output)? {
             Op::Nop => {
                 if let Some(value) = maybe_value {
-                    Ok(CompResult::Unchanged(Entry::new(
-                        Some(c_key),
-                        value,
-                        false,
-                        false,
-                    )))
+                    let md = cache.base.entry_metadata_with_hash(&c_key, c_hash);
+                    let entry = Entry::new(Some(c_key), value, false, false).with_metadata(md);
+                    Ok(CompResult::Unchanged(entry))
                 } else {
                     Ok(CompResult::StillNone(c_key))
                 }
             }
             Op::Put(value) => {
-                cache
-                    .insert_with_hash(Arc::clone(&c_key), c_hash, value.clone())
+                let prev_v = cache
+                    .swap_with_hash_locked(Arc::clone(&c_key), c_hash, value.clone())
                     .await;
+                let md = cache.base.entry_metadata_with_hash(&c_key, c_hash);
                 if entry_existed {
                     crossbeam_epoch::pin().flush();
-                    let entry = Entry::new(Some(c_key), value, true, true);
+                    let entry = Entry::new(Some(c_key), value, true, true)
+                        .with_old_value(prev_v)
+                        .with_metadata(md);
                     Ok(CompResult::ReplacedWith(entry))
                 } else {
-                    let entry = Entry::new(Some(c_key), value, true, false);
+                    let entry = Entry::new(Some(c_key), value, true, false).with_metadata(md);
                     Ok(CompResult::Inserted(entry))
                 }
             }
             Op::Remove => {
-                let maybe_prev_v = cache.invalidate_with_hash(&c_key, c_hash, true).await;
+                let maybe_prev_v = cache
+                    .invalidate_with_hash_locked(&c_key, c_hash, true)
+                    .await;
                 if let Some(prev_v) = maybe_prev_v {
                     crossbeam_epoch::pin().flush();
                     let entry = Entry::new(Some(c_key), prev_v, false, false);
@@ -49,13 +49,20 @@
 //! - Thread-safe, synchronous caches:
 //!     - [`sync::Cache`][sync-cache-struct]
 //!     - [`sync::SegmentedCache`][sync-seg-cache-struct]
-//! - An asynchronous (futures aware) cache:
-//!     - [`future::Cache`][future-cache-struct] (Requires "future" feature)
+//! - Asynchronous (futures aware) caches (Requires "future" feature):
+//!     - [`future::Cache`][future-cache-struct]
+//!     - [`future::SegmentedCache`][future-seg-cache-struct]
 //!
 //! [future-cache-struct]: ./future/struct.Cache.html
+//! [future-seg-cache-struct]: ./future/struct.SegmentedCache.html
 //! [sync-cache-struct]: ./sync/struct.Cache.html
 //! [sync-seg-cache-struct]: ./sync/struct.SegmentedCache.html
 //!
+//! The lock-free concurrent hash table used by the caches is also available as an
+//! unbounded map, [`cht::HashMap`][cht-hash-map-struct].
+//!
+//! [cht-hash-map-struct]: ./cht/struct.HashMap.html
+//!
 //! **NOTE:** The following caches have been moved to a separate crate called
 //! "[mini-moka][mini-moka-crate]".
 //!
@@ -104,7 +111,12 @@ pub mod sync;
 pub mod notification;
 
 #[cfg(any(feature = "sync", feature = "future"))]
-pub(crate) mod cht;
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub mod cht;
+
+#[cfg(any(feature = "sync", feature = "future"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub mod clock;
 
 #[cfg(any(feature = "sync", feature = "future"))]
 pub(crate) mod common;
@@ -121,11 +133,27 @@ pub(crate) mod sync_base;
 
 #[cfg(any(feature = "sync", feature = "future"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
-pub use common::error::PredicateError;
+pub use common::error::{KeyLockError, PredicateError};
+
+#[cfg(any(feature = "sync", feature = "future"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub use common::maintenance_stats::{DurationHistogram, MaintenanceStats};
+
+#[cfg(any(feature = "sync", feature = "future"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub use common::memory_report::{HeapSize, MemoryReport};
+
+#[cfg(any(feature = "sync", feature = "future"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub use common::predicate::PredicateStatus;
+
+#[cfg(any(feature = "sync", feature = "future"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
+pub use common::tag::{Tag, Tags};
 
 #[cfg(any(feature = "sync", feature = "future"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
-pub use common::entry::Entry;
+pub use common::entry::{Entry, EntryMetadata, EntryRegion};
 
 #[cfg(any(feature = "sync", feature = "future"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "sync", feature = "future"))))]
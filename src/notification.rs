@@ -27,7 +27,10 @@ pub(crate) type AsyncEvictionListener<K, V> =
 // have been invalidated and their notifications have been sent.
 
 /// Indicates the reason why a cached entry was removed.
+///
+/// This enum is `#[non_exhaustive]`; new causes may be added in future versions.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum RemovalCause {
     /// The entry's expiration timestamp has passed.
     Expired,
@@ -38,9 +41,18 @@ pub enum RemovalCause {
     Replaced,
     /// The entry was evicted due to size constraints.
     Size,
+    /// The entry was removed because one of the entries it depends on was
+    /// removed. See the `add_dependencies` method of the cache.
+    Cascaded,
 }
 
 impl RemovalCause {
+    /// Returns `true` if the entry was removed by the cache itself because it
+    /// expired (`Expired`) or to make room for other entries (`Size`).
+    ///
+    /// Returns `false` for the other causes, including `Cascaded`, as the cascading
+    /// removal is triggered by the removal of another entry rather than by the
+    /// eviction policy.
     pub fn was_evicted(&self) -> bool {
         matches!(self, Self::Expired | Self::Size)
     }
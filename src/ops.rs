@@ -30,7 +30,9 @@ pub mod compute {
         /// the inserted value.
         Inserted(Entry<K, V>),
         /// The entry already existed and its value was replaced with a new one. The
-        /// returned entry contains the new value (not the replaced value).
+        /// returned entry contains the new value, and its
+        /// [`old_value`](../../struct.Entry.html#method.old_value) method returns
+        /// the replaced value.
         ReplacedWith(Entry<K, V>),
         /// The entry already existed and was removed. The returned entry contains
         /// the removed value.
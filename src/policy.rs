@@ -1,4 +1,5 @@
 use std::{
+    any::Any,
     fmt,
     sync::Arc,
     time::{Duration, Instant},
@@ -33,7 +34,6 @@ impl Policy {
         self.max_capacity
     }
 
-    #[cfg(feature = "sync")]
     pub(crate) fn set_max_capacity(&mut self, capacity: Option<u64>) {
         self.max_capacity = capacity;
     }
@@ -43,7 +43,6 @@ impl Policy {
         self.num_segments
     }
 
-    #[cfg(feature = "sync")]
     pub(crate) fn set_num_segments(&mut self, num: usize) {
         self.num_segments = num;
     }
@@ -125,16 +124,118 @@ impl fmt::Debug for EvictionPolicy {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub(crate) enum EvictionPolicyConfig {
+    #[default]
     TinyLfu,
     Lru,
 }
 
-impl Default for EvictionPolicyConfig {
-    fn default() -> Self {
-        Self::TinyLfu
+/// The kind of a user-supplied callback that panicked. Passed to the handler of
+/// [`CallbackPanicPolicy::handler`](./struct.CallbackPanicPolicy.html#method.handler).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallbackKind {
+    /// The eviction listener.
+    EvictionListener,
+    /// The weigher closure.
+    Weigher,
+    /// One of the methods of the [`Expiry`](./trait.Expiry.html) implementation.
+    Expiry,
+    /// The `init` closure or future of `get_with` family, or the `f` closure of
+    /// `and_compute_with` family.
+    Init,
+}
+
+type CallbackPanicHandler = Arc<dyn Fn(CallbackKind, &(dyn Any + Send)) + Send + Sync + 'static>;
+
+/// The policy for handling panics raised by user-supplied callbacks: the eviction
+/// listener, the weigher, the [`Expiry`](./trait.Expiry.html) and the `init`
+/// closures.
+///
+/// The default is [`CallbackPanicPolicy::disable`](#method.disable).
+///
+/// | Policy                | Eviction listener         | Weigher / Expiry             | Init         |
+/// |:----------------------|:--------------------------|:-----------------------------|:-------------|
+/// | `disable` (default)   | Disabled after a panic    | Panic is propagated          | Propagated   |
+/// | `log_and_continue`    | Logged, stays enabled     | Logged, a fallback is used   | Logged, propagated |
+/// | `handler`             | Handled, stays enabled    | Handled, a fallback is used  | Handled, propagated |
+/// | `propagate`           | Panic is propagated       | Panic is propagated          | Propagated   |
+///
+/// When a panic is caught from the weigher, the weight `1` is used. When it is
+/// caught from the `Expiry`, the entry gets no per-entry expiration on creation,
+/// and keeps its current expiration on read or update.
+///
+/// A panic from an `init` closure is always propagated to the caller, because
+/// there is no value to fall back to. The `log_and_continue` and `handler`
+/// policies only report it before that.
+#[derive(Clone, Default)]
+pub struct CallbackPanicPolicy {
+    pub(crate) config: CallbackPanicPolicyConfig,
+}
+
+impl CallbackPanicPolicy {
+    /// Returns the policy that disables the eviction listener once it panicked.
+    /// Panics from the other callbacks are propagated to the caller.
+    ///
+    /// This is the default policy. If the `logging` or `tracing` feature is
+    /// enabled, the listener panic is also logged.
+    pub fn disable() -> Self {
+        Self {
+            config: CallbackPanicPolicyConfig::Disable,
+        }
+    }
+
+    /// Returns the policy that logs panics from the eviction listener, the weigher
+    /// and the `Expiry`, and keeps the cache running with them enabled.
+    ///
+    /// The panics are logged only if the `logging` or `tracing` feature is enabled.
+    pub fn log_and_continue() -> Self {
+        Self {
+            config: CallbackPanicPolicyConfig::LogAndContinue,
+        }
     }
+
+    /// Returns the policy that does not catch any panics. A panic from the eviction
+    /// listener will be propagated to the thread that called it, which is usually
+    /// the one running the pending maintenance tasks.
+    pub fn propagate() -> Self {
+        Self {
+            config: CallbackPanicPolicyConfig::Propagate,
+        }
+    }
+
+    /// Returns the policy that calls the given `handler` with the kind of the
+    /// callback and the panic payload, and keeps the cache running the same way as
+    /// [`log_and_continue`](#method.log_and_continue).
+    pub fn handler(
+        handler: impl Fn(CallbackKind, &(dyn Any + Send)) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            config: CallbackPanicPolicyConfig::Handler(Arc::new(handler)),
+        }
+    }
+}
+
+impl fmt::Debug for CallbackPanicPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.config {
+            CallbackPanicPolicyConfig::Disable => write!(f, "CallbackPanicPolicy::Disable"),
+            CallbackPanicPolicyConfig::LogAndContinue => {
+                write!(f, "CallbackPanicPolicy::LogAndContinue")
+            }
+            CallbackPanicPolicyConfig::Propagate => write!(f, "CallbackPanicPolicy::Propagate"),
+            CallbackPanicPolicyConfig::Handler(_) => write!(f, "CallbackPanicPolicy::Handler"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) enum CallbackPanicPolicyConfig {
+    #[default]
+    Disable,
+    LogAndContinue,
+    Propagate,
+    Handler(CallbackPanicHandler),
 }
 
 /// Calculates when cache entries expire. A single expiration time is retained on
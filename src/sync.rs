@@ -3,14 +3,20 @@
 mod builder;
 mod cache;
 mod entry_selector;
+mod maintenance;
 mod segment;
 mod value_initializer;
 
-pub use crate::sync_base::{iter::Iter, PredicateId};
+pub use crate::sync_base::{
+    iter::{Iter, IterWithMetadata, Keys, Values},
+    key_lock::KeyGuard,
+    PredicateId,
+};
 pub use {
     builder::CacheBuilder,
     cache::Cache,
     entry_selector::{OwnedKeyEntrySelector, RefKeyEntrySelector},
+    maintenance::MaintenanceScheduler,
     segment::SegmentedCache,
 };
 
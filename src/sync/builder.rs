@@ -1,9 +1,10 @@
-use super::{Cache, SegmentedCache};
+use super::{Cache, MaintenanceScheduler, SegmentedCache};
 use crate::{
-    common::{builder_utils, concurrent::Weigher, HousekeeperConfig},
+    clock::CacheClock,
+    common::{builder_utils, concurrent::Weigher, tag::TagExtractor, HousekeeperConfig},
     notification::{EvictionListener, RemovalCause},
-    policy::{EvictionPolicy, ExpirationPolicy},
-    Expiry,
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    Expiry, Tags,
 };
 
 use std::{
@@ -55,9 +56,14 @@ pub struct CacheBuilder<K, V, C> {
     weigher: Option<Weigher<K, V>>,
     eviction_policy: EvictionPolicy,
     eviction_listener: Option<EvictionListener<K, V>>,
+    callback_panic_policy: CallbackPanicPolicy,
     expiration_policy: ExpirationPolicy<K, V>,
     housekeeper_config: HousekeeperConfig,
     invalidator_enabled: bool,
+    key_locks_enabled: bool,
+    tag_extractor: Option<TagExtractor<K, V>>,
+    clock: Option<Arc<dyn CacheClock>>,
+    maintenance_scheduler: Option<MaintenanceScheduler>,
     cache_type: PhantomData<C>,
 }
 
@@ -74,10 +80,15 @@ where
             num_segments: None,
             weigher: None,
             eviction_listener: None,
+            callback_panic_policy: CallbackPanicPolicy::default(),
             eviction_policy: EvictionPolicy::default(),
             expiration_policy: ExpirationPolicy::default(),
             housekeeper_config: HousekeeperConfig::default(),
             invalidator_enabled: false,
+            key_locks_enabled: false,
+            tag_extractor: None,
+            clock: None,
+            maintenance_scheduler: None,
             cache_type: PhantomData,
         }
     }
@@ -116,9 +127,14 @@ where
             weigher: self.weigher,
             eviction_policy: self.eviction_policy,
             eviction_listener: self.eviction_listener,
+            callback_panic_policy: self.callback_panic_policy,
             expiration_policy: self.expiration_policy,
             housekeeper_config: self.housekeeper_config,
             invalidator_enabled: self.invalidator_enabled,
+            key_locks_enabled: self.key_locks_enabled,
+            tag_extractor: self.tag_extractor,
+            clock: self.clock,
+            maintenance_scheduler: self.maintenance_scheduler,
             cache_type: PhantomData,
         }
     }
@@ -133,10 +149,19 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build(self) -> Cache<K, V, RandomState> {
         let build_hasher = RandomState::default();
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         Cache::with_everything(
             self.name,
             self.max_capacity,
@@ -145,9 +170,14 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 
@@ -219,12 +249,21 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build_with_hasher<S>(self, hasher: S) -> Cache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         Cache::with_everything(
             self.name,
             self.max_capacity,
@@ -233,9 +272,14 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 }
@@ -255,10 +299,19 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build(self) -> SegmentedCache<K, V, RandomState> {
         let build_hasher = RandomState::default();
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         SegmentedCache::with_everything(
             self.name,
             self.max_capacity,
@@ -268,9 +321,14 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 
@@ -343,12 +401,21 @@ where
     /// Panics if configured with either `time_to_live` or `time_to_idle` higher than
     /// 1000 years. This is done to protect against overflow when computing key
     /// expiration.
+    ///
+    /// Also panics if configured with a maintenance scheduler whose interval is
+    /// zero, or with an invalid value for one of the maintenance tuning options,
+    /// such as `eviction_batch_size`. See the `# Panics` sections of their
+    /// methods.
     pub fn build_with_hasher<S>(self, hasher: S) -> SegmentedCache<K, V, S>
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
         let exp = &self.expiration_policy;
         builder_utils::ensure_expirations_or_panic(exp.time_to_live(), exp.time_to_idle());
+        builder_utils::ensure_maintenance_interval_or_panic(
+            self.maintenance_scheduler.as_ref().map(|s| s.interval),
+        );
+        builder_utils::ensure_housekeeper_config_or_panic(&self.housekeeper_config);
         SegmentedCache::with_everything(
             self.name,
             self.max_capacity,
@@ -358,9 +425,14 @@ where
             self.weigher,
             self.eviction_policy,
             self.eviction_listener,
+            self.callback_panic_policy,
             self.expiration_policy,
             self.housekeeper_config,
             self.invalidator_enabled,
+            self.key_locks_enabled,
+            self.tag_extractor,
+            self.clock,
+            self.maintenance_scheduler,
         )
     }
 }
@@ -425,7 +497,9 @@ impl<K, V, C> CacheBuilder<K, V, C> {
     /// It is very important to make the listener closure not to panic. Otherwise,
     /// the cache will stop calling the listener after a panic. This is an intended
     /// behavior because the cache cannot know whether it is memory safe or not to
-    /// call the panicked listener again.
+    /// call the panicked listener again. Use the
+    /// [`callback_panic_policy`](#method.callback_panic_policy) method to change
+    /// this behavior.
     ///
     /// [removal-cause]: ../notification/enum.RemovalCause.html
     pub fn eviction_listener(
@@ -438,6 +512,22 @@ impl<K, V, C> CacheBuilder<K, V, C> {
         }
     }
 
+    /// Sets the policy for handling panics raised by the eviction listener, the
+    /// weigher, the expiry and the `init` closures.
+    ///
+    /// The default policy is [`CallbackPanicPolicy::disable`][disable], which
+    /// disables the eviction listener once it panicked. See
+    /// [`CallbackPanicPolicy`][callback-panic-policy] for the other policies.
+    ///
+    /// [disable]: ../policy/struct.CallbackPanicPolicy.html#method.disable
+    /// [callback-panic-policy]: ../policy/struct.CallbackPanicPolicy.html
+    pub fn callback_panic_policy(self, policy: CallbackPanicPolicy) -> Self {
+        Self {
+            callback_panic_policy: policy,
+            ..self
+        }
+    }
+
     /// Sets the time to live of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from
@@ -504,6 +594,170 @@ impl<K, V, C> CacheBuilder<K, V, C> {
             ..self
         }
     }
+
+    /// Enables support for [`Cache::lock_key`][cache-lock-key] method.
+    ///
+    /// The cache will lock the key of an entry while inserting, updating or
+    /// removing the entry, so that these operations are serialized with the ones
+    /// done while holding the lock returned by `lock_key`. This adds some overhead
+    /// to the write operations.
+    ///
+    /// The key locks are always enabled if the cache has an eviction listener.
+    ///
+    /// [cache-lock-key]: ./struct.Cache.html#method.lock_key
+    pub fn support_key_locks(self) -> Self {
+        Self {
+            key_locks_enabled: true,
+            ..self
+        }
+    }
+
+    /// Sets the tag extractor closure to the cache, and enables
+    /// [`Cache::invalidate_tag`][cache-invalidate-tag] method.
+    ///
+    /// The closure returns the [`Tags`][tags] of a cached entry. The cache will
+    /// maintain an index from the tags to the keys of the entries, so that
+    /// `invalidate_tag` can discard all entries having a tag without scanning the
+    /// whole cache.
+    ///
+    /// The closure is called every time an entry is inserted or updated, and when
+    /// `invalidate_tag` checks the entries. It should be cheap and must return the
+    /// same tags for the same key and value.
+    ///
+    /// [cache-invalidate-tag]: ./struct.Cache.html#method.invalidate_tag
+    /// [tags]: ../type.Tags.html
+    pub fn tags(self, extractor: impl Fn(&K, &V) -> Tags + Send + Sync + 'static) -> Self {
+        Self {
+            tag_extractor: Some(Arc::new(extractor)),
+            ..self
+        }
+    }
+
+    /// Sets the clock the cache uses to measure the time for the expiration
+    /// policies, such as time-to-live, time-to-idle and per-entry expiration.
+    ///
+    /// By default, the cache uses a monotonic clock of the system. A custom clock
+    /// is useful to test code depending on the expiration without waiting for the
+    /// real time to pass. See [`MockClock`][mock-clock], which is available when
+    /// the `testing` feature is enabled.
+    ///
+    /// The clock must be monotonic; the cache treats a clock going backward as not
+    /// advancing.
+    ///
+    /// [mock-clock]: ../clock/struct.MockClock.html
+    pub fn clock(self, clock: impl CacheClock) -> Self {
+        Self {
+            clock: Some(Arc::new(clock)),
+            ..self
+        }
+    }
+
+    /// Sets the [`MaintenanceScheduler`][scheduler] to run the maintenance tasks of
+    /// the cache in the background.
+    ///
+    /// Without a scheduler, the maintenance tasks, such as removing expired entries
+    /// and calling the eviction listener, run only while the cache is being used.
+    ///
+    /// [scheduler]: ./struct.MaintenanceScheduler.html
+    pub fn maintenance_scheduler(self, scheduler: MaintenanceScheduler) -> Self {
+        Self {
+            maintenance_scheduler: Some(scheduler),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of a single run of the maintenance tasks.
+    ///
+    /// The maintenance tasks may be run by a cache read or write. When an eviction
+    /// listener is set, the tasks call it for the removed entries, so a slow
+    /// listener could block the read or write for a long time. The tasks stop when
+    /// the timeout is reached, and the remaining work is done by the next run. Only
+    /// used when an eviction listener is set.
+    ///
+    /// The default value is 100 milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `timeout` is zero.
+    pub fn maintenance_task_timeout(self, timeout: Duration) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.maintenance_task_timeout = timeout;
+        builder
+    }
+
+    /// Sets the maximum number of times a single run of the maintenance tasks
+    /// drains the read and write logs while they keep filling up.
+    ///
+    /// The default value is 4.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `repeats` is zero.
+    pub fn max_log_sync_repeats(self, repeats: u32) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.max_log_sync_repeats = repeats;
+        builder
+    }
+
+    /// Sets the maximum number of entries processed at once by each step of the
+    /// maintenance tasks, such as removing expired entries.
+    ///
+    /// A smaller value makes the maintenance tasks check the timeout more often.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `batch_size` is
+    /// zero.
+    pub fn eviction_batch_size(self, batch_size: u32) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.eviction_batch_size = batch_size;
+        builder
+    }
+
+    /// Sets the capacity of the read log, where the cache records the reads to
+    /// apply them to the cache policy later.
+    ///
+    /// When the log is full, reads are not recorded. This is counted as
+    /// [`read_ops_dropped`][read-ops-dropped] in the maintenance statistics.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `capacity` is smaller
+    /// than 64, the number of the recorded reads that triggers the maintenance
+    /// tasks.
+    ///
+    /// [read-ops-dropped]: ../struct.MaintenanceStats.html#method.read_ops_dropped
+    pub fn read_log_capacity(self, capacity: usize) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.read_log_capacity = capacity;
+        builder
+    }
+
+    /// Sets the capacity of the write log, where the cache records the writes to
+    /// apply them to the cache policy later.
+    ///
+    /// When the log is full, writes wait for the maintenance tasks to drain it.
+    /// This is counted as [`write_op_retries`][write-op-retries] in the maintenance
+    /// statistics.
+    ///
+    /// The default value is 384.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `capacity` is smaller
+    /// than 64, the number of the recorded writes that triggers the maintenance
+    /// tasks.
+    ///
+    /// [write-op-retries]: ../struct.MaintenanceStats.html#method.write_op_retries
+    pub fn write_log_capacity(self, capacity: usize) -> Self {
+        let mut builder = self;
+        builder.housekeeper_config.write_log_capacity = capacity;
+        builder
+    }
 }
 
 #[cfg(test)]
@@ -603,4 +857,84 @@ mod tests {
             .time_to_idle(duration + Duration::from_secs(1))
             .build();
     }
+
+    #[test]
+    fn build_cache_with_maintenance_options() {
+        let builder = CacheBuilder::new(100)
+            .maintenance_task_timeout(Duration::from_millis(10))
+            .max_log_sync_repeats(2)
+            .eviction_batch_size(100)
+            .read_log_capacity(64)
+            .write_log_capacity(1024);
+        let conf = &builder.housekeeper_config;
+        assert_eq!(conf.maintenance_task_timeout, Duration::from_millis(10));
+        assert_eq!(conf.max_log_sync_repeats, 2);
+        assert_eq!(conf.eviction_batch_size, 100);
+        assert_eq!(conf.read_log_capacity, 64);
+        assert_eq!(conf.write_log_capacity, 1024);
+
+        let cache = builder.build();
+        cache.insert('a', "Alice");
+        assert_eq!(cache.get(&'a'), Some("Alice"));
+    }
+
+    #[test]
+    #[should_panic(expected = "read_log_capacity must be at least 64")]
+    fn build_cache_too_small_read_log() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.read_log_capacity(63).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "eviction_batch_size must not be zero")]
+    fn build_cache_zero_eviction_batch_size() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.eviction_batch_size(0).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "the interval of the maintenance scheduler must not be zero")]
+    fn build_cache_zero_maintenance_interval() {
+        use crate::sync::MaintenanceScheduler;
+
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder
+            .maintenance_scheduler(MaintenanceScheduler::thread().interval(Duration::ZERO))
+            .build();
+    }
+
+    #[test]
+    #[should_panic(expected = "maintenance_task_timeout must not be zero")]
+    fn build_cache_zero_maintenance_task_timeout() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.maintenance_task_timeout(Duration::ZERO).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "max_log_sync_repeats must not be zero")]
+    fn build_cache_zero_max_log_sync_repeats() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.max_log_sync_repeats(0).build();
+    }
+
+    #[test]
+    #[should_panic(expected = "write_log_capacity must be at least 64")]
+    fn build_cache_too_small_write_log() {
+        let builder: CacheBuilder<char, String, _> = CacheBuilder::new(100);
+        builder.write_log_capacity(63).build();
+    }
+
+    #[test]
+    fn build_cache_with_overridden_maintenance_options() {
+        // The options are validated by `build`, so an invalid value can be
+        // overridden by a valid one before that.
+        let cache = CacheBuilder::new(100)
+            .eviction_batch_size(0)
+            .eviction_batch_size(1)
+            .read_log_capacity(0)
+            .read_log_capacity(64)
+            .build();
+        cache.insert('a', "Alice");
+        assert_eq!(cache.get(&'a'), Some("Alice"));
+    }
 }
@@ -1,34 +1,40 @@
 use super::{
     value_initializer::{InitResult, ValueInitializer},
-    CacheBuilder, OwnedKeyEntrySelector, RefKeyEntrySelector,
+    CacheBuilder, MaintenanceScheduler, OwnedKeyEntrySelector, RefKeyEntrySelector,
 };
 use crate::{
+    clock::CacheClock,
     common::{
+        callback_panic::CallbackPanicReporter,
         concurrent::{
-            constants::WRITE_RETRY_INTERVAL_MICROS, housekeeper::InnerSync, Weigher, WriteOp,
+            constants::WRITE_RETRY_INTERVAL_MICROS, housekeeper::InnerSync, KvEntry, Weigher,
+            WriteOp,
         },
+        tag::TagExtractor,
         time::Instant,
         HousekeeperConfig,
     },
-    notification::EvictionListener,
+    notification::{EvictionListener, RemovalCause},
     ops::compute::{self, CompResult},
-    policy::{EvictionPolicy, ExpirationPolicy},
-    sync::{Iter, PredicateId},
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    sync::{Iter, IterWithMetadata, KeyGuard, Keys, PredicateId, Values},
     sync_base::{
         base_cache::{BaseCache, HouseKeeperArc},
         iter::ScanningGet,
     },
-    Entry, Policy, PredicateError,
+    Entry, EntryMetadata, HeapSize, KeyLockError, MaintenanceStats, MemoryReport, Policy,
+    PredicateError, PredicateStatus,
 };
 
 use crossbeam_channel::{Sender, TrySendError};
 use std::{
     borrow::Borrow,
+    cell::RefCell,
     collections::hash_map::RandomState,
     fmt,
     hash::{BuildHasher, Hash},
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 /// A thread-safe concurrent synchronous in-memory cache.
@@ -639,6 +645,16 @@ impl<K, V, S> Cache<K, V, S> {
         self.base.policy()
     }
 
+    /// Returns `true` if the eviction listener is set and has not been disabled.
+    ///
+    /// Under the default [`CallbackPanicPolicy`][callback-panic-policy], the cache
+    /// disables the listener once it panicked. This method lets you detect it.
+    ///
+    /// [callback-panic-policy]: ../policy/struct.CallbackPanicPolicy.html
+    pub fn listener_enabled(&self) -> bool {
+        self.base.is_listener_enabled()
+    }
+
     /// Returns an approximate number of entries in this cache.
     ///
     /// The value returned is _an estimate_; the actual count may differ if there are
@@ -708,9 +724,14 @@ where
             None,
             EvictionPolicy::default(),
             None,
+            CallbackPanicPolicy::default(),
             ExpirationPolicy::default(),
             HousekeeperConfig::default(),
             false,
+            false,
+            None,
+            None,
+            None,
         )
     }
 
@@ -739,11 +760,17 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<EvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
         expiration_policy: ExpirationPolicy<K, V>,
         housekeeper_config: HousekeeperConfig,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
     ) -> Self {
-        Self {
+        let panic_reporter = CallbackPanicReporter::new(callback_panic_policy, name.as_deref());
+        let mut cache = Self {
             base: BaseCache::new(
                 name,
                 max_capacity,
@@ -752,12 +779,24 @@ where
                 weigher,
                 eviction_policy,
                 eviction_listener,
+                panic_reporter.clone(),
                 expiration_policy,
                 housekeeper_config,
                 invalidator_enabled,
+                key_locks_enabled,
+                tag_extractor,
+                clock,
             ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            value_initializer: Arc::new(ValueInitializer::new(build_hasher, panic_reporter)),
+        };
+        if let Some(scheduler) = maintenance_scheduler {
+            let (handle, waker) = scheduler.start(vec![cache.base.maintenance_job()]);
+            cache.base.set_maintenance_handle(handle);
+            if let Some(waker) = waker {
+                cache.base.set_maintenance_waker(waker);
+            }
         }
+        cache
     }
 
     /// Returns `true` if the cache contains a value for the key.
@@ -804,6 +843,117 @@ where
             .map(Entry::into_value)
     }
 
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the
+    /// [`EntryMetadata`](../struct.EntryMetadata.html) of the entry.
+    ///
+    /// This is considered a cache read operation, same as the `get` method.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::builder()
+    ///     .time_to_live(Duration::from_secs(60))
+    ///     .build();
+    /// cache.insert("key", "value");
+    ///
+    /// let entry = cache.get_entry(&"key").unwrap();
+    /// assert_eq!(entry.value(), &"value");
+    ///
+    /// let metadata = entry.metadata().unwrap();
+    /// let lm = metadata.last_modified().unwrap();
+    /// assert_eq!(metadata.expiration_time(), Some(lm + Duration::from_secs(60)));
+    /// ```
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_entry_with_hash(key, self.base.hash(key))
+    }
+
+    pub(crate) fn get_entry_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get_entry_with_hash(key, hash)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key without affecting
+    /// the cache policies.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read operation,
+    /// so it does not update the historic popularity estimator, move the entry in
+    /// the access order queue, or reset the idle timer for the key. The
+    /// `expire_after_read` method of the [`Expiry`](../policy/trait.Expiry.html) is
+    /// not called either. This is useful for monitoring or dumping the cache
+    /// without skewing its eviction policy.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::builder()
+    ///     .time_to_idle(Duration::from_secs(60))
+    ///     .build();
+    /// cache.insert("key", "value");
+    ///
+    /// let last_accessed = |cache: &Cache<_, _>| {
+    ///     cache.peek_entry(&"key").unwrap().metadata().unwrap().last_accessed()
+    /// };
+    /// let before = last_accessed(&cache);
+    /// assert_eq!(cache.peek(&"key"), Some("value"));
+    /// assert_eq!(last_accessed(&cache), before);
+    /// ```
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_with_hash(key, self.base.hash(key), false)
+            .map(Entry::into_value)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the
+    /// [`EntryMetadata`](../struct.EntryMetadata.html) of the entry, without
+    /// affecting the cache policies.
+    ///
+    /// See [`peek`](#method.peek) for the difference from the `get` family of
+    /// methods.
+    pub fn peek_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_with_hash(key, self.base.hash(key), true)
+    }
+
+    pub(crate) fn peek_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_metadata: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.peek_with_hash(key, hash, need_metadata)
+    }
+
     pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64, need_key: bool) -> Option<Entry<K, V>>
     where
         K: Borrow<Q>,
@@ -875,6 +1025,169 @@ where
         RefKeyEntrySelector::new(key, hash, self)
     }
 
+    /// Performs a compute operation on multiple keys atomically.
+    ///
+    /// This method locks all the keys, and calls the `f` closure with an array of
+    /// the current entries for the keys, in the same order as `keys`. The closure
+    /// returns an array of `compute::Op`s, one for each key in the same order, and
+    /// the ops are applied before the keys are unlocked:
+    ///
+    /// - `Op::Put(V)`: Puts the new value `V` to the cache.
+    /// - `Op::Remove`: Removes the current entry from the cache.
+    /// - `Op::Nop`: Does nothing.
+    ///
+    /// This method returns a `compute::CompResult` for each key. See
+    /// [`and_compute_with`][compute-with] for the variants.
+    ///
+    /// The keys are locked in the same way as [`lock_key`](#method.lock_key), in a
+    /// deterministic order so that the concurrent calls with overlapping keys do
+    /// not deadlock. While the keys are locked, the other threads inserting,
+    /// updating or removing the entries for the keys wait for this method to
+    /// return. The methods reading the entries, such as `get`, do not wait; they
+    /// may see some of the ops applied and the others not yet applied.
+    ///
+    /// If a key is given more than once, the closure gets the same entry for each
+    /// occurrence, and the ops for the key are applied in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::{ops::compute::Op, sync::Cache};
+    ///
+    /// let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+    /// cache.insert("alice", 100);
+    /// cache.insert("bob", 20);
+    ///
+    /// // Transfer 30 from alice to bob.
+    /// let results = cache
+    ///     .compute_many(["alice", "bob"], |entries| {
+    ///         let alice = entries[0].as_ref().map_or(0, |e| *e.value());
+    ///         let bob = entries[1].as_ref().map_or(0, |e| *e.value());
+    ///         if alice < 30 {
+    ///             return [Op::Nop, Op::Nop];
+    ///         }
+    ///         [Op::Put(alice - 30), Op::Put(bob + 30)]
+    ///     })
+    ///     .unwrap();
+    ///
+    /// assert_eq!(results.len(), 2);
+    /// assert_eq!(cache.get(&"alice"), Some(70));
+    /// assert_eq!(cache.get(&"bob"), Some(50));
+    /// ```
+    ///
+    /// [compute-with]: ./struct.OwnedKeyEntrySelector.html#method.and_compute_with
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub fn compute_many<const N: usize, F>(
+        &self,
+        keys: [K; N],
+        f: F,
+    ) -> Result<Vec<compute::CompResult<K, V>>, KeyLockError>
+    where
+        F: FnOnce(&[Option<Entry<K, V>>; N]) -> [compute::Op<V>; N],
+    {
+        let keys = keys.map(Arc::new);
+        let hashes: [u64; N] = std::array::from_fn(|i| self.base.hash(&keys[i]));
+        let guards = self.base.lock_keys(&keys)?;
+
+        let entries = std::array::from_fn(|i| {
+            let ignore_if = None as Option<&mut fn(&V) -> bool>;
+            self.base
+                .get_with_hash_and_ignore_if(&keys[i], hashes[i], ignore_if, true)
+        });
+        let ops = f(&entries);
+
+        let mut results = Vec::with_capacity(N);
+        let mut write_ops = Vec::new();
+        let mut removed_keys = Vec::new();
+
+        for ((key, hash), op) in keys.into_iter().zip(hashes).zip(ops) {
+            // Get the current value, which reflects the ops already applied for
+            // the same key.
+            let ignore_if = None as Option<&mut fn(&V) -> bool>;
+            let maybe_value = self
+                .base
+                .get_with_hash_without_recording(&key, hash, ignore_if);
+
+            let result = match op {
+                compute::Op::Nop => {
+                    if let Some(value) = maybe_value {
+                        let md = self.base.entry_metadata_with_hash(&key, hash);
+                        let entry = Entry::new(Some(key), value, false, false).with_metadata(md);
+                        CompResult::Unchanged(entry)
+                    } else {
+                        CompResult::StillNone(key)
+                    }
+                }
+                compute::Op::Put(value) => {
+                    let prev_v = if self.base.is_map_disabled() {
+                        None
+                    } else {
+                        let (op, now, prev) = self.base.do_insert_with_hash(
+                            Arc::clone(&key),
+                            hash,
+                            value.clone(),
+                            None,
+                        );
+                        write_ops.push((op, now));
+                        prev.map(|entry| entry.value.clone())
+                    };
+                    let md = self.base.entry_metadata_with_hash(&key, hash);
+                    if maybe_value.is_some() {
+                        let entry = Entry::new(Some(key), value, true, true)
+                            .with_old_value(prev_v)
+                            .with_metadata(md);
+                        CompResult::ReplacedWith(entry)
+                    } else {
+                        let entry = Entry::new(Some(key), value, true, false).with_metadata(md);
+                        CompResult::Inserted(entry)
+                    }
+                }
+                compute::Op::Remove => {
+                    let removed = self.remove_and_notify_without_scheduling(
+                        &*key,
+                        hash,
+                        RemovalCause::Explicit,
+                        || self.base.remove_entry(&*key, hash),
+                    );
+                    if let Some((kv, op, now)) = removed {
+                        write_ops.push((op, now));
+                        let prev_v = kv.entry.value.clone();
+                        removed_keys.push(kv.key);
+                        CompResult::Removed(Entry::new(Some(key), prev_v, false, false))
+                    } else {
+                        CompResult::StillNone(key)
+                    }
+                }
+            };
+            results.push(result);
+        }
+
+        // Unlock the keys before scheduling the write ops to avoid a potential
+        // dead lock. (See `remove_and_notify_without_scheduling`)
+        std::mem::drop(guards);
+
+        if !write_ops.is_empty() {
+            for (op, now) in write_ops {
+                if matches!(op, WriteOp::Remove { .. }) {
+                    self.schedule_remove_op(op, now);
+                } else {
+                    self.schedule_upsert_op(op, now);
+                }
+            }
+            crossbeam_epoch::pin().flush();
+        }
+        for key in removed_keys {
+            self.invalidate_dependents(&key);
+        }
+
+        Ok(results)
+    }
+
     /// Returns a _clone_ of the value corresponding to the key. If the value does
     /// not exist, evaluates the `init` closure and inserts the output.
     ///
@@ -1037,7 +1350,12 @@ where
             self.base
                 .get_with_hash_without_recording(&key, hash, replace_if.as_mut())
         };
-        let insert = |v| self.insert_with_hash(key.clone(), hash, v);
+        let deps = RefCell::default();
+        let init = || self.track_init(init, &deps);
+        let insert = |v| {
+            self.insert_with_hash(key.clone(), hash, v);
+            self.add_tracked_dependencies(&key, hash, &deps);
+        };
 
         let k = if need_key {
             Some(Arc::clone(&key))
@@ -1047,10 +1365,14 @@ where
 
         let type_id = ValueInitializer::<K, V, S>::type_id_for_get_with();
         let post_init = ValueInitializer::<K, V, S>::post_init_for_get_with;
+        let metrics = self.base.metrics_counters();
+
+        #[cfg(feature = "tracing")]
+        let _span = crate::common::trace::load_span(self.base.name(), hash).entered();
 
         match self
             .value_initializer
-            .try_init_or_read(&key, type_id, get, init, insert, post_init)
+            .try_init_or_read(&key, type_id, metrics, get, init, insert, post_init)
         {
             InitResult::Initialized(v) => {
                 crossbeam_epoch::pin().flush();
@@ -1261,7 +1583,12 @@ where
             self.base
                 .get_with_hash_without_recording(&key, hash, ignore_if)
         };
-        let insert = |v| self.insert_with_hash(key.clone(), hash, v);
+        let deps = RefCell::default();
+        let init = || self.track_init(init, &deps);
+        let insert = |v| {
+            self.insert_with_hash(key.clone(), hash, v);
+            self.add_tracked_dependencies(&key, hash, &deps);
+        };
 
         let k = if need_key {
             Some(Arc::clone(&key))
@@ -1271,10 +1598,14 @@ where
 
         let type_id = ValueInitializer::<K, V, S>::type_id_for_optionally_get_with();
         let post_init = ValueInitializer::<K, V, S>::post_init_for_optionally_get_with;
+        let metrics = self.base.metrics_counters();
+
+        #[cfg(feature = "tracing")]
+        let _span = crate::common::trace::load_span(self.base.name(), hash).entered();
 
         match self
             .value_initializer
-            .try_init_or_read(&key, type_id, get, init, insert, post_init)
+            .try_init_or_read(&key, type_id, metrics, get, init, insert, post_init)
         {
             InitResult::Initialized(v) => {
                 crossbeam_epoch::pin().flush();
@@ -1455,7 +1786,12 @@ where
             self.base
                 .get_with_hash_without_recording(&key, hash, ignore_if)
         };
-        let insert = |v| self.insert_with_hash(key.clone(), hash, v);
+        let deps = RefCell::default();
+        let init = || self.track_init(init, &deps);
+        let insert = |v| {
+            self.insert_with_hash(key.clone(), hash, v);
+            self.add_tracked_dependencies(&key, hash, &deps);
+        };
 
         let k = if need_key {
             Some(Arc::clone(&key))
@@ -1465,10 +1801,14 @@ where
 
         let type_id = ValueInitializer::<K, V, S>::type_id_for_try_get_with::<E>();
         let post_init = ValueInitializer::<K, V, S>::post_init_for_try_get_with;
+        let metrics = self.base.metrics_counters();
+
+        #[cfg(feature = "tracing")]
+        let _span = crate::common::trace::load_span(self.base.name(), hash).entered();
 
         match self
             .value_initializer
-            .try_init_or_read(&key, type_id, get, init, insert, post_init)
+            .try_init_or_read(&key, type_id, metrics, get, init, insert, post_init)
         {
             InitResult::Initialized(v) => {
                 crossbeam_epoch::pin().flush();
@@ -1491,439 +1831,3161 @@ where
         self.insert_with_hash(key, hash, value);
     }
 
-    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
-        if self.base.is_map_disabled() {
-            return;
-        }
+    /// Inserts a key-value pair into the cache, and makes the entry expire after
+    /// the given duration.
+    ///
+    /// If the cache has this key present, the value is updated and its expiration
+    /// time is reset to the given duration from now.
+    ///
+    /// The duration takes precedence over the
+    /// [`Expiry`](../policy/trait.Expiry.html) configured to the cache, which is not
+    /// called for this insertion. The time-to-live and time-to-idle policies of the
+    /// cache still apply, so the entry will expire at whichever comes first.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert_with_ttl("key", "value", Duration::from_secs(30));
+    ///
+    /// let remaining = cache.expires_in(&"key").unwrap();
+    /// assert!(remaining <= Duration::from_secs(30));
+    /// ```
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_ttl(key, hash, value, Some(ttl));
+    }
 
-        let (op, now) = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::schedule_write_op(
-            self.base.inner.as_ref(),
-            &self.base.write_op_ch,
-            op,
-            now,
-            hk,
-        )
-        .expect("Failed to insert");
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given deadline.
+    ///
+    /// If the deadline has already passed, the entry is inserted but will never be
+    /// returned by the cache. See [`insert_with_ttl`](#method.insert_with_ttl) for
+    /// how the deadline interacts with other expiration policies.
+    pub fn insert_with_deadline(&self, key: K, value: V, deadline: std::time::Instant) {
+        let ttl = self.duration_until(deadline);
+        self.insert_with_ttl(key, value, ttl);
     }
 
-    pub(crate) fn compute_with_hash_and_fun<F>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        f: F,
-    ) -> compute::CompResult<K, V>
-    where
-        F: FnOnce(Option<Entry<K, V>>) -> compute::Op<V>,
-    {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_compute_with;
-        match self
-            .value_initializer
-            .try_compute(key, hash, self, f, post_init, true)
-        {
-            Ok(result) => result,
-            Err(_) => unreachable!(),
-        }
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given wall-clock time, such as the time of an HTTP `Expires` header or the
+    /// `notAfter` field of a certificate.
+    ///
+    /// Returns `false` without inserting the entry if `expires_at` has already
+    /// passed. In that case, the existing entry for the key, if any, is left as it
+    /// is.
+    ///
+    /// The deadline is converted into a duration from now at insertion. If the
+    /// system clock jumps afterward, the expiration time of the entry is converted
+    /// again from the deadline when the cache runs its pending tasks. Updating the
+    /// entry by other methods discards the deadline. See
+    /// [`insert_with_ttl`](#method.insert_with_ttl) for how the deadline interacts
+    /// with other expiration policies.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// let expires_at = SystemTime::now() + Duration::from_secs(60);
+    /// assert!(cache.insert_expiring_at("fresh", "value", expires_at));
+    /// assert_eq!(cache.get(&"fresh"), Some("value"));
+    ///
+    /// // A deadline in the past is refused.
+    /// let expired_at = SystemTime::now() - Duration::from_secs(1);
+    /// assert!(!cache.insert_expiring_at("stale", "value", expired_at));
+    /// assert!(!cache.contains_key(&"stale"));
+    /// ```
+    pub fn insert_expiring_at(&self, key: K, value: V, expires_at: SystemTime) -> bool {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_expiring_at(key, hash, value, expires_at)
     }
 
-    pub(crate) fn try_compute_with_hash_and_fun<F, E>(
-        &self,
-        key: Arc<K>,
-        hash: u64,
-        f: F,
-    ) -> Result<compute::CompResult<K, V>, E>
-    where
-        F: FnOnce(Option<Entry<K, V>>) -> Result<compute::Op<V>, E>,
-        E: Send + Sync + 'static,
-    {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_compute_with;
-        self.value_initializer
-            .try_compute(key, hash, self, f, post_init, true)
+    /// Inserts a key-value pair into the cache, and returns a _clone_ of the value
+    /// that was replaced, if any.
+    ///
+    /// Unlike calling [`get`](#method.get) and then [`insert`](#method.insert),
+    /// the previous value is the one actually replaced by this insertion. `None` is
+    /// returned if the cache did not have a live entry for the key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// assert_eq!(cache.swap("key", "alice"), None);
+    /// assert_eq!(cache.swap("key", "bob"), Some("alice"));
+    /// assert_eq!(cache.get(&"key"), Some("bob"));
+    /// ```
+    pub fn swap(&self, key: K, value: V) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.swap_with_hash(key, hash, value)
     }
 
-    pub(crate) fn upsert_with_hash_and_fun<F>(&self, key: Arc<K>, hash: u64, f: F) -> Entry<K, V>
-    where
-        F: FnOnce(Option<Entry<K, V>>) -> V,
-    {
-        let post_init = ValueInitializer::<K, V, S>::post_init_for_upsert_with;
-        match self
-            .value_initializer
-            .try_compute(key, hash, self, f, post_init, false)
-        {
-            Ok(CompResult::Inserted(entry) | CompResult::ReplacedWith(entry)) => entry,
-            _ => unreachable!(),
-        }
+    /// Inserts a key-value pair into the cache only if the cache does not have a
+    /// live entry for the key.
+    ///
+    /// Returns `None` if the value was inserted. Otherwise, returns a _clone_ of
+    /// the existing value, and the given value is discarded.
+    ///
+    /// Unlike [`get_with`](#method.get_with), this method does not wait for
+    /// concurrent calls for the same key; it is an atomic operation on the
+    /// internal hash table.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// assert_eq!(cache.insert_if_absent("key", "alice"), None);
+    /// assert_eq!(cache.insert_if_absent("key", "bob"), Some("alice"));
+    /// assert_eq!(cache.get(&"key"), Some("alice"));
+    /// ```
+    pub fn insert_if_absent(&self, key: K, value: V) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_if_absent_with_hash(key, hash, value)
     }
 
-    /// Discards any cached value for the key.
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key and `condition` returns `true` for the current value.
     ///
-    /// If you need to get a the value that has been discarded, use the
-    /// [`remove`](#method.remove) method instead.
+    /// Returns a _clone_ of the previous value if the value was replaced.
+    /// Otherwise, returns `None`.
+    ///
+    /// The check and the replacement are done atomically; no other update to the
+    /// entry can happen between them. `condition` may be called more than once
+    /// when it conflicts with concurrent updates to the same entry.
     ///
     /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
     /// on the borrowed form _must_ match those for the key type.
-    pub fn invalidate<Q>(&self, key: &Q)
+    pub fn replace_if<Q>(&self, key: &Q, value: V, condition: impl FnMut(&V) -> bool) -> Option<V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let hash = self.base.hash(key);
-        self.invalidate_with_hash(key, hash, false);
+        self.replace_with_hash_if(key, hash, value, condition).ok()
     }
 
-    /// Discards any cached value for the key and returns a _clone_ of the value.
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key.
     ///
-    /// If you do not need to get the value that has been discarded, use the
-    /// [`invalidate`](#method.invalidate) method instead.
+    /// Returns a _clone_ of the previous value if the value was replaced.
+    /// Otherwise, returns `None` and the key will not be inserted.
     ///
     /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
     /// on the borrowed form _must_ match those for the key type.
-    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    pub fn replace_if_present<Q>(&self, key: &Q, value: V) -> Option<V>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        let hash = self.base.hash(key);
-        self.invalidate_with_hash(key, hash, true)
+        self.replace_if(key, value, |_| true)
     }
 
-    pub(crate) fn invalidate_with_hash<Q>(&self, key: &Q, hash: u64, need_value: bool) -> Option<V>
+    /// Replaces the value for the key only if the current value is equal to
+    /// `expected`.
+    ///
+    /// Returns `Ok` with a _clone_ of the previous value if the value was
+    /// replaced. Otherwise, returns `Err` with a _clone_ of the current value, or
+    /// `Err(None)` if the cache does not have a live entry for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("counter", 1);
+    ///
+    /// assert_eq!(cache.replace(&"counter", &1, 2), Ok(1));
+    /// assert_eq!(cache.replace(&"counter", &1, 3), Err(Some(2)));
+    /// assert_eq!(cache.replace(&"missing", &1, 3), Err(None));
+    /// ```
+    pub fn replace<Q>(&self, key: &Q, expected: &V, new: V) -> Result<V, Option<V>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
+        V: PartialEq,
     {
-        // Lock the key for removal if blocking removal notification is enabled.
-        let mut kl = None;
-        let mut klg = None;
-        if self.base.is_removal_notifier_enabled() {
-            // To lock the key, we have to get Arc<K> for key (&Q).
-            //
-            // TODO: Enhance this if possible. This is rather hack now because
-            // it cannot prevent race conditions like this:
-            //
-            // 1. We miss the key because it does not exist. So we do not lock
-            //    the key.
-            // 2. Somebody else (other thread) inserts the key.
-            // 3. We remove the entry for the key, but without the key lock!
-            //
-            if let Some(arc_key) = self.base.get_key_with_hash(key, hash) {
-                kl = self.base.maybe_key_lock(&arc_key);
-                klg = kl.as_ref().map(|kl| kl.lock());
-            }
+        let hash = self.base.hash(key);
+        self.replace_with_hash_if(key, hash, new, |v| v == expected)
+    }
+
+    pub(crate) fn duration_until(&self, deadline: std::time::Instant) -> Duration {
+        self.base.duration_until(deadline)
+    }
+
+    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        self.insert_with_hash_and_ttl(key, hash, value, None);
+    }
+
+    pub(crate) fn insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        ttl: Option<Duration>,
+    ) {
+        self.do_insert_with_hash_and_ttl(key, hash, value, ttl, false);
+    }
+
+    pub(crate) fn insert_with_hash_expiring_at(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        expires_at: SystemTime,
+    ) -> bool {
+        if self.base.is_map_disabled() {
+            return false;
         }
+        let Some(ttl) = self.base.duration_until_system_time(expires_at) else {
+            return false;
+        };
 
-        match self.base.remove_entry(key, hash) {
-            None => None,
-            Some(kv) => {
-                let now = self.base.current_time_from_expiration_clock();
+        let (op, now, _) = self
+            .base
+            .do_insert_with_hash(Arc::clone(&key), hash, value, Some(ttl));
+        self.base.register_wall_clock_deadline(key, expires_at, &op);
+        self.schedule_upsert_op(op, now);
+        true
+    }
 
-                let info = kv.entry.entry_info();
-                let entry_gen = info.incr_entry_gen();
+    pub(crate) fn swap_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> Option<V> {
+        self.do_insert_with_hash_and_ttl(key, hash, value, None, true)
+    }
 
-                if self.base.is_removal_notifier_enabled() {
-                    self.base.notify_invalidate(&kv.key, &kv.entry);
-                }
-                // Drop the locks before scheduling write op to avoid a potential
-                // dead lock. (Scheduling write can do spin lock when the queue is
-                // full, and queue will be drained by the housekeeping thread that
-                // can lock the same key)
-                std::mem::drop(klg);
-                std::mem::drop(kl);
+    fn do_insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        ttl: Option<Duration>,
+        need_prev: bool,
+    ) -> Option<V> {
+        if self.base.is_map_disabled() {
+            return None;
+        }
 
-                let maybe_v = if need_value {
-                    Some(kv.entry.value.clone())
-                } else {
-                    None
-                };
+        let (op, now, prev) = self.base.do_insert_with_hash(key, hash, value, ttl);
+        self.schedule_upsert_op(op, now);
 
-                let op = WriteOp::Remove {
-                    kv_entry: kv,
-                    entry_gen,
-                };
-                let hk = self.base.housekeeper.as_ref();
-                Self::schedule_write_op(
-                    self.base.inner.as_ref(),
-                    &self.base.write_op_ch,
-                    op,
-                    now,
-                    hk,
-                )
-                .expect("Failed to remove");
-                crossbeam_epoch::pin().flush();
-                maybe_v
+        if need_prev {
+            prev.map(|entry| entry.value.clone())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn insert_if_absent_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> Option<V> {
+        if self.base.is_map_disabled() {
+            return None;
+        }
+
+        match self.base.do_insert_if_absent_with_hash(key, hash, value) {
+            Ok((op, now)) => {
+                self.schedule_upsert_op(op, now);
+                None
             }
+            Err(current) => Some(current),
         }
     }
 
-    /// Discards all cached values.
+    pub(crate) fn replace_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        value: V,
+        condition: impl FnMut(&V) -> bool,
+    ) -> Result<V, Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.base.is_map_disabled() {
+            return Err(None);
+        }
+
+        let (op, now, prev) = self
+            .base
+            .do_replace_with_hash_if(key, hash, value, condition)?;
+        self.schedule_upsert_op(op, now);
+        Ok(prev)
+    }
+
+    fn schedule_upsert_op(&self, op: WriteOp<K, V>, now: Instant) {
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(
+            self.base.inner.as_ref(),
+            &self.base.write_op_ch,
+            op,
+            now,
+            hk,
+        )
+        .expect("Failed to insert");
+    }
+
+    pub(crate) fn compute_with_hash_and_fun<F>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: F,
+    ) -> compute::CompResult<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> compute::Op<V>,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_compute_with;
+        match self
+            .value_initializer
+            .try_compute(key, hash, self, f, post_init, true)
+        {
+            Ok(result) => result,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    pub(crate) fn try_compute_with_hash_and_fun<F, E>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: F,
+    ) -> Result<compute::CompResult<K, V>, E>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> Result<compute::Op<V>, E>,
+        E: Send + Sync + 'static,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_try_compute_with;
+        self.value_initializer
+            .try_compute(key, hash, self, f, post_init, true)
+    }
+
+    pub(crate) fn upsert_with_hash_and_fun<F>(&self, key: Arc<K>, hash: u64, f: F) -> Entry<K, V>
+    where
+        F: FnOnce(Option<Entry<K, V>>) -> V,
+    {
+        let post_init = ValueInitializer::<K, V, S>::post_init_for_upsert_with;
+        match self
+            .value_initializer
+            .try_compute(key, hash, self, f, post_init, false)
+        {
+            Ok(CompResult::Inserted(entry) | CompResult::ReplacedWith(entry)) => entry,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Sets the expiration of the existing entry for the key to the given duration
+    /// from now. Returns `false` if the cache does not contain a value for the
+    /// key.
     ///
-    /// This method returns immediately and a background thread will evict all the
-    /// cached values inserted before the time when this method was called. It is
-    /// guaranteed that the `get` method must not return these invalidated values
-    /// even if they have not been evicted.
+    /// Like [`insert_with_ttl`](#method.insert_with_ttl), this overrides the
+    /// per-entry expiration time set by an insertion or by the
+    /// [`Expiry`](../policy/trait.Expiry.html), but the time-to-live and
+    /// time-to-idle policies of the cache still apply.
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
-    pub fn invalidate_all(&self) {
-        self.base.invalidate_all();
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn set_expiry<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.set_expiry_with_hash(key, self.base.hash(key), ttl)
     }
 
-    /// Discards cached values that satisfy a predicate.
+    pub(crate) fn set_expiry_with_hash<Q>(&self, key: &Q, hash: u64, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some((op, now)) = self.base.set_expiry_with_hash(key, hash, ttl) else {
+            return false;
+        };
+        let hk = self.base.housekeeper.as_ref();
+        // The write op channel is disconnected only when the cache is dropped. The
+        // new expiration time has been set to the entry anyway, so ignore the error.
+        let _ = Self::schedule_write_op(
+            self.base.inner.as_ref(),
+            &self.base.write_op_ch,
+            op,
+            now,
+            hk,
+        );
+        true
+    }
+
+    /// Records a read access to the entry for the key. Returns `false` if the cache
+    /// does not contain a value for the key.
     ///
-    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
-    /// method returns immediately and a background thread will apply the closure to
-    /// each cached value inserted before the time when `invalidate_entries_if` was
-    /// called. If the closure returns `true` on a value, that value will be evicted
-    /// from the cache.
+    /// This is the same as the `get` method except that it does not return the
+    /// value. It resets the idle timer of the entry and calls `expire_after_read`
+    /// of the [`Expiry`](../policy/trait.Expiry.html) if any.
     ///
-    /// Also the `get` method will apply the closure to a value to determine if it
-    /// should have been invalidated. Therefore, it is guaranteed that the `get`
-    /// method must not return invalidated values.
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn touch<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base
+            .get_with_hash(key, self.base.hash(key), false)
+            .is_some()
+    }
+
+    /// Returns the remaining time until the entry for the key expires.
     ///
-    /// Note that you must call
-    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
-    /// at the cache creation time as the cache needs to maintain additional internal
-    /// data structures to support this method. Otherwise, calling this method will
-    /// fail with a
-    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    /// The returned duration is the earliest of the per-entry expiration time, the
+    /// time-to-live and the time-to-idle. Returns `None` if the cache does not
+    /// contain a value for the key or the entry does not expire.
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation.
     ///
-    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
-    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
-    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn expires_in<Q>(&self, key: &Q) -> Option<Duration>
     where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        self.base.invalidate_entries_if(Arc::new(predicate))
+        self.expires_in_with_hash(key, self.base.hash(key))
     }
 
-    pub(crate) fn invalidate_entries_with_arc_fun<F>(
-        &self,
-        predicate: Arc<F>,
-    ) -> Result<PredicateId, PredicateError>
+    pub(crate) fn expires_in_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<Duration>
     where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        self.base.invalidate_entries_if(predicate)
+        self.base.expires_in_with_hash(key, hash)
     }
 
-    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
-    /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
-    /// value.
+    /// Discards any cached value for the key.
     ///
-    /// Iterators do not block concurrent reads and writes on the cache. An entry can
-    /// be inserted to, invalidated or evicted from a cache while iterators are alive
-    /// on the same cache.
+    /// If you need to get a the value that has been discarded, use the
+    /// [`remove`](#method.remove) method instead.
     ///
-    /// Unlike the `get` method, visiting entries via an iterator do not update the
-    /// historic popularity estimator or reset idle timers for keys.
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.base.hash(key);
+        self.invalidate_with_hash(key, hash, false);
+    }
+
+    /// Discards any cached value for the key and returns a _clone_ of the value.
     ///
-    /// # Guarantees
+    /// If you do not need to get the value that has been discarded, use the
+    /// [`invalidate`](#method.invalidate) method instead.
     ///
-    /// In order to allow concurrent access to the cache, iterator's `next` method
-    /// does _not_ guarantee the following:
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.base.hash(key);
+        self.invalidate_with_hash(key, hash, true)
+    }
+
+    /// Discards the cached value for the key only if `condition` returns `true`
+    /// for the value, and returns a _clone_ of the discarded value.
     ///
-    /// - It does not guarantee to return a key-value pair (an entry) if its key has
-    ///   been inserted to the cache _after_ the iterator was created.
-    ///   - Such an entry may or may not be returned depending on key's hash and
-    ///     timing.
-    ///
-    /// and the `next` method guarantees the followings:
+    /// The check and the removal are done atomically; no other update to the
+    /// entry can happen between them. `condition` may be called more than once
+    /// when it conflicts with concurrent updates to the same entry.
     ///
-    /// - It guarantees not to return the same entry more than once.
-    /// - It guarantees not to return an entry if it has been removed from the cache
-    ///   after the iterator was created.
-    ///     - Note: An entry can be removed by following reasons:
-    ///         - Manually invalidated.
-    ///         - Expired (e.g. time-to-live).
-    ///         - Evicted as the cache capacity exceeded.
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
     ///
-    /// # Examples
+    /// # Example
     ///
     /// ```rust
     /// use moka2::sync::Cache;
     ///
     /// let cache = Cache::new(100);
-    /// cache.insert("Julia", 14);
+    /// cache.insert("key", 10);
     ///
-    /// let mut iter = cache.iter();
-    /// let (k, v) = iter.next().unwrap(); // (Arc<K>, V)
-    /// assert_eq!(*k, "Julia");
-    /// assert_eq!(v, 14);
+    /// assert_eq!(cache.remove_if(&"key", |v| *v > 10), None);
+    /// assert_eq!(cache.remove_if(&"key", |v| *v == 10), Some(10));
+    /// assert!(cache.get(&"key").is_none());
+    /// ```
+    pub fn remove_if<Q>(&self, key: &Q, condition: impl FnMut(&V) -> bool) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.base.hash(key);
+        self.remove_with_hash_if(key, hash, condition)
+    }
+
+    pub(crate) fn invalidate_with_hash<Q>(&self, key: &Q, hash: u64, need_value: bool) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_invalidate_with_hash(key, hash, need_value, || self.base.remove_entry(key, hash))
+    }
+
+    pub(crate) fn remove_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        condition: impl FnMut(&V) -> bool,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_invalidate_with_hash(key, hash, true, || {
+            self.base.remove_entry_if(key, hash, condition)
+        })
+    }
+
+    fn do_invalidate_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_value: bool,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let kv = self.remove_and_notify(key, hash, RemovalCause::Explicit, remove)?;
+        self.invalidate_dependents(&kv.key);
+        if need_value {
+            Some(kv.entry.value.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Invalidates the entries depending on the removed key, the entries depending
+    /// on them, and so on.
+    fn invalidate_dependents(&self, key: &K) {
+        let mut keys = self.base.take_dependents(key);
+        while let Some(key) = keys.pop() {
+            let hash = self.base.hash(&*key);
+            self.remove_and_notify(&*key, hash, RemovalCause::Cascaded, || {
+                self.base.remove_entry(&*key, hash)
+            });
+            keys.extend(self.base.take_dependents(&key));
+        }
+    }
+
+    /// Removes the entry, notifies the removal and schedules the write op. Returns
+    /// the removed entry.
+    fn remove_and_notify<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        cause: RemovalCause,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<KvEntry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (kv, op, now) = self.remove_and_notify_without_scheduling(key, hash, cause, remove)?;
+        self.schedule_remove_op(op, now);
+        crossbeam_epoch::pin().flush();
+        Some(kv)
+    }
+
+    /// Removes the entry and notifies the removal. Returns the removed entry and
+    /// the write op to schedule.
+    // https://rust-lang.github.io/rust-clippy/master/index.html#type_complexity
+    #[allow(clippy::type_complexity)]
+    fn remove_and_notify_without_scheduling<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        cause: RemovalCause,
+        remove: impl FnOnce() -> Option<KvEntry<K, V>>,
+    ) -> Option<(KvEntry<K, V>, WriteOp<K, V>, Instant)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Lock the key for removal if blocking removal notification or key locks
+        // are enabled.
+        let mut kl = None;
+        let mut klg = None;
+        if self.base.has_key_locks() {
+            // To lock the key, we have to get Arc<K> for key (&Q).
+            //
+            // TODO: Enhance this if possible. This is rather hack now because
+            // it cannot prevent race conditions like this:
+            //
+            // 1. We miss the key because it does not exist. So we do not lock
+            //    the key.
+            // 2. Somebody else (other thread) inserts the key.
+            // 3. We remove the entry for the key, but without the key lock!
+            //
+            if let Some(arc_key) = self.base.get_key_with_hash(key, hash) {
+                kl = self.base.maybe_key_lock(&arc_key);
+                klg = kl.as_ref().map(|kl| kl.lock());
+            }
+        }
+
+        match remove() {
+            None => None,
+            Some(kv) => {
+                let now = self.base.current_time_from_expiration_clock();
+
+                let info = kv.entry.entry_info();
+                let entry_gen = info.incr_entry_gen();
+
+                if self.base.is_removal_notifier_enabled() {
+                    self.base.notify_invalidate(&kv.key, &kv.entry, cause);
+                }
+                // Drop the locks before scheduling write op to avoid a potential
+                // dead lock. (Scheduling write can do spin lock when the queue is
+                // full, and queue will be drained by the housekeeping thread that
+                // can lock the same key)
+                std::mem::drop(klg);
+                std::mem::drop(kl);
+
+                let op = WriteOp::Remove {
+                    kv_entry: kv.clone(),
+                    entry_gen,
+                };
+                Some((kv, op, now))
+            }
+        }
+    }
+
+    fn schedule_remove_op(&self, op: WriteOp<K, V>, now: Instant) {
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(
+            self.base.inner.as_ref(),
+            &self.base.write_op_ch,
+            op,
+            now,
+            hk,
+        )
+        .expect("Failed to remove");
+    }
+
+    /// Locks the key and returns a [`KeyGuard`] that unlocks it when dropped.
     ///
-    /// assert!(iter.next().is_none());
+    /// While a thread holds the `KeyGuard`, the other threads inserting, updating
+    /// or removing the entry for the key wait until the guard is dropped. The
+    /// maintenance tasks do not wait for the lock; they defer the eviction and
+    /// expiration of the entry until the guard is dropped. The thread holding the
+    /// guard can call the methods of the cache on the key as usual. This is useful
+    /// for a read-modify-write flow that does not fit into a single
+    /// [`and_compute_with`][compute-with] closure.
+    ///
+    /// The methods reading the entry, such as `get`, do not wait for the lock. The
+    /// compute methods, such as `and_compute_with`, hold the lock while they read
+    /// the entry, evaluate the closure and write the result.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::builder().support_key_locks().build();
+    /// cache.insert("balance", 100);
+    ///
+    /// {
+    ///     let _guard = cache.lock_key(&"balance").unwrap();
+    ///     // No other thread can update the balance until the guard is dropped.
+    ///     let balance = cache.get(&"balance").unwrap();
+    ///     cache.insert("balance", balance - 30);
+    /// }
+    ///
+    /// assert_eq!(cache.get(&"balance"), Some(70));
     /// ```
     ///
-    pub fn iter(&self) -> Iter<'_, K, V> {
-        Iter::with_single_cache_segment(&self.base, self.num_cht_segments())
+    /// [compute-with]: ./struct.OwnedKeyEntrySelector.html#method.and_compute_with
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub fn lock_key<Q>(&self, key: &Q) -> Result<KeyGuard<'_, K, S>, KeyLockError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        self.base.lock_key(Arc::new(key.to_owned()))
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    pub fn invalidate_all(&self) {
+        self.base.invalidate_all();
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
+    /// method returns immediately and a background thread will apply the closure to
+    /// each cached value inserted before the time when `invalidate_entries_if` was
+    /// called. If the closure returns `true` on a value, that value will be evicted
+    /// from the cache.
+    ///
+    /// Also the `get` method will apply the closure to a value to determine if it
+    /// should have been invalidated. Therefore, it is guaranteed that the `get`
+    /// method must not return invalidated values.
+    ///
+    /// Note that you must call
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
+    /// at the cache creation time as the cache needs to maintain additional internal
+    /// data structures to support this method. Otherwise, calling this method will
+    /// fail with a
+    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
+    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(Arc::new(predicate))
+    }
+
+    /// Returns the status of a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method.
+    ///
+    /// Returns `None` if there is no predicate with the given ID. The status of a
+    /// finished (completed or cancelled) predicate is kept for a while after it has
+    /// finished; the cache remembers the status of the most recent 1024 finished
+    /// predicates.
+    ///
+    /// See [`PredicateStatus`][predicate-status] for the details of the status.
+    ///
+    /// [predicate-status]: ../enum.PredicateStatus.html
+    pub fn predicate_status(&self, id: &str) -> Option<PredicateStatus> {
+        self.base.predicate_status(id)
+    }
+
+    /// Cancels a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method.
+    ///
+    /// After the cancellation, the predicate will no longer be applied to the
+    /// cached entries. Entries that have already been invalidated by the predicate
+    /// are not restored.
+    ///
+    /// Returns `true` if the predicate was pending or in progress and has been
+    /// cancelled. Returns `false` if there is no such predicate or it has already
+    /// finished.
+    pub fn cancel_predicate(&self, id: &str) -> bool {
+        self.base.cancel_predicate(id)
+    }
+
+    /// Waits until a predicate registered by the
+    /// [`invalidate_entries_if`](#method.invalidate_entries_if) method has
+    /// finished, and returns its final status.
+    ///
+    /// This method runs the pending maintenance tasks of the cache until the scan
+    /// for the predicate is completed, so it can take a while if the cache has many
+    /// entries. Returns `None` if there is no predicate with the given ID.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::{sync::Cache, PredicateStatus};
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .support_invalidation_closures()
+    ///     .build();
+    /// for i in 0..10 {
+    ///     cache.insert(i, i * 10);
+    /// }
+    ///
+    /// let id = cache.invalidate_entries_if(|_k, v| v % 20 == 0)?;
+    ///
+    /// let status = cache.wait_predicate(&id);
+    /// assert_eq!(
+    ///     status,
+    ///     Some(PredicateStatus::Completed {
+    ///         scanned: 10,
+    ///         removed: 5
+    ///     })
+    /// );
+    /// # Ok::<(), moka2::PredicateError>(())
+    /// ```
+    pub fn wait_predicate(&self, id: &str) -> Option<PredicateStatus> {
+        loop {
+            match self.predicate_status(id) {
+                Some(status) if !status.is_finished() => self.run_pending_tasks(),
+                status => return status,
+            }
+        }
+    }
+
+    /// Discards all cached entries having the given tag.
+    ///
+    /// The tags of the entries are given by the tag extractor closure set by
+    /// [`CacheBuilder::tags`][builder-tags] method. This method does nothing if the
+    /// cache does not have a tag extractor.
+    ///
+    /// Unlike the [`invalidate_entries_if`](#method.invalidate_entries_if) method,
+    /// this method looks up the entries in an index maintained by the cache, and
+    /// discards them before returning. It takes time proportional to the number of
+    /// the entries having the tag. The index includes the entries that have been
+    /// just inserted, so they are discarded even if the pending maintenance tasks
+    /// have not been run yet. The eviction listener is called for every discarded
+    /// entry with `RemovalCause::Explicit`.
+    ///
+    /// Entries inserted or updated concurrently with this method may or may not
+    /// be discarded.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// // The keys are `(tenant, record)` pairs.
+    /// let cache: Cache<(&str, u32), String> = Cache::builder()
+    ///     .max_capacity(100)
+    ///     .tags(|(tenant, _record), _value| vec![format!("tenant:{tenant}").into()].into())
+    ///     .build();
+    ///
+    /// cache.insert(("alice", 0), "a0".to_string());
+    /// cache.insert(("alice", 1), "a1".to_string());
+    /// cache.insert(("bob", 0), "b0".to_string());
+    ///
+    /// cache.invalidate_tag("tenant:alice");
+    ///
+    /// assert!(cache.get(&("alice", 0)).is_none());
+    /// assert!(cache.get(&("alice", 1)).is_none());
+    /// assert_eq!(cache.get(&("bob", 0)), Some("b0".to_string()));
+    /// ```
+    ///
+    /// [builder-tags]: ./struct.CacheBuilder.html#method.tags
+    pub fn invalidate_tag(&self, tag: &str) {
+        let Some(keys) = self.base.keys_for_tag(tag) else {
+            return;
+        };
+        for key in keys {
+            let hash = self.base.hash(&*key);
+            self.remove_with_hash_if(&*key, hash, |v| self.base.has_tag(&key, v, tag));
+        }
+    }
+
+    /// Declares that the entry for `key` depends on the entries for
+    /// `dependencies`.
+    ///
+    /// When any of the dependencies is invalidated, expired or evicted, the entry
+    /// for `key` is discarded too, and so are the entries depending on it. The
+    /// eviction listener is called for these entries with
+    /// `RemovalCause::Cascaded`. Dependency cycles are allowed; every entry in a
+    /// cycle is discarded at most once.
+    ///
+    /// Returns `false` if the cache does not have `key`, or if it does not have
+    /// some of the dependencies. In the latter case, the entry for `key` is
+    /// discarded immediately as if the missing dependencies had just been
+    /// invalidated.
+    ///
+    /// The dependencies are also recorded automatically for the `get_with` family
+    /// of methods: the entries read from this cache (by `get`, `get_with` and so
+    /// on) while the `init` closure is running on the same thread become the
+    /// dependencies of the inserted entry.
+    ///
+    /// Dependencies are held per key. Updating the value of a key does not
+    /// discard its dependents, and does not drop its own dependencies.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("config", "v1".to_string());
+    ///
+    /// // Explicitly declare the dependency.
+    /// cache.insert("page1", "page1 rendered with v1".to_string());
+    /// assert!(cache.add_dependencies("page1", ["config"]));
+    ///
+    /// // Or let `get_with` record it automatically.
+    /// let page2 = cache.get_with("page2", || {
+    ///     let config = cache.get("config").unwrap();
+    ///     format!("page2 rendered with {config}")
+    /// });
+    /// assert_eq!(page2, "page2 rendered with v1");
+    ///
+    /// cache.invalidate("config");
+    ///
+    /// assert!(cache.get("page1").is_none());
+    /// assert!(cache.get("page2").is_none());
+    /// ```
+    pub fn add_dependencies<'a, Q>(
+        &self,
+        key: &Q,
+        dependencies: impl IntoIterator<Item = &'a Q>,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+    {
+        let hash = self.base.hash(key);
+        let Some(key) = self.base.get_key_with_hash(key, hash) else {
+            return false;
+        };
+
+        let mut deps = Vec::new();
+        for dep in dependencies {
+            match self.base.get_key_with_hash(dep, self.base.hash(dep)) {
+                Some(dep) => deps.push(dep),
+                None => {
+                    self.invalidate_cascaded(&key, hash);
+                    return false;
+                }
+            }
+        }
+        self.add_dependencies_with_hash(&key, hash, deps)
+    }
+
+    fn add_dependencies_with_hash(&self, key: &Arc<K>, hash: u64, deps: Vec<Arc<K>>) -> bool {
+        if deps.is_empty() {
+            return true;
+        }
+        self.base.add_dependencies(key, &deps);
+
+        // A dependency may have been removed before the edges were added. Then its
+        // dependents have already been taken, so discard the entry here.
+        let all_present = deps.iter().all(|dep| {
+            self.base
+                .contains_key_with_hash(&**dep, self.base.hash(&**dep))
+        });
+        if !all_present {
+            self.invalidate_cascaded(key, hash);
+        }
+        all_present
+    }
+
+    fn invalidate_cascaded(&self, key: &Arc<K>, hash: u64) {
+        self.remove_and_notify(&**key, hash, RemovalCause::Cascaded, || {
+            self.base.remove_entry(&**key, hash)
+        });
+        self.invalidate_dependents(key);
+    }
+
+    /// Runs `init` while recording the keys read from this cache, and stores them
+    /// to `deps`.
+    fn track_init<O>(&self, init: impl FnOnce() -> O, deps: &RefCell<Vec<Arc<K>>>) -> O {
+        let (output, keys) = self.base.track_reads(init);
+        *deps.borrow_mut() = keys;
+        output
+    }
+
+    /// Makes the inserted entry depend on the keys read by its `init` closure, and
+    /// records the key as read by the enclosing `init` closure, if any.
+    fn add_tracked_dependencies(&self, key: &Arc<K>, hash: u64, deps: &RefCell<Vec<Arc<K>>>) {
+        self.base.record_read(key);
+        self.add_dependencies_with_hash(key, hash, deps.take());
+    }
+
+    pub(crate) fn invalidate_entries_with_arc_fun<F>(
+        &self,
+        predicate: Arc<F>,
+    ) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(predicate)
+    }
+
+    /// Creates an iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
+    /// value.
+    ///
+    /// Iterators do not block concurrent reads and writes on the cache. An entry can
+    /// be inserted to, invalidated or evicted from a cache while iterators are alive
+    /// on the same cache.
+    ///
+    /// Unlike the `get` method, visiting entries via an iterator do not update the
+    /// historic popularity estimator or reset idle timers for keys.
+    ///
+    /// # Guarantees
+    ///
+    /// In order to allow concurrent access to the cache, iterator's `next` method
+    /// does _not_ guarantee the following:
+    ///
+    /// - It does not guarantee to return a key-value pair (an entry) if its key has
+    ///   been inserted to the cache _after_ the iterator was created.
+    ///   - Such an entry may or may not be returned depending on key's hash and
+    ///     timing.
+    ///
+    /// and the `next` method guarantees the followings:
+    ///
+    /// - It guarantees not to return the same entry more than once.
+    /// - It guarantees not to return an entry if it has been removed from the cache
+    ///   after the iterator was created.
+    ///     - Note: An entry can be removed by following reasons:
+    ///         - Manually invalidated.
+    ///         - Expired (e.g. time-to-live).
+    ///         - Evicted as the cache capacity exceeded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("Julia", 14);
+    ///
+    /// let mut iter = cache.iter();
+    /// let (k, v) = iter.next().unwrap(); // (Arc<K>, V)
+    /// assert_eq!(*k, "Julia");
+    /// assert_eq!(v, 14);
+    ///
+    /// assert!(iter.next().is_none());
+    /// ```
+    ///
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::with_single_cache_segment(&self.base, self.num_cht_segments())
+    }
+
+    /// Creates an iterator visiting all entries in arbitrary order, yielding a
+    /// snapshot of the metadata of each entry along with the key and value. The
+    /// iterator element type is `(Arc<K>, V, EntryMetadata)`.
+    ///
+    /// Like the [`iter`](#method.iter) method, iterating does not update the
+    /// historic popularity estimator or reset the idle timer of the entries.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("Julia", 14);
+    ///
+    /// let mut iter = cache.iter_with_metadata();
+    /// let (k, v, metadata) = iter.next().unwrap();
+    /// assert_eq!(*k, "Julia");
+    /// assert_eq!(v, 14);
+    /// assert_eq!(metadata.policy_weight(), 1);
+    ///
+    /// assert!(iter.next().is_none());
+    /// ```
+    pub fn iter_with_metadata(&self) -> IterWithMetadata<'_, K, V> {
+        IterWithMetadata::new(self.iter())
+    }
+
+    /// Creates an iterator visiting all keys in arbitrary order. The iterator
+    /// element type is `Arc<K>`.
+    ///
+    /// Unlike the [`iter`](#method.iter) method, this does not clone the values.
+    /// The same guarantees about concurrently inserted or removed entries apply.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("Julia", 14);
+    ///
+    /// let keys: Vec<_> = cache.keys().collect();
+    /// assert_eq!(keys.len(), 1);
+    /// assert_eq!(*keys[0], "Julia");
+    /// ```
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(self.iter())
+    }
+
+    /// Creates an iterator visiting all values in arbitrary order. The iterator
+    /// element type is `V`, a _clone_ of the cached value.
+    ///
+    /// The same guarantees as the [`iter`](#method.iter) method about
+    /// concurrently inserted or removed entries apply.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(self.iter())
+    }
+
+    /// Retains only the entries for which `f` returns `true`, and discards the
+    /// others immediately.
+    ///
+    /// Unlike the [`invalidate_entries_if`](#method.invalidate_entries_if) method,
+    /// this method scans the cache and discards the entries before it returns, and
+    /// does not require the cache to support invalidation closures. The eviction
+    /// listener is called for every discarded entry with
+    /// `RemovalCause::Explicit`.
+    ///
+    /// Each entry is checked and discarded atomically, but the scan is not a
+    /// snapshot of the whole cache; entries inserted or updated during the scan
+    /// may or may not be visited.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// for i in 0..10 {
+    ///     cache.insert(i, i * 10);
+    /// }
+    ///
+    /// cache.retain(|k, _v| k % 2 == 0);
+    ///
+    /// assert_eq!(cache.get(&2), Some(20));
+    /// assert!(cache.get(&3).is_none());
+    /// ```
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        self.retain_and(|k, v| f(k, v), |_, _| ());
+    }
+
+    /// Discards all entries from the cache immediately, and returns them as a
+    /// `Vec` of `(Arc<K>, V)` in arbitrary order.
+    ///
+    /// Like the [`retain`](#method.retain) method, the eviction listener is called
+    /// for every discarded entry with `RemovalCause::Explicit`. Entries that have
+    /// already expired are not returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("Julia", 14);
+    ///
+    /// let drained = cache.drain();
+    /// assert_eq!(drained.len(), 1);
+    /// assert_eq!((*drained[0].0, drained[0].1), ("Julia", 14));
+    /// assert!(cache.get(&"Julia").is_none());
+    /// ```
+    pub fn drain(&self) -> Vec<(Arc<K>, V)> {
+        let mut drained = Vec::new();
+        self.retain_and(|_, _| false, |k, v| drained.push((k, v)));
+        drained
+    }
+
+    /// Discards the entries for which `f` returns `false`, calling `on_remove`
+    /// with each discarded entry.
+    pub(crate) fn retain_and(
+        &self,
+        mut f: impl FnMut(&K, &V) -> bool,
+        mut on_remove: impl FnMut(Arc<K>, V),
+    ) {
+        let mut iter = self.iter();
+        while let Some(key) = iter.next_key() {
+            let hash = self.base.hash(&*key);
+            if let Some(v) = self.remove_with_hash_if(&*key, hash, |v| !f(&key, v)) {
+                on_remove(key, v);
+            }
+        }
+    }
+
+    /// Performs any pending maintenance operations needed by the cache.
+    pub fn run_pending_tasks(&self) {
+        if let Some(hk) = &self.base.housekeeper {
+            hk.run_pending_tasks(&*self.base.inner);
+        }
+    }
+
+    /// Shrinks the internal concurrent hash table to fit the current number of
+    /// entries, releasing the memory of the bucket arrays that are no longer
+    /// needed.
+    ///
+    /// The hash table only grows while entries are inserted. The maintenance tasks
+    /// shrink it when it has become mostly empty, but leave some room for the
+    /// entries to be inserted again. This method shrinks it further, e.g. after the
+    /// cache has been cleared and will not be refilled soon.
+    ///
+    /// The entries discarded by `invalidate_all` and `invalidate_entries_if` are
+    /// removed from the hash table by the maintenance tasks, so call
+    /// [`run_pending_tasks`](#method.run_pending_tasks) before this method to
+    /// release the memory used for them. Other threads can keep reading and
+    /// writing the cache while the hash table is being shrunk.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100_000);
+    /// for i in 0..50_000 {
+    ///     cache.insert(i, i);
+    /// }
+    /// cache.run_pending_tasks();
+    /// let grown = cache.memory_usage().hash_table();
+    ///
+    /// cache.invalidate_all();
+    /// cache.run_pending_tasks();
+    /// cache.shrink_to_fit();
+    /// assert!(cache.memory_usage().hash_table() < grown / 4);
+    /// ```
+    pub fn shrink_to_fit(&self) {
+        self.base.shrink_to_fit();
+    }
+
+    /// Returns the earliest time when an entry with per-entry expiration will
+    /// expire, or `None` if there is no such entry.
+    ///
+    /// The entries are expired by the timers, which have a resolution of about one
+    /// second. The returned time is the start of the timer tick containing the
+    /// expiration time, so it may be up to one tick earlier than the actual
+    /// expiration time. The time is given by the clock of the cache.
+    ///
+    /// Only the entries whose insertions have been processed by the maintenance
+    /// tasks are considered. Call [`run_pending_tasks`](#method.run_pending_tasks)
+    /// first to include the recently inserted entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::{sync::Cache, Expiry};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// struct MyExpiry;
+    ///
+    /// impl Expiry<&'static str, &'static str> for MyExpiry {
+    ///     fn expire_after_create(
+    ///         &self,
+    ///         _key: &&'static str,
+    ///         _value: &&'static str,
+    ///         _created_at: Instant,
+    ///     ) -> Option<Duration> {
+    ///         Some(Duration::from_secs(30))
+    ///     }
+    /// }
+    ///
+    /// let cache = Cache::builder().expire_after(MyExpiry).build();
+    /// assert_eq!(cache.next_expiration(), None);
+    ///
+    /// cache.insert("key", "value");
+    /// cache.run_pending_tasks();
+    /// let next = cache.next_expiration().unwrap();
+    /// assert!(next <= Instant::now() + Duration::from_secs(30));
+    /// ```
+    pub fn next_expiration(&self) -> Option<std::time::Instant> {
+        self.base.next_expiration()
+    }
+
+    /// Returns a snapshot of the statistics of the maintenance tasks of the cache.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("key", "value");
+    /// cache.get(&"key");
+    /// cache.run_pending_tasks();
+    ///
+    /// let stats = cache.maintenance_stats();
+    /// assert!(stats.runs() >= 1);
+    /// assert_eq!(stats.writes_applied(), 1);
+    /// assert_eq!(stats.reads_applied(), 1);
+    /// assert_eq!(stats.run_duration().count(), stats.runs());
+    /// ```
+    pub fn maintenance_stats(&self) -> MaintenanceStats {
+        self.base.maintenance_stats()
+    }
+
+    /// Writes the metrics of the cache in the [Prometheus text exposition
+    /// format][prometheus-format], labeled with `cache="<name>"`.
+    ///
+    /// The metrics are the number of entries, the weighted size and the maximum
+    /// capacity of the cache, the numbers of hits, misses, evictions (by cause) and
+    /// loads (by result), and a histogram of the load durations. Loads are the
+    /// calls to the `get_with` family methods that ran their `init` closures.
+    ///
+    /// This method does not need any crate feature. To export the same metrics to
+    /// the [`metrics`][metrics-crate] crate facade instead, enable the `metrics`
+    /// feature.
+    ///
+    /// [prometheus-format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+    /// [metrics-crate]: https://crates.io/crates/metrics
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::builder().name("users").max_capacity(100).build();
+    /// cache.get_with(1, || "alice");
+    /// cache.get(&1);
+    ///
+    /// let mut buf = Vec::new();
+    /// cache.render_prometheus(&mut buf).unwrap();
+    /// let text = String::from_utf8(buf).unwrap();
+    /// assert!(text.contains("moka_cache_hits_total{cache=\"users\"} 1"));
+    /// assert!(text.contains("moka_cache_loads_total{cache=\"users\",result=\"success\"} 1"));
+    /// ```
+    pub fn render_prometheus(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        self.base
+            .metrics_snapshot()
+            .render_prometheus(self.name(), w)
+    }
+
+    /// Returns an approximate breakdown of the memory used by the cache, such as
+    /// the concurrent hash table, the cache entries, the deques, the timer wheel
+    /// and the frequency sketch. See [`MemoryReport`][memory-report] for the
+    /// details.
+    ///
+    /// The report does not include the heap memory owned by the keys and values.
+    /// Use [`memory_usage_with_heap_size`](#method.memory_usage_with_heap_size) to
+    /// include it.
+    ///
+    /// This method briefly takes the internal locks used by the maintenance tasks,
+    /// so avoid calling it too frequently.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert(1, "one".to_string());
+    /// cache.run_pending_tasks();
+    ///
+    /// let report = cache.memory_usage();
+    /// assert!(report.entries() > 0);
+    /// assert!(report.total() > report.hash_table() + report.entries());
+    /// assert_eq!(report.key_value_heap(), None);
+    /// ```
+    ///
+    /// [memory-report]: ../struct.MemoryReport.html
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut report = self.base.memory_report();
+        self.value_initializer.record_memory_usage(&mut report);
+        report
+    }
+
+    /// Returns the same report as [`memory_usage`](#method.memory_usage), but also
+    /// includes the heap memory owned by the keys and values, reported by their
+    /// [`HeapSize`][heap-size] implementations.
+    ///
+    /// This method visits all entries in the cache, so it takes time proportional
+    /// to the number of entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka2::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert(1, "a".repeat(64));
+    ///
+    /// let report = cache.memory_usage_with_heap_size();
+    /// assert_eq!(report.key_value_heap(), Some(64));
+    /// ```
+    ///
+    /// [heap-size]: ../trait.HeapSize.html
+    pub fn memory_usage_with_heap_size(&self) -> MemoryReport
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let mut report = self.memory_usage();
+        report.key_value_heap = Some(self.base.heap_size_of_entries());
+        report
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V);
+
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+//
+// Iterator support
+//
+impl<K, V, S> ScanningGet<K, V> for Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn num_cht_segments(&self) -> usize {
+        self.base.num_cht_segments()
+    }
+
+    fn scanning_get(&self, key: &Arc<K>) -> Option<V> {
+        self.base.scanning_get(key)
+    }
+
+    fn scanning_get_with_metadata(&self, key: &Arc<K>) -> Option<(V, EntryMetadata)> {
+        self.base.scanning_get_with_metadata(key)
+    }
+
+    fn scanning_contains_key(&self, key: &Arc<K>) -> bool {
+        self.base.scanning_contains_key(key)
+    }
+
+    fn keys(&self, cht_segment: usize) -> Option<Vec<Arc<K>>> {
+        self.base.keys(cht_segment)
+    }
+}
+
+//
+// private methods
+//
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    // TODO: Like future::Cache, move this method to BaseCache.
+    #[inline]
+    fn schedule_write_op(
+        inner: &impl InnerSync,
+        ch: &Sender<WriteOp<K, V>>,
+        op: WriteOp<K, V>,
+        now: Instant,
+        housekeeper: Option<&HouseKeeperArc>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let mut op = op;
+
+        // NOTES:
+        // - This will block when the channel is full.
+        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
+        //   but we got a notable performance degradation.
+        loop {
+            BaseCache::<K, V, S>::apply_reads_writes_if_needed(inner, ch, now, housekeeper);
+            match ch.try_send(op) {
+                Ok(()) => break,
+                Err(TrySendError::Full(op1)) => {
+                    op = op1;
+                    inner.maintenance_counters().record_write_op_retry();
+                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+                }
+                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// For unit tests.
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S> {
+    pub(crate) fn is_table_empty(&self) -> bool {
+        self.entry_count() == 0
+    }
+
+    pub(crate) fn is_waiter_map_empty(&self) -> bool {
+        self.value_initializer.waiter_count() == 0
+    }
+}
+
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn invalidation_predicate_count(&self) -> usize {
+        self.base.invalidation_predicate_count()
+    }
+
+    pub(crate) fn reconfigure_for_testing(&mut self) {
+        self.base.reconfigure_for_testing();
+    }
+
+    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
+        self.base.set_expiration_clock(clock);
+    }
+
+    pub(crate) fn key_locks_map_is_empty(&self) -> bool {
+        self.base.key_locks_map_is_empty()
+    }
+}
+
+// To see the debug prints, run test as `cargo test -- --nocapture`
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use crate::{
+        common::{time::Clock, HousekeeperConfig},
+        notification::RemovalCause,
+        policy::{test_utils::ExpiryCallCounters, EvictionPolicy},
+        Expiry, PredicateStatus,
+    };
+
+    use parking_lot::Mutex;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicU8, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant as StdInstant},
+    };
+
+    #[test]
+    fn max_capacity_zero() {
+        let mut cache = Cache::new(0);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, ());
+
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).is_none());
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&0));
+        assert!(cache.get(&0).is_none());
+        assert_eq!(cache.entry_count(), 0)
+    }
+
+    #[test]
+    fn basic_single_thread() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1
+
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        assert!(cache.contains_key(&"c"));
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks();
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks();
+        // counts: a -> 2, b -> 2, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "david"); //   count: d -> 0
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None); //   d -> 1
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", "david");
+        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d"), None); //   d -> 2
+
+        // "d" should be admitted and "c" should be evicted
+        // because d's frequency is higher than c's.
+        cache.insert("d", "dennis");
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some("dennis"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        cache.invalidate(&"b");
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"b"), None);
+        assert!(!cache.contains_key(&"b"));
+
+        assert!(cache.remove(&"b").is_none());
+        assert_eq!(cache.remove(&"d"), Some("dennis"));
+        expected.push((Arc::new("d"), "dennis", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None);
+        assert!(!cache.contains_key(&"d"));
+
+        verify_notification_vec(&cache, actual, &expected);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn basic_lru_single_thread() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .eviction_policy(EvictionPolicy::lru())
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // a -> b
+
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        assert!(cache.contains_key(&"c"));
+        cache.run_pending_tasks();
+        // a -> b -> c
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks();
+        // c -> a -> b
+
+        // "d" should be admitted because the cache uses the LRU strategy.
+        cache.insert("d", "david");
+        // "c" is the LRU and should have be evicted.
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some("david"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+        cache.run_pending_tasks();
+        // a -> b -> d
+
+        cache.invalidate(&"b");
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+        // a -> d
+        assert_eq!(cache.get(&"b"), None);
+        assert!(!cache.contains_key(&"b"));
+
+        assert!(cache.remove(&"b").is_none());
+        assert_eq!(cache.remove(&"d"), Some("david"));
+        expected.push((Arc::new("d"), "david", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+        // a
+        assert_eq!(cache.get(&"d"), None);
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("e", "emily");
+        cache.insert("f", "frank");
+        // "a" should be evicted because it is the LRU.
+        cache.insert("g", "gina");
+        expected.push((Arc::new("a"), "alice", RemovalCause::Size));
+        cache.run_pending_tasks();
+        // e -> f -> g
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"e"), Some("emily"));
+        assert_eq!(cache.get(&"f"), Some("frank"));
+        assert_eq!(cache.get(&"g"), Some("gina"));
+        assert!(!cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"e"));
+        assert!(cache.contains_key(&"f"));
+        assert!(cache.contains_key(&"g"));
+
+        verify_notification_vec(&cache, actual, &expected);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn size_aware_eviction() {
+        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
+
+        let alice = ("alice", 10);
+        let bob = ("bob", 15);
+        let bill = ("bill", 20);
+        let cindy = ("cindy", 5);
+        let david = ("david", 15);
+        let dennis = ("dennis", 15);
+
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(31)
+            .weigher(weigher)
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", alice);
+        cache.insert("b", bob);
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        cache.run_pending_tasks();
+        // order (LRU -> MRU) and counts: a -> 1, b -> 1
+
+        cache.insert("c", cindy);
+        assert_eq!(cache.get(&"c"), Some(cindy));
+        assert!(cache.contains_key(&"c"));
+        // order and counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks();
+
+        assert!(cache.contains_key(&"a"));
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert!(cache.contains_key(&"b"));
+        cache.run_pending_tasks();
+        // order and counts: c -> 1, a -> 2, b -> 2
+
+        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
+        // "d" must have higher count than 3, which is the aggregated count
+        // of "a" and "c".
+        cache.insert("d", david); //   count: d -> 0
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None); //   d -> 1
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", david);
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d"), None); //   d -> 2
+
+        cache.insert("d", david);
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None); //   d -> 3
+        assert!(!cache.contains_key(&"d"));
+
+        cache.insert("d", david);
+        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"d"), None); //   d -> 4
+
+        // Finally "d" should be admitted by evicting "c" and "a".
+        cache.insert("d", dennis);
+        expected.push((Arc::new("c"), cindy, RemovalCause::Size));
+        expected.push((Arc::new("a"), alice, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some(dennis));
+        assert!(!cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
+        cache.insert("b", bill);
+        expected.push((Arc::new("b"), bob, RemovalCause::Replaced));
+        expected.push((Arc::new("d"), dennis, RemovalCause::Size));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"b"), Some(bill));
+        assert_eq!(cache.get(&"d"), None);
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"d"));
+
+        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
+        cache.insert("a", alice);
+        cache.insert("b", bob);
+        expected.push((Arc::new("b"), bill, RemovalCause::Replaced));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some(alice));
+        assert_eq!(cache.get(&"b"), Some(bob));
+        assert_eq!(cache.get(&"d"), None);
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"d"));
+
+        // Verify the sizes.
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.weighted_size(), 25);
+
+        verify_notification_vec(&cache, actual, &expected);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn basic_multi_threads() {
+        let num_threads = 4;
+        let cache = Cache::new(100);
+
+        // https://rust-lang.github.io/rust-clippy/master/index.html#needless_collect
+        #[allow(clippy::needless_collect)]
+        let handles = (0..num_threads)
+            .map(|id| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    cache.insert(10, format!("{id}-100"));
+                    cache.get(&10);
+                    cache.insert(20, format!("{id}-200"));
+                    cache.invalidate(&10);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        assert!(cache.get(&10).is_none());
+        assert!(cache.get(&20).is_some());
+        assert!(!cache.contains_key(&10));
+        assert!(cache.contains_key(&20));
+    }
+
+    #[test]
+    fn invalidate_all() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(cache.contains_key(&"c"));
+
+        // `cache.run_pending_tasks()` is no longer needed here before invalidating. The last
+        // modified timestamp of the entries were updated when they were inserted.
+        // https://github.com/moka-rs/moka/issues/155
+
+        cache.invalidate_all();
+        expected.push((Arc::new("a"), "alice", RemovalCause::Explicit));
+        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+        assert_eq!(cache.get(&"d"), Some("david"));
+        assert!(!cache.contains_key(&"a"));
+        assert!(!cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+        assert!(cache.contains_key(&"d"));
+
+        verify_notification_vec(&cache, actual, &expected);
+    }
+
+    #[test]
+    fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        use std::collections::HashSet;
+
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice");
+        cache.insert(1, "bob");
+        cache.insert(2, "alex");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&0), Some("alice"));
+        assert_eq!(cache.get(&1), Some("bob"));
+        assert_eq!(cache.get(&2), Some("alex"));
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+
+        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
+        let id = cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
+        assert_eq!(cache.base.invalidation_predicate_count(), 1);
+        expected.push((Arc::new(0), "alice", RemovalCause::Explicit));
+        expected.push((Arc::new(2), "alex", RemovalCause::Explicit));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        cache.insert(3, "alice");
+
+        // Run the invalidation task and wait for it to finish.
+        assert!(matches!(
+            cache.wait_predicate(&id),
+            Some(PredicateStatus::Completed { .. })
+        ));
+
+        assert!(cache.get(&0).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&1), Some("bob"));
+        // This should survive as it was inserted after calling invalidate_entries_if.
+        assert_eq!(cache.get(&3), Some("alice"));
+
+        assert!(!cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+
+        let id1 = cache.invalidate_entries_if(|_k, &v| v == "alice")?;
+        let id2 = cache.invalidate_entries_if(|_k, &v| v == "bob")?;
+        assert_eq!(cache.invalidation_predicate_count(), 2);
+        // key 1 was inserted before key 3.
+        expected.push((Arc::new(1), "bob", RemovalCause::Explicit));
+        expected.push((Arc::new(3), "alice", RemovalCause::Explicit));
+
+        // Run the invalidation task and wait for it to finish.
+        assert!(matches!(
+            cache.wait_predicate(&id1),
+            Some(PredicateStatus::Completed { .. })
+        ));
+        assert!(matches!(
+            cache.wait_predicate(&id2),
+            Some(PredicateStatus::Completed { .. })
+        ));
+
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&3).is_none());
+
+        assert!(!cache.contains_key(&1));
+        assert!(!cache.contains_key(&3));
+
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        verify_notification_vec(&cache, actual, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalidate_tag() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Tag each entry with its value.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .tags(|_k, v: &&str| vec![(*v).into()].into())
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(1, "red");
+        cache.insert(2, "red");
+        cache.insert(3, "blue");
+        cache.run_pending_tasks();
+
+        // Change the tag of key 2 without running the pending tasks.
+        cache.insert(2, "blue");
+        expected.push((Arc::new(2), "red", RemovalCause::Replaced));
+
+        cache.invalidate_tag("red");
+        expected.push((Arc::new(1), "red", RemovalCause::Explicit));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.get(&2), Some("blue"));
+
+        cache.invalidate_tag("green");
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 2);
+
+        cache.invalidate_tag("blue");
+        expected.push((Arc::new(2), "blue", RemovalCause::Explicit));
+        expected.push((Arc::new(3), "blue", RemovalCause::Explicit));
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        // A removed entry is no longer indexed.
+        cache.insert(4, "red");
+        cache.invalidate(&4);
+        expected.push((Arc::new(4), "red", RemovalCause::Explicit));
+        cache.insert(5, "yellow");
+        cache.run_pending_tasks();
+        cache.invalidate_tag("red");
+        assert_eq!(cache.entry_count(), 1);
+
+        // The entries are visited in arbitrary order, so sort the notifications
+        // for the "blue" tag.
+        actual.lock()[2..4].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
+        verify_notification_vec(&cache, actual, &expected);
+
+        // A cache without a tag extractor does nothing.
+        let cache = Cache::new(100);
+        cache.insert(1, "red");
+        cache.invalidate_tag("red");
+        assert_eq!(cache.get(&1), Some("red"));
+    }
+
+    #[test]
+    fn invalidate_tag_before_maintenance() {
+        // Tag each entry with its value.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .tags(|_k, v: &&str| vec![(*v).into()].into())
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // None of the entries are admitted to the cache policies yet. Key 3 is
+        // updated from "red" to "blue".
+        cache.insert(1, "red");
+        cache.insert(2, "blue");
+        cache.insert(3, "red");
+        cache.insert(3, "blue");
+        assert_eq!(cache.base.write_op_ch.len(), 4);
+
+        cache.invalidate_tag("red");
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+
+        // The pending write ops were not applied. (One op was added for the
+        // removal)
+        assert_eq!(cache.base.write_op_ch.len(), 5);
+
+        cache.invalidate_tag("blue");
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+
+        // The removed entries are no longer indexed.
+        assert_eq!(cache.base.keys_for_tag("red"), Some(vec![]));
+        assert_eq!(cache.base.keys_for_tag("blue"), Some(vec![]));
+    }
+
+    #[test]
+    fn dependencies() {
+        // The following `Vec`s will hold actual and expected notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(listener)
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 1..=6 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+
+        // 3 -> 2 -> 1 -> 3 (cycle)
+        assert!(cache.add_dependencies(&2, [&1]));
+        assert!(cache.add_dependencies(&3, [&2]));
+        assert!(cache.add_dependencies(&1, [&3]));
+
+        cache.invalidate(&1);
+        expected.push((Arc::new(1), 10, RemovalCause::Explicit));
+        expected.push((Arc::new(2), 20, RemovalCause::Cascaded));
+        expected.push((Arc::new(3), 30, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&2));
+        assert!(!cache.contains_key(&3));
+
+        // A missing dependency discards the dependent immediately.
+        assert!(!cache.add_dependencies(&4, [&5, &99]));
+        expected.push((Arc::new(4), 40, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&4));
+
+        // A missing dependent is ignored.
+        assert!(!cache.add_dependencies(&100, [&5]));
+        assert!(cache.contains_key(&5));
+
+        // The dependencies are recorded by `get_with`. 8 depends on 7, and 7
+        // depends on 6.
+        let v = cache.get_with(8, || cache.get_with(7, || cache.get(&6).unwrap() + 1) + 1);
+        assert_eq!(v, 62);
+
+        cache.invalidate(&6);
+        expected.push((Arc::new(6), 60, RemovalCause::Explicit));
+        expected.push((Arc::new(7), 61, RemovalCause::Cascaded));
+        expected.push((Arc::new(8), 62, RemovalCause::Cascaded));
+        assert!(!cache.contains_key(&7));
+        assert!(!cache.contains_key(&8));
+
+        // Expiration cascades to the dependents.
+        mock.increment(Duration::from_secs(5)); // 5 secs.
+        assert_eq!(cache.get_with(9, || cache.get(&5).unwrap() + 1), 51);
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(6)); // 11 secs.
+        cache.run_pending_tasks();
+        expected.push((Arc::new(5), 50, RemovalCause::Expired));
+        expected.push((Arc::new(9), 51, RemovalCause::Cascaded));
+        assert_eq!(cache.entry_count(), 0);
+
+        verify_notification_vec(&cache, actual, &expected);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn custom_clock() {
+        use crate::clock::{CacheClock, MockClock};
+
+        // An expiry that checks the current time given by the cache.
+        struct MyExpiry {
+            clock: MockClock,
+        }
+
+        impl Expiry<&str, &str> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                _value: &&str,
+                current_time: StdInstant,
+            ) -> Option<Duration> {
+                assert_eq!(current_time, self.clock.now());
+                Some(Duration::from_secs(10))
+            }
+        }
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let cache = Cache::builder()
+            .max_capacity(100)
+            .expire_after(MyExpiry {
+                clock: clock.clone(),
+            })
+            .clock(clock.clone())
+            .build();
+
+        cache.insert("a", "alice");
+        clock.increment(Duration::from_secs(9)); // 9 secs from the start.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        cache.insert("b", "bob");
+        clock.increment(Duration::from_secs(1)); // 10 secs.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+
+        // Setting the time backward does not bring expired entries back.
+        clock.set(start);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+
+        clock.set(start + Duration::from_secs(19)); // 19 secs.
+        assert_eq!(cache.get(&"b"), None);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn insert_expiring_at() {
+        use crate::clock::{CacheClock, MockClock};
+
+        let clock = MockClock::new();
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .clock(clock.clone())
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let start = clock.system_now();
+        let secs = Duration::from_secs;
+
+        assert!(cache.insert_expiring_at("a", "alice", start + secs(10)));
+        assert!(cache.insert_expiring_at("b", "bob", start + secs(20)));
+        // Deadlines that have already passed are refused.
+        assert!(!cache.insert_expiring_at("c", "cindy", start - secs(1)));
+        assert!(!cache.insert_expiring_at("d", "david", start));
+        assert!(!cache.contains_key(&"c"));
+        assert!(!cache.contains_key(&"d"));
+
+        // The deadline of "e" is discarded by the following update.
+        assert!(cache.insert_expiring_at("e", "emily", start + secs(10)));
+        cache.insert("e", "eve");
+        cache.run_pending_tasks();
+
+        clock.increment(secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        // The wall clock jumps 10 secs forward.
+        clock.set_system_time(start + secs(15));
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"e"), Some("eve"));
+
+        clock.increment(secs(4)); // 19 secs by the wall clock.
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        clock.increment(secs(1)); // 20 secs.
+        assert_eq!(cache.get(&"b"), None);
+
+        // The wall clock jumps backward.
+        assert!(cache.insert_expiring_at("f", "frank", start + secs(30)));
+        cache.run_pending_tasks();
+        clock.set_system_time(start);
+        cache.run_pending_tasks();
+        clock.increment(secs(20)); // 20 secs by the wall clock.
+        assert_eq!(cache.get(&"f"), Some("frank"));
+        clock.increment(secs(10)); // 30 secs.
+        assert_eq!(cache.get(&"f"), None);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[test]
+    fn maintenance_scheduler() {
+        use crate::sync::MaintenanceScheduler;
+
+        // Waits for the scheduler thread without asserting how soon it runs. The
+        // long timeout only matters when the test fails.
+        fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+            let deadline = StdInstant::now() + Duration::from_secs(30);
+            while StdInstant::now() < deadline {
+                if condition() {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            condition()
+        }
+
+        let scheduler = MaintenanceScheduler::thread().interval(Duration::from_millis(20));
+
+        // The following `Vec` will hold actual notifications.
+        let actual = Arc::new(Mutex::new(Vec::new()));
+
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+
+        // Create a cache with the eviction listener and the scheduler.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(listener)
+            .maintenance_scheduler(scheduler.clone())
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        mock.increment(Duration::from_secs(10));
+
+        // The expired entry is removed without calling any method of the cache.
+        assert!(wait_until(|| !actual.lock().is_empty()));
+        assert_eq!(
+            *actual.lock(),
+            vec![(Arc::new("a"), "alice", RemovalCause::Expired)]
+        );
+        assert_eq!(cache.entry_count(), 0);
+
+        // The scheduler does not keep the cache alive.
+        let value = Arc::new(());
+        let cache = Cache::builder()
+            .maintenance_scheduler(scheduler)
+            .segments(4)
+            .build();
+        cache.insert(0, Arc::clone(&value));
+        drop(cache);
+        assert!(wait_until(|| Arc::strong_count(&value) == 1));
+    }
+
+    #[test]
+    fn growing_hash_table_is_not_shrunk() {
+        const INITIAL_CAPACITY: usize = 50_000;
+        const NUM_KEYS: u32 = 1_000;
+
+        let cache = Cache::builder().initial_capacity(INITIAL_CAPACITY).build();
+        let capacity = cache.base.inner.cache.capacity();
+
+        // The load factor of the hash table stays below the shrink threshold, but
+        // the maintenance tasks do not rehash it as no entry has been removed.
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i);
+            if i % 100 == 0 {
+                cache.run_pending_tasks();
+            }
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.base.inner.cache.capacity(), capacity);
+
+        // Updating the entries does not count as removals either.
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i + 1);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.base.inner.cache.capacity(), capacity);
+
+        // The hash table is shrunk once most of the entries have been removed.
+        for i in 10..NUM_KEYS {
+            cache.invalidate(&i);
+        }
+        cache.run_pending_tasks();
+        assert!(cache.base.inner.cache.capacity() < capacity);
+        assert_eq!(cache.entry_count(), 10);
+    }
+
+    #[test]
+    fn shrink_hash_table() {
+        const NUM_KEYS: u32 = 50_000;
+        const NUM_RETAINED: u32 = 1_000;
+
+        let cache = Cache::new(NUM_KEYS as u64);
+
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        let grown = cache.memory_usage().hash_table();
+
+        // The maintenance tasks shrink the hash table after most of the entries
+        // have been removed.
+        for i in NUM_RETAINED..NUM_KEYS {
+            cache.invalidate(&i);
+        }
+        cache.run_pending_tasks();
+        let shrunk = cache.memory_usage().hash_table();
+        assert!(shrunk < grown / 4);
+
+        // `shrink_to_fit` shrinks it further.
+        cache.shrink_to_fit();
+        assert!(cache.memory_usage().hash_table() <= shrunk);
+        assert_eq!(cache.entry_count(), NUM_RETAINED as u64);
+        for i in 0..NUM_RETAINED {
+            assert_eq!(cache.get(&i), Some(i));
+        }
+
+        cache.invalidate_all();
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        cache.shrink_to_fit();
+        assert!(cache.memory_usage().hash_table() <= shrunk);
+    }
+
+    #[test]
+    fn lock_key() {
+        use crate::KeyLockError;
+        use std::sync::atomic::AtomicBool;
+
+        let cache = Cache::new(100);
+        cache.insert(0, 0);
+        assert!(matches!(
+            cache.lock_key(&0),
+            Err(KeyLockError::KeyLocksDisabled)
+        ));
+
+        let cache = Cache::builder().support_key_locks().build();
+        cache.insert(0, 0);
+
+        let guard = cache.lock_key(&0).unwrap();
+        assert_eq!(**guard.key(), 0);
+
+        // Another thread waits for the guard to be dropped before updating the key.
+        let updated = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let cache = cache.clone();
+            let updated = Arc::clone(&updated);
+            move || {
+                cache.insert(0, 10);
+                updated.store(true, Ordering::Release);
+            }
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!updated.load(Ordering::Acquire));
+
+        // The thread holding the guard can update the key, and the other keys are
+        // not locked.
+        let v = cache.get(&0).unwrap();
+        cache.insert(0, v + 1);
+        assert_eq!(cache.get(&0), Some(1));
+        cache.insert(1, 1);
+        cache.invalidate(&1);
+
+        drop(guard);
+        handle.join().expect("Failed to join");
+        assert!(updated.load(Ordering::Acquire));
+        assert_eq!(cache.get(&0), Some(10));
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn lock_key_does_not_block_maintenance() {
+        use std::sync::mpsc;
+
+        const WRITE_LOG_CAPACITY: usize = 64;
+
+        let cache = Cache::builder()
+            .max_capacity(10)
+            .eviction_policy(EvictionPolicy::lru())
+            .write_log_capacity(WRITE_LOG_CAPACITY)
+            .support_key_locks()
+            .build();
+        cache.insert(0, 0);
+        cache.run_pending_tasks();
+
+        let guard = cache.lock_key(&0).unwrap();
+
+        // Another thread inserts more entries than the write log can hold. The key
+        // 0 is the first victim of the eviction, but the maintenance tasks must
+        // skip it instead of waiting for the guard. Otherwise, the inserts will
+        // wait forever for the write log to have room.
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn({
+            let cache = cache.clone();
+            move || {
+                for i in 1..=(WRITE_LOG_CAPACITY as u32 * 4) {
+                    cache.insert(i, i);
+                }
+                cache.run_pending_tasks();
+                tx.send(()).unwrap();
+            }
+        });
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("Inserts were blocked by the key lock");
+
+        // The locked key has not been evicted.
+        assert_eq!(cache.get(&0), Some(0));
+
+        drop(guard);
+        cache.run_pending_tasks();
+        assert!(cache.entry_count() <= 10);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn lock_key_keeps_oversize_candidate_out_of_policy() {
+        const MAX_CAPACITY: u64 = 10;
+
+        let cache = Cache::builder()
+            .max_capacity(MAX_CAPACITY)
+            .weigher(|_k, v: &u32| *v)
+            .support_key_locks()
+            .build();
+        cache.insert(0, 5);
+        cache.run_pending_tasks();
+
+        // Insert a value heavier than the max capacity while holding the lock. The
+        // candidate cannot be rejected yet, but it must not be admitted either.
+        let guard = cache.lock_key(&1).unwrap();
+        cache.insert(1, 20);
+        cache.run_pending_tasks();
+        assert!(cache.weighted_size() <= MAX_CAPACITY);
+        assert_eq!(cache.get(&0), Some(5));
+
+        // The candidate is rejected once the lock is released.
+        drop(guard);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.weighted_size(), 5);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn lock_key_and_invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::mpsc;
+
+        let cache = Cache::builder()
+            .support_invalidation_closures()
+            .support_key_locks()
+            .build();
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+
+        // Another thread holds the lock, as the lock is reentrant for this thread.
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (unlock_tx, unlock_rx) = mpsc::channel::<()>();
+        let handle = std::thread::spawn({
+            let cache = cache.clone();
+            move || {
+                let _guard = cache.lock_key(&0).unwrap();
+                locked_tx.send(()).unwrap();
+                unlock_rx.recv().unwrap();
+            }
+        });
+        locked_rx.recv()?;
+
+        // The locked entry is skipped, and the predicate is kept to retry it.
+        cache.invalidate_entries_if(|_k, _v| true)?;
+        for _ in 0..3 {
+            cache.run_pending_tasks();
+            assert_eq!(cache.entry_count(), 1);
+            assert_eq!(cache.invalidation_predicate_count(), 1);
+        }
+
+        unlock_tx.send(())?;
+        handle.join().expect("Failed to join");
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn lock_key_and_compute_with() {
+        use crate::ops::compute::Op;
+
+        const NUM_UPDATES: u32 = 500;
+
+        let cache = Cache::builder().support_key_locks().build();
+        cache.insert(0, 0);
+
+        // Two threads increment the value under a `KeyGuard`, and the other two
+        // threads increment it by `and_compute_with`. No increment must be lost.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..NUM_UPDATES {
+                        if i % 2 == 0 {
+                            let _guard = cache.lock_key(&0).unwrap();
+                            let v = cache.get(&0).unwrap();
+                            std::thread::yield_now();
+                            cache.insert(0, v + 1);
+                        } else {
+                            cache.entry(0).and_compute_with(|entry| {
+                                let v = *entry.unwrap().value();
+                                std::thread::yield_now();
+                                Op::Put(v + 1)
+                            });
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+
+        assert_eq!(cache.get(&0), Some(NUM_UPDATES * 4));
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn compute_many() {
+        use crate::{
+            ops::compute::{CompResult, Op},
+            KeyLockError,
+        };
+
+        let cache = Cache::new(100);
+        assert!(matches!(
+            cache.compute_many([0], |_| [Op::Put(0)]),
+            Err(KeyLockError::KeyLocksDisabled)
+        ));
+
+        let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+        cache.insert("a", 1000);
+        cache.insert("b", 0);
+
+        // Transfer 1 from "a" to "b" on four threads. Half of them give the keys in
+        // the reverse order, which must not deadlock.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    let keys = if i % 2 == 0 { ["a", "b"] } else { ["b", "a"] };
+                    let (a, b) = if i % 2 == 0 { (0, 1) } else { (1, 0) };
+                    for _ in 0..100 {
+                        cache
+                            .compute_many(keys, |entries| {
+                                let mut ops = [Op::Nop, Op::Nop];
+                                ops[a] = Op::Put(*entries[a].as_ref().unwrap().value() - 1);
+                                ops[b] = Op::Put(*entries[b].as_ref().unwrap().value() + 1);
+                                ops
+                            })
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+        assert_eq!(cache.get(&"a"), Some(600));
+        assert_eq!(cache.get(&"b"), Some(400));
+
+        let results = cache
+            .compute_many(["a", "c", "b"], |entries| {
+                assert!(entries[1].is_none());
+                [Op::Remove, Op::Put(5), Op::Nop]
+            })
+            .unwrap();
+        assert!(matches!(&results[0], CompResult::Removed(e) if *e.value() == 600));
+        assert!(matches!(&results[1], CompResult::Inserted(e) if *e.value() == 5));
+        assert!(matches!(&results[2], CompResult::Unchanged(e) if *e.value() == 400));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"c"), Some(5));
+
+        // The ops for the same key are applied in order.
+        let results = cache
+            .compute_many(["c", "c"], |entries| {
+                let v = *entries[0].as_ref().unwrap().value();
+                [Op::Put(v + 1), Op::Remove]
+            })
+            .unwrap();
+        assert!(matches!(&results[0], CompResult::ReplacedWith(e) if *e.value() == 6));
+        assert!(matches!(&results[1], CompResult::Removed(e) if *e.value() == 6));
+        assert_eq!(cache.get(&"c"), None);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn compute_many_and_compute_with() {
+        use crate::ops::compute::Op;
+
+        let cache: Cache<&str, u32> = Cache::builder().support_key_locks().build();
+        cache.insert("a", 1000);
+        cache.insert("b", 0);
+
+        // Two threads transfer 1 from "a" to "b" by `compute_many`, and the other
+        // two threads rewrite the value of "a" or "b" by `and_compute_with`. If
+        // `and_compute_with` wrote back a value read before a transfer, the sum
+        // would change.
+        let handles = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        if i % 2 == 0 {
+                            cache
+                                .compute_many(["a", "b"], |entries| {
+                                    let a = *entries[0].as_ref().unwrap().value();
+                                    let b = *entries[1].as_ref().unwrap().value();
+                                    std::thread::yield_now();
+                                    [Op::Put(a - 1), Op::Put(b + 1)]
+                                })
+                                .unwrap();
+                        } else {
+                            let key = if i == 1 { "a" } else { "b" };
+                            cache.entry(key).and_compute_with(|entry| {
+                                let v = *entry.unwrap().value();
+                                std::thread::yield_now();
+                                Op::Put(v)
+                            });
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().expect("Failed to join");
+        }
+
+        assert_eq!(cache.get(&"a"), Some(600));
+        assert_eq!(cache.get(&"b"), Some(400));
+        assert!(cache.key_locks_map_is_empty());
+    }
+
+    #[test]
+    fn maintenance_stats() {
+        let mut cache = Cache::builder()
+            .max_capacity(3)
+            .eviction_policy(EvictionPolicy::lru())
+            .time_to_live(Duration::from_secs(10))
+            .read_log_capacity(64)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..5 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.runs(), 1);
+        assert_eq!(stats.run_duration().count(), 1);
+        assert_eq!(stats.writes_applied(), 5);
+        assert_eq!(stats.entries_evicted(), 2);
+        assert_eq!(cache.entry_count(), 3);
+
+        // The reads exceeding the capacity of the read log are dropped.
+        for _ in 0..100 {
+            cache.get(&4);
+        }
+        cache.run_pending_tasks();
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.reads_applied(), 64);
+        assert_eq!(stats.read_ops_dropped(), 36);
+
+        cache.invalidate_entries_if(|k, _| *k == 4).unwrap();
+        cache.run_pending_tasks();
+        assert_eq!(cache.maintenance_stats().entries_invalidated(), 1);
+
+        mock.increment(Duration::from_secs(10));
+        cache.run_pending_tasks();
+        let stats = cache.maintenance_stats();
+        assert_eq!(stats.entries_expired(), 2);
+        assert_eq!(stats.runs(), 4);
+        assert_eq!(stats.run_duration().count(), 4);
+        assert_eq!(stats.write_op_retries(), 0);
+        assert_eq!(stats.timeouts(), 0);
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[test]
+    fn render_prometheus() {
+        let mut cache = Cache::builder()
+            .name("my \"cache\"")
+            .max_capacity(2)
+            .eviction_policy(EvictionPolicy::lru())
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.get_with(0, || 0), 0); // miss and load
+        assert_eq!(cache.get_with(0, || 1), 0); // hit
+        assert!(cache.try_get_with(1, || Err("error")).is_err()); // miss and load failure
+        assert_eq!(cache.get(&2), None); // miss
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 2);
+
+        mock.increment(Duration::from_secs(10));
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+
+        let mut buf = Vec::new();
+        cache.render_prometheus(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let samples = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>();
+
+        let label = r#"cache="my \"cache\"""#;
+        let expected = [
+            format!("moka_cache_entry_count{{{label}}} 0"),
+            format!("moka_cache_weighted_size{{{label}}} 0"),
+            format!("moka_cache_max_capacity{{{label}}} 2"),
+            format!("moka_cache_hits_total{{{label}}} 1"),
+            format!("moka_cache_misses_total{{{label}}} 3"),
+            format!(r#"moka_cache_evictions_total{{{label},cause="size"}} 1"#),
+            format!(r#"moka_cache_evictions_total{{{label},cause="expired"}} 2"#),
+            format!(r#"moka_cache_loads_total{{{label},result="success"}} 1"#),
+            format!(r#"moka_cache_loads_total{{{label},result="failure"}} 1"#),
+        ];
+        assert_eq!(&samples[..expected.len()], &expected);
+
+        let histogram = &samples[expected.len()..];
+        assert_eq!(histogram.len(), 7 + 2);
+        assert!(histogram[0].starts_with(&format!(
+            r#"moka_cache_load_duration_seconds_bucket{{{label},le="0.00001"}} "#
+        )));
+        assert_eq!(
+            histogram[histogram.len() - 3],
+            format!(r#"moka_cache_load_duration_seconds_bucket{{{label},le="+Inf"}} 2"#)
+        );
+        assert_eq!(
+            histogram.last().unwrap(),
+            &format!("moka_cache_load_duration_seconds_count{{{label}}} 2")
+        );
+    }
+
+    #[test]
+    fn memory_usage() {
+        use crate::common::{
+            concurrent::KeyHashDate, deque::DeqNode, memory_report::entry_alloc_size,
+        };
+
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(|_k, _v, _cause| {})
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..50u32 {
+            cache.insert(i, "a".repeat(16));
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 50);
+
+        let report = cache.memory_usage();
+        assert_eq!(
+            report.entries(),
+            50 * entry_alloc_size::<u32, String>() as u64
+        );
+        // Each entry has a node in the access-order and write-order queues.
+        assert_eq!(
+            report.deque_nodes(),
+            100 * std::mem::size_of::<DeqNode<KeyHashDate<u32>>>() as u64
+        );
+        // The timer wheel is not used without a per-entry expiration policy.
+        assert_eq!(report.timer_nodes(), 0);
+        assert!(report.hash_table() > 0);
+        assert!(report.frequency_sketch() > 0);
+        assert!(report.op_channels() > 0);
+        assert!(report.key_locks() > 0);
+        // The waiter map has no entries but has its initial bucket array.
+        assert!(report.waiters() > 0);
+        assert_eq!(report.key_value_heap(), None);
+        assert_eq!(
+            report.total(),
+            report.hash_table()
+                + report.retired_hash_tables()
+                + report.entries()
+                + report.deque_nodes()
+                + report.timer_nodes()
+                + report.frequency_sketch()
+                + report.op_channels()
+                + report.waiters()
+                + report.key_locks()
+        );
+
+        let report = cache.memory_usage_with_heap_size();
+        assert_eq!(report.key_value_heap(), Some(50 * 16));
     }
 
-    /// Performs any pending maintenance operations needed by the cache.
-    pub fn run_pending_tasks(&self) {
-        if let Some(hk) = &self.base.housekeeper {
-            hk.run_pending_tasks(&*self.base.inner);
+    #[cfg(feature = "testing")]
+    #[test]
+    fn next_expiration() {
+        use crate::clock::{CacheClock, MockClock};
+
+        // The resolution of the timer wheel.
+        const TICK: Duration = Duration::from_nanos(1 << 30);
+
+        struct MyExpiry;
+
+        impl Expiry<&str, u64> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                value: &u64,
+                _current_time: StdInstant,
+            ) -> Option<Duration> {
+                Some(Duration::from_secs(*value))
+            }
         }
-    }
-}
 
-impl<'a, K, V, S> IntoIterator for &'a Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    type Item = (Arc<K>, V);
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut cache = Cache::builder()
+            .expire_after(MyExpiry)
+            .clock(clock.clone())
+            .build();
+        cache.reconfigure_for_testing();
 
-    type IntoIter = Iter<'a, K, V>;
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
-    }
-}
+        assert_eq!(cache.next_expiration(), None);
 
-//
-// Iterator support
-//
-impl<K, V, S> ScanningGet<K, V> for Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    fn num_cht_segments(&self) -> usize {
-        self.base.num_cht_segments()
-    }
+        cache.insert("a", 10);
+        cache.insert("b", 20);
+        cache.run_pending_tasks();
+        let next = cache.next_expiration().expect("No next expiration");
+        assert!(next >= start + Duration::from_secs(10) - TICK);
+        assert!(next <= start + Duration::from_secs(10) + TICK);
 
-    fn scanning_get(&self, key: &Arc<K>) -> Option<V> {
-        self.base.scanning_get(key)
-    }
+        // After "a" expires, the next expiration is the one of "b".
+        clock.increment(Duration::from_secs(11));
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"a"));
+        let next = cache.next_expiration().expect("No next expiration");
+        assert!(next >= start + Duration::from_secs(20) - TICK);
+        assert!(next <= start + Duration::from_secs(20) + TICK);
 
-    fn keys(&self, cht_segment: usize) -> Option<Vec<Arc<K>>> {
-        self.base.keys(cht_segment)
+        cache.invalidate(&"b");
+        cache.run_pending_tasks();
+        assert_eq!(cache.next_expiration(), None);
     }
-}
 
-//
-// private methods
-//
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    // TODO: Like future::Cache, move this method to BaseCache.
-    #[inline]
-    fn schedule_write_op(
-        inner: &impl InnerSync,
-        ch: &Sender<WriteOp<K, V>>,
-        op: WriteOp<K, V>,
-        now: Instant,
-        housekeeper: Option<&HouseKeeperArc>,
-    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
-        let mut op = op;
+    #[test]
+    fn proactive_expiration() {
+        use crate::sync::MaintenanceScheduler;
 
-        // NOTES:
-        // - This will block when the channel is full.
-        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
-        //   but we got a notable performance degradation.
-        loop {
-            BaseCache::<K, V, S>::apply_reads_writes_if_needed(inner, ch, now, housekeeper);
-            match ch.try_send(op) {
-                Ok(()) => break,
-                Err(TrySendError::Full(op1)) => {
-                    op = op1;
-                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
-                }
-                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+        const TTL: Duration = Duration::from_millis(500);
+
+        struct MyExpiry;
+
+        impl Expiry<&str, &str> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                _value: &&str,
+                _current_time: StdInstant,
+            ) -> Option<Duration> {
+                Some(TTL)
             }
         }
-        Ok(())
-    }
-}
-
-// For unit tests.
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S> {
-    pub(crate) fn is_table_empty(&self) -> bool {
-        self.entry_count() == 0
-    }
 
-    pub(crate) fn is_waiter_map_empty(&self) -> bool {
-        self.value_initializer.waiter_count() == 0
-    }
-}
+        // Use a long interval so that only the expiration timers can remove the
+        // entry.
+        let scheduler = MaintenanceScheduler::thread()
+            .interval(Duration::from_secs(3600))
+            .proactive_expiration(true);
 
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    pub(crate) fn invalidation_predicate_count(&self) -> usize {
-        self.base.invalidation_predicate_count()
-    }
+        // The following `Vec` will hold actual notifications and their times.
+        let actual = Arc::new(Mutex::new(Vec::new()));
 
-    pub(crate) fn reconfigure_for_testing(&mut self) {
-        self.base.reconfigure_for_testing();
-    }
+        // Create an eviction listener.
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| a1.lock().push((k, v, cause, StdInstant::now()));
 
-    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
-        self.base.set_expiration_clock(clock);
-    }
+        let mut cache = Cache::builder()
+            .expire_after(MyExpiry)
+            .eviction_listener(listener)
+            .maintenance_scheduler(scheduler)
+            .build();
+        cache.reconfigure_for_testing();
 
-    pub(crate) fn key_locks_map_is_empty(&self) -> bool {
-        self.base.key_locks_map_is_empty()
-    }
-}
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-// To see the debug prints, run test as `cargo test -- --nocapture`
-#[cfg(test)]
-mod tests {
-    use super::Cache;
-    use crate::{
-        common::{time::Clock, HousekeeperConfig},
-        notification::RemovalCause,
-        policy::{test_utils::ExpiryCallCounters, EvictionPolicy},
-        Expiry,
-    };
+        assert_eq!(cache.next_expiration(), None);
 
-    use parking_lot::Mutex;
-    use std::{
-        convert::Infallible,
-        sync::{
-            atomic::{AtomicU8, Ordering},
-            Arc,
-        },
-        time::{Duration, Instant as StdInstant},
-    };
+        let inserted_at = StdInstant::now();
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+        assert!(cache.next_expiration().is_some());
+
+        // The eviction listener is called without calling any method of the cache,
+        // long before the next maintenance interval. Do not assert how soon it is
+        // called, as it depends on the load of the machine. The long timeout only
+        // matters when the test fails.
+        let deadline = StdInstant::now() + Duration::from_secs(30);
+        while actual.lock().is_empty() && StdInstant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let actual = actual.lock();
+        assert_eq!(actual.len(), 1);
+        let (k, v, cause, notified_at) = &actual[0];
+        assert_eq!(
+            (k, v, cause),
+            (&Arc::new("a"), &"alice", &RemovalCause::Expired)
+        );
+        // The entry is not removed before it expires.
+        assert!(*notified_at >= inserted_at + TTL);
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.next_expiration(), None);
+    }
 
     #[test]
-    fn max_capacity_zero() {
-        let mut cache = Cache::new(0);
+    fn predicate_status_cancel_and_wait() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .support_invalidation_closures()
+            .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert(0, ());
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+
+        let id = cache.invalidate_entries_if(|_k, &v| v % 20 == 0)?;
+        assert_eq!(cache.predicate_status(&id), Some(PredicateStatus::Pending));
+
+        let status = cache.wait_predicate(&id);
+        assert_eq!(
+            status,
+            Some(PredicateStatus::Completed {
+                scanned: 10,
+                removed: 5
+            })
+        );
+        // The status of a finished predicate can still be queried.
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        assert_eq!(cache.entry_count(), 5);
+        assert!(!cache.cancel_predicate(&id));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+
+        // Cancel a predicate before the cache starts scanning for it.
+        let id = cache.invalidate_entries_if(|_k, _v| true)?;
+        assert!(cache.cancel_predicate(&id));
+        assert!(!cache.cancel_predicate(&id));
+        let status = Some(PredicateStatus::Cancelled {
+            scanned: 0,
+            removed: 0,
+        });
+        assert_eq!(cache.predicate_status(&id), status);
+        assert_eq!(cache.wait_predicate(&id), status);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
 
-        assert!(!cache.contains_key(&0));
-        assert!(cache.get(&0).is_none());
         cache.run_pending_tasks();
-        assert!(!cache.contains_key(&0));
-        assert!(cache.get(&0).is_none());
-        assert_eq!(cache.entry_count(), 0)
+        assert_eq!(cache.entry_count(), 5);
+        assert_eq!(cache.get(&1), Some(10));
+
+        assert!(cache.predicate_status("no-such-predicate").is_none());
+        assert!(cache.wait_predicate("no-such-predicate").is_none());
+        assert!(!cache.cancel_predicate("no-such-predicate"));
+
+        Ok(())
     }
 
     #[test]
-    fn basic_single_thread() {
+    fn time_to_live() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -1934,82 +4996,78 @@ mod tests {
 
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(3)
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
             .eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
         cache.insert("a", "alice");
-        cache.insert("b", "bob");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
         cache.run_pending_tasks();
-        // counts: a -> 1, b -> 1
 
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        assert!(cache.contains_key(&"c"));
-        // counts: a -> 1, b -> 1, c -> 1
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
         cache.run_pending_tasks();
 
-        assert!(cache.contains_key(&"a"));
         assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert!(cache.contains_key(&"b"));
-        cache.run_pending_tasks();
-        // counts: a -> 2, b -> 2, c -> 1
+        assert!(cache.contains_key(&"a"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        expected.push((Arc::new("a"), "alice", RemovalCause::Expired));
+        assert_eq!(cache.get(&"a"), None);
+        assert!(!cache.contains_key(&"a"));
+
+        assert_eq!(cache.iter().count(), 0);
 
-        // "d" should not be admitted because its frequency is too low.
-        cache.insert("d", "david"); //   count: d -> 0
-        expected.push((Arc::new("d"), "david", RemovalCause::Size));
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"d"), None); //   d -> 1
-        assert!(!cache.contains_key(&"d"));
+        assert!(cache.is_table_empty());
 
-        cache.insert("d", "david");
-        expected.push((Arc::new("d"), "david", RemovalCause::Size));
+        cache.insert("b", "bob");
         cache.run_pending_tasks();
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d"), None); //   d -> 2
 
-        // "d" should be admitted and "c" should be evicted
-        // because d's frequency is higher than c's.
-        cache.insert("d", "dennis");
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        assert_eq!(cache.entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some("dennis"));
-        assert!(cache.contains_key(&"a"));
+
+        assert_eq!(cache.get(&"b"), Some("bob"));
         assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 1);
 
-        cache.invalidate(&"b");
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        cache.insert("b", "bill");
+        expected.push((Arc::new("b"), "bob", RemovalCause::Replaced));
         cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 20 secs
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"b"), Some("bill"));
+        assert!(cache.contains_key(&"b"));
+        assert_eq!(cache.entry_count(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 25 secs
+        expected.push((Arc::new("b"), "bill", RemovalCause::Expired));
+
+        assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), None);
+        assert!(!cache.contains_key(&"a"));
         assert!(!cache.contains_key(&"b"));
 
-        assert!(cache.remove(&"b").is_none());
-        assert_eq!(cache.remove(&"d"), Some("dennis"));
-        expected.push((Arc::new("d"), "dennis", RemovalCause::Explicit));
+        assert_eq!(cache.iter().count(), 0);
+
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"d"), None);
-        assert!(!cache.contains_key(&"d"));
+        assert!(cache.is_table_empty());
 
         verify_notification_vec(&cache, actual, &expected);
-        assert!(cache.key_locks_map_is_empty());
     }
 
     #[test]
-    fn basic_lru_single_thread() {
+    fn per_entry_expiration() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2020,298 +5078,291 @@ mod tests {
 
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(3)
-            .eviction_policy(EvictionPolicy::lru())
+            .max_capacity(100)
             .eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", "alice");
+        cache.insert_with_ttl("a", "alice", Duration::from_secs(5));
         cache.insert("b", "bob");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
+        let deadline = std::time::Instant::now() + Duration::from_secs(8);
+        cache.insert_with_deadline("c", "cindy", deadline);
         cache.run_pending_tasks();
-        // a -> b
 
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        assert!(cache.contains_key(&"c"));
-        cache.run_pending_tasks();
-        // a -> b -> c
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(5)));
+        assert_eq!(cache.expires_in(&"b"), None);
+        assert!(cache.expires_in(&"c").is_some());
+        assert_eq!(cache.expires_in(&"d"), None);
 
-        assert!(cache.contains_key(&"a"));
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert!(cache.contains_key(&"b"));
+        mock.increment(Duration::from_secs(3)); // 3 secs from the start.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(2)));
+
+        assert!(cache.set_expiry(&"a", Duration::from_secs(10)));
+        assert!(cache.set_expiry(&"b", Duration::from_secs(4)));
+        assert!(!cache.set_expiry(&"d", Duration::from_secs(4)));
         cache.run_pending_tasks();
-        // c -> a -> b
 
-        // "d" should be admitted because the cache uses the LRU strategy.
-        cache.insert("d", "david");
-        // "c" is the LRU and should have be evicted.
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Size));
+        mock.increment(Duration::from_secs(4)); // 7 secs.
+        expected.push((Arc::new("b"), "bob", RemovalCause::Expired));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.expires_in(&"b"), None);
+        assert!(!cache.set_expiry(&"b", Duration::from_secs(4)));
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(6)));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+
         cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 2);
 
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
+        mock.increment(Duration::from_secs(2)); // 9 secs.
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Expired));
         assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some("david"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
+
         cache.run_pending_tasks();
-        // a -> b -> d
+        assert_eq!(cache.entry_count(), 1);
 
-        cache.invalidate(&"b");
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
+        // Updating the value will not change the per-entry expiration time.
+        cache.insert("a", "anna");
+        expected.push((Arc::new("a"), "alice", RemovalCause::Replaced));
         cache.run_pending_tasks();
-        // a -> d
-        assert_eq!(cache.get(&"b"), None);
-        assert!(!cache.contains_key(&"b"));
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(4)));
+
+        mock.increment(Duration::from_secs(4)); // 13 secs.
+        assert_eq!(cache.get(&"a"), None);
+
+        // Updating the expired entry before it is evicted will clear the
+        // per-entry expiration time.
+        cache.insert("a", "amy");
+        expected.push((Arc::new("a"), "anna", RemovalCause::Replaced));
+        assert_eq!(cache.get(&"a"), Some("amy"));
+        assert_eq!(cache.expires_in(&"a"), None);
 
-        assert!(cache.remove(&"b").is_none());
-        assert_eq!(cache.remove(&"d"), Some("david"));
-        expected.push((Arc::new("d"), "david", RemovalCause::Explicit));
         cache.run_pending_tasks();
-        // a
-        assert_eq!(cache.get(&"d"), None);
-        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.get(&"a"), Some("amy"));
 
-        cache.insert("e", "emily");
-        cache.insert("f", "frank");
-        // "a" should be evicted because it is the LRU.
-        cache.insert("g", "gina");
-        expected.push((Arc::new("a"), "alice", RemovalCause::Size));
+        cache.invalidate(&"a");
+        expected.push((Arc::new("a"), "amy", RemovalCause::Explicit));
         cache.run_pending_tasks();
-        // e -> f -> g
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"e"), Some("emily"));
-        assert_eq!(cache.get(&"f"), Some("frank"));
-        assert_eq!(cache.get(&"g"), Some("gina"));
-        assert!(!cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"e"));
-        assert!(cache.contains_key(&"f"));
-        assert!(cache.contains_key(&"g"));
+        assert!(cache.is_table_empty());
 
         verify_notification_vec(&cache, actual, &expected);
-        assert!(cache.key_locks_map_is_empty());
     }
 
     #[test]
-    fn size_aware_eviction() {
-        let weigher = |_k: &&str, v: &(&str, u32)| v.1;
-
-        let alice = ("alice", 10);
-        let bob = ("bob", 15);
-        let bill = ("bill", 20);
-        let cindy = ("cindy", 5);
-        let david = ("david", 15);
-        let dennis = ("dennis", 15);
+    fn set_expiry_when_read_log_is_full() {
+        const READ_LOG_CAPACITY: usize = 64;
 
-        // The following `Vec`s will hold actual and expected notifications.
-        let actual = Arc::new(Mutex::new(Vec::new()));
-        let mut expected = Vec::new();
-
-        // Create an eviction listener.
-        let a1 = Arc::clone(&actual);
-        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
-
-        // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
-            .max_capacity(31)
-            .weigher(weigher)
-            .eviction_listener(listener)
+            .max_capacity(100)
+            .read_log_capacity(READ_LOG_CAPACITY)
             .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", alice);
-        cache.insert("b", bob);
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.get(&"b"), Some(bob));
+        cache.insert_with_ttl("a", "alice", Duration::from_secs(5));
         cache.run_pending_tasks();
-        // order (LRU -> MRU) and counts: a -> 1, b -> 1
 
-        cache.insert("c", cindy);
-        assert_eq!(cache.get(&"c"), Some(cindy));
-        assert!(cache.contains_key(&"c"));
-        // order and counts: a -> 1, b -> 1, c -> 1
-        cache.run_pending_tasks();
+        // Fill the read log, so that the read ops will be discarded.
+        for _ in 0..(READ_LOG_CAPACITY * 2) {
+            assert_eq!(cache.get(&"b"), None);
+        }
 
-        assert!(cache.contains_key(&"a"));
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert!(cache.contains_key(&"b"));
+        // The new expiration time must not be discarded with the read ops.
+        assert!(cache.set_expiry(&"a", Duration::from_secs(1)));
         cache.run_pending_tasks();
-        // order and counts: c -> 1, a -> 2, b -> 2
 
-        // To enter "d" (weight: 15), it needs to evict "c" (w: 5) and "a" (w: 10).
-        // "d" must have higher count than 3, which is the aggregated count
-        // of "a" and "c".
-        cache.insert("d", david); //   count: d -> 0
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
+        mock.increment(Duration::from_secs(2));
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"d"), None); //   d -> 1
-        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 0);
+    }
 
-        cache.insert("d", david);
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
-        cache.run_pending_tasks();
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d"), None); //   d -> 2
+    #[test]
+    fn per_entry_expiration_with_time_to_idle() {
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
 
-        cache.insert("d", david);
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
-        cache.run_pending_tasks();
-        assert_eq!(cache.get(&"d"), None); //   d -> 3
-        assert!(!cache.contains_key(&"d"));
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
 
-        cache.insert("d", david);
-        expected.push((Arc::new("d"), david, RemovalCause::Size));
-        cache.run_pending_tasks();
-        assert!(!cache.contains_key(&"d"));
-        assert_eq!(cache.get(&"d"), None); //   d -> 4
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        // Finally "d" should be admitted by evicting "c" and "a".
-        cache.insert("d", dennis);
-        expected.push((Arc::new("c"), cindy, RemovalCause::Size));
-        expected.push((Arc::new("a"), alice, RemovalCause::Size));
+        cache.insert_with_ttl("a", "alice", Duration::from_secs(15));
+        cache.insert_with_ttl("b", "bob", Duration::from_secs(5));
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some(dennis));
-        assert!(!cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
 
-        // Update "b" with "bill" (w: 15 -> 20). This should evict "d" (w: 15).
-        cache.insert("b", bill);
-        expected.push((Arc::new("b"), bob, RemovalCause::Replaced));
-        expected.push((Arc::new("d"), dennis, RemovalCause::Size));
-        cache.run_pending_tasks();
-        assert_eq!(cache.get(&"b"), Some(bill));
-        assert_eq!(cache.get(&"d"), None);
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"d"));
+        // The earlier of the per-entry expiration time and the time-to-idle wins.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.expires_in(&"b"), Some(Duration::from_secs(5)));
 
-        // Re-add "a" (w: 10) and update "b" with "bob" (w: 20 -> 15).
-        cache.insert("a", alice);
-        cache.insert("b", bob);
-        expected.push((Arc::new("b"), bill, RemovalCause::Replaced));
+        mock.increment(Duration::from_secs(4)); // 4 secs from the start.
+        assert!(cache.touch(&"a"));
+        assert!(cache.touch(&"b"));
+        assert!(!cache.touch(&"c"));
         cache.run_pending_tasks();
-        assert_eq!(cache.get(&"a"), Some(alice));
-        assert_eq!(cache.get(&"b"), Some(bob));
-        assert_eq!(cache.get(&"d"), None);
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"d"));
 
-        // Verify the sizes.
-        assert_eq!(cache.entry_count(), 2);
-        assert_eq!(cache.weighted_size(), 25);
+        // Touching resets the idle timer but not the per-entry expiration time.
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.expires_in(&"b"), Some(Duration::from_secs(1)));
 
-        verify_notification_vec(&cache, actual, &expected);
-        assert!(cache.key_locks_map_is_empty());
+        mock.increment(Duration::from_secs(8)); // 12 secs.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.expires_in(&"a"), Some(Duration::from_secs(3)));
+
+        mock.increment(Duration::from_secs(3)); // 15 secs.
+        assert_eq!(cache.get(&"a"), None);
+
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
     }
 
     #[test]
-    fn basic_multi_threads() {
-        let num_threads = 4;
-        let cache = Cache::new(100);
+    fn entry_metadata() {
+        use crate::{
+            ops::compute::{CompResult, Op},
+            EntryRegion,
+        };
 
-        // https://rust-lang.github.io/rust-clippy/master/index.html#needless_collect
-        #[allow(clippy::needless_collect)]
-        let handles = (0..num_threads)
-            .map(|id| {
-                let cache = cache.clone();
-                std::thread::spawn(move || {
-                    cache.insert(10, format!("{id}-100"));
-                    cache.get(&10);
-                    cache.insert(20, format!("{id}-200"));
-                    cache.invalidate(&10);
-                })
-            })
-            .collect::<Vec<_>>();
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .weigher(|_k, v: &&str| v.len() as u32)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+
+        // The entry has not been admitted to the cache policy yet.
+        let entry = cache.get_entry(&"a").expect("Entry not found");
+        assert_eq!(entry.key(), &"a");
+        assert_eq!(entry.value(), &"alice");
+        let md = entry.metadata().expect("Metadata not found");
+        let t0 = md.last_modified().expect("Last modified not set");
+        assert_eq!(md.last_accessed(), Some(t0));
+        assert_eq!(md.expiration_time(), Some(t0 + Duration::from_secs(10)));
+        assert_eq!(md.policy_weight(), 5);
+        assert_eq!(md.region(), None);
 
-        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(3)); // 3 secs from the start.
 
-        assert!(cache.get(&10).is_none());
-        assert!(cache.get(&20).is_some());
-        assert!(!cache.contains_key(&10));
-        assert!(cache.contains_key(&20));
-    }
+        let entry = cache.get_entry(&"a").expect("Entry not found");
+        let md = entry.metadata().expect("Metadata not found");
+        assert_eq!(md.last_modified(), Some(t0));
+        assert_eq!(md.last_accessed(), Some(t0 + Duration::from_secs(3)));
+        assert_eq!(md.region(), Some(EntryRegion::MainProbation));
+        assert!(cache.get_entry(&"b").is_none());
 
-    #[test]
-    fn invalidate_all() {
-        // The following `Vec`s will hold actual and expected notifications.
-        let actual = Arc::new(Mutex::new(Vec::new()));
-        let mut expected = Vec::new();
+        // Other methods returning an `Entry` do not carry metadata.
+        assert!(cache.entry("a").or_insert("anna").metadata().is_none());
 
-        // Create an eviction listener.
-        let a1 = Arc::clone(&actual);
-        let listener = move |k, v, cause| a1.lock().push((k, v, cause));
+        cache.insert_with_ttl("b", "bob", Duration::from_secs(4));
+        cache.run_pending_tasks();
 
-        // Create a cache with the eviction listener.
+        let mut entries = cache.iter_with_metadata().collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(k, ..)| **k);
+        assert_eq!(entries.len(), 2);
+        assert_eq!((*entries[0].0, entries[0].1), ("a", "alice"));
+        assert_eq!(
+            entries[0].2.expiration_time(),
+            Some(t0 + Duration::from_secs(10))
+        );
+        assert_eq!((*entries[1].0, entries[1].1), ("b", "bob"));
+        assert_eq!(entries[1].2.policy_weight(), 3);
+        assert_eq!(
+            entries[1].2.expiration_time(),
+            Some(t0 + Duration::from_secs(7))
+        );
+
+        // Iterating does not update the last accessed time.
+        mock.increment(Duration::from_secs(1)); // 4 secs.
+        let (_, _, md) = cache
+            .iter_with_metadata()
+            .find(|(k, ..)| **k == "a")
+            .expect("Entry not found");
+        assert_eq!(md.last_accessed(), Some(t0 + Duration::from_secs(3)));
+
+        match cache.entry("a").and_compute_with(|_| Op::Put("anna")) {
+            CompResult::ReplacedWith(entry) => {
+                let md = entry.metadata().expect("Metadata not found");
+                assert_eq!(md.last_modified(), Some(t0 + Duration::from_secs(4)));
+                assert_eq!(md.policy_weight(), 4);
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+
+        match cache.entry("a").and_compute_with(|_| Op::Nop) {
+            CompResult::Unchanged(entry) => {
+                let md = entry.metadata().expect("Metadata not found");
+                assert_eq!(md.expiration_time(), Some(t0 + Duration::from_secs(14)));
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+
+        match cache.entry("a").and_compute_with(|_| Op::Remove) {
+            CompResult::Removed(entry) => assert!(entry.metadata().is_none()),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn peek() {
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .eviction_listener(listener)
+            .time_to_idle(Duration::from_secs(10))
             .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
         cache.insert("a", "alice");
         cache.insert("b", "bob");
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        assert!(cache.contains_key(&"a"));
-        assert!(cache.contains_key(&"b"));
-        assert!(cache.contains_key(&"c"));
-
-        // `cache.run_pending_tasks()` is no longer needed here before invalidating. The last
-        // modified timestamp of the entries were updated when they were inserted.
-        // https://github.com/moka-rs/moka/issues/155
-
-        cache.invalidate_all();
-        expected.push((Arc::new("a"), "alice", RemovalCause::Explicit));
-        expected.push((Arc::new("b"), "bob", RemovalCause::Explicit));
-        expected.push((Arc::new("c"), "cindy", RemovalCause::Explicit));
         cache.run_pending_tasks();
 
-        cache.insert("d", "david");
-        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(6)); // 6 secs from the start.
+        assert_eq!(cache.peek(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.peek(&"c"), None);
 
-        assert!(cache.get(&"a").is_none());
-        assert!(cache.get(&"b").is_none());
-        assert!(cache.get(&"c").is_none());
-        assert_eq!(cache.get(&"d"), Some("david"));
-        assert!(!cache.contains_key(&"a"));
-        assert!(!cache.contains_key(&"b"));
-        assert!(!cache.contains_key(&"c"));
-        assert!(cache.contains_key(&"d"));
+        let entry = cache.peek_entry(&"a").expect("Entry not found");
+        assert_eq!(entry.key(), &"a");
+        let md = entry.metadata().expect("Metadata not found");
+        assert_eq!(md.last_accessed(), md.last_modified());
+        cache.run_pending_tasks();
 
-        verify_notification_vec(&cache, actual, &expected);
+        // Peeking did not reset the idle timer of "a".
+        mock.increment(Duration::from_secs(6)); // 12 secs.
+        assert_eq!(cache.peek(&"a"), None);
+        assert!(cache.peek_entry(&"a").is_none());
+        assert_eq!(cache.peek(&"b"), Some("bob"));
     }
 
     #[test]
-    fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
-        use std::collections::HashSet;
-
+    fn conditional_mutations() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2323,7 +5374,7 @@ mod tests {
         // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .support_invalidation_closures()
+            .time_to_live(Duration::from_secs(10))
             .eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing();
@@ -2334,82 +5385,91 @@ mod tests {
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert(0, "alice");
-        cache.insert(1, "bob");
-        cache.insert(2, "alex");
-        cache.run_pending_tasks();
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.insert_if_absent("a", "alice"), None);
+        assert_eq!(cache.insert_if_absent("a", "anna"), Some("alice"));
+        assert_eq!(cache.replace_if_present(&"b", "bob"), None);
+        assert!(cache.get(&"b").is_none());
         cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
 
-        assert_eq!(cache.get(&0), Some("alice"));
-        assert_eq!(cache.get(&1), Some("bob"));
-        assert_eq!(cache.get(&2), Some("alex"));
-        assert!(cache.contains_key(&0));
-        assert!(cache.contains_key(&1));
-        assert!(cache.contains_key(&2));
+        assert_eq!(cache.replace(&"a", &"anna", "amy"), Err(Some("alice")));
+        assert_eq!(cache.replace(&"a", &"alice", "amy"), Ok("alice"));
+        expected.push((Arc::new("a"), "alice", RemovalCause::Replaced));
+        assert_eq!(cache.replace(&"b", &"bob", "bill"), Err(None));
 
-        let names = ["alice", "alex"].iter().cloned().collect::<HashSet<_>>();
-        cache.invalidate_entries_if(move |_k, &v| names.contains(v))?;
-        assert_eq!(cache.base.invalidation_predicate_count(), 1);
-        expected.push((Arc::new(0), "alice", RemovalCause::Explicit));
-        expected.push((Arc::new(2), "alex", RemovalCause::Explicit));
+        assert_eq!(cache.replace_if(&"a", "anna", |v| v.starts_with('b')), None);
+        assert_eq!(cache.replace_if(&"a", "anna", |v| *v == "amy"), Some("amy"));
+        expected.push((Arc::new("a"), "amy", RemovalCause::Replaced));
+        assert_eq!(cache.get(&"a"), Some("anna"));
 
-        mock.increment(Duration::from_secs(5)); // 10 secs from the start.
+        assert_eq!(cache.remove_if(&"a", |v| *v == "amy"), None);
+        assert_eq!(cache.remove_if(&"a", |v| *v == "anna"), Some("anna"));
+        expected.push((Arc::new("a"), "anna", RemovalCause::Explicit));
+        assert!(cache.get(&"a").is_none());
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
 
-        cache.insert(3, "alice");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
 
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.run_pending_tasks(); // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.run_pending_tasks(); // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
+        // An expired entry is treated as absent even if it has not been evicted.
+        mock.increment(Duration::from_secs(11)); // 11 secs from the start.
+        assert_eq!(cache.replace_if_present(&"c", "carl"), None);
+        assert_eq!(cache.remove_if(&"c", |_| true), None);
+        assert_eq!(cache.insert_if_absent("c", "carol"), None);
+        expected.push((Arc::new("c"), "cindy", RemovalCause::Expired));
+        assert_eq!(cache.get(&"c"), Some("carol"));
 
-        assert!(cache.get(&0).is_none());
-        assert!(cache.get(&2).is_none());
-        assert_eq!(cache.get(&1), Some("bob"));
-        // This should survive as it was inserted after calling invalidate_entries_if.
-        assert_eq!(cache.get(&3), Some("alice"));
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
 
-        assert!(!cache.contains_key(&0));
-        assert!(cache.contains_key(&1));
-        assert!(!cache.contains_key(&2));
-        assert!(cache.contains_key(&3));
+        verify_notification_vec(&cache, actual, &expected);
+    }
 
-        assert_eq!(cache.entry_count(), 2);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+    #[test]
+    fn swap_and_old_value() {
+        use crate::ops::compute;
 
-        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
 
-        cache.invalidate_entries_if(|_k, &v| v == "alice")?;
-        cache.invalidate_entries_if(|_k, &v| v == "bob")?;
-        assert_eq!(cache.invalidation_predicate_count(), 2);
-        // key 1 was inserted before key 3.
-        expected.push((Arc::new(1), "bob", RemovalCause::Explicit));
-        expected.push((Arc::new(3), "alice", RemovalCause::Explicit));
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
 
-        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.run_pending_tasks(); // To submit the invalidation task.
-        std::thread::sleep(Duration::from_millis(200));
-        cache.run_pending_tasks(); // To process the task result.
-        std::thread::sleep(Duration::from_millis(200));
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        assert!(cache.get(&1).is_none());
-        assert!(cache.get(&3).is_none());
+        assert_eq!(cache.swap("a", "alice"), None);
+        assert_eq!(cache.swap("a", "anna"), Some("alice"));
+        assert_eq!(cache.get(&"a"), Some("anna"));
 
-        assert!(!cache.contains_key(&1));
-        assert!(!cache.contains_key(&3));
+        let res = cache
+            .entry("a")
+            .and_compute_with(|_| compute::Op::Put("amy"));
+        let compute::CompResult::ReplacedWith(entry) = res else {
+            panic!("Expected `ReplacedWith`. Got {res:?}")
+        };
+        assert_eq!(entry.old_value(), Some(&"anna"));
+        assert_eq!(entry.into_value_and_old_value(), ("amy", Some("anna")));
 
-        assert_eq!(cache.entry_count(), 0);
-        assert_eq!(cache.invalidation_predicate_count(), 0);
+        let entry = cache.entry_by_ref(&"a").and_upsert_with(|_| "ada");
+        assert_eq!(entry.old_value(), Some(&"amy"));
 
-        verify_notification_vec(&cache, actual, &expected);
+        let entry = cache.entry("b").and_upsert_with(|_| "bob");
+        assert!(entry.old_value().is_none());
+        cache.run_pending_tasks();
 
-        Ok(())
+        // An expired value is not returned as the previous value.
+        mock.increment(Duration::from_secs(11)); // 11 secs from the start.
+        assert_eq!(cache.swap("a", "alma"), None);
+        assert_eq!(cache.get(&"a"), Some("alma"));
     }
 
     #[test]
-    fn time_to_live() {
+    fn keys_values_retain_drain() {
         // The following `Vec`s will hold actual and expected notifications.
         let actual = Arc::new(Mutex::new(Vec::new()));
         let mut expected = Vec::new();
@@ -2418,75 +5478,55 @@ mod tests {
         let a1 = Arc::clone(&actual);
         let listener = move |k, v, cause| a1.lock().push((k, v, cause));
 
-        // Create a cache with the eviction listener.
         let mut cache = Cache::builder()
             .max_capacity(100)
-            .time_to_live(Duration::from_secs(10))
             .eviction_listener(listener)
             .build();
         cache.reconfigure_for_testing();
 
-        let (clock, mock) = Clock::mock();
-        cache.set_expiration_clock(Some(clock));
-
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", "alice");
-        cache.run_pending_tasks();
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.run_pending_tasks();
-
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert!(cache.contains_key(&"a"));
-
-        mock.increment(Duration::from_secs(5)); // 10 secs.
-        expected.push((Arc::new("a"), "alice", RemovalCause::Expired));
-        assert_eq!(cache.get(&"a"), None);
-        assert!(!cache.contains_key(&"a"));
-
-        assert_eq!(cache.iter().count(), 0);
-
-        cache.run_pending_tasks();
-        assert!(cache.is_table_empty());
-
-        cache.insert("b", "bob");
-        cache.run_pending_tasks();
-
-        assert_eq!(cache.entry_count(), 1);
-
-        mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.run_pending_tasks();
-
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.entry_count(), 1);
-
-        cache.insert("b", "bill");
-        expected.push((Arc::new("b"), "bob", RemovalCause::Replaced));
-        cache.run_pending_tasks();
-
-        mock.increment(Duration::from_secs(5)); // 20 secs
-        cache.run_pending_tasks();
-
-        assert_eq!(cache.get(&"b"), Some("bill"));
-        assert!(cache.contains_key(&"b"));
-        assert_eq!(cache.entry_count(), 1);
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
 
-        mock.increment(Duration::from_secs(5)); // 25 secs
-        expected.push((Arc::new("b"), "bill", RemovalCause::Expired));
+        let mut keys = cache.keys().map(|k| *k).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
 
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), None);
-        assert!(!cache.contains_key(&"a"));
-        assert!(!cache.contains_key(&"b"));
+        let mut values = cache.values().collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, (0..10).map(|i| i * 10).collect::<Vec<_>>());
 
-        assert_eq!(cache.iter().count(), 0);
+        // Discard the entries with odd keys.
+        cache.retain(|k, _v| k % 2 == 0);
+        for i in (1..10).step_by(2) {
+            expected.push((Arc::new(i), i * 10, RemovalCause::Explicit));
+        }
+        assert!(cache.contains_key(&0));
+        assert!(!cache.contains_key(&1));
 
-        cache.run_pending_tasks();
-        assert!(cache.is_table_empty());
+        let mut drained = cache
+            .drain()
+            .into_iter()
+            .map(|(k, v)| (*k, v))
+            .collect::<Vec<_>>();
+        drained.sort_unstable();
+        assert_eq!(
+            drained,
+            (0..10).step_by(2).map(|i| (i, i * 10)).collect::<Vec<_>>()
+        );
+        for i in (0..10).step_by(2) {
+            expected.push((Arc::new(i), i * 10, RemovalCause::Explicit));
+        }
+        assert_eq!(cache.keys().count(), 0);
+        assert!(cache.drain().is_empty());
 
+        // The entries are visited in arbitrary order, so sort the notifications
+        // within each of the retain and drain calls.
+        actual.lock()[..5].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
+        actual.lock()[5..].sort_unstable_by_key(|(k, _, _)| Arc::clone(k));
         verify_notification_vec(&cache, actual, &expected);
     }
 
@@ -4780,7 +7820,7 @@ mod tests {
     fn no_batch_size_limit_on_eviction() {
         const MAX_CAPACITY: u64 = 20;
 
-        const EVICTION_TIMEOUT: Duration = Duration::from_nanos(0);
+        const EVICTION_TIMEOUT: Duration = Duration::from_nanos(1);
         const MAX_LOG_SYNC_REPEATS: u32 = 1;
         const EVICTION_BATCH_SIZE: u32 = 1;
 
@@ -4961,6 +8001,7 @@ mod tests {
 
         // Make the cache exterior immutable.
         let cache = cache;
+        assert!(cache.listener_enabled());
 
         // Insert an okay value.
         cache.insert("alice", "a0");
@@ -4970,11 +8011,13 @@ mod tests {
         cache.insert("alice", "panic now!");
         expected.push((Arc::new("alice"), "a0", RemovalCause::Replaced));
         cache.run_pending_tasks();
+        assert!(cache.listener_enabled());
 
         // Insert an okay value. This will replace the previous
         // value "panic now!" so the eviction listener will panic.
         cache.insert("alice", "a2");
         cache.run_pending_tasks();
+        assert!(!cache.listener_enabled());
         // No more removal notification should be sent.
 
         // Invalidate the okay value.
@@ -4984,6 +8027,94 @@ mod tests {
         verify_notification_vec(&cache, actual, &expected);
     }
 
+    #[test]
+    fn handle_panicking_callbacks() {
+        use crate::policy::{CallbackKind, CallbackPanicPolicy};
+
+        struct MyExpiry;
+
+        impl Expiry<&str, &str> for MyExpiry {
+            fn expire_after_create(
+                &self,
+                _key: &&str,
+                value: &&str,
+                _created_at: StdInstant,
+            ) -> Option<Duration> {
+                if *value == "panic now!" {
+                    panic!("Panic in expiry");
+                }
+                Some(Duration::from_secs(10))
+            }
+        }
+
+        let actual = Arc::new(Mutex::new(Vec::new()));
+        let mut expected = Vec::new();
+        let panicked = Arc::new(Mutex::new(Vec::new()));
+
+        let a1 = Arc::clone(&actual);
+        let listener = move |k, v, cause| {
+            if v == "panic now!" {
+                panic!("Panic in listener");
+            }
+            a1.lock().push((k, v, cause))
+        };
+        let p1 = Arc::clone(&panicked);
+        let handler = move |kind, _payload: &(dyn std::any::Any + Send)| p1.lock().push(kind);
+
+        let mut cache = Cache::builder()
+            .max_capacity(100)
+            .weigher(|_k, v: &&str| {
+                if *v == "panic now!" {
+                    panic!("Panic in weigher");
+                }
+                v.len() as u32
+            })
+            .expire_after(MyExpiry)
+            .eviction_listener(listener)
+            .callback_panic_policy(CallbackPanicPolicy::handler(handler))
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // The weigher and expiry panic. The weight falls back to 1 and the entry
+        // gets no expiration.
+        cache.insert("alice", "panic now!");
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 1);
+        let entry = cache.get_entry(&"alice").expect("Entry not found");
+        let md = entry.metadata().expect("Metadata not found");
+        assert_eq!(md.expiration_time(), None);
+        assert_eq!(
+            *panicked.lock(),
+            [CallbackKind::Weigher, CallbackKind::Expiry]
+        );
+
+        // The listener panics, but it stays enabled.
+        cache.insert("alice", "a1");
+        cache.run_pending_tasks();
+        assert!(cache.listener_enabled());
+        assert_eq!(
+            panicked.lock().last(),
+            Some(&CallbackKind::EvictionListener)
+        );
+
+        cache.invalidate(&"alice");
+        expected.push((Arc::new("alice"), "a1", RemovalCause::Explicit));
+        cache.run_pending_tasks();
+
+        // The init closure panics. The panic is reported and then propagated.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.get_with("bob", || panic!("Panic in init"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(panicked.lock().last(), Some(&CallbackKind::Init));
+        assert_eq!(panicked.lock().len(), 4);
+
+        verify_notification_vec(&cache, actual, &expected);
+    }
+
     // This test ensures that the `contains_key`, `get` and `invalidate` can use
     // borrowed form `&[u8]` for key with type `Vec<u8>`.
     // https://github.com/moka-rs/moka/issues/166
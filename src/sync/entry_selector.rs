@@ -57,7 +57,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -177,7 +177,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -599,7 +599,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -719,7 +719,7 @@ where
     /// | [`Op<V>`] | [`Entry<K, V>`] already exists? | [`CompResult<K, V>`] | Notes |
     /// |:--------- |:--- |:--------------------------- |:------------------------------- |
     /// | `Put(V)`  | no  | `Inserted(Entry<K, V>)`     | The new entry is returned.      |
-    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned.      |
+    /// | `Put(V)`  | yes | `ReplacedWith(Entry<K, V>)` | The new entry is returned. Its `old_value` is the replaced value. |
     /// | `Remove`  | no  | `StillNone(Arc<K>)`         |                                 |
     /// | `Remove`  | yes | `Removed(Entry<K, V>)`      | The removed entry is returned.  |
     /// | `Nop`     | no  | `StillNone(Arc<K>)`         |                                 |
@@ -0,0 +1,115 @@
+use crate::common::concurrent::{
+    constants::DEFAULT_MAINTENANCE_INTERVAL_MILLIS,
+    maintenance::{self, MaintenanceHandle, MaintenanceWaker},
+};
+
+use std::time::Duration;
+
+/// Runs the maintenance tasks of a cache in the background.
+///
+/// By default, a cache runs its maintenance tasks, such as removing expired
+/// entries and calling the eviction listener, only while it is being read or
+/// written, or when [`run_pending_tasks`][run-pending-tasks] is called. An idle
+/// cache keeps its expired entries, and the eviction listener is not called for
+/// them until the cache is used again.
+///
+/// Set a `MaintenanceScheduler` to the cache by the `maintenance_scheduler` method
+/// of the [`CacheBuilder`][builder] to run the tasks in the background. The
+/// scheduler runs them when the next entry will expire or after the interval,
+/// whichever comes first. It stops when the cache and all of its clones are
+/// dropped.
+///
+/// By default, the eviction listener is called for an expired entry at the next
+/// run of the tasks, so it can be called up to the interval later than the entry
+/// has expired. Enable [`proactive_expiration`][proactive-expiration] to call it
+/// closer to the expiration time of the entries with per-entry expiration.
+///
+/// [run-pending-tasks]: ./struct.Cache.html#method.run_pending_tasks
+/// [builder]: ./struct.CacheBuilder.html
+/// [proactive-expiration]: #method.proactive_expiration
+///
+/// # Example
+///
+/// ```rust
+/// use moka2::sync::{Cache, MaintenanceScheduler};
+/// use std::time::Duration;
+///
+/// let cache = Cache::builder()
+///     .time_to_live(Duration::from_secs(30))
+///     .eviction_listener(|key, _value, cause| {
+///         println!("Evicted {key:?} ({cause:?})");
+///     })
+///     .maintenance_scheduler(
+///         MaintenanceScheduler::thread().interval(Duration::from_secs(5)),
+///     )
+///     .build();
+///
+/// cache.insert("key", "value");
+/// ```
+#[derive(Clone, Debug)]
+pub struct MaintenanceScheduler {
+    pub(super) interval: Duration,
+    proactive_expiration: bool,
+}
+
+impl MaintenanceScheduler {
+    /// Creates a scheduler that runs the maintenance tasks on a dedicated thread.
+    ///
+    /// The thread is spawned when the cache is built.
+    pub fn thread() -> Self {
+        Self {
+            interval: Duration::from_millis(DEFAULT_MAINTENANCE_INTERVAL_MILLIS),
+            proactive_expiration: false,
+        }
+    }
+
+    /// Sets the maximum interval between two runs of the maintenance tasks.
+    ///
+    /// The default value is one second.
+    ///
+    /// # Panics
+    ///
+    /// `CacheBuilder::build*` methods will panic if the given `interval` is zero.
+    pub fn interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Sets whether to remove the entries with per-entry expiration (set by an
+    /// [`Expiry`][expiry] or `insert_with_ttl`) at their expiration times.
+    ///
+    /// When enabled, the scheduler sleeps until the earliest of these entries
+    /// expires, and then removes the expired entries and calls the eviction
+    /// listener for them. It does not run the other maintenance tasks until the
+    /// interval has elapsed. The listener is called within about one second (the
+    /// resolution of the timers) after the expiration time.
+    ///
+    /// An entry gets its timer when its insertion is processed by the maintenance
+    /// tasks, so an entry inserted to an idle cache may expire before that.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [expiry]: ../policy/trait.Expiry.html
+    pub fn proactive_expiration(self, enabled: bool) -> Self {
+        Self {
+            proactive_expiration: enabled,
+            ..self
+        }
+    }
+
+    /// Starts a thread that runs the given maintenance jobs. A job returns the
+    /// delay until its next run, or `None` if its cache has been dropped.
+    ///
+    /// Returns a waker for the thread if the expiration timers should run
+    /// proactively.
+    pub(crate) fn start<F>(&self, mut jobs: Vec<F>) -> (MaintenanceHandle, Option<MaintenanceWaker>)
+    where
+        F: FnMut(Duration) -> Option<Duration> + Send + 'static,
+    {
+        let interval = self.interval;
+        let (handle, waker) = maintenance::spawn_maintenance_thread(interval, move || {
+            jobs.iter_mut()
+                .try_fold(interval, |delay, job| job(interval).map(|d| delay.min(d)))
+        });
+        (handle, self.proactive_expiration.then_some(waker))
+    }
+}
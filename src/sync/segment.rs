@@ -1,11 +1,19 @@
-use super::{cache::Cache, CacheBuilder, OwnedKeyEntrySelector, RefKeyEntrySelector};
-use crate::common::concurrent::Weigher;
+use super::{
+    cache::Cache, CacheBuilder, KeyGuard, MaintenanceScheduler, OwnedKeyEntrySelector,
+    RefKeyEntrySelector,
+};
+use crate::common::{
+    cache_metrics::MetricsSnapshot,
+    concurrent::{maintenance::MaintenanceHandle, Weigher},
+    tag::TagExtractor,
+};
 use crate::{
+    clock::CacheClock,
     common::HousekeeperConfig,
     notification::EvictionListener,
-    policy::{EvictionPolicy, ExpirationPolicy},
-    sync_base::iter::{Iter, ScanningGet},
-    Entry, Policy, PredicateError,
+    policy::{CallbackPanicPolicy, EvictionPolicy, ExpirationPolicy},
+    sync_base::iter::{Iter, IterWithMetadata, Keys, ScanningGet, Values},
+    Entry, HeapSize, KeyLockError, MaintenanceStats, MemoryReport, Policy, PredicateError,
 };
 
 use std::{
@@ -14,6 +22,7 @@ use std::{
     fmt,
     hash::{BuildHasher, Hash, Hasher},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 /// A thread-safe concurrent in-memory cache, with multiple internal segments.
@@ -105,9 +114,14 @@ where
             None,
             EvictionPolicy::default(),
             None,
+            CallbackPanicPolicy::default(),
             ExpirationPolicy::default(),
             HousekeeperConfig::default(),
             false,
+            false,
+            None,
+            None,
+            None,
         )
     }
 
@@ -137,6 +151,17 @@ impl<K, V, S> SegmentedCache<K, V, S> {
         policy
     }
 
+    /// Returns `true` if the eviction listener is set and has not been disabled in
+    /// any segment.
+    ///
+    /// Under the default [`CallbackPanicPolicy`][callback-panic-policy], the cache
+    /// disables the listener once it panicked. This method lets you detect it.
+    ///
+    /// [callback-panic-policy]: ../policy/struct.CallbackPanicPolicy.html
+    pub fn listener_enabled(&self) -> bool {
+        self.inner.segments.iter().all(Cache::listener_enabled)
+    }
+
     /// Returns an approximate number of entries in this cache.
     ///
     /// The value returned is _an estimate_; the actual count may differ if there are
@@ -212,9 +237,14 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<EvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
         expiration_policy: ExpirationPolicy<K, V>,
         housekeeper_config: HousekeeperConfig,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
     ) -> Self {
         Self {
             inner: Arc::new(Inner::new(
@@ -226,9 +256,14 @@ where
                 weigher,
                 eviction_policy,
                 eviction_listener,
+                callback_panic_policy,
                 expiration_policy,
                 housekeeper_config,
                 invalidator_enabled,
+                key_locks_enabled,
+                tag_extractor,
+                clock,
+                maintenance_scheduler,
             )),
         }
     }
@@ -272,6 +307,51 @@ where
             .map(Entry::into_value)
     }
 
+    /// Returns a _clone_ of the value corresponding to the key without affecting
+    /// the cache policies.
+    ///
+    /// See [`Cache::peek`](./struct.Cache.html#method.peek) for more details.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .peek_with_hash(key, hash, false)
+            .map(Entry::into_value)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the metadata of the entry,
+    /// without affecting the cache policies.
+    ///
+    /// See [`Cache::peek_entry`](./struct.Cache.html#method.peek_entry) for more
+    /// details.
+    pub fn peek_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).peek_with_hash(key, hash, true)
+    }
+
+    /// Returns an [`Entry`](../struct.Entry.html) holding a _clone_ of the key and
+    /// value corresponding to the key, and a snapshot of the metadata of the entry.
+    ///
+    /// See [`Cache::get_entry`](./struct.Cache.html#method.get_entry) for more
+    /// details.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).get_entry_with_hash(key, hash)
+    }
+
     pub fn entry(&self, key: K) -> OwnedKeyEntrySelector<'_, K, V, S>
     where
         K: Hash + Eq,
@@ -465,6 +545,154 @@ where
         self.inner.select(hash).insert_with_hash(key, hash, value);
     }
 
+    /// Inserts a key-value pair into the cache, and makes the entry expire after
+    /// the given duration.
+    ///
+    /// See [`Cache::insert_with_ttl`](./struct.Cache.html#method.insert_with_ttl)
+    /// for more details.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash_and_ttl(key, hash, value, Some(ttl));
+    }
+
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given deadline.
+    ///
+    /// See [`Cache::insert_with_deadline`](./struct.Cache.html#method.insert_with_deadline)
+    /// for more details.
+    pub fn insert_with_deadline(&self, key: K, value: V, deadline: std::time::Instant) {
+        let hash = self.inner.hash(&key);
+        let segment = self.inner.select(hash);
+        let ttl = segment.duration_until(deadline);
+        segment.insert_with_hash_and_ttl(Arc::new(key), hash, value, Some(ttl));
+    }
+
+    /// Inserts a key-value pair into the cache, and makes the entry expire at the
+    /// given wall-clock time. Returns `false` if the time has already passed.
+    ///
+    /// See [`Cache::insert_expiring_at`](./struct.Cache.html#method.insert_expiring_at)
+    /// for more details.
+    pub fn insert_expiring_at(&self, key: K, value: V, expires_at: SystemTime) -> bool {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash_expiring_at(key, hash, value, expires_at)
+    }
+
+    /// Inserts a key-value pair into the cache, and returns a _clone_ of the value
+    /// that was replaced, if any.
+    ///
+    /// See [`Cache::swap`](./struct.Cache.html#method.swap) for more details.
+    pub fn swap(&self, key: K, value: V) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner.select(hash).swap_with_hash(key, hash, value)
+    }
+
+    /// Inserts a key-value pair into the cache only if the cache does not have a
+    /// live entry for the key. Returns a _clone_ of the existing value if any.
+    ///
+    /// See [`Cache::insert_if_absent`](./struct.Cache.html#method.insert_if_absent)
+    /// for more details.
+    pub fn insert_if_absent(&self, key: K, value: V) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_if_absent_with_hash(key, hash, value)
+    }
+
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key and `condition` returns `true` for the current value. Returns a _clone_
+    /// of the previous value if the value was replaced.
+    ///
+    /// See [`Cache::replace_if`](./struct.Cache.html#method.replace_if) for more
+    /// details.
+    pub fn replace_if<Q>(&self, key: &Q, value: V, condition: impl FnMut(&V) -> bool) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .replace_with_hash_if(key, hash, value, condition)
+            .ok()
+    }
+
+    /// Replaces the value for the key only if the cache has a live entry for the
+    /// key. Returns a _clone_ of the previous value if the value was replaced.
+    pub fn replace_if_present<Q>(&self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.replace_if(key, value, |_| true)
+    }
+
+    /// Replaces the value for the key only if the current value is equal to
+    /// `expected`.
+    ///
+    /// See [`Cache::replace`](./struct.Cache.html#method.replace) for more
+    /// details.
+    pub fn replace<Q>(&self, key: &Q, expected: &V, new: V) -> Result<V, Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .replace_with_hash_if(key, hash, new, |v| v == expected)
+    }
+
+    /// Sets the expiration of the existing entry for the key to the given duration
+    /// from now. Returns `false` if the cache does not contain a value for the
+    /// key.
+    ///
+    /// See [`Cache::set_expiry`](./struct.Cache.html#method.set_expiry) for more
+    /// details.
+    pub fn set_expiry<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).set_expiry_with_hash(key, hash, ttl)
+    }
+
+    /// Records a read access to the entry for the key. Returns `false` if the cache
+    /// does not contain a value for the key.
+    pub fn touch<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .get_with_hash(key, hash, false)
+            .is_some()
+    }
+
+    /// Returns the remaining time until the entry for the key expires.
+    ///
+    /// See [`Cache::expires_in`](./struct.Cache.html#method.expires_in) for more
+    /// details.
+    pub fn expires_in<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).expires_in_with_hash(key, hash)
+    }
+
     /// Discards any cached value for the key.
     ///
     /// If you need to get a the value that has been discarded, use the
@@ -501,6 +729,41 @@ where
             .invalidate_with_hash(key, hash, true)
     }
 
+    /// Discards the cached value for the key only if `condition` returns `true`
+    /// for the value, and returns a _clone_ of the discarded value.
+    ///
+    /// See [`Cache::remove_if`](./struct.Cache.html#method.remove_if) for more
+    /// details.
+    pub fn remove_if<Q>(&self, key: &Q, condition: impl FnMut(&V) -> bool) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select(hash)
+            .remove_with_hash_if(key, hash, condition)
+    }
+
+    /// Locks the key and returns a [`KeyGuard`] that unlocks it when dropped.
+    ///
+    /// See [`Cache::lock_key`] for the details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyLockError::KeyLocksDisabled`] if the cache was built without
+    /// calling the [`support_key_locks`][support-key-locks] method of the builder.
+    ///
+    /// [support-key-locks]: ./struct.CacheBuilder.html#method.support_key_locks
+    pub fn lock_key<Q>(&self, key: &Q) -> Result<KeyGuard<'_, K, S>, KeyLockError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).lock_key(key)
+    }
+
     /// Discards all cached values.
     ///
     /// This method returns immediately and a background thread will evict all the
@@ -553,6 +816,22 @@ where
         Ok(())
     }
 
+    /// Discards all cached entries having the given tag.
+    ///
+    /// The tags of the entries are given by the tag extractor closure set by
+    /// [`CacheBuilder::tags`][builder-tags] method. This method does nothing if the
+    /// cache does not have a tag extractor.
+    ///
+    /// See [`Cache::invalidate_tag`][cache-invalidate-tag] for more details.
+    ///
+    /// [builder-tags]: ./struct.CacheBuilder.html#method.tags
+    /// [cache-invalidate-tag]: ./struct.Cache.html#method.invalidate_tag
+    pub fn invalidate_tag(&self, tag: &str) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_tag(tag);
+        }
+    }
+
     /// Creates an iterator visiting all key-value pairs in arbitrary order. The
     /// iterator element type is `(Arc<K>, V)`, where `V` is a clone of a stored
     /// value.
@@ -612,6 +891,50 @@ where
         Iter::with_multiple_cache_segments(segments, num_cht_segments)
     }
 
+    /// Creates an iterator visiting all entries in arbitrary order, yielding a
+    /// snapshot of the metadata of each entry along with the key and value.
+    ///
+    /// See [`Cache::iter_with_metadata`](./struct.Cache.html#method.iter_with_metadata)
+    /// for more details.
+    pub fn iter_with_metadata(&self) -> IterWithMetadata<'_, K, V> {
+        IterWithMetadata::new(self.iter())
+    }
+
+    /// Creates an iterator visiting all keys in arbitrary order.
+    ///
+    /// See [`Cache::keys`](./struct.Cache.html#method.keys) for more details.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(self.iter())
+    }
+
+    /// Creates an iterator visiting all values in arbitrary order.
+    ///
+    /// See [`Cache::values`](./struct.Cache.html#method.values) for more details.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(self.iter())
+    }
+
+    /// Retains only the entries for which `f` returns `true`, and discards the
+    /// others immediately.
+    ///
+    /// See [`Cache::retain`](./struct.Cache.html#method.retain) for more details.
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        for segment in self.inner.segments.iter() {
+            segment.retain_and(&mut f, |_, _| ());
+        }
+    }
+
+    /// Discards all entries from the cache immediately, and returns them.
+    ///
+    /// See [`Cache::drain`](./struct.Cache.html#method.drain) for more details.
+    pub fn drain(&self) -> Vec<(Arc<K>, V)> {
+        let mut drained = Vec::new();
+        for segment in self.inner.segments.iter() {
+            segment.retain_and(|_, _| false, |k, v| drained.push((k, v)));
+        }
+        drained
+    }
+
     /// Performs any pending maintenance operations needed by the cache.
     pub fn run_pending_tasks(&self) {
         for segment in self.inner.segments.iter() {
@@ -619,6 +942,84 @@ where
         }
     }
 
+    /// Shrinks the internal concurrent hash tables of the segments to fit their
+    /// current number of entries.
+    ///
+    /// See [`Cache::shrink_to_fit`](./struct.Cache.html#method.shrink_to_fit) for
+    /// more details.
+    pub fn shrink_to_fit(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.shrink_to_fit();
+        }
+    }
+
+    /// Returns the earliest time when an entry with per-entry expiration will
+    /// expire, or `None` if there is no such entry.
+    ///
+    /// See [`Cache::next_expiration`](./struct.Cache.html#method.next_expiration)
+    /// for the details.
+    pub fn next_expiration(&self) -> Option<std::time::Instant> {
+        self.inner
+            .segments
+            .iter()
+            .filter_map(|seg| seg.next_expiration())
+            .min()
+    }
+
+    /// Returns a snapshot of the statistics of the maintenance tasks of the cache,
+    /// summed over all segments.
+    pub fn maintenance_stats(&self) -> MaintenanceStats {
+        let mut stats = MaintenanceStats::default();
+        for segment in self.inner.segments.iter() {
+            stats.merge(&segment.maintenance_stats());
+        }
+        stats
+    }
+
+    /// Writes the metrics of the cache, summed over all segments, in the
+    /// Prometheus text exposition format. See [`Cache::render_prometheus`][render]
+    /// for the details.
+    ///
+    /// [render]: ./struct.Cache.html#method.render_prometheus
+    pub fn render_prometheus(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        let mut metrics = MetricsSnapshot::default();
+        for segment in self.inner.segments.iter() {
+            metrics.merge(&segment.base.metrics_snapshot());
+        }
+        metrics.max_capacity = self.policy().max_capacity();
+        metrics.render_prometheus(self.name(), w)
+    }
+
+    /// Returns an approximate breakdown of the memory used by the cache, summed
+    /// over all segments. See [`Cache::memory_usage`][memory-usage] for the
+    /// details.
+    ///
+    /// [memory-usage]: ./struct.Cache.html#method.memory_usage
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        for segment in self.inner.segments.iter() {
+            report.merge(&segment.memory_usage());
+        }
+        report
+    }
+
+    /// Returns the same report as [`memory_usage`](#method.memory_usage), but also
+    /// includes the heap memory owned by the keys and values. See
+    /// [`Cache::memory_usage_with_heap_size`][with-heap-size] for the details.
+    ///
+    /// [with-heap-size]: ./struct.Cache.html#method.memory_usage_with_heap_size
+    pub fn memory_usage_with_heap_size(&self) -> MemoryReport
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let mut report = MemoryReport::default();
+        for segment in self.inner.segments.iter() {
+            report.merge(&segment.memory_usage_with_heap_size());
+        }
+        report
+    }
+
     // /// This is used by unit tests to get consistent result.
     // #[cfg(test)]
     // pub(crate) fn reconfigure_for_testing(&mut self) {
@@ -717,6 +1118,8 @@ struct Inner<K, V, S> {
     segments: Box<[Cache<K, V, S>]>,
     build_hasher: S,
     segment_shift: u32,
+    /// Stops the maintenance thread when dropped.
+    _maintenance: Option<MaintenanceHandle>,
 }
 
 impl<K, V, S> Inner<K, V, S>
@@ -738,9 +1141,14 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<EvictionListener<K, V>>,
+        callback_panic_policy: CallbackPanicPolicy,
         expiration_policy: ExpirationPolicy<K, V>,
         housekeeper_config: HousekeeperConfig,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
+        maintenance_scheduler: Option<MaintenanceScheduler>,
     ) -> Self {
         assert!(num_segments > 0);
 
@@ -762,18 +1170,36 @@ where
                     weigher.clone(),
                     eviction_policy.clone(),
                     eviction_listener.clone(),
+                    callback_panic_policy.clone(),
                     expiration_policy.clone(),
                     housekeeper_config.clone(),
                     invalidator_enabled,
+                    key_locks_enabled,
+                    tag_extractor.clone(),
+                    clock.clone(),
+                    None,
                 )
             })
             .collect::<Vec<_>>();
 
+        // Run the maintenance tasks of all segments on one thread.
+        let maintenance = maintenance_scheduler.map(|scheduler| {
+            let jobs = segments.iter().map(|c| c.base.maintenance_job()).collect();
+            let (handle, waker) = scheduler.start(jobs);
+            if let Some(waker) = waker {
+                for segment in &segments {
+                    segment.base.set_maintenance_waker(waker.clone());
+                }
+            }
+            handle
+        });
+
         Self {
             desired_capacity: max_capacity,
             segments: segments.into_boxed_slice(),
             build_hasher,
             segment_shift,
+            _maintenance: maintenance,
         }
     }
 
@@ -1254,6 +1680,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn invalidate_tag() {
+        const NUM_KEYS: usize = 50;
+
+        let cache = SegmentedCache::builder(4)
+            .max_capacity(100)
+            .tags(|k: &usize, _v| vec![format!("mod3:{}", k % 3).into()].into())
+            .build();
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, key);
+        }
+
+        cache.invalidate_tag("mod3:0");
+
+        for key in 0..NUM_KEYS {
+            assert_eq!(cache.contains_key(&key), key % 3 != 0, "key: {key}");
+        }
+    }
+
     #[test]
     fn test_iter() {
         const NUM_KEYS: usize = 50;
@@ -8,7 +8,13 @@ use std::{
 use triomphe::Arc as TrioArc;
 
 use crate::{
+    common::{
+        cache_metrics::{LoadOutcome, LoadRecorder, MetricsCounters},
+        callback_panic::CallbackPanicReporter,
+        memory_report::{trio_arc_alloc_size, MemoryReport},
+    },
     ops::compute::{CompResult, Op},
+    policy::CallbackKind,
     Entry,
 };
 
@@ -52,6 +58,7 @@ pub(crate) struct ValueInitializer<K, V, S> {
     // we can always downcast the trait object ErrorObject (in Waiter<V>) into
     // its concrete type.
     waiters: crate::cht::SegmentedHashMap<(Arc<K>, TypeId), Waiter<V>, S>,
+    panic_reporter: CallbackPanicReporter,
 }
 
 impl<K, V, S> ValueInitializer<K, V, S>
@@ -60,21 +67,31 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn with_hasher(hasher: S) -> Self {
+    pub(crate) fn new(hasher: S, panic_reporter: CallbackPanicReporter) -> Self {
         Self {
             waiters: crate::cht::SegmentedHashMap::with_num_segments_and_hasher(
                 WAITER_MAP_NUM_SEGMENTS,
                 hasher,
             ),
+            panic_reporter,
         }
     }
 
+    pub(crate) fn record_memory_usage(&self, report: &mut MemoryReport) {
+        let usage = self.waiters.memory_usage();
+        report.waiters =
+            report.add_hash_table(&usage, trio_arc_alloc_size::<RwLock<WaiterValue<V>>>());
+    }
+
     /// # Panics
     /// Panics if the `init` closure has been panicked.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn try_init_or_read<O, E>(
         &self,
         key: &Arc<K>,
         type_id: TypeId,
+        // Counters to record the outcome of the load.
+        metrics: &MetricsCounters,
         // Closure to get an existing value from cache.
         mut get: impl FnMut() -> Option<V>,
         // Closure to initialize a new value.
@@ -94,10 +111,14 @@ where
         const MAX_RETRIES: usize = 200;
         let mut retries = 0;
 
+        let recorder = LoadRecorder::start(metrics);
         let (w_key, w_hash) = self.waiter_key_hash(key, type_id);
 
         let waiter = TrioArc::new(RwLock::new(WaiterValue::Computing));
         let mut lock = waiter.write();
+        // The number of the other callers waiting for our waiter. (Our waiter is
+        // also referenced by the waiter map)
+        let num_waiters = || TrioArc::count(&waiter) - 2;
 
         loop {
             let Some(existing_waiter) = self.try_insert_waiter(w_key.clone(), w_hash, &waiter)
@@ -109,8 +130,14 @@ where
             // Somebody else's waiter already exists, so wait for its result to become available.
             let waiter_result = existing_waiter.read();
             match &*waiter_result {
-                WaiterValue::Ready(Ok(value)) => return ReadExisting(value.clone()),
-                WaiterValue::Ready(Err(e)) => return InitErr(Arc::clone(e).downcast().unwrap()),
+                WaiterValue::Ready(Ok(value)) => {
+                    recorder.finish(LoadOutcome::Coalesced, 0);
+                    return ReadExisting(value.clone());
+                }
+                WaiterValue::Ready(Err(e)) => {
+                    recorder.finish(LoadOutcome::Coalesced, 0);
+                    return InitErr(Arc::clone(e).downcast().unwrap());
+                }
                 // Somebody else's init closure has been panicked.
                 WaiterValue::InitClosurePanicked => {
                     retries += 1;
@@ -138,6 +165,7 @@ where
             // Yes. Set the waiter value, remove our waiter, and return
             // the existing value.
             *lock = WaiterValue::Ready(Ok(value.clone()));
+            recorder.finish(LoadOutcome::ReadExisting, num_waiters());
             self.remove_waiter(w_key, w_hash);
             return InitResult::ReadExisting(value);
         }
@@ -148,24 +176,30 @@ where
         match catch_unwind(AssertUnwindSafe(init)) {
             // Evaluated.
             Ok(value) => {
-                let init_res = match post_init(value) {
+                let (init_res, outcome) = match post_init(value) {
                     Ok(value) => {
                         insert(value.clone());
                         *lock = WaiterValue::Ready(Ok(value.clone()));
-                        InitResult::Initialized(value)
+                        (InitResult::Initialized(value), LoadOutcome::Initialized)
                     }
                     Err(e) => {
                         let err: ErrorObject = Arc::new(e);
                         *lock = WaiterValue::Ready(Err(Arc::clone(&err)));
-                        InitResult::InitErr(err.downcast().unwrap())
+                        (
+                            InitResult::InitErr(err.downcast().unwrap()),
+                            LoadOutcome::InitError,
+                        )
                     }
                 };
+                recorder.finish(outcome, num_waiters());
                 self.remove_waiter(w_key, w_hash);
                 init_res
             }
             // Panicked.
             Err(payload) => {
                 *lock = WaiterValue::InitClosurePanicked;
+                self.panic_reporter.report(CallbackKind::Init, &*payload);
+                recorder.finish(LoadOutcome::Panicked, num_waiters());
                 // Remove the waiter so that others can retry.
                 self.remove_waiter(w_key, w_hash);
                 resume_unwind(payload);
@@ -192,6 +226,13 @@ where
     {
         use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 
+        // Hold the key lock (if enabled) across the read, the closure and the write
+        // below, so that they do not interleave with the updates made under a
+        // `KeyGuard`. Lock it before inserting our waiter, because the holder of the
+        // key lock may be waiting for the waiter.
+        let kl = cache.base.maybe_key_lock(&c_key);
+        let _klg = &kl.as_ref().map(|kl| kl.lock());
+
         let type_id = TypeId::of::<ComputeNone>();
         let (w_key, w_hash) = self.waiter_key_hash(&c_key, type_id);
         let waiter = TrioArc::new(RwLock::new(WaiterValue::Computing));
@@ -247,6 +288,7 @@ where
             // Panicked.
             Err(payload) => {
                 *lock = WaiterValue::InitClosurePanicked;
+                self.panic_reporter.report(CallbackKind::Init, &*payload);
                 // Remove the waiter so that others can retry.
                 self.remove_waiter(w_key, w_hash);
                 resume_unwind(payload);
@@ -264,24 +306,24 @@ where
         let result = match op {
             Op::Nop => {
                 if let Some(value) = maybe_value {
-                    Ok(CompResult::Unchanged(Entry::new(
-                        Some(c_key),
-                        value,
-                        false,
-                        false,
-                    )))
+                    let md = cache.base.entry_metadata_with_hash(&c_key, c_hash);
+                    let entry = Entry::new(Some(c_key), value, false, false).with_metadata(md);
+                    Ok(CompResult::Unchanged(entry))
                 } else {
                     Ok(CompResult::StillNone(c_key))
                 }
             }
             Op::Put(value) => {
-                cache.insert_with_hash(Arc::clone(&c_key), c_hash, value.clone());
+                let prev_v = cache.swap_with_hash(Arc::clone(&c_key), c_hash, value.clone());
+                let md = cache.base.entry_metadata_with_hash(&c_key, c_hash);
                 if entry_existed {
                     crossbeam_epoch::pin().flush();
-                    let entry = Entry::new(Some(c_key), value, true, true);
+                    let entry = Entry::new(Some(c_key), value, true, true)
+                        .with_old_value(prev_v)
+                        .with_metadata(md);
                     Ok(CompResult::ReplacedWith(entry))
                 } else {
-                    let entry = Entry::new(Some(c_key), value, true, false);
+                    let entry = Entry::new(Some(c_key), value, true, false).with_metadata(md);
                     Ok(CompResult::Inserted(entry))
                 }
             }
@@ -7,7 +7,7 @@ pub(crate) mod base_cache;
 mod invalidator;
 
 #[cfg(feature = "sync")]
-mod key_lock;
+pub(crate) mod key_lock;
 
 /// The type of the unique ID to identify a predicate used by
 /// [`Cache::invalidate_entries_if`][invalidate-if] method.
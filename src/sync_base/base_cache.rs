@@ -1,33 +1,45 @@
 use super::{
     invalidator::{Invalidator, KeyDateLite, PredicateFun},
     iter::ScanningGet,
-    key_lock::{KeyLock, KeyLockMap},
-    PredicateId,
+    key_lock::{KeyGuard, KeyLock, KeyLockMap},
+    PredicateId, PredicateIdStr,
 };
 
 use crate::{
+    cht,
+    clock::CacheClock,
     common::{
         self,
+        cache_metrics::{MetricsCounters, MetricsSnapshot},
+        callback_panic::CallbackPanicReporter,
         concurrent::{
             atomic_time::AtomicInstant,
             constants::{
-                READ_LOG_CH_SIZE, READ_LOG_FLUSH_POINT, WRITE_LOG_CH_SIZE, WRITE_LOG_FLUSH_POINT,
+                MIN_MAINTENANCE_DELAY_MILLIS, READ_LOG_FLUSH_POINT, WRITE_LOG_FLUSH_POINT,
             },
             deques::Deques,
             entry_info::EntryInfo,
             housekeeper::{Housekeeper, InnerSync},
+            maintenance::{MaintenanceHandle, MaintenanceWaker},
             AccessTime, KeyHash, KeyHashDate, KvEntry, OldEntryInfo, ReadOp, ValueEntry, Weigher,
             WriteOp,
         },
+        dependency::DependencyGraph,
         deque::{DeqNode, Deque},
         frequency_sketch::FrequencySketch,
+        maintenance_stats::{MaintenanceCounters, PhaseCounts},
+        memory_report::{self, MemoryReport},
+        predicate::PredicateStatus,
+        tag::{TagExtractor, TagIndex},
         time::{CheckedTimeOps, Clock, Instant},
         timer_wheel::{ReschedulingResult, TimerWheel},
+        trace::enter_span,
+        wall_clock::WallClockDeadlines,
         CacheRegion, HousekeeperConfig,
     },
     notification::{notifier::RemovalNotifier, EvictionListener, RemovalCause},
     policy::{EvictionPolicy, EvictionPolicyConfig, ExpirationPolicy},
-    Entry, Expiry, Policy, PredicateError,
+    Entry, EntryMetadata, Expiry, HeapSize, KeyLockError, MaintenanceStats, Policy, PredicateError,
 };
 
 use crossbeam_channel::{Receiver, Sender, TrySendError};
@@ -41,9 +53,9 @@ use std::{
     rc::Rc,
     sync::{
         atomic::{AtomicBool, AtomicU8, Ordering},
-        Arc,
+        Arc, Weak,
     },
-    time::{Duration, Instant as StdInstant},
+    time::{Duration, Instant as StdInstant, SystemTime},
 };
 use triomphe::Arc as TrioArc;
 
@@ -54,6 +66,9 @@ pub(crate) struct BaseCache<K, V, S = RandomState> {
     read_op_ch: Sender<ReadOp<K, V>>,
     pub(crate) write_op_ch: Sender<WriteOp<K, V>>,
     pub(crate) housekeeper: Option<HouseKeeperArc>,
+    /// The handle to the background maintenance, if the cache has a maintenance
+    /// scheduler.
+    maintenance: Option<MaintenanceHandle>,
 }
 
 impl<K, V, S> Clone for BaseCache<K, V, S> {
@@ -67,6 +82,7 @@ impl<K, V, S> Clone for BaseCache<K, V, S> {
             read_op_ch: self.read_op_ch.clone(),
             write_op_ch: self.write_op_ch.clone(),
             housekeeper: self.housekeeper.clone(),
+            maintenance: self.maintenance.clone(),
         }
     }
 }
@@ -104,17 +120,25 @@ impl<K, V, S> BaseCache<K, V, S> {
         self.inner.is_removal_notifier_enabled()
     }
 
+    pub(crate) fn is_listener_enabled(&self) -> bool {
+        self.inner.is_listener_enabled()
+    }
+
     #[inline]
     pub(crate) fn current_time_from_expiration_clock(&self) -> Instant {
         self.inner.current_time_from_expiration_clock()
     }
 
-    pub(crate) fn notify_invalidate(&self, key: &Arc<K>, entry: &TrioArc<ValueEntry<K, V>>)
-    where
+    pub(crate) fn notify_invalidate(
+        &self,
+        key: &Arc<K>,
+        entry: &TrioArc<ValueEntry<K, V>>,
+        cause: RemovalCause,
+    ) where
         K: Send + Sync + 'static,
         V: Clone + Send + Sync + 'static,
     {
-        self.inner.notify_invalidate(key, entry);
+        self.inner.notify_invalidate(key, entry, cause);
     }
 }
 
@@ -126,6 +150,31 @@ where
     pub(crate) fn maybe_key_lock(&self, key: &Arc<K>) -> Option<KeyLock<'_, K, S>> {
         self.inner.maybe_key_lock(key)
     }
+
+    pub(crate) fn has_key_locks(&self) -> bool {
+        self.inner.key_locks.is_some()
+    }
+
+    pub(crate) fn lock_key(&self, key: Arc<K>) -> Result<KeyGuard<'_, K, S>, KeyLockError> {
+        let key_locks = self
+            .inner
+            .key_locks
+            .as_ref()
+            .ok_or(KeyLockError::KeyLocksDisabled)?;
+        Ok(key_locks.key_lock(&key).into_guard())
+    }
+
+    pub(crate) fn lock_keys(
+        &self,
+        keys: &[Arc<K>],
+    ) -> Result<Vec<KeyGuard<'_, K, S>>, KeyLockError> {
+        let key_locks = self
+            .inner
+            .key_locks
+            .as_ref()
+            .ok_or(KeyLockError::KeyLocksDisabled)?;
+        Ok(key_locks.lock_keys(keys))
+    }
 }
 
 impl<K, V, S> BaseCache<K, V, S>
@@ -144,14 +193,19 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<EvictionListener<K, V>>,
+        panic_reporter: CallbackPanicReporter,
         expiration_policy: ExpirationPolicy<K, V>,
         housekeeper_config: HousekeeperConfig,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
     ) -> Self {
         let (r_size, w_size) = if max_capacity == Some(0) {
             (0, 0)
         } else {
-            (READ_LOG_CH_SIZE, WRITE_LOG_CH_SIZE)
+            let conf = &housekeeper_config;
+            (conf.read_log_capacity, conf.write_log_capacity)
         };
         let is_eviction_listener_enabled = eviction_listener.is_some();
 
@@ -166,10 +220,14 @@ where
             weigher,
             eviction_policy,
             eviction_listener,
+            panic_reporter,
             r_rcv,
             w_rcv,
             expiration_policy,
             invalidator_enabled,
+            key_locks_enabled,
+            tag_extractor,
+            clock,
         ));
 
         Self {
@@ -180,6 +238,7 @@ where
                 is_eviction_listener_enabled,
                 housekeeper_config,
             ))),
+            maintenance: None,
         }
     }
 
@@ -218,12 +277,9 @@ where
         Q: Hash + Eq + ?Sized,
     {
         // Define a closure to record a read op.
-        let record = |op, now| {
-            self.record_read_op(op, now)
-                .expect("Failed to record a get op");
-        };
+        let record = |op, now| self.record_get_op(op, now);
         let ignore_if = None as Option<&mut fn(&V) -> bool>;
-        self.do_get_with_hash(key, hash, record, ignore_if, need_key)
+        self.do_get_with_hash(key, hash, record, ignore_if, need_key, false)
     }
 
     pub(crate) fn get_with_hash_and_ignore_if<Q, I>(
@@ -239,11 +295,8 @@ where
         I: FnMut(&V) -> bool,
     {
         // Define a closure to record a read op.
-        let record = |op, now| {
-            self.record_read_op(op, now)
-                .expect("Failed to record a get op");
-        };
-        self.do_get_with_hash(key, hash, record, ignore_if, need_key)
+        let record = |op, now| self.record_get_op(op, now);
+        self.do_get_with_hash(key, hash, record, ignore_if, need_key, false)
     }
 
     pub(crate) fn get_with_hash_without_recording<Q, I>(
@@ -259,10 +312,22 @@ where
     {
         // Define a closure that skips to record a read op.
         let record = |_op, _now| {};
-        self.do_get_with_hash(key, hash, record, ignore_if, false)
+        self.do_get_with_hash(key, hash, record, ignore_if, false, false)
             .map(Entry::into_value)
     }
 
+    /// Returns the entry with its key and metadata, and records a read.
+    pub(crate) fn get_entry_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Define a closure to record a read op.
+        let record = |op, now| self.record_get_op(op, now);
+        let ignore_if = None as Option<&mut fn(&V) -> bool>;
+        self.do_get_with_hash(key, hash, record, ignore_if, true, true)
+    }
+
     fn do_get_with_hash<Q, R, I>(
         &self,
         key: &Q,
@@ -270,6 +335,7 @@ where
         read_recorder: R,
         mut ignore_if: Option<&mut I>,
         need_key: bool,
+        need_metadata: bool,
     ) -> Option<Entry<K, V>>
     where
         K: Borrow<Q>,
@@ -311,6 +377,9 @@ where
             });
 
         if let Some((maybe_key, entry)) = maybe_entry {
+            self.inner
+                .dependencies
+                .record_read(&entry.entry_info().key_hash().key);
             let mut is_expiry_modified = false;
 
             // Call the user supplied `expire_after_read` method if any.
@@ -359,12 +428,13 @@ where
             entry.set_last_accessed(now);
 
             let v = entry.value.clone();
+            let maybe_md = need_metadata.then(|| self.entry_metadata(&entry));
             let op = ReadOp::Hit {
                 value_entry: entry,
                 is_expiry_modified,
             };
             read_recorder(op, now);
-            Some(Entry::new(maybe_key, v, false, false))
+            Some(Entry::new(maybe_key, v, false, false).with_metadata(maybe_md))
         } else {
             read_recorder(ReadOp::Miss(hash), now);
             None
@@ -380,6 +450,113 @@ where
             .get_key_value_and(key, hash, |k, _entry| Arc::clone(k))
     }
 
+    /// Returns the live entry for the key without recording a read. Unlike
+    /// `get_with_hash_without_recording`, this does not update the last accessed
+    /// time of the entry or call the `expire_after_read` method of the `Expiry`.
+    pub(crate) fn peek_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        need_metadata: bool,
+    ) -> Option<Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_map_disabled() {
+            return None;
+        }
+
+        let now = self.current_time_from_expiration_clock();
+        self.inner.get_key_value_and_then(key, hash, |k, entry| {
+            if self.is_valid_entry(k, entry, now) {
+                let maybe_md = need_metadata.then(|| self.entry_metadata(entry));
+                let ent = Entry::new(Some(Arc::clone(k)), entry.value.clone(), false, false);
+                Some(ent.with_metadata(maybe_md))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns a snapshot of the metadata of the live entry for the key. Unlike
+    /// `get_with_hash`, this does not record a read.
+    pub(crate) fn entry_metadata_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<EntryMetadata>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.current_time_from_expiration_clock();
+        self.inner.get_key_value_and_then(key, hash, |k, entry| {
+            if self.is_valid_entry(k, entry, now) {
+                Some(self.entry_metadata(entry))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Converts the given deadline to a duration from the current time of the
+    /// expiration clock. Returns `Duration::ZERO` if the deadline has passed.
+    pub(crate) fn duration_until(&self, deadline: StdInstant) -> Duration {
+        let now = self.current_time_from_expiration_clock();
+        deadline.saturating_duration_since(self.inner.clocks().to_std_instant(now))
+    }
+
+    /// Sets the per-entry expiration time of the live entry for the key to the
+    /// current time plus `ttl`. Returns the write op to schedule, or `None` if
+    /// there is no such entry.
+    pub(crate) fn set_expiry_with_hash<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        ttl: Duration,
+    ) -> Option<(WriteOp<K, V>, Instant)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_map_disabled() {
+            return None;
+        }
+
+        let now = self.current_time_from_expiration_clock();
+        let entry = self.inner.get_key_value_and_then(key, hash, |k, entry| {
+            if self.is_valid_entry(k, entry, now) {
+                Some(TrioArc::clone(entry))
+            } else {
+                None
+            }
+        })?;
+
+        entry.entry_info().set_expiration_time(now.checked_add(ttl));
+        // Return a write op rather than recording a read op, because read ops are
+        // discarded when the read log is full. The caller must schedule the op, so
+        // that `apply_writes` will reschedule the timer for the entry.
+        let op = WriteOp::SetExpiry { value_entry: entry };
+        Some((op, now))
+    }
+
+    /// Returns the remaining duration until the live entry for the key expires
+    /// by the per-entry expiration time, the time-to-live or the time-to-idle,
+    /// whichever comes first. Returns `None` if there is no such entry or the
+    /// entry never expires.
+    pub(crate) fn expires_in_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.current_time_from_expiration_clock();
+        self.inner.get_key_value_and_then(key, hash, |k, entry| {
+            if !self.is_valid_entry(k, entry, now) {
+                return None;
+            }
+            let i = &self.inner;
+            earliest_expiration_time(entry.entry_info(), i.time_to_live(), i.time_to_idle())
+                .and_then(|time| time.checked_duration_since(now))
+        })
+    }
+
     #[inline]
     pub(crate) fn remove_entry<Q>(&self, key: &Q, hash: u64) -> Option<KvEntry<K, V>>
     where
@@ -410,6 +587,10 @@ where
         self.inner.set_valid_after(now);
     }
 
+    pub(crate) fn shrink_to_fit(&self) {
+        self.inner.cache.shrink(cht::SHRINK_TO_FIT_LOAD_FACTOR);
+    }
+
     pub(crate) fn invalidate_entries_if(
         &self,
         predicate: PredicateFun<K, V>,
@@ -417,6 +598,241 @@ where
         let now = self.current_time_from_expiration_clock();
         self.inner.register_invalidation_predicate(predicate, now)
     }
+
+    pub(crate) fn predicate_status(&self, id: PredicateIdStr<'_>) -> Option<PredicateStatus> {
+        self.inner
+            .invalidator
+            .as_ref()
+            .and_then(|inv| inv.predicate_status(id))
+    }
+
+    pub(crate) fn cancel_predicate(&self, id: PredicateIdStr<'_>) -> bool {
+        self.inner
+            .invalidator
+            .as_ref()
+            .map_or(false, |inv| inv.cancel_predicate(id))
+    }
+
+    /// Returns the keys indexed under the tag, or `None` if this cache does not
+    /// have a tag extractor.
+    pub(crate) fn keys_for_tag(&self, tag: &str) -> Option<Vec<Arc<K>>> {
+        self.inner.tag_index.as_ref().map(|ti| ti.keys(tag))
+    }
+
+    pub(crate) fn has_tag(&self, key: &K, value: &V, tag: &str) -> bool {
+        self.inner
+            .tag_index
+            .as_ref()
+            .map_or(false, |ti| ti.has_tag(key, value, tag))
+    }
+
+    /// Calls `f` while tracking the keys read from this cache on the current
+    /// thread.
+    pub(crate) fn track_reads<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<Arc<K>>) {
+        self.inner.dependencies.track_reads(f)
+    }
+
+    pub(crate) fn record_read(&self, key: &Arc<K>) {
+        self.inner.dependencies.record_read(key);
+    }
+
+    pub(crate) fn add_dependencies(&self, key: &Arc<K>, dependencies: &[Arc<K>]) {
+        self.inner.dependencies.add(key, dependencies);
+    }
+
+    /// Removes the key from the dependency graph and returns the keys that
+    /// depended on it.
+    pub(crate) fn take_dependents(&self, key: &K) -> Vec<Arc<K>> {
+        self.inner.dependencies.take_dependents(key)
+    }
+
+    /// Returns a job that runs the pending tasks of this cache and returns the delay
+    /// until the next run, capped by the given interval. The job returns `None` if
+    /// the cache has been dropped.
+    pub(crate) fn maintenance_job(
+        &self,
+    ) -> impl FnMut(Duration) -> Option<Duration> + Send + 'static {
+        let mut job = MaintenanceJob {
+            inner: Arc::downgrade(&self.inner),
+            housekeeper: self.housekeeper.as_ref().map(Arc::downgrade),
+            next_full_run: None,
+        };
+        move |interval| job.run(interval)
+    }
+
+    pub(crate) fn set_maintenance_handle(&mut self, handle: MaintenanceHandle) {
+        self.maintenance = Some(handle);
+    }
+
+    /// Makes the background maintenance run the expiration timers proactively. The
+    /// waker is used to wake the maintenance up when an entry will expire before
+    /// its next run.
+    pub(crate) fn set_maintenance_waker(&self, waker: MaintenanceWaker) {
+        *self.inner.maintenance_waker.write() = Some(waker);
+    }
+
+    pub(crate) fn maintenance_stats(&self) -> MaintenanceStats {
+        self.inner.maintenance_counters.snapshot()
+    }
+
+    pub(crate) fn metrics_counters(&self) -> &MetricsCounters {
+        &self.inner.metrics_counters
+    }
+
+    pub(crate) fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let maintenance = self.inner.maintenance_counters.snapshot();
+        let mut metrics = self.inner.metrics_counters.snapshot();
+        metrics.entry_count = self.entry_count();
+        metrics.weighted_size = self.weighted_size();
+        metrics.max_capacity = self.inner.max_capacity;
+        metrics.entries_evicted = maintenance.entries_evicted();
+        metrics.entries_expired = maintenance.entries_expired();
+        metrics
+    }
+
+    pub(crate) fn memory_report(&self) -> MemoryReport {
+        let inner = &self.inner;
+        let mut report = MemoryReport::default();
+
+        let usage = inner.cache.memory_usage();
+        report.hash_table = report.add_hash_table(&usage, 0);
+        report.entries = (usage.len * memory_report::entry_alloc_size::<K, V>()) as u64;
+        report.deque_nodes = inner.deques.lock().allocated_bytes() as u64;
+        report.timer_nodes = inner.timer_wheel.lock().allocated_bytes() as u64;
+        report.frequency_sketch = inner.frequency_sketch.read().table_size();
+        report.op_channels =
+            (memory_report::channel_alloc_size::<ReadOp<K, V>>(inner.read_op_ch.capacity())
+                + memory_report::channel_alloc_size::<WriteOp<K, V>>(inner.write_op_ch.capacity()))
+                as u64;
+        if let Some(key_locks) = &inner.key_locks {
+            key_locks.record_memory_usage(&mut report);
+        }
+        report
+    }
+
+    /// Returns the heap memory owned by the keys and values of the entries.
+    pub(crate) fn heap_size_of_entries(&self) -> u64
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        let cache = &self.inner.cache;
+        let mut size = 0;
+        for segment in 0..cache.actual_num_segments() {
+            for key in cache.keys(segment, Arc::clone).unwrap_or_default() {
+                let hash = self.hash(&key);
+                size += key.heap_size();
+                size += cache
+                    .get_key_value_and_then(
+                        hash,
+                        |k| k == &key,
+                        |_, entry| Some(entry.value.heap_size()),
+                    )
+                    .unwrap_or_default();
+            }
+        }
+        size as u64
+    }
+
+    /// Returns the time when the earliest entry in the timer wheel will expire.
+    pub(crate) fn next_expiration(&self) -> Option<StdInstant> {
+        let time = self.inner.timer_wheel.lock().next_expiration_time()?;
+        Some(self.inner.clocks().to_std_instant(time))
+    }
+
+    /// Converts the given wall-clock deadline to a duration from the current
+    /// time. Returns `None` if the deadline has passed.
+    pub(crate) fn duration_until_system_time(&self, expires_at: SystemTime) -> Option<Duration> {
+        expires_at
+            .duration_since(self.inner.system_time_from_expiration_clock())
+            .ok()
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Records the wall-clock deadline of the entry inserted by the upsert op, so
+    /// that its expiration time can be converted again when the wall clock jumps.
+    pub(crate) fn register_wall_clock_deadline(
+        &self,
+        key: Arc<K>,
+        expires_at: SystemTime,
+        op: &WriteOp<K, V>,
+    ) {
+        if let WriteOp::Upsert {
+            value_entry,
+            entry_gen,
+            ..
+        } = op
+        {
+            let i = &self.inner;
+            let anchor = (
+                i.system_time_from_expiration_clock(),
+                i.current_time_from_expiration_clock(),
+            );
+            i.wall_clock_deadlines.register(
+                key,
+                expires_at,
+                value_entry.entry_info(),
+                *entry_gen,
+                anchor,
+            );
+        }
+    }
+}
+
+/// Runs the pending tasks of a cache on a maintenance thread. Holds weak
+/// references to the cache so that it does not keep the cache alive.
+struct MaintenanceJob<K, V, S> {
+    inner: Weak<Inner<K, V, S>>,
+    housekeeper: Option<Weak<Housekeeper>>,
+    /// When to run all the pending tasks next time. Used only when the expiration
+    /// timers run proactively.
+    next_full_run: Option<Instant>,
+}
+
+// TODO: https://github.com/moka-rs/moka/issues/54
+#[allow(clippy::non_send_fields_in_send_ty)]
+// The same as `sync::Cache`, which holds strong references to the same data.
+unsafe impl<K, V, S> Send for MaintenanceJob<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send,
+{
+}
+
+impl<K, V, S> MaintenanceJob<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn run(&mut self, interval: Duration) -> Option<Duration> {
+        let hk = self.housekeeper.as_ref()?.upgrade()?;
+        let inner = self.inner.upgrade()?;
+        let proactive = inner.maintenance_waker.read().is_some();
+        let now = inner.current_time_from_expiration_clock();
+
+        if !proactive || self.next_full_run.map_or(true, |t| t <= now) {
+            hk.run_pending_tasks(&*inner);
+            // The housekeeper needs to be dropped before the inner is dropped.
+            std::mem::drop(hk);
+            let delay = inner.next_maintenance_delay(interval, !proactive);
+            if !proactive {
+                return Some(delay);
+            }
+            self.next_full_run = now.checked_add(delay);
+        } else {
+            std::mem::drop(hk);
+            inner.run_expiration_timers();
+        }
+
+        let now = inner.current_time_from_expiration_clock();
+        let until_full_run = self
+            .next_full_run
+            .and_then(|t| t.checked_duration_since(now))
+            .unwrap_or_default();
+        Some(inner.schedule_timer_wakeup(now, until_full_run))
+    }
 }
 
 //
@@ -453,6 +869,28 @@ where
         })
     }
 
+    fn scanning_get_with_metadata(&self, key: &Arc<K>) -> Option<(V, EntryMetadata)> {
+        let hash = self.hash(key);
+        let now = self.current_time_from_expiration_clock();
+        self.inner.get_key_value_and_then(key, hash, |k, entry| {
+            if self.is_valid_entry(k, entry, now) {
+                Some((entry.value.clone(), self.entry_metadata(entry)))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn scanning_contains_key(&self, key: &Arc<K>) -> bool {
+        let hash = self.hash(key);
+        let now = self.current_time_from_expiration_clock();
+        self.inner
+            .get_key_value_and_then(key, hash, |k, entry| {
+                self.is_valid_entry(k, entry, now).then_some(())
+            })
+            .is_some()
+    }
+
     fn keys(&self, cht_segment: usize) -> Option<Vec<Arc<K>>> {
         self.inner.keys(cht_segment)
     }
@@ -467,6 +905,47 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
+    /// Returns a snapshot of the metadata of the entry.
+    fn entry_metadata(&self, entry: &ValueEntry<K, V>) -> EntryMetadata {
+        let i = &self.inner;
+        let ei = entry.entry_info();
+        let to_std = |time| i.clocks().to_std_instant(time);
+        EntryMetadata::new(
+            ei.last_modified().map(to_std),
+            ei.last_accessed().map(to_std),
+            earliest_expiration_time(ei, i.time_to_live(), i.time_to_idle()).map(to_std),
+            ei.policy_weight(),
+            entry.region().and_then(CacheRegion::entry_region),
+        )
+    }
+
+    /// Returns `true` if the entry is neither expired nor invalidated at `now`.
+    #[inline]
+    fn is_valid_entry(
+        &self,
+        key: &Arc<K>,
+        entry: &TrioArc<ValueEntry<K, V>>,
+        now: Instant,
+    ) -> bool {
+        let i = &self.inner;
+        let (ttl, tti, va) = (&i.time_to_live(), &i.time_to_idle(), &i.valid_after());
+
+        !is_expired_by_per_entry_ttl(entry.entry_info(), now)
+            && !is_expired_entry_wo(ttl, va, entry, now)
+            && !is_expired_entry_ao(tti, va, entry, now)
+            && !i.is_invalidated_entry(key, entry)
+    }
+
+    /// Records a read op of a `get` method, and counts it as a hit or a miss.
+    fn record_get_op(&self, op: ReadOp<K, V>, now: Instant) {
+        match &op {
+            ReadOp::Hit { .. } => self.inner.metrics_counters.record_hit(),
+            ReadOp::Miss(_) => self.inner.metrics_counters.record_miss(),
+        }
+        self.record_read_op(op, now)
+            .expect("Failed to record a get op");
+    }
+
     #[inline]
     fn record_read_op(
         &self,
@@ -476,19 +955,32 @@ where
         self.apply_reads_if_needed(&self.inner, now);
         let ch = &self.read_op_ch;
         match ch.try_send(op) {
+            Ok(()) => Ok(()),
             // Discard the ReadOp when the channel is full.
-            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.inner.maintenance_counters.record_read_op_dropped();
+                Ok(())
+            }
             Err(e @ TrySendError::Disconnected(_)) => Err(e),
         }
     }
 
+    /// Inserts or updates the entry. If `ttl` is `Some`, the per-entry expiration
+    /// time of the entry is set to the insertion time plus `ttl`, and the
+    /// `expire_after_*` methods of the `Expiry` (if any) are not called.
+    ///
+    /// Also returns the previous entry for the key if it was live (neither
+    /// expired nor invalidated) when it was replaced.
     #[inline]
+    // https://rust-lang.github.io/rust-clippy/master/index.html#type_complexity
+    #[allow(clippy::type_complexity)]
     pub(crate) fn do_insert_with_hash(
         &self,
         key: Arc<K>,
         hash: u64,
         value: V,
-    ) -> (WriteOp<K, V>, Instant) {
+        ttl: Option<Duration>,
+    ) -> (WriteOp<K, V>, Instant, Option<TrioArc<ValueEntry<K, V>>>) {
         let weight = self.inner.weigh(&key, &value);
         let op_cnt1 = Rc::new(AtomicU8::new(0));
         let op_cnt2 = Rc::clone(&op_cnt1);
@@ -525,8 +1017,9 @@ where
                 entry
             },
             // on_modify
-            |_k, old_entry| {
+            |k, old_entry| {
                 let old_weight = old_entry.policy_weight();
+                let is_live = self.is_valid_entry(k, old_entry, ts);
 
                 // Create this OldEntryInfo _before_ creating a new ValueEntry, so
                 // that the OldEntryInfo can preserve the old EntryInfo's
@@ -535,30 +1028,208 @@ where
                 let (entry, gen) = self.new_value_entry_from(value.clone(), ts, weight, old_entry);
                 let upd_op = WriteOp::new_upsert(&key, hash, &entry, gen, old_weight, weight);
                 let cnt = op_cnt2.fetch_add(1, Ordering::Relaxed);
-                op2 = Some((cnt, old_info, upd_op));
+                op2 = Some((cnt, is_live, old_info, upd_op));
                 entry
             },
         );
 
         match (op1, op2) {
-            (Some((_cnt, ins_op)), None) => self.do_post_insert_steps(ts, &key, ins_op),
+            (Some((_cnt, ins_op)), None) => {
+                let (op, ts) = self.do_post_insert_steps(ts, &key, ins_op, ttl);
+                (op, ts, None)
+            }
             (Some((cnt1, ins_op)), Some((cnt2, ..))) if cnt1 > cnt2 => {
-                self.do_post_insert_steps(ts, &key, ins_op)
+                let (op, ts) = self.do_post_insert_steps(ts, &key, ins_op, ttl);
+                (op, ts, None)
             }
-            (_, Some((_cnt, old_info, upd_op))) => {
-                self.do_post_update_steps(ts, key, old_info, upd_op)
+            (_, Some((_cnt, is_live, old_info, upd_op))) => {
+                let prev = is_live.then(|| TrioArc::clone(&old_info.entry));
+                let (op, ts) = self.do_post_update_steps(ts, key, old_info, upd_op, ttl);
+                (op, ts, prev)
             }
             (None, None) => unreachable!(),
         }
     }
 
+    /// Replaces the value of the live entry for the key if `condition` returns
+    /// `true` for the current value. On success, returns the write op and the
+    /// previous value. Otherwise, returns the current value if the entry is live.
+    ///
+    /// `condition` may be invoked more than once.
+    pub(crate) fn do_replace_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        value: V,
+        mut condition: impl FnMut(&V) -> bool,
+    ) -> Result<(WriteOp<K, V>, Instant, V), Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Lock the key for update if blocking removal notification is enabled.
+        let arc_key = if self.has_key_locks() {
+            self.get_key_with_hash(key, hash)
+        } else {
+            None
+        };
+        let kl = arc_key.as_ref().and_then(|k| self.maybe_key_lock(k));
+        let _klg = &kl.as_ref().map(|kl| kl.lock());
+
+        let ts = self.current_time_from_expiration_clock();
+        let mut current = None;
+
+        let replaced = self.do_replace_entry_with_hash_if(key, hash, value, ts, |k, entry| {
+            current = None;
+            if !self.is_valid_entry(k, entry, ts) {
+                false
+            } else if condition(&entry.value) {
+                true
+            } else {
+                current = Some(entry.value.clone());
+                false
+            }
+        });
+
+        match replaced {
+            Some((key, old_info, upd_op)) => {
+                let prev = old_info.entry.value.clone();
+                let (op, ts) = self.do_post_update_steps(ts, key, old_info, upd_op, None);
+                Ok((op, ts, prev))
+            }
+            None => Err(current),
+        }
+    }
+
+    /// Inserts the value if there is no live entry for the key. On success,
+    /// returns the write op. Otherwise, returns the current value.
+    pub(crate) fn do_insert_if_absent_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<(WriteOp<K, V>, Instant), V> {
+        let weight = self.inner.weigh(&key, &value);
+
+        // Lock the key for update if blocking removal notification is enabled.
+        let kl = self.maybe_key_lock(&key);
+        let _klg = &kl.as_ref().map(|kl| kl.lock());
+
+        let ts = self.current_time_from_expiration_clock();
+
+        loop {
+            let (entry, gen) = self.new_value_entry(&key, hash, value.clone(), ts, weight);
+            let ins_op = WriteOp::new_upsert(&key, hash, &entry, gen, 0, weight);
+
+            let Some(current) =
+                self.inner
+                    .cache
+                    .insert_if_not_present(Arc::clone(&key), hash, entry)
+            else {
+                return Ok(self.do_post_insert_steps(ts, &key, ins_op, None));
+            };
+
+            if self.is_valid_entry(&key, &current, ts) {
+                return Err(current.value.clone());
+            }
+
+            // The current entry has expired or been invalidated but not evicted
+            // yet. Replace it unless another thread has already done so.
+            if let Some((key, old_info, upd_op)) =
+                self.do_replace_entry_with_hash_if(&*key, hash, value.clone(), ts, |_k, entry| {
+                    TrioArc::ptr_eq(entry, &current)
+                })
+            {
+                return Ok(self.do_post_update_steps(ts, key, old_info, upd_op, None));
+            }
+        }
+    }
+
+    /// Removes the live entry for the key if `condition` returns `true` for its
+    /// value.
+    ///
+    /// `condition` may be invoked more than once.
+    pub(crate) fn remove_entry_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        mut condition: impl FnMut(&V) -> bool,
+    ) -> Option<KvEntry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.current_time_from_expiration_clock();
+        self.inner.cache.remove_entry_if_and(
+            hash,
+            |k| (k as &K).borrow() == key,
+            |k, entry| self.is_valid_entry(k, entry, now) && condition(&entry.value),
+            |k, entry| KvEntry::new(Arc::clone(k), TrioArc::clone(entry)),
+        )
+    }
+
+    /// Replaces the entry for the key with a new one holding `value` if
+    /// `condition` returns `true` for the current entry. Returns the key, the
+    /// old entry info and the write op for the replacement.
+    // https://rust-lang.github.io/rust-clippy/master/index.html#type_complexity
+    #[allow(clippy::type_complexity)]
+    fn do_replace_entry_with_hash_if<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+        value: V,
+        ts: Instant,
+        mut condition: impl FnMut(&Arc<K>, &TrioArc<ValueEntry<K, V>>) -> bool,
+    ) -> Option<(Arc<K>, OldEntryInfo<K, V>, WriteOp<K, V>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut op = None;
+
+        // Since the cht may retry the modification on a conflict, the modifier
+        // can be invoked more than once. The op made by the last invocation is the
+        // one for the actual replacement.
+        let replaced = self.inner.cache.modify_entry_if_and(
+            hash,
+            |k| (k as &K).borrow() == key,
+            |k, old_entry| {
+                op = None;
+                if !condition(k, old_entry) {
+                    return None;
+                }
+                let weight = self.inner.weigh(k, &value);
+                let old_weight = old_entry.policy_weight();
+                // Create this OldEntryInfo _before_ creating a new ValueEntry, so
+                // that the OldEntryInfo can preserve the old EntryInfo's
+                // last_accessed and last_modified timestamps.
+                let old_info = OldEntryInfo::new(old_entry);
+                let (entry, gen) = self.new_value_entry_from(value.clone(), ts, weight, old_entry);
+                let upd_op = WriteOp::new_upsert(k, hash, &entry, gen, old_weight, weight);
+                op = Some((Arc::clone(k), old_info, upd_op));
+                Some(entry)
+            },
+            |_k, _old_entry| (),
+        );
+
+        replaced.and(op)
+    }
+
     fn do_post_insert_steps(
         &self,
         ts: Instant,
         key: &Arc<K>,
         ins_op: WriteOp<K, V>,
+        ttl: Option<Duration>,
     ) -> (WriteOp<K, V>, Instant) {
-        if let (Some(expiry), WriteOp::Upsert { value_entry, .. }) =
+        if let WriteOp::Upsert { value_entry, .. } = &ins_op {
+            self.inner.add_tags(value_entry);
+        }
+        if let (Some(ttl), WriteOp::Upsert { value_entry, .. }) = (ttl, &ins_op) {
+            value_entry
+                .entry_info()
+                .set_expiration_time(ts.checked_add(ttl));
+        } else if let (Some(expiry), WriteOp::Upsert { value_entry, .. }) =
             (&self.inner.expiration_policy.expiry(), &ins_op)
         {
             Self::expire_after_create(expiry, key, value_entry, ts, self.inner.clocks());
@@ -572,19 +1243,31 @@ where
         key: Arc<K>,
         old_info: OldEntryInfo<K, V>,
         upd_op: WriteOp<K, V>,
+        ttl: Option<Duration>,
     ) -> (WriteOp<K, V>, Instant) {
-        if let (Some(expiry), WriteOp::Upsert { value_entry, .. }) =
-            (&self.inner.expiration_policy.expiry(), &upd_op)
-        {
-            Self::expire_after_read_or_update(
-                |k, v, t, d| expiry.expire_after_update(k, v, t, d),
-                &key,
-                value_entry,
-                self.inner.expiration_policy.time_to_live(),
-                self.inner.expiration_policy.time_to_idle(),
-                ts,
-                self.inner.clocks(),
-            );
+        if let WriteOp::Upsert { value_entry, .. } = &upd_op {
+            self.inner.add_tags(value_entry);
+            let ei = value_entry.entry_info();
+            if let Some(ttl) = ttl {
+                ei.set_expiration_time(ts.checked_add(ttl));
+            } else {
+                // The per-entry expiration time of the old value may have passed.
+                // Clear it so that the new value will not be expired by it.
+                if is_expired_by_per_entry_ttl(ei, ts) {
+                    ei.set_expiration_time(None);
+                }
+                if let Some(expiry) = &self.inner.expiration_policy.expiry() {
+                    Self::expire_after_read_or_update(
+                        |k, v, t, d| expiry.expire_after_update(k, v, t, d),
+                        &key,
+                        value_entry,
+                        self.inner.expiration_policy.time_to_live(),
+                        self.inner.expiration_policy.time_to_idle(),
+                        ts,
+                        self.inner.clocks(),
+                    );
+                }
+            }
         }
 
         if self.is_removal_notifier_enabled() {
@@ -682,13 +1365,7 @@ impl<K, V, S> BaseCache<K, V, S> {
         let current_time = clocks.to_std_instant(ts);
         let ei = &value_entry.entry_info();
 
-        let exp_time = IntoIterator::into_iter([
-            ei.expiration_time(),
-            ttl.and_then(|dur| ei.last_modified().and_then(|ts| ts.checked_add(dur))),
-            tti.and_then(|dur| ei.last_accessed().and_then(|ts| ts.checked_add(dur))),
-        ])
-        .flatten()
-        .min();
+        let exp_time = earliest_expiration_time(ei, ttl, tti);
 
         let current_duration = exp_time.and_then(|time| {
             let std_time = clocks.to_std_instant(time);
@@ -747,6 +1424,16 @@ where
     }
 }
 
+/// The upsert of a candidate that was to be rejected while another thread held
+/// the lock of its key. It is retried by the next run of the maintenance tasks.
+struct DeferredUpsert<K, V> {
+    key_hash: KeyHash<K>,
+    value_entry: TrioArc<ValueEntry<K, V>>,
+    entry_gen: u16,
+    old_weight: u32,
+    new_weight: u32,
+}
+
 struct EvictionState<'a, K, V> {
     counters: EvictionCounters,
     notifier: Option<&'a RemovalNotifier<K, V>>,
@@ -791,6 +1478,7 @@ struct EvictionCounters {
     entry_count: u64,
     weighted_size: u64,
     eviction_count: u64,
+    removal_count: u64,
 }
 
 impl EvictionCounters {
@@ -800,6 +1488,7 @@ impl EvictionCounters {
             entry_count,
             weighted_size,
             eviction_count: 0,
+            removal_count: 0,
         }
     }
 
@@ -813,6 +1502,7 @@ impl EvictionCounters {
     #[inline]
     fn saturating_sub(&mut self, entry_count: u64, weight: u32) {
         self.entry_count -= entry_count;
+        self.removal_count += entry_count;
         let total = &mut self.weighted_size;
         *total = total.saturating_sub(weight as u64);
     }
@@ -878,13 +1568,20 @@ struct Clocks {
 }
 
 impl Clocks {
-    fn new(time: Instant, std_time: StdInstant) -> Self {
+    fn new(clock: Option<Clock>) -> Self {
+        // Assume that getting `moka2::common::Instant::now` has lower latency than
+        // `StdInstant::now`.
+        let (origin_std, origin) = match &clock {
+            Some(clock) => (clock.std_now(), Instant::new(clock.now())),
+            None => (StdInstant::now(), Instant::now()),
+        };
+        let mutable_origin = clock.as_ref().map(|_| (origin, origin_std));
         Self {
-            has_expiration_clock: AtomicBool::default(),
-            expiration_clock: Default::default(),
-            origin: time,
-            origin_std: std_time,
-            mutable_origin: Default::default(),
+            has_expiration_clock: AtomicBool::new(clock.is_some()),
+            expiration_clock: RwLock::new(clock),
+            origin,
+            origin_std,
+            mutable_origin: RwLock::new(mutable_origin),
         }
     }
 
@@ -910,6 +1607,9 @@ pub(crate) struct Inner<K, V, S> {
     max_capacity: Option<u64>,
     entry_count: AtomicCell<u64>,
     weighted_size: AtomicCell<u64>,
+    /// The number of entries removed since the hash table was last checked for
+    /// shrinking.
+    removals_since_shrink: AtomicCell<u64>,
     pub(crate) cache: CacheStore<K, V, S>,
     build_hasher: S,
     deques: Mutex<Deques<K>>,
@@ -925,6 +1625,17 @@ pub(crate) struct Inner<K, V, S> {
     removal_notifier: Option<RemovalNotifier<K, V>>,
     key_locks: Option<KeyLockMap<K, S>>,
     invalidator: Option<Invalidator<K, V, S>>,
+    tag_index: Option<TagIndex<K, V>>,
+    dependencies: DependencyGraph<K>,
+    deferred_upserts: Mutex<Vec<DeferredUpsert<K, V>>>,
+    wall_clock_deadlines: WallClockDeadlines<K>,
+    maintenance_counters: MaintenanceCounters,
+    metrics_counters: MetricsCounters,
+    /// Set when the background maintenance runs the expiration timers
+    /// proactively.
+    maintenance_waker: RwLock<Option<MaintenanceWaker>>,
+    /// When the background maintenance will run the expiration timers next time.
+    next_timer_wakeup: AtomicInstant,
     clocks: Clocks,
 }
 
@@ -972,6 +1683,14 @@ impl<K, V, S> Inner<K, V, S> {
         self.removal_notifier.is_some()
     }
 
+    /// Returns `true` if the eviction listener is set and has not been disabled
+    /// because it panicked.
+    pub(crate) fn is_listener_enabled(&self) -> bool {
+        self.removal_notifier
+            .as_ref()
+            .map_or(false, |rn| rn.is_enabled())
+    }
+
     pub(crate) fn maybe_key_lock(&self, key: &Arc<K>) -> Option<KeyLock<'_, K, S>>
     where
         K: Hash + Eq,
@@ -980,6 +1699,26 @@ impl<K, V, S> Inner<K, V, S> {
         self.key_locks.as_ref().map(|kls| kls.key_lock(key))
     }
 
+    /// Locks the key for removal by the maintenance tasks if the key locks are
+    /// enabled. Returns `Err(())` if another thread holds the lock.
+    ///
+    /// Unlike the other operations, the maintenance tasks do not wait for the lock
+    /// as a user may hold it for a long time by `lock_key`. Instead, they skip the
+    /// entry and try it again later.
+    pub(crate) fn try_lock_key_for_removal(
+        &self,
+        key: &Arc<K>,
+    ) -> Result<Option<KeyGuard<'_, K, S>>, ()>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        match self.maybe_key_lock(key) {
+            None => Ok(None),
+            Some(kl) => kl.try_into_guard().map(Some).map_err(drop),
+        }
+    }
+
     #[inline]
     fn current_time_from_expiration_clock(&self) -> Instant {
         if self.clocks.has_expiration_clock.load(Ordering::Relaxed) {
@@ -996,6 +1735,19 @@ impl<K, V, S> Inner<K, V, S> {
         }
     }
 
+    fn system_time_from_expiration_clock(&self) -> SystemTime {
+        if self.clocks.has_expiration_clock.load(Ordering::Relaxed) {
+            self.clocks
+                .expiration_clock
+                .read()
+                .as_ref()
+                .expect("Cannot get the expiration clock")
+                .system_now()
+        } else {
+            SystemTime::now()
+        }
+    }
+
     fn clocks(&self) -> &Clocks {
         &self.clocks
     }
@@ -1004,6 +1756,61 @@ impl<K, V, S> Inner<K, V, S> {
         self.cache.actual_num_segments()
     }
 
+    /// Returns the duration until the earliest expiration of the entries, capped by
+    /// `interval`. The expiration times in the timer wheel are considered only if
+    /// `with_timers` is `true`.
+    fn next_maintenance_delay(&self, interval: Duration, with_timers: bool) -> Duration {
+        let now = self.current_time_from_expiration_clock();
+        let deqs = self.deques.lock();
+        let timer_wheel = self.timer_wheel.lock();
+
+        let by_ttl = self.time_to_live().and_then(|ttl| {
+            let front = deqs.write_order.peek_front()?;
+            front.element.last_modified()?.checked_add(ttl)
+        });
+        let by_tti = self.time_to_idle().and_then(|tti| {
+            [&deqs.window, &deqs.probation, &deqs.protected]
+                .iter()
+                .filter_map(|deq| deq.peek_front()?.element.last_accessed())
+                .min()?
+                .checked_add(tti)
+        });
+        let by_timers = with_timers
+            .then(|| timer_wheel.next_expiration_time())
+            .flatten();
+        let next = [by_timers, by_ttl, by_tti].into_iter().flatten().min();
+
+        next.map_or(interval, |time| delay_until(time, now).min(interval))
+    }
+
+    /// Returns the duration until the background maintenance should run the
+    /// expiration timers, capped by `max_delay`, and records the time to decide
+    /// whether to wake the maintenance up.
+    fn schedule_timer_wakeup(&self, now: Instant, max_delay: Duration) -> Duration {
+        let next = self.timer_wheel.lock().next_expiration_time();
+        let delay = next.map_or(max_delay, |time| delay_until(time, now).min(max_delay));
+        if let Some(wakeup) = now.checked_add(delay) {
+            self.next_timer_wakeup.set_instant(wakeup);
+        }
+        delay
+    }
+
+    /// Wakes the background maintenance up if it runs the expiration timers
+    /// proactively, and the earliest entry in the timer wheel will expire before
+    /// the maintenance runs the timers next time.
+    fn wake_maintenance_for_timers(&self, timer_wheel: &TimerWheel<K>) {
+        let waker = self.maintenance_waker.read();
+        let Some(waker) = &*waker else {
+            return;
+        };
+        if let Some(time) = timer_wheel.next_expiration_time() {
+            if self.next_timer_wakeup.instant().map_or(true, |w| time < w) {
+                self.next_timer_wakeup.set_instant(time);
+                waker.wake();
+            }
+        }
+    }
+
     #[inline]
     fn time_to_live(&self) -> Option<Duration> {
         self.expiration_policy.time_to_live()
@@ -1058,10 +1865,14 @@ where
         weigher: Option<Weigher<K, V>>,
         eviction_policy: EvictionPolicy,
         eviction_listener: Option<EvictionListener<K, V>>,
+        panic_reporter: CallbackPanicReporter,
         read_op_ch: Receiver<ReadOp<K, V>>,
         write_op_ch: Receiver<WriteOp<K, V>>,
-        expiration_policy: ExpirationPolicy<K, V>,
+        mut expiration_policy: ExpirationPolicy<K, V>,
         invalidator_enabled: bool,
+        key_locks_enabled: bool,
+        tag_extractor: Option<TagExtractor<K, V>>,
+        clock: Option<Arc<dyn CacheClock>>,
     ) -> Self {
         // TODO: Calculate the number of segments based on the max capacity and the
         // number of CPUs.
@@ -1069,7 +1880,7 @@ where
             (1, 0)
         } else {
             let ic = initial_capacity
-                .map(|cap| cap + WRITE_LOG_CH_SIZE)
+                .map(|cap| cap + write_op_ch.capacity().unwrap_or_default())
                 .unwrap_or_default();
             (64, ic)
         };
@@ -1079,19 +1890,18 @@ where
             build_hasher.clone(),
         );
 
-        // Assume that getting `moka2::common::Instant::now` has lower latency than
-        // `StdInstant::now`.
-        let now_std = StdInstant::now();
-        let now = Instant::now();
-        let clocks = Clocks::new(now, now_std);
-        let timer_wheel = Mutex::new(TimerWheel::new(now));
-
-        let (removal_notifier, key_locks) = if let Some(listener) = eviction_listener {
-            let rn = RemovalNotifier::new(listener, name.clone());
-            let kl = KeyLockMap::with_hasher(build_hasher.clone());
-            (Some(rn), Some(kl))
+        let clocks = Clocks::new(clock.map(Clock::custom));
+        let timer_wheel = Mutex::new(TimerWheel::new(clocks.origin));
+
+        let weigher = weigher.map(|w| panic_reporter.guard_weigher(w));
+        panic_reporter.guard_expiry(&mut expiration_policy);
+
+        let removal_notifier = eviction_listener
+            .map(|listener| RemovalNotifier::new(listener, panic_reporter.clone()));
+        let key_locks = if removal_notifier.is_some() || key_locks_enabled {
+            Some(KeyLockMap::with_hasher(build_hasher.clone()))
         } else {
-            (None, None)
+            None
         };
 
         let invalidator = if invalidator_enabled {
@@ -1100,11 +1910,15 @@ where
             None
         };
 
+        let tag_index = tag_extractor.map(TagIndex::new);
+        let metrics_counters = MetricsCounters::new(name.as_deref(), max_capacity);
+
         Self {
             name,
             max_capacity,
             entry_count: AtomicCell::default(),
             weighted_size: AtomicCell::default(),
+            removals_since_shrink: AtomicCell::default(),
             cache,
             build_hasher,
             deques: Mutex::default(),
@@ -1120,6 +1934,14 @@ where
             removal_notifier,
             key_locks,
             invalidator,
+            tag_index,
+            dependencies: DependencyGraph::default(),
+            deferred_upserts: Mutex::default(),
+            wall_clock_deadlines: WallClockDeadlines::default(),
+            maintenance_counters: MaintenanceCounters::default(),
+            metrics_counters,
+            maintenance_waker: RwLock::default(),
+            next_timer_wakeup: AtomicInstant::default(),
             clocks,
         }
     }
@@ -1220,12 +2042,21 @@ where
         max_log_sync_repeats: u32,
         eviction_batch_size: u32,
     ) -> bool {
+        let _span = enter_span!(
+            DEBUG,
+            "run_pending_tasks",
+            cache = self.name().unwrap_or_default()
+        );
         self.do_run_pending_tasks(timeout, max_log_sync_repeats, eviction_batch_size)
     }
 
     fn now(&self) -> Instant {
         self.current_time_from_expiration_clock()
     }
+
+    fn maintenance_counters(&self) -> &MaintenanceCounters {
+        &self.maintenance_counters
+    }
 }
 
 impl<K, V, S> Inner<K, V, S>
@@ -1248,6 +2079,10 @@ where
         let mut deqs = self.deques.lock();
         let mut timer_wheel = self.timer_wheel.lock();
 
+        let run_started = StdInstant::now();
+        let mut phases = PhaseCounts::default();
+        let mut timed_out = false;
+
         let started_at = if timeout.is_some() {
             Some(self.current_time_from_expiration_clock())
         } else {
@@ -1260,16 +2095,25 @@ where
         let mut eviction_state =
             EvictionState::new(current_ec, current_ws, self.removal_notifier.as_ref());
 
+        self.reanchor_wall_clock_deadlines(&mut timer_wheel);
+        self.retry_deferred_upserts(&mut deqs, &mut timer_wheel, &mut eviction_state);
+
         loop {
             if should_process_logs {
                 let r_len = self.read_op_ch.len();
                 if r_len > 0 {
+                    let _phase = enter_span!(TRACE, "apply_reads", count = r_len);
                     self.apply_reads(&mut deqs, &mut timer_wheel, r_len);
+                    phases.reads_applied += r_len as u64;
                 }
 
                 let w_len = self.write_op_ch.len();
                 if w_len > 0 {
+                    let _phase = enter_span!(TRACE, "apply_writes", count = w_len);
+                    let evicted = eviction_state.counters.eviction_count;
                     self.apply_writes(&mut deqs, &mut timer_wheel, w_len, &mut eviction_state);
+                    phases.writes_applied += w_len as u64;
+                    phases.entries_evicted += eviction_state.counters.eviction_count - evicted;
                 }
 
                 if self.eviction_policy == EvictionPolicyConfig::TinyLfu
@@ -1289,7 +2133,9 @@ where
 
             // Evict entries if there are any expired entries in the hierarchical
             // timer wheels.
+            let expired = eviction_state.counters.eviction_count;
             if timer_wheel.is_enabled() {
+                let _phase = enter_span!(TRACE, "expire_entries", using = "timers");
                 self.evict_expired_entries_using_timers(
                     &mut timer_wheel,
                     &mut deqs,
@@ -1300,6 +2146,7 @@ where
             // Evict entries if there are any expired entries in the write order or
             // access order deques.
             if self.has_expiry() || self.has_valid_after() {
+                let _phase = enter_span!(TRACE, "expire_entries", using = "deques");
                 self.evict_expired_entries_using_deqs(
                     &mut deqs,
                     &mut timer_wheel,
@@ -1308,10 +2155,14 @@ where
                 );
             }
 
+            phases.entries_expired += eviction_state.counters.eviction_count - expired;
+
             // Evict entries if there are any invalidation predicates set by the
             // `invalidate_entries_if` method.
+            let invalidated = eviction_state.counters.entry_count;
             if let Some(invalidator) = &self.invalidator {
                 if !invalidator.is_empty() {
+                    let _phase = enter_span!(TRACE, "invalidate_entries");
                     self.invalidate_entries(
                         invalidator,
                         &mut deqs,
@@ -1321,10 +2172,13 @@ where
                     );
                 }
             }
+            phases.entries_invalidated += invalidated - eviction_state.counters.entry_count;
 
             // Evict if this cache has more entries than its capacity.
+            let evicted = eviction_state.counters.eviction_count;
             let weights_to_evict = self.weights_to_evict(&eviction_state.counters);
             if weights_to_evict > 0 {
+                let _phase = enter_span!(TRACE, "evict_lru_entries", weights = weights_to_evict);
                 self.evict_lru_entries(
                     &mut deqs,
                     &mut timer_wheel,
@@ -1334,6 +2188,13 @@ where
                 );
             }
 
+            phases.entries_evicted += eviction_state.counters.eviction_count - evicted;
+
+            // Remove the entries depending on the removed entries.
+            let dependents = eviction_state.counters.entry_count;
+            self.remove_dependents(&mut deqs, &mut timer_wheel, &mut eviction_state);
+            phases.entries_invalidated += dependents - eviction_state.counters.entry_count;
+
             // Check whether to continue this loop or not.
 
             should_process_logs = calls <= max_log_sync_repeats
@@ -1357,6 +2218,13 @@ where
                     .checked_duration_since(started)
                     .expect("Arithmetic overflow occurred on calculating the elapse time");
                 if elapsed >= to {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        cache = self.name().unwrap_or_default(),
+                        timeout = ?to,
+                        "The maintenance tasks were stopped by the timeout",
+                    );
+                    timed_out = true;
                     break;
                 }
             }
@@ -1368,6 +2236,27 @@ where
         self.weighted_size
             .store(eviction_state.counters.weighted_size);
 
+        // Shrink the hash table if most of its entries have been removed, e.g. by
+        // `invalidate_all`. Only check it after at least as many entries have been
+        // removed as remain, so that a table that has only grown (or was allocated
+        // for the initial capacity) is not rehashed.
+        let removals = self.removals_since_shrink.load() + eviction_state.counters.removal_count;
+        if !timed_out && removals > 0 && removals >= eviction_state.counters.entry_count {
+            self.cache.shrink(cht::SHRINK_ON_MAINTENANCE_LOAD_FACTOR);
+            self.removals_since_shrink.store(0);
+        } else {
+            self.removals_since_shrink.store(removals);
+        }
+
+        self.wake_maintenance_for_timers(&timer_wheel);
+        self.maintenance_counters
+            .record_run(run_started.elapsed(), &phases, timed_out);
+        self.metrics_counters.record_maintenance(
+            &phases,
+            eviction_state.counters.entry_count as i64 - current_ec as i64,
+            eviction_state.counters.weighted_size as i64 - current_ws as i64,
+        );
+
         crossbeam_epoch::pin().flush();
 
         // Ensure the deqs lock is held until here.
@@ -1375,6 +2264,57 @@ where
 
         eviction_state.more_entries_to_evict
     }
+
+    /// Removes the entries expired in the timer wheel, and the entries depending on
+    /// them. Unlike `do_run_pending_tasks`, does not apply the pending reads and
+    /// writes, so it is cheap enough to run at the expiration time of each entry.
+    fn run_expiration_timers(&self) {
+        let _span = enter_span!(
+            DEBUG,
+            "run_expiration_timers",
+            cache = self.name().unwrap_or_default()
+        );
+        let mut deqs = self.deques.lock();
+        let mut timer_wheel = self.timer_wheel.lock();
+        if !timer_wheel.is_enabled() {
+            return;
+        }
+
+        let current_ec = self.entry_count.load();
+        let current_ws = self.weighted_size.load();
+        let mut eviction_state =
+            EvictionState::new(current_ec, current_ws, self.removal_notifier.as_ref());
+
+        self.evict_expired_entries_using_timers(&mut timer_wheel, &mut deqs, &mut eviction_state);
+        let dependents = eviction_state.counters.entry_count;
+        self.remove_dependents(&mut deqs, &mut timer_wheel, &mut eviction_state);
+
+        let phases = PhaseCounts {
+            entries_expired: eviction_state.counters.eviction_count,
+            entries_invalidated: dependents - eviction_state.counters.entry_count,
+            ..Default::default()
+        };
+        self.maintenance_counters.record_phases(&phases);
+        self.metrics_counters.record_maintenance(
+            &phases,
+            eviction_state.counters.entry_count as i64 - current_ec as i64,
+            eviction_state.counters.weighted_size as i64 - current_ws as i64,
+        );
+
+        self.entry_count.store(eviction_state.counters.entry_count);
+        self.weighted_size
+            .store(eviction_state.counters.weighted_size);
+
+        crossbeam_epoch::pin().flush();
+    }
+}
+
+/// Returns the duration from `now` until `time`, but at least
+/// `MIN_MAINTENANCE_DELAY_MILLIS`.
+fn delay_until(time: Instant, now: Instant) -> Duration {
+    let min_delay = Duration::from_millis(MIN_MAINTENANCE_DELAY_MILLIS);
+    let delay = time.checked_duration_since(now).unwrap_or_default();
+    delay.max(min_delay)
 }
 
 //
@@ -1471,7 +2411,7 @@ where
     ) where
         V: Clone,
     {
-        use WriteOp::{Remove, Upsert};
+        use WriteOp::{Remove, SetExpiry, Upsert};
         let freq = self.frequency_sketch.read();
         let ch = &self.write_op_ch;
 
@@ -1498,7 +2438,7 @@ where
                     kv_entry: KvEntry { key: _key, entry },
                     entry_gen: gen,
                 }) => {
-                    Self::handle_remove(
+                    self.handle_remove(
                         deqs,
                         timer_wheel,
                         entry,
@@ -1506,6 +2446,14 @@ where
                         &mut eviction_state.counters,
                     );
                 }
+                Ok(SetExpiry { value_entry: entry }) => {
+                    // If the entry has not been admitted, the timer will be scheduled
+                    // when it is admitted. If it has been removed, there is nothing
+                    // to do.
+                    if entry.is_admitted() {
+                        self.update_timer_wheel(&entry, timer_wheel);
+                    }
+                }
                 Err(_) => break,
             };
         }
@@ -1537,6 +2485,7 @@ where
                 deqs.move_to_back_ao(&entry);
                 deqs.move_to_back_wo(&entry);
                 entry.entry_info().set_policy_gen(gen);
+                self.index_tags(&entry);
                 return;
             }
 
@@ -1553,9 +2502,13 @@ where
             if new_weight as u64 > max {
                 // The candidate is too big to fit in the cache. Reject it.
 
-                // Lock the key for removal if blocking removal notification is enabled.
-                let kl = self.maybe_key_lock(&kh.key);
-                let _klg = &kl.as_ref().map(|kl| kl.lock());
+                // Lock the key for removal if blocking removal notification or key
+                // locks are enabled. If another thread holds the lock, keep the
+                // candidate out of the cache policy and retry it in the next run.
+                let Ok(_klg) = self.try_lock_key_for_removal(&kh.key) else {
+                    self.defer_upsert(kh, entry, gen, old_weight, new_weight);
+                    return;
+                };
 
                 let removed = self.cache.remove_if(
                     kh.hash,
@@ -1571,6 +2524,9 @@ where
                         eviction_state.notify_entry_removal(key, &entry, RemovalCause::Size);
                     }
                     eviction_state.counters.incr_eviction_count();
+                    self.unindex_tags(&entry);
+                    self.dependencies.queue_dependents(&kh.key);
+                    self.wall_clock_deadlines.unregister(entry.entry_info());
                 }
                 entry.entry_info().set_policy_gen(gen);
                 return;
@@ -1599,16 +2555,20 @@ where
                     let vic_key = vic_kh.key;
                     let vic_hash = vic_kh.hash;
 
-                    // Lock the key for removal if blocking removal notification is enabled.
-                    let kl = self.maybe_key_lock(&vic_key);
-                    let _klg = &kl.as_ref().map(|kl| kl.lock());
-
-                    if let Some((vic_key, vic_entry)) = self.cache.remove_entry_if_and(
-                        vic_hash,
-                        |k| k == &vic_key,
-                        |_, entry| entry.entry_info().last_accessed() == vic_la,
-                        |k, v| (k.clone(), v.clone()),
-                    ) {
+                    // Lock the key for removal if blocking removal notification or
+                    // key locks are enabled. If another thread holds the lock, skip
+                    // the victim.
+                    let klg = self.try_lock_key_for_removal(&vic_key);
+                    let removed = klg.as_ref().ok().and_then(|_| {
+                        self.cache.remove_entry_if_and(
+                            vic_hash,
+                            |k| k == &vic_key,
+                            |_, entry| entry.entry_info().last_accessed() == vic_la,
+                            |k, v| (k.clone(), v.clone()),
+                        )
+                    });
+
+                    if let Some((vic_key, vic_entry)) = removed {
                         if eviction_state.is_notifier_enabled() {
                             eviction_state.notify_entry_removal(
                                 vic_key,
@@ -1618,7 +2578,7 @@ where
                         }
                         eviction_state.counters.incr_eviction_count();
                         // And then remove the victim from the deques.
-                        Self::handle_remove(
+                        self.handle_remove(
                             deqs,
                             timer_wheel,
                             vic_entry,
@@ -1627,7 +2587,7 @@ where
                         );
                     } else {
                         // Could not remove the victim from the cache. Skip it as its
-                        // ValueEntry might have been invalidated.
+                        // ValueEntry might have been invalidated or its key is locked.
                         if let Some(node) = deqs.probation.peek_front() {
                             if node.element.key() == &vic_key && node.element.hash() == vic_hash {
                                 deqs.probation.move_front_to_back();
@@ -1647,9 +2607,21 @@ where
                 entry.entry_info().set_policy_gen(gen);
             }
             AdmissionResult::Rejected => {
-                // Lock the key for removal if blocking removal notification is enabled.
-                let kl = self.maybe_key_lock(&kh.key);
-                let _klg = &kl.as_ref().map(|kl| kl.lock());
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    cache = self.name().unwrap_or_default(),
+                    key_hash = kh.hash,
+                    weight = new_weight,
+                    "TinyLFU rejected the admission of an entry",
+                );
+
+                // Lock the key for removal if blocking removal notification or key
+                // locks are enabled. If another thread holds the lock, keep the
+                // candidate out of the cache policy and retry it in the next run.
+                let Ok(_klg) = self.try_lock_key_for_removal(&kh.key) else {
+                    self.defer_upsert(kh, entry, gen, old_weight, new_weight);
+                    return;
+                };
 
                 // Remove the candidate from the cache (hash map) if the entry
                 // generation matches.
@@ -1669,11 +2641,83 @@ where
                         eviction_state.notify_entry_removal(key, &entry, RemovalCause::Size);
                     }
                     eviction_state.counters.incr_eviction_count();
+                    self.unindex_tags(&entry);
+                    self.dependencies.queue_dependents(&kh.key);
+                    self.wall_clock_deadlines.unregister(entry.entry_info());
                 }
             }
         };
     }
 
+    fn defer_upsert(
+        &self,
+        key_hash: KeyHash<K>,
+        value_entry: TrioArc<ValueEntry<K, V>>,
+        entry_gen: u16,
+        old_weight: u32,
+        new_weight: u32,
+    ) {
+        self.deferred_upserts.lock().push(DeferredUpsert {
+            key_hash,
+            value_entry,
+            entry_gen,
+            old_weight,
+            new_weight,
+        });
+    }
+
+    /// Retries the upserts deferred by `defer_upsert`. An upsert is dropped if its
+    /// entry has been updated or removed since, as the write op of the update or
+    /// removal takes it over.
+    fn retry_deferred_upserts(
+        &self,
+        deqs: &mut Deques<K>,
+        timer_wheel: &mut TimerWheel<K>,
+        eviction_state: &mut EvictionState<'_, K, V>,
+    ) where
+        V: Clone,
+    {
+        let upserts = std::mem::take(&mut *self.deferred_upserts.lock());
+        if upserts.is_empty() {
+            return;
+        }
+
+        let freq = self.frequency_sketch.read();
+        for upsert in upserts {
+            let DeferredUpsert {
+                key_hash: kh,
+                value_entry: entry,
+                entry_gen: gen,
+                old_weight,
+                new_weight,
+            } = upsert;
+            let is_current = self
+                .cache
+                .get_key_value_and(
+                    kh.hash,
+                    |k| k == &kh.key,
+                    |_, current_entry| {
+                        TrioArc::ptr_eq(entry.entry_info(), current_entry.entry_info())
+                            && current_entry.entry_info().entry_gen() == gen
+                    },
+                )
+                .unwrap_or_default();
+            if is_current {
+                self.handle_upsert(
+                    kh,
+                    entry,
+                    gen,
+                    old_weight,
+                    new_weight,
+                    deqs,
+                    timer_wheel,
+                    &freq,
+                    eviction_state,
+                );
+            }
+        }
+    }
+
     /// Performs size-aware admission explained in the paper:
     /// [Lightweight Robust Size Aware Cache Management][size-aware-cache-paper]
     /// by Gil Einziger, Ohad Eytan, Roy Friedman, Ben Manes.
@@ -1780,6 +2824,69 @@ where
             deqs.push_back_wo(KeyHashDate::new(entry.entry_info()), entry);
         }
         entry.set_admitted(true);
+        self.index_tags(entry);
+    }
+
+    fn add_tags(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        if let Some(ti) = &self.tag_index {
+            ti.add(entry);
+        }
+    }
+
+    fn index_tags(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        if let Some(ti) = &self.tag_index {
+            ti.index(entry);
+        }
+    }
+
+    fn unindex_tags(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        if let Some(ti) = &self.tag_index {
+            ti.unindex(entry);
+        }
+    }
+
+    /// Queues the dependents of the removed entry for removal, unless the key has
+    /// been inserted again since then.
+    fn queue_dependents(&self, entry: &TrioArc<ValueEntry<K, V>>) {
+        if self.dependencies.is_empty() {
+            return;
+        }
+        let kh = entry.entry_info().key_hash();
+        let reinserted = self
+            .cache
+            .get(kh.hash, |k| k == &kh.key)
+            .map_or(false, |e| {
+                !TrioArc::ptr_eq(e.entry_info(), entry.entry_info())
+            });
+        if !reinserted {
+            self.dependencies.queue_dependents(&kh.key);
+        }
+    }
+
+    /// Converts the wall-clock deadlines of the entries into their expiration
+    /// times again if the wall clock has jumped since the last check.
+    fn reanchor_wall_clock_deadlines(&self, timer_wheel: &mut TimerWheel<K>) {
+        if self.wall_clock_deadlines.is_empty() {
+            return;
+        }
+
+        let now = self.current_time_from_expiration_clock();
+        let system_now = self.system_time_from_expiration_clock();
+        for r in self.wall_clock_deadlines.reanchor(system_now, now) {
+            let ttl = r.expires_at.duration_since(system_now).unwrap_or_default();
+            r.entry_info.set_expiration_time(now.checked_add(ttl));
+
+            // Reschedule the timer if the entry has been admitted. Otherwise, the
+            // timer will be scheduled when the entry is admitted.
+            let kh = r.entry_info.key_hash();
+            let entry = self
+                .cache
+                .get(kh.hash, |k| k == &kh.key)
+                .filter(|e| TrioArc::ptr_eq(e.entry_info(), &r.entry_info) && e.is_admitted());
+            if let Some(entry) = entry {
+                self.update_timer_wheel(&entry, timer_wheel);
+            }
+        }
     }
 
     /// NOTE: This method may enable the timer wheel.
@@ -1832,6 +2939,7 @@ where
     }
 
     fn handle_remove(
+        &self,
         deqs: &mut Deques<K>,
         timer_wheel: &mut TimerWheel<K>,
         entry: TrioArc<ValueEntry<K, V>>,
@@ -1841,10 +2949,11 @@ where
         if let Some(timer_node) = entry.take_timer_node() {
             timer_wheel.deschedule(timer_node);
         }
-        Self::handle_remove_without_timer_wheel(deqs, entry, gen, counters);
+        self.handle_remove_without_timer_wheel(deqs, entry, gen, counters);
     }
 
     fn handle_remove_without_timer_wheel(
+        &self,
         deqs: &mut Deques<K>,
         entry: TrioArc<ValueEntry<K, V>>,
         gen: Option<u16>,
@@ -1859,12 +2968,16 @@ where
         } else {
             entry.unset_q_nodes();
         }
+        self.unindex_tags(&entry);
+        self.queue_dependents(&entry);
+        self.wall_clock_deadlines.unregister(entry.entry_info());
         if let Some(g) = gen {
             entry.entry_info().set_policy_gen(g);
         }
     }
 
     fn handle_remove_with_deques(
+        &self,
         ao_deq_name: &str,
         ao_deq: &mut Deque<KeyHashDate<K>>,
         wo_deq: &mut Deque<KeyHashDate<K>>,
@@ -1884,6 +2997,9 @@ where
         } else {
             entry.unset_q_nodes();
         }
+        self.unindex_tags(&entry);
+        self.queue_dependents(&entry);
+        self.wall_clock_deadlines.unregister(entry.entry_info());
     }
 
     fn evict_expired_entries_using_timers(
@@ -1909,6 +3025,10 @@ where
         //      described above.
         //    - When necessary, a new timer node will be recreated for the current or
         //      new `ValueEntry` when its `WriteOp` or `ReadOp` is processed.
+        // 3. If the key is locked by other thread, we will schedule the timer node
+        //    again after advancing the timer wheel, so that the entry will be
+        //    removed by a later call.
+        let mut locked_nodes = Vec::new();
         for event in timer_wheel.advance(now) {
             // We do not have to do anything if event is `TimerEvent::Descheduled(_)`
             // or `TimerEvent::Rescheduled(_)`.
@@ -1925,10 +3045,12 @@ where
                 let key = &kh.key;
                 let hash = kh.hash;
 
-                // Lock the key for removal if blocking removal notification is
-                // enabled.
-                let kl = self.maybe_key_lock(key);
-                let _klg = &kl.as_ref().map(|kl| kl.lock());
+                // Lock the key for removal if blocking removal notification or key
+                // locks are enabled.
+                let Ok(_klg) = self.try_lock_key_for_removal(key) else {
+                    locked_nodes.push(node);
+                    continue;
+                };
 
                 // Remove the key from the map only when the entry is really expired.
                 let maybe_entry = self.cache.remove_if(
@@ -1943,7 +3065,7 @@ where
                         eviction_state.notify_entry_removal(key, &entry, RemovalCause::Expired);
                     }
                     eviction_state.counters.incr_eviction_count();
-                    Self::handle_remove_without_timer_wheel(
+                    self.handle_remove_without_timer_wheel(
                         deqs,
                         entry,
                         None,
@@ -1955,6 +3077,10 @@ where
                 }
             }
         }
+
+        for node in locked_nodes {
+            timer_wheel.schedule_expired(node);
+        }
     }
 
     fn evict_expired_entries_using_deqs(
@@ -1999,6 +3125,7 @@ where
         let deq_name = cache_region.name();
         let (ao_deq, wo_deq) = deqs.select_mut(cache_region);
         let mut more_to_evict = true;
+        let mut first_locked_key = None;
 
         for _ in 0..batch_size {
             let maybe_key_hash_ts = ao_deq.peek_front().map(|node| {
@@ -2043,9 +3170,24 @@ where
                 }
             };
 
-            // Lock the key for removal if blocking removal notification is enabled.
-            let kl = self.maybe_key_lock(&key);
-            let _klg = &kl.as_ref().map(|kl| kl.lock());
+            // Stop when the scan comes back to an entry skipped because its key
+            // was locked, so that a locked key is tried only once per run.
+            if first_locked_key
+                .as_ref()
+                .map_or(false, |k| Arc::ptr_eq(k, &key))
+            {
+                more_to_evict = false;
+                break;
+            }
+
+            // Lock the key for removal if blocking removal notification or key locks
+            // are enabled. If other thread holds the lock, skip the entry.
+            let Ok(_klg) = self.try_lock_key_for_removal(&key) else {
+                self.skip_updated_entry_ao(&key, hash, deq_name, ao_deq, wo_deq);
+                first_locked_key.get_or_insert_with(|| Arc::clone(&key));
+                more_to_evict = false;
+                continue;
+            };
 
             // Remove the key from the map only when the entry is really
             // expired. This check is needed because it is possible that the entry in
@@ -2062,7 +3204,7 @@ where
                     eviction_state.notify_entry_removal(key, &entry, cause);
                 }
                 eviction_state.counters.incr_eviction_count();
-                Self::handle_remove_with_deques(
+                self.handle_remove_with_deques(
                     deq_name,
                     ao_deq,
                     wo_deq,
@@ -2136,6 +3278,7 @@ where
         let ttl = &self.expiration_policy.time_to_live();
         let va = &self.valid_after();
         let mut more_to_evict = true;
+        let mut first_locked_key = None;
 
         for _ in 0..batch_size {
             let maybe_key_hash_ts = deqs.write_order.peek_front().map(|node| {
@@ -2174,9 +3317,24 @@ where
                 }
             };
 
-            // Lock the key for removal if blocking removal notification is enabled.
-            let kl = self.maybe_key_lock(&key);
-            let _klg = &kl.as_ref().map(|kl| kl.lock());
+            // Stop when the scan comes back to an entry skipped because its key
+            // was locked, so that a locked key is tried only once per run.
+            if first_locked_key
+                .as_ref()
+                .map_or(false, |k| Arc::ptr_eq(k, &key))
+            {
+                more_to_evict = false;
+                break;
+            }
+
+            // Lock the key for removal if blocking removal notification or key locks
+            // are enabled. If other thread holds the lock, skip the entry.
+            let Ok(_klg) = self.try_lock_key_for_removal(&key) else {
+                self.skip_updated_entry_wo(&key, hash, deqs);
+                first_locked_key.get_or_insert_with(|| Arc::clone(&key));
+                more_to_evict = false;
+                continue;
+            };
 
             let maybe_entry = self.cache.remove_if(
                 hash,
@@ -2189,7 +3347,7 @@ where
                     eviction_state.notify_entry_removal(key, &entry, cause);
                 }
                 eviction_state.counters.incr_eviction_count();
-                Self::handle_remove(deqs, timer_wheel, entry, None, &mut eviction_state.counters);
+                self.handle_remove(deqs, timer_wheel, entry, None, &mut eviction_state.counters);
             } else {
                 self.skip_updated_entry_wo(&key, hash, deqs);
                 more_to_evict = false;
@@ -2220,6 +3378,18 @@ where
             return;
         }
 
+        // If the scan has finished but skipped some entries because their keys were
+        // locked, retry only those entries.
+        if let Some((invalidated, is_done)) = invalidator.retry_locked_entries(self) {
+            for KvEntry { key: _key, entry } in invalidated {
+                self.handle_remove(deqs, timer_wheel, entry, None, &mut eviction_state.counters);
+            }
+            if is_done {
+                deqs.write_order.reset_cursor();
+            }
+            return;
+        }
+
         let mut candidates = Vec::new();
         let mut len = 0;
         let has_next;
@@ -2253,7 +3423,7 @@ where
             invalidator.scan_and_invalidate(self, candidates, is_truncated);
 
         for KvEntry { key: _key, entry } in invalidated {
-            Self::handle_remove(deqs, timer_wheel, entry, None, &mut eviction_state.counters);
+            self.handle_remove(deqs, timer_wheel, entry, None, &mut eviction_state.counters);
         }
         if is_done {
             deqs.write_order.reset_cursor();
@@ -2263,6 +3433,59 @@ where
         }
     }
 
+    fn remove_dependents(
+        &self,
+        deqs: &mut Deques<K>,
+        timer_wheel: &mut TimerWheel<K>,
+        eviction_state: &mut EvictionState<'_, K, V>,
+    ) where
+        V: Clone,
+    {
+        // Removing a dependent may queue its own dependents, so repeat until the
+        // queue becomes empty. This terminates for dependency cycles because the
+        // edges are removed from the graph when queued.
+        let mut locked_keys = Vec::new();
+        loop {
+            let keys = self.dependencies.take_pending();
+            if keys.is_empty() {
+                break;
+            }
+
+            for key in keys {
+                let hash = self.hash(&key);
+
+                // Lock the key for removal if blocking removal notification or key
+                // locks are enabled. If other thread holds the lock, queue the key
+                // again after this loop.
+                let Ok(_klg) = self.try_lock_key_for_removal(&key) else {
+                    locked_keys.push(key);
+                    continue;
+                };
+
+                let maybe_entry = self.cache.remove_entry_if_and(
+                    hash,
+                    |k| k == &key,
+                    |_, _| true,
+                    |k, v| (k.clone(), v.clone()),
+                );
+
+                if let Some((key, entry)) = maybe_entry {
+                    if eviction_state.is_notifier_enabled() {
+                        eviction_state.notify_entry_removal(key, &entry, RemovalCause::Cascaded);
+                    }
+                    self.handle_remove(
+                        deqs,
+                        timer_wheel,
+                        entry,
+                        None,
+                        &mut eviction_state.counters,
+                    );
+                }
+            }
+        }
+        self.dependencies.requeue_pending(locked_keys);
+    }
+
     fn evict_lru_entries(
         &self,
         deqs: &mut Deques<K>,
@@ -2278,6 +3501,7 @@ where
         let (ao_deq, wo_deq) = deqs.select_mut(CACHE_REGION);
         let mut evicted = 0u64;
         let mut more_to_evict = true;
+        let mut first_locked_key = None;
 
         for _ in 0..batch_size {
             if evicted >= weights_to_evict {
@@ -2317,9 +3541,24 @@ where
                 }
             };
 
-            // Lock the key for removal if blocking removal notification is enabled.
-            let kl = self.maybe_key_lock(&key);
-            let _klg = &kl.as_ref().map(|kl| kl.lock());
+            // Stop when the scan comes back to an entry skipped because its key
+            // was locked, so that a locked key is tried only once per run.
+            if first_locked_key
+                .as_ref()
+                .map_or(false, |k| Arc::ptr_eq(k, &key))
+            {
+                more_to_evict = false;
+                break;
+            }
+
+            // Lock the key for removal if blocking removal notification or key locks
+            // are enabled. If other thread holds the lock, skip the entry.
+            let Ok(_klg) = self.try_lock_key_for_removal(&key) else {
+                self.skip_updated_entry_ao(&key, hash, deq_name, ao_deq, wo_deq);
+                first_locked_key.get_or_insert_with(|| Arc::clone(&key));
+                more_to_evict = false;
+                continue;
+            };
 
             let maybe_entry = self.cache.remove_if(
                 hash,
@@ -2339,7 +3578,7 @@ where
                 }
                 eviction_state.counters.incr_eviction_count();
                 let weight = entry.policy_weight();
-                Self::handle_remove_with_deques(
+                self.handle_remove_with_deques(
                     deq_name,
                     ao_deq,
                     wo_deq,
@@ -2407,21 +3646,28 @@ where
     }
 
     #[inline]
-    fn notify_invalidate(&self, key: &Arc<K>, entry: &TrioArc<ValueEntry<K, V>>) {
+    /// Notifies the removal of the entry. An explicit removal is notified as
+    /// `Expired` if the entry has already expired.
+    fn notify_invalidate(
+        &self,
+        key: &Arc<K>,
+        entry: &TrioArc<ValueEntry<K, V>>,
+        mut cause: RemovalCause,
+    ) {
         let now = self.current_time_from_expiration_clock();
         let exp = &self.expiration_policy;
 
-        let mut cause = RemovalCause::Explicit;
-
-        if let Some(last_accessed) = entry.last_accessed() {
-            if is_expired_by_tti(&exp.time_to_idle(), last_accessed, now) {
-                cause = RemovalCause::Expired;
+        if cause == RemovalCause::Explicit {
+            if let Some(last_accessed) = entry.last_accessed() {
+                if is_expired_by_tti(&exp.time_to_idle(), last_accessed, now) {
+                    cause = RemovalCause::Expired;
+                }
             }
-        }
 
-        if let Some(last_modified) = entry.last_modified() {
-            if is_expired_by_ttl(&exp.time_to_live(), last_modified, now) {
-                cause = RemovalCause::Expired;
+            if let Some(last_modified) = entry.last_modified() {
+                if is_expired_by_ttl(&exp.time_to_live(), last_modified, now) {
+                    cause = RemovalCause::Expired;
+                }
             }
         }
 
@@ -2449,7 +3695,7 @@ where
     fn set_expiration_clock(&self, clock: Option<Clock>) {
         let mut exp_clock = self.clocks.expiration_clock.write();
         if let Some(clock) = clock {
-            let std_now = StdInstant::now();
+            let std_now = clock.std_now();
             let now = Instant::new(clock.now());
             *exp_clock = Some(clock);
             self.clocks
@@ -2488,6 +3734,24 @@ fn is_expired_by_per_entry_ttl<K>(entry_info: &TrioArc<EntryInfo<K>>, now: Insta
     }
 }
 
+/// Returns the earliest time among the per-entry expiration time and the times
+/// computed from the time-to-live and time-to-idle configs of the cache.
+#[inline]
+fn earliest_expiration_time<K>(
+    entry_info: &EntryInfo<K>,
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+) -> Option<Instant> {
+    let ei = entry_info;
+    IntoIterator::into_iter([
+        ei.expiration_time(),
+        time_to_live.and_then(|dur| ei.last_modified().and_then(|ts| ts.checked_add(dur))),
+        time_to_idle.and_then(|dur| ei.last_accessed().and_then(|ts| ts.checked_add(dur))),
+    ])
+    .flatten()
+    .min()
+}
+
 /// Returns `true` when one of the followings conditions is met:
 ///
 /// - This entry is expired by the time-to-idle config of this cache instance.
@@ -2590,7 +3854,7 @@ fn is_expired_by_ttl(
 #[cfg(test)]
 mod tests {
     use crate::{
-        common::HousekeeperConfig,
+        common::{callback_panic::CallbackPanicReporter, HousekeeperConfig},
         policy::{EvictionPolicy, ExpirationPolicy},
     };
 
@@ -2613,9 +3877,13 @@ mod tests {
                 None,
                 EvictionPolicy::default(),
                 None,
+                CallbackPanicReporter::default(),
                 ExpirationPolicy::default(),
                 HousekeeperConfig::default(),
                 false,
+                false,
+                None,
+                None,
             );
             cache.inner.enable_frequency_sketch_for_testing();
             assert_eq!(
@@ -2680,7 +3948,7 @@ mod tests {
         }
 
         fn insert(cache: &BaseCache<Key, Value>, key: Key, hash: u64, value: Value) {
-            let (op, _now) = cache.do_insert_with_hash(Arc::new(key), hash, value);
+            let (op, _now, _prev) = cache.do_insert_with_hash(Arc::new(key), hash, value, None);
             cache.write_op_ch.send(op).expect("Failed to send");
         }
 
@@ -2959,6 +4227,7 @@ mod tests {
             None,
             EvictionPolicy::default(),
             None,
+            CallbackPanicReporter::default(),
             ExpirationPolicy::new(
                 Some(Duration::from_secs(TTL)),
                 Some(Duration::from_secs(TTI)),
@@ -2966,6 +4235,9 @@ mod tests {
             ),
             HousekeeperConfig::default(),
             false,
+            false,
+            None,
+            None,
         );
         cache.reconfigure_for_testing();
 
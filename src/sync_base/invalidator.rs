@@ -2,6 +2,7 @@ use super::{base_cache::Inner, PredicateId, PredicateIdStr};
 use crate::{
     common::{
         concurrent::{AccessTime, KvEntry, ValueEntry},
+        predicate::{FinishedPredicates, PredicateProgress, PredicateStatus},
         time::Instant,
     },
     notification::RemovalCause,
@@ -53,6 +54,7 @@ pub(crate) struct Invalidator<K, V, S> {
     predicates: crate::cht::SegmentedHashMap<PredicateId, Predicate<K, V>, S>,
     is_empty: AtomicBool,
     scan_context: Arc<ScanContext<K, V>>,
+    finished: FinishedPredicates,
 }
 
 //
@@ -73,6 +75,7 @@ impl<K, V, S> Invalidator<K, V, S> {
             predicates,
             is_empty: AtomicBool::new(true),
             scan_context: Arc::new(ScanContext::default()),
+            finished: FinishedPredicates::default(),
         }
     }
 
@@ -88,20 +91,53 @@ impl<K, V, S> Invalidator<K, V, S> {
     {
         let pred_map = &self.predicates;
 
-        let removing_ids = pred_map
+        let removing = pred_map
             .iter()
             .filter(|(_, pred)| pred.registered_at <= ts)
-            .map(|(id, _)| id)
+            .map(|(_, pred)| pred)
             .collect::<Vec<_>>();
 
-        for id in removing_ids {
-            let hash = pred_map.hash(&id);
-            pred_map.remove(hash, |k| k == &id);
+        self.remove_predicates(&removing);
+        self.scan_context.reset_locked_entries();
+    }
+
+    pub(crate) fn predicate_status(&self, id: PredicateIdStr<'_>) -> Option<PredicateStatus>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let hash = self.predicates.hash(id);
+        if let Some(pred) = self.predicates.get(hash, |k| k == id) {
+            Some(pred.progress.status())
+        } else {
+            self.finished.status(id)
+        }
+    }
+
+    /// Cancels the predicate. Returns `false` if the predicate does not exist or
+    /// has already been finished.
+    pub(crate) fn cancel_predicate(&self, id: PredicateIdStr<'_>) -> bool
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let pred_map = &self.predicates;
+        let hash = pred_map.hash(id);
+        let Some(pred) = pred_map.get(hash, |k| k == id) else {
+            return false;
+        };
+        if !pred.progress.cancel() {
+            return false;
         }
 
+        // Record the status before removing the predicate from the registry, so
+        // that the status can always be queried.
+        self.finished.record(id, &pred.progress);
+        pred_map.remove(hash, |k| k == id);
         if pred_map.is_empty() {
             self.is_empty.store(true, Ordering::Release);
         }
+        true
     }
 
     pub(crate) fn register_predicate(
@@ -156,6 +192,7 @@ impl<K, V, S> Invalidator<K, V, S> {
                 &entry.value,
                 ts,
             )
+            .is_some()
         } else {
             false
         }
@@ -167,6 +204,40 @@ impl<K, V, S> Invalidator<K, V, S> {
         candidates: Vec<KeyDateLite<K>>,
         is_truncated: bool,
     ) -> (Vec<KvEntry<K, V>>, bool)
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+        S: BuildHasher,
+    {
+        self.do_scan_and_invalidate(cache, candidates, is_truncated, false)
+    }
+
+    /// Retries invalidating the entries skipped by the finished pass of the scan
+    /// because their keys were locked. Returns `None` if there is no such pass, so
+    /// that the caller continues the scan.
+    pub(crate) fn retry_locked_entries(
+        &self,
+        cache: &Inner<K, V, S>,
+    ) -> Option<(Vec<KvEntry<K, V>>, bool)>
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+        S: BuildHasher,
+    {
+        if !self.scan_context.is_scan_finished.load(Ordering::Relaxed) {
+            return None;
+        }
+        let candidates = std::mem::take(&mut *self.scan_context.locked_entries.lock());
+        Some(self.do_scan_and_invalidate(cache, candidates, false, true))
+    }
+
+    fn do_scan_and_invalidate(
+        &self,
+        cache: &Inner<K, V, S>,
+        candidates: Vec<KeyDateLite<K>>,
+        is_truncated: bool,
+        is_retry: bool,
+    ) -> (Vec<KvEntry<K, V>>, bool)
     where
         K: Hash + Eq + Send + Sync + 'static,
         V: Clone + Send + Sync + 'static,
@@ -175,26 +246,48 @@ impl<K, V, S> Invalidator<K, V, S> {
         let mut predicates = self.scan_context.predicates.lock();
         if predicates.is_empty() {
             *predicates = self.predicates.iter().map(|(_k, v)| v).collect();
+            for pred in predicates.iter() {
+                pred.progress.start();
+            }
+        } else {
+            predicates.retain(|p| !p.progress.is_cancelled());
         }
 
         let mut invalidated = Vec::default();
+        let mut locked_entries = Vec::default();
         let mut newest_timestamp = None;
 
         for candidate in &candidates {
             let key = &candidate.key;
             let hash = candidate.hash;
             let ts = candidate.timestamp;
-            if self.apply(&predicates, cache, key, hash, ts) {
-                if let Some(entry) = Self::invalidate(cache, key, hash, ts) {
-                    invalidated.push(KvEntry {
-                        key: Arc::clone(key),
-                        entry,
-                    });
+            if !is_retry {
+                for pred in predicates.iter().filter(|p| p.is_applicable(ts)) {
+                    pred.progress.increment_scanned();
+                }
+            }
+            if let Some(pred) = self.apply(&predicates, cache, key, hash, ts) {
+                match Self::invalidate(cache, key, hash, ts) {
+                    Ok(Some(entry)) => {
+                        invalidated.push(KvEntry {
+                            key: Arc::clone(key),
+                            entry,
+                        });
+                        pred.progress.increment_removed();
+                    }
+                    Ok(None) => (),
+                    // The key is locked by other thread. Keep the predicates
+                    // to retry invalidating the entry in a later run.
+                    Err(()) => locked_entries.push(candidate.clone()),
                 }
             }
             newest_timestamp = Some(ts);
         }
 
+        self.scan_context
+            .locked_entries
+            .lock()
+            .extend(locked_entries);
         self.remove_finished_predicates(predicates, is_truncated, newest_timestamp);
 
         (invalidated, self.predicates.is_empty())
@@ -210,16 +303,18 @@ where
     S: BuildHasher,
 {
     #[inline]
-    fn do_apply_predicates<I>(predicates: I, key: &K, value: &V, ts: Instant) -> bool
+    fn do_apply_predicates<I>(
+        predicates: I,
+        key: &K,
+        value: &V,
+        ts: Instant,
+    ) -> Option<Predicate<K, V>>
     where
         I: Iterator<Item = Predicate<K, V>>,
     {
-        for predicate in predicates {
-            if predicate.is_applicable(ts) && predicate.apply(key, value) {
-                return true;
-            }
-        }
-        false
+        predicates
+            .into_iter()
+            .find(|p| p.is_applicable(ts) && p.apply(key, value))
     }
 
     fn remove_finished_predicates(
@@ -232,7 +327,10 @@ where
         S: BuildHasher,
     {
         let predicates = &mut *predicates;
-        if is_truncated {
+        let has_locked_entries = !self.scan_context.locked_entries.lock().is_empty();
+        if is_truncated && has_locked_entries {
+            // Keep all the predicates until the locked entries are invalidated.
+        } else if is_truncated {
             if let Some(ts) = newest_timestamp {
                 let (active, finished): (Vec<_>, Vec<_>) =
                     predicates.drain(..).partition(|p| p.is_applicable(ts));
@@ -244,10 +342,19 @@ where
             } else {
                 unreachable!();
             }
+        } else if has_locked_entries {
+            // Keep all the predicates, and retry only the locked entries in the
+            // later runs instead of scanning all the entries again.
+            self.scan_context
+                .is_scan_finished
+                .store(true, Ordering::Relaxed);
         } else {
             // Remove all the predicates from the predicate registry and scan context.
             self.remove_predicates(predicates);
             predicates.clear();
+            self.scan_context
+                .is_scan_finished
+                .store(false, Ordering::Relaxed);
         }
     }
 
@@ -258,6 +365,11 @@ where
     {
         let pred_map = &self.predicates;
         for p in predicates.iter() {
+            // Record the status before removing the predicate from the registry, so
+            // that the status can always be queried.
+            if p.progress.complete() {
+                self.finished.record(p.id(), &p.progress);
+            }
             let hash = pred_map.hash(p.id());
             pred_map.remove(hash, |k| k == p.id());
         }
@@ -274,7 +386,7 @@ where
         key: &Arc<K>,
         hash: u64,
         ts: Instant,
-    ) -> bool {
+    ) -> Option<Predicate<K, V>> {
         if let Some(entry) = cache.cache.get(hash, |k| k == key) {
             if let Some(lm) = entry.last_modified() {
                 if lm == ts {
@@ -288,7 +400,7 @@ where
             }
         }
 
-        false
+        None
     }
 
     fn invalidate(
@@ -296,14 +408,14 @@ where
         key: &Arc<K>,
         hash: u64,
         ts: Instant,
-    ) -> Option<TrioArc<ValueEntry<K, V>>>
+    ) -> Result<Option<TrioArc<ValueEntry<K, V>>>, ()>
     where
         K: Send + Sync + 'static,
         V: Clone + Send + Sync + 'static,
     {
-        // Lock the key for removal if blocking removal notification is enabled.
-        let kl = cache.maybe_key_lock(key);
-        let _klg = &kl.as_ref().map(|kl| kl.lock());
+        // Lock the key for removal if blocking removal notification or key locks
+        // are enabled. Do not wait for the lock if other thread holds it.
+        let _klg = cache.try_lock_key_for_removal(key)?;
 
         let maybe_entry = cache.cache.remove_if(
             hash,
@@ -321,7 +433,7 @@ where
                 cache.notify_single_removal(Arc::clone(key), entry, RemovalCause::Explicit);
             }
         }
-        maybe_entry
+        Ok(maybe_entry)
     }
 }
 
@@ -337,12 +449,27 @@ impl<K, V, S> Invalidator<K, V, S> {
 
 struct ScanContext<K, V> {
     predicates: Mutex<Vec<Predicate<K, V>>>,
+    /// The entries skipped by the current pass of the scan because their keys were
+    /// locked.
+    locked_entries: Mutex<Vec<KeyDateLite<K>>>,
+    /// Whether the current pass of the scan has finished, leaving only the locked
+    /// entries to invalidate.
+    is_scan_finished: AtomicBool,
+}
+
+impl<K, V> ScanContext<K, V> {
+    fn reset_locked_entries(&self) {
+        self.locked_entries.lock().clear();
+        self.is_scan_finished.store(false, Ordering::Relaxed);
+    }
 }
 
 impl<K, V> Default for ScanContext<K, V> {
     fn default() -> Self {
         Self {
             predicates: Mutex::new(Vec::default()),
+            locked_entries: Mutex::default(),
+            is_scan_finished: AtomicBool::default(),
         }
     }
 }
@@ -351,6 +478,7 @@ struct Predicate<K, V> {
     id: PredicateId,
     f: PredicateFun<K, V>,
     registered_at: Instant,
+    progress: Arc<PredicateProgress>,
 }
 
 impl<K, V> Clone for Predicate<K, V> {
@@ -359,6 +487,7 @@ impl<K, V> Clone for Predicate<K, V> {
             id: self.id.clone(),
             f: Arc::clone(&self.f),
             registered_at: self.registered_at,
+            progress: Arc::clone(&self.progress),
         }
     }
 }
@@ -369,6 +498,7 @@ impl<K, V> Predicate<K, V> {
             id: id.to_string(),
             f,
             registered_at,
+            progress: Arc::default(),
         }
     }
 
@@ -377,7 +507,7 @@ impl<K, V> Predicate<K, V> {
     }
 
     fn is_applicable(&self, last_modified: Instant) -> bool {
-        last_modified <= self.registered_at
+        last_modified <= self.registered_at && !self.progress.is_cancelled()
     }
 
     fn apply(&self, key: &K, value: &V) -> bool {
@@ -1,3 +1,5 @@
+use crate::EntryMetadata;
+
 use std::{hash::Hash, sync::Arc};
 
 // This trait is implemented by `sync::BaseCache` and `sync::Cache`.
@@ -12,6 +14,14 @@ pub(crate) trait ScanningGet<K, V> {
     /// the idle timer for the key.
     fn scanning_get(&self, key: &Arc<K>) -> Option<V>;
 
+    /// Same as `scanning_get`, but also returns a snapshot of the metadata of the
+    /// entry.
+    fn scanning_get_with_metadata(&self, key: &Arc<K>) -> Option<(V, EntryMetadata)>;
+
+    /// Returns `true` if the cache has a live entry for the key. Like
+    /// `scanning_get`, this method is not considered a cache read operation.
+    fn scanning_contains_key(&self, key: &Arc<K>) -> bool;
+
     /// Returns a vec of keys in a specified segment of the concurrent hash table.
     fn keys(&self, cht_segment: usize) -> Option<Vec<Arc<K>>>;
 }
@@ -43,7 +53,6 @@ impl<'i, K, V> Iter<'i, K, V> {
         }
     }
 
-    #[cfg(feature = "sync")]
     pub(crate) fn with_multiple_cache_segments(
         cache_segments: Box<[&'i dyn ScanningGet<K, V>]>,
         num_cht_segments: usize,
@@ -82,16 +91,114 @@ where
     }
 }
 
+/// Iterator visiting all entries in a cache with their metadata in arbitrary
+/// order.
+///
+/// Call [`Cache::iter_with_metadata`](./struct.Cache.html#method.iter_with_metadata)
+/// method to obtain an `IterWithMetadata`.
+pub struct IterWithMetadata<'i, K, V>(Iter<'i, K, V>);
+
+impl<'i, K, V> IterWithMetadata<'i, K, V> {
+    pub(crate) fn new(iter: Iter<'i, K, V>) -> Self {
+        Self(iter)
+    }
+}
+
+impl<K, V> Iterator for IterWithMetadata<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = (Arc<K>, V, EntryMetadata);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let iter = &mut self.0;
+        if iter.is_done {
+            return None;
+        }
+
+        while let Some(key) = iter.next_key() {
+            if let Some((v, metadata)) = iter.cache().scanning_get_with_metadata(&key) {
+                return Some((key, v, metadata));
+            }
+        }
+
+        iter.is_done = true;
+        None
+    }
+}
+
+/// Iterator visiting all keys in a cache in arbitrary order.
+///
+/// Call [`Cache::keys`](./struct.Cache.html#method.keys) method to obtain a `Keys`.
+pub struct Keys<'i, K, V>(Iter<'i, K, V>);
+
+impl<'i, K, V> Keys<'i, K, V> {
+    pub(crate) fn new(iter: Iter<'i, K, V>) -> Self {
+        Self(iter)
+    }
+}
+
+impl<K, V> Iterator for Keys<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = Arc<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let iter = &mut self.0;
+        if iter.is_done {
+            return None;
+        }
+
+        while let Some(key) = iter.next_key() {
+            if iter.cache().scanning_contains_key(&key) {
+                return Some(key);
+            }
+        }
+
+        iter.is_done = true;
+        None
+    }
+}
+
+/// Iterator visiting all values in a cache in arbitrary order.
+///
+/// Call [`Cache::values`](./struct.Cache.html#method.values) method to obtain a
+/// `Values`.
+pub struct Values<'i, K, V>(Iter<'i, K, V>);
+
+impl<'i, K, V> Values<'i, K, V> {
+    pub(crate) fn new(iter: Iter<'i, K, V>) -> Self {
+        Self(iter)
+    }
+}
+
+impl<K, V> Iterator for Values<'_, K, V>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+}
+
 impl<'i, K, V> Iter<'i, K, V>
 where
     K: Eq + Hash + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
 {
-    fn cache(&self) -> &'i dyn ScanningGet<K, V> {
+    pub(crate) fn cache(&self) -> &'i dyn ScanningGet<K, V> {
         self.cache_segments[self.cache_seg_index]
     }
 
-    fn next_key(&mut self) -> Option<Arc<K>> {
+    /// Returns the next key in the cache. The caller must not call this method
+    /// again after it returned `None`.
+    pub(crate) fn next_key(&mut self) -> Option<Arc<K>> {
         while let Some(keys) = self.current_keys() {
             if let key @ Some(_) = keys.pop() {
                 return key;
@@ -1,16 +1,23 @@
 use std::{
+    fmt,
     hash::{BuildHasher, Hash},
     sync::Arc,
 };
 
-use crate::cht::SegmentedHashMap;
+use crate::{
+    cht::SegmentedHashMap,
+    common::memory_report::{arc_alloc_size, MemoryReport},
+};
 
-use parking_lot::{Mutex, MutexGuard};
-use triomphe::Arc as TrioArc;
+use parking_lot::{
+    ArcReentrantMutexGuard, RawMutex, RawThreadId, ReentrantMutex, ReentrantMutexGuard,
+};
 
 const LOCK_MAP_NUM_SEGMENTS: usize = 64;
 
-type LockMap<K, S> = SegmentedHashMap<Arc<K>, TrioArc<Mutex<()>>, S>;
+// The locks are reentrant so that the thread holding a `KeyGuard` can call the
+// methods of the cache on the same key.
+type LockMap<K, S> = SegmentedHashMap<Arc<K>, Arc<ReentrantMutex<()>>, S>;
 
 // We need the `where` clause here because of the Drop impl.
 pub(crate) struct KeyLock<'a, K, S>
@@ -21,7 +28,7 @@ where
     map: &'a LockMap<K, S>,
     key: Arc<K>,
     hash: u64,
-    lock: TrioArc<Mutex<()>>,
+    lock: Arc<ReentrantMutex<()>>,
 }
 
 impl<K, S> Drop for KeyLock<'_, K, S>
@@ -30,11 +37,11 @@ where
     S: BuildHasher,
 {
     fn drop(&mut self) {
-        if TrioArc::count(&self.lock) <= 2 {
+        if Arc::strong_count(&self.lock) <= 2 {
             self.map.remove_if(
                 self.hash,
                 |k| k == &self.key,
-                |_k, v| TrioArc::count(v) <= 2,
+                |_k, v| Arc::strong_count(v) <= 2,
             );
         }
     }
@@ -45,7 +52,7 @@ where
     K: Eq + Hash,
     S: BuildHasher,
 {
-    fn new(map: &'a LockMap<K, S>, key: &Arc<K>, hash: u64, lock: TrioArc<Mutex<()>>) -> Self {
+    fn new(map: &'a LockMap<K, S>, key: &Arc<K>, hash: u64, lock: Arc<ReentrantMutex<()>>) -> Self {
         Self {
             map,
             key: Arc::clone(key),
@@ -54,9 +61,34 @@ where
         }
     }
 
-    pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+    pub(crate) fn lock(&self) -> ReentrantMutexGuard<'_, ()> {
         self.lock.lock()
     }
+
+    /// Returns the order to acquire the lock in. The hash orders the locks, and
+    /// the address of the lock breaks ties between the keys with the same hash.
+    fn lock_order(&self) -> (u64, usize) {
+        (self.hash, Arc::as_ptr(&self.lock) as usize)
+    }
+
+    pub(crate) fn into_guard(self) -> KeyGuard<'a, K, S> {
+        KeyGuard {
+            _guard: self.lock.lock_arc(),
+            key_lock: self,
+        }
+    }
+
+    /// Locks the key without waiting. Returns the `KeyLock` back if another thread
+    /// holds the lock.
+    pub(crate) fn try_into_guard(self) -> Result<KeyGuard<'a, K, S>, Self> {
+        match self.lock.try_lock_arc() {
+            Some(guard) => Ok(KeyGuard {
+                _guard: guard,
+                key_lock: self,
+            }),
+            None => Err(self),
+        }
+    }
 }
 
 pub(crate) struct KeyLockMap<K, S> {
@@ -76,15 +108,30 @@ where
 
     pub(crate) fn key_lock(&self, key: &Arc<K>) -> KeyLock<'_, K, S> {
         let hash = self.locks.hash(key);
-        let kl = TrioArc::new(Mutex::new(()));
+        let kl = Arc::new(ReentrantMutex::new(()));
         match self
             .locks
-            .insert_if_not_present(Arc::clone(key), hash, kl.clone())
+            .insert_if_not_present(Arc::clone(key), hash, Arc::clone(&kl))
         {
             None => KeyLock::new(&self.locks, key, hash, kl),
             Some(existing_kl) => KeyLock::new(&self.locks, key, hash, existing_kl),
         }
     }
+
+    /// Locks the keys in a deterministic order, so that the callers locking
+    /// overlapping sets of keys do not deadlock each other. A key given more than
+    /// once is locked only once.
+    pub(crate) fn lock_keys(&self, keys: &[Arc<K>]) -> Vec<KeyGuard<'_, K, S>> {
+        let mut kls = keys.iter().map(|k| self.key_lock(k)).collect::<Vec<_>>();
+        kls.sort_unstable_by_key(|kl| kl.lock_order());
+        kls.dedup_by_key(|kl| kl.lock_order());
+        kls.into_iter().map(KeyLock::into_guard).collect()
+    }
+
+    pub(crate) fn record_memory_usage(&self, report: &mut MemoryReport) {
+        let usage = self.locks.memory_usage();
+        report.key_locks = report.add_hash_table(&usage, arc_alloc_size::<ReentrantMutex<()>>());
+    }
 }
 
 #[cfg(test)]
@@ -93,3 +140,47 @@ impl<K, S> KeyLockMap<K, S> {
         self.locks.len() == 0
     }
 }
+
+/// A lock on a key of a [`Cache`][cache-struct], returned by the
+/// [`Cache::lock_key`][lock-key-method] method.
+///
+/// While a thread holds a `KeyGuard`, the other threads cannot insert, update or
+/// remove the entry for the key; they wait until the `KeyGuard` is dropped. The
+/// thread holding the `KeyGuard` can call the methods of the cache on the key as
+/// usual.
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [lock-key-method]: ./struct.Cache.html#method.lock_key
+#[must_use = "the key is unlocked as soon as the guard is dropped"]
+pub struct KeyGuard<'a, K, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    // Declared before `key_lock` to unlock before removing the lock from the map.
+    _guard: ArcReentrantMutexGuard<RawMutex, RawThreadId, ()>,
+    key_lock: KeyLock<'a, K, S>,
+}
+
+impl<K, S> KeyGuard<'_, K, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Returns the locked key.
+    pub fn key(&self) -> &Arc<K> {
+        &self.key_lock.key
+    }
+}
+
+impl<K, S> fmt::Debug for KeyGuard<'_, K, S>
+where
+    K: fmt::Debug + Eq + Hash,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyGuard")
+            .field("key", &self.key_lock.key)
+            .finish()
+    }
+}
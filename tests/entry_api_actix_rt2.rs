@@ -7,7 +7,7 @@ use std::sync::{
 
 use actix_rt::Runtime;
 use async_lock::Barrier;
-use moka::future::Cache;
+use moka2::future::Cache;
 
 const NUM_THREADS: u8 = 16;
 
@@ -6,7 +6,7 @@ use std::sync::{
 };
 
 use async_lock::Barrier;
-use moka::future::Cache;
+use moka2::future::Cache;
 
 const NUM_THREADS: u8 = 16;
 
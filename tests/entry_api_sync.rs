@@ -7,7 +7,7 @@ use std::{
     thread,
 };
 
-use moka::{
+use moka2::{
     sync::{Cache, SegmentedCache},
     Entry,
 };
@@ -6,7 +6,7 @@ use std::sync::{
 };
 
 use async_lock::Barrier;
-use moka::{future::Cache, Entry};
+use moka2::{future::Cache, Entry};
 
 const NUM_THREADS: u8 = 16;
 const SITE: &str = "https://www.rust-lang.org/";
@@ -3,7 +3,7 @@
 use std::sync::Arc;
 
 use actix_rt::System;
-use moka::future::Cache;
+use moka2::future::Cache;
 use tokio::sync::Barrier;
 
 #[actix_rt::test]
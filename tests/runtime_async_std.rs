@@ -5,7 +5,7 @@ use std::sync::Arc;
 // Use async_lock's Barrier instead of async_std's Barrier as the latter requires
 // `unstable` feature (v1.12.0).
 use async_lock::Barrier;
-use moka::future::Cache;
+use moka2::future::Cache;
 
 #[async_std::test]
 async fn main() {
@@ -2,7 +2,7 @@
 
 use std::sync::Arc;
 
-use moka::future::Cache;
+use moka2::future::Cache;
 use tokio::sync::Barrier;
 
 #[tokio::test]